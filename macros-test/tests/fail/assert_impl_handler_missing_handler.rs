@@ -0,0 +1,17 @@
+use xtra::prelude::*;
+
+#[derive(xtra::Actor)]
+struct Door;
+
+struct Open;
+struct Lock;
+
+impl Handler<Open> for Door {
+    type Return = ();
+
+    async fn handle(&mut self, _: Open, _: &mut Context<Self>) {}
+}
+
+xtra::assert_impl_handler!(Door, [Open, Lock]);
+
+fn main() {}
@@ -0,0 +1,15 @@
+use xtra::prelude::*;
+
+#[derive(xtra::Actor)]
+struct MyActor;
+
+struct Ping;
+
+#[xtra::handlers]
+impl MyActor {
+    async fn ping(&mut self, _: Ping, _ctx: &mut Context<Self>, extra: u32) -> u32 {
+        extra
+    }
+}
+
+fn main() {}
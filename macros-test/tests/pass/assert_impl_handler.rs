@@ -0,0 +1,26 @@
+use xtra::prelude::*;
+
+#[derive(xtra::Actor)]
+struct Door;
+
+struct Open;
+struct Close;
+
+impl Handler<Open> for Door {
+    type Return = ();
+
+    async fn handle(&mut self, _: Open, _: &mut Context<Self>) {}
+}
+
+impl Handler<Close> for Door {
+    type Return = bool;
+
+    async fn handle(&mut self, _: Close, _: &mut Context<Self>) -> bool {
+        true
+    }
+}
+
+xtra::assert_impl_handler!(Door, [Open, Close]);
+xtra::assert_impl_handler!(Door, [Open, Close,]);
+
+fn main() {}
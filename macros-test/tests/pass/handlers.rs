@@ -0,0 +1,42 @@
+use xtra::prelude::*;
+
+#[derive(xtra::Actor)]
+struct MyActor<T> {
+    items: Vec<T>,
+}
+
+struct Push<T>(T);
+
+struct Len;
+
+struct Pop;
+
+#[xtra::handlers]
+impl<T> MyActor<T>
+where
+    T: Send + 'static,
+{
+    async fn push(&mut self, Push(item): Push<T>) {
+        self.items.push(item);
+    }
+
+    async fn len(&mut self, _: Len, _ctx: &mut Context<Self>) -> usize {
+        self.items.len()
+    }
+
+    async fn pop(&mut self, _: Pop) -> Result<T, &'static str> {
+        self.items.pop().ok_or("empty")
+    }
+
+    fn helper(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+fn assert_handler<A: Handler<M, Return = R>, M, R>() {}
+
+fn main() {
+    assert_handler::<MyActor<u8>, Push<u8>, ()>();
+    assert_handler::<MyActor<u8>, Len, usize>();
+    assert_handler::<MyActor<u8>, Pop, Result<u8, &'static str>>();
+}
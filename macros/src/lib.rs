@@ -1,6 +1,10 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_quote, DeriveInput, GenericParam, WherePredicate};
+use quote::{quote, quote_spanned};
+use syn::spanned::Spanned;
+use syn::{
+    parse_quote, DeriveInput, FnArg, GenericParam, ImplItem, ImplItemMethod, ItemImpl, ReturnType,
+    WherePredicate,
+};
 
 #[proc_macro_derive(Actor)]
 pub fn actor_derive(input: TokenStream) -> TokenStream {
@@ -28,6 +32,107 @@ pub fn actor_derive(input: TokenStream) -> TokenStream {
     .into()
 }
 
+#[proc_macro_attribute]
+pub fn handlers(attr: TokenStream, item: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(
+            proc_macro2::TokenStream::from(attr).span(),
+            "`handlers` takes no arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let item_impl = match syn::parse::<ItemImpl>(item) {
+        Ok(item_impl) if item_impl.trait_.is_none() => item_impl,
+        Ok(item_impl) => {
+            return syn::Error::new(
+                item_impl.span(),
+                "`handlers` must be used on an inherent impl block",
+            )
+            .to_compile_error()
+            .into()
+        }
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    let handler_impls = item_impl
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            ImplItem::Method(method) => handler_impl(&item_impl, method),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    quote! {
+        #item_impl
+
+        #(#handler_impls)*
+    }
+    .into()
+}
+
+/// Generate the [`Handler`] impl for a method of a `#[handlers]` impl block, if it is a handler,
+/// i.e. an `async fn` taking `&mut self`, the message and optionally the context.
+fn handler_impl(item_impl: &ItemImpl, method: &ImplItemMethod) -> Option<proc_macro2::TokenStream> {
+    let sig = &method.sig;
+
+    sig.asyncness?;
+
+    let mut inputs = sig.inputs.iter();
+
+    match inputs.next() {
+        Some(FnArg::Receiver(receiver)) if receiver.mutability.is_some() => {}
+        _ => return None,
+    }
+
+    let message_ty = match inputs.next() {
+        Some(FnArg::Typed(message)) => &message.ty,
+        _ => return None,
+    };
+    let with_context = inputs.next().is_some();
+
+    if let Some(extra) = inputs.next() {
+        return Some(quote_spanned! { extra.span() =>
+            compile_error!("a handler takes the message and optionally the context, but nothing else");
+        });
+    }
+
+    if !sig.generics.params.is_empty() {
+        return Some(quote_spanned! { sig.generics.span() =>
+            compile_error!("a handler cannot have generic parameters of its own");
+        });
+    }
+
+    let self_ty = &item_impl.self_ty;
+    let (impl_generics, _, where_clause) = item_impl.generics.split_for_impl();
+    let method_ident = &sig.ident;
+    let return_ty = match &sig.output {
+        ReturnType::Default => quote! { () },
+        ReturnType::Type(_, ty) => quote! { #ty },
+    };
+    let call = if with_context {
+        quote! { Self::#method_ident(self, message, ctx).await }
+    } else {
+        quote! { Self::#method_ident(self, message).await }
+    };
+
+    Some(quote! {
+        impl #impl_generics xtra::Handler<#message_ty> for #self_ty #where_clause {
+            type Return = #return_ty;
+
+            async fn handle(
+                &mut self,
+                message: #message_ty,
+                #[allow(unused_variables)] ctx: &mut xtra::Context<Self>,
+            ) -> #return_ty {
+                #call
+            }
+        }
+    })
+}
+
 /// Generics a `: Send + 'static` predicate for each type parameter present in the generics.
 fn send_and_static_bounds(input: &DeriveInput) -> Vec<WherePredicate> {
     input
@@ -0,0 +1,82 @@
+//! Counts the heap allocations needed to send a message to an actor and await its reply.
+//!
+//! Handlers are `async fn`s in the trait, so the future returned by [`Handler::handle`] is never
+//! boxed by the handler itself. [`xtra::run`] still boxes it once per message to dispatch it
+//! dynamically, which [`xtra::run_monomorphic`] avoids for messages of a single type.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures_util::future;
+use xtra::{Actor, Context, Handler, Mailbox};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const NUM_MESSAGES: usize = 10_000;
+
+struct Counter(u64);
+
+impl Actor for Counter {
+    type Stop = ();
+    async fn stopped(self) {}
+}
+
+struct IncrementZst;
+
+impl Handler<IncrementZst> for Counter {
+    type Return = ();
+
+    async fn handle(&mut self, _: IncrementZst, _ctx: &mut Context<Self>) {
+        self.0 += 1;
+    }
+}
+
+fn allocations_per_message<F, Fut>(name: &str, run: F)
+where
+    F: Fn(Mailbox<Counter>, Counter) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let (address, mailbox) = Mailbox::bounded(1);
+
+    let send = async move {
+        // Warm up, so that lazily allocated buffers are not counted.
+        address.send(IncrementZst).await.unwrap();
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..NUM_MESSAGES {
+            address.send(IncrementZst).await.unwrap();
+        }
+        let after = ALLOCATIONS.load(Ordering::Relaxed);
+
+        (after - before) as f64 / NUM_MESSAGES as f64
+    };
+
+    let (_, allocations) = smol::block_on(future::join(run(mailbox, Counter(0)), send));
+
+    println!("{name}: {allocations:.2} allocations per message");
+}
+
+fn main() {
+    allocations_per_message("send_zst", xtra::run);
+    allocations_per_message(
+        "send_zst_monomorphic",
+        xtra::run_monomorphic::<_, IncrementZst>,
+    );
+}
@@ -0,0 +1,78 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tokio::runtime::Runtime;
+use xtra::{Actor, Context, Handler, Mailbox, MailboxBacking};
+
+struct Counter(u64);
+
+impl Actor for Counter {
+    type Stop = ();
+    async fn stopped(self) {}
+}
+
+struct Increment;
+
+impl Handler<Increment> for Counter {
+    type Return = u64;
+
+    async fn handle(&mut self, _: Increment, _ctx: &mut Context<Self>) -> u64 {
+        self.0 += 1;
+        self.0
+    }
+}
+
+const BACKINGS: [MailboxBacking; 2] = [MailboxBacking::Heap, MailboxBacking::Segmented];
+
+/// Queue all messages at once, so that they pile up in the mailbox, then wait for the last one.
+fn bursty(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mailbox_bursty");
+    let runtime = Runtime::new().unwrap();
+
+    for backing in BACKINGS {
+        for num_messages in [10, 100, 1000, 10000] {
+            let (address, mailbox) = Mailbox::unbounded();
+            let _task = runtime.spawn(xtra::run(mailbox.with_backing(backing), Counter(0)));
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("{backing:?}"), num_messages),
+                &num_messages,
+                |b, &num_messages| {
+                    b.to_async(&runtime).iter(|| async {
+                        for _ in 0..num_messages - 1 {
+                            let _ = address.send(Increment).detach().await;
+                        }
+
+                        address.send(Increment).await.unwrap()
+                    });
+                },
+            );
+        }
+    }
+}
+
+/// Wait for each message before sending the next, so that the mailbox never holds more than one.
+fn steady(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mailbox_steady");
+    let runtime = Runtime::new().unwrap();
+
+    for backing in BACKINGS {
+        for num_messages in [10, 100, 1000] {
+            let (address, mailbox) = Mailbox::unbounded();
+            let _task = runtime.spawn(xtra::run(mailbox.with_backing(backing), Counter(0)));
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("{backing:?}"), num_messages),
+                &num_messages,
+                |b, &num_messages| {
+                    b.to_async(&runtime).iter(|| async {
+                        for _ in 0..num_messages {
+                            address.send(Increment).await.unwrap();
+                        }
+                    });
+                },
+            );
+        }
+    }
+}
+
+criterion_group!(benches, bursty, steady);
+criterion_main!(benches);
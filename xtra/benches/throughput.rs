@@ -1,3 +1,5 @@
+use std::future::Future;
+
 use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 use tokio::runtime::Runtime;
 use xtra::{Actor, Context, Handler, Mailbox};
@@ -28,14 +30,18 @@ impl Handler<Finish> for Counter {
     }
 }
 
-fn throughput(c: &mut Criterion) {
-    let mut group = c.benchmark_group("send_zst");
+fn send_zst<F, Fut>(c: &mut Criterion, name: &str, run: F)
+where
+    F: Fn(Mailbox<Counter>, Counter) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let mut group = c.benchmark_group(name);
     let runtime = Runtime::new().unwrap();
     let _g = runtime.enter();
 
     for num_messages in [1, 10, 100, 1000] {
         let (address, mailbox) = Mailbox::bounded(num_messages);
-        let _task = smol::spawn(xtra::run(mailbox, Counter(0)));
+        let _task = smol::spawn(run(mailbox, Counter(0)));
 
         group.bench_with_input(
             BenchmarkId::from_parameter(num_messages),
@@ -53,5 +59,17 @@ fn throughput(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, throughput);
+fn throughput(c: &mut Criterion) {
+    send_zst(c, "send_zst", xtra::run);
+}
+
+fn throughput_monomorphic(c: &mut Criterion) {
+    send_zst(
+        c,
+        "send_zst_monomorphic",
+        xtra::run_monomorphic::<_, IncrementZst>,
+    );
+}
+
+criterion_group!(benches, throughput, throughput_monomorphic);
 criterion_main!(benches);
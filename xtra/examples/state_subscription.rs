@@ -0,0 +1,58 @@
+use futures_util::StreamExt;
+use xtra::prelude::*;
+use xtra::subscription::Overflow;
+
+/// A counter which publishes its value whenever it changes.
+#[derive(Default)]
+struct Counter {
+    value: u64,
+}
+
+impl Actor for Counter {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+/// A snapshot of the value of the counter.
+#[derive(Clone, Copy)]
+struct Count(u64);
+
+struct Increment;
+
+impl Handler<Increment> for Counter {
+    type Return = ();
+
+    async fn handle(&mut self, _: Increment, ctx: &mut Context<Self>) {
+        self.value += 1;
+        ctx.publish_state(Count(self.value));
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let counter = xtra::spawn_tokio(Counter::default(), Mailbox::unbounded());
+
+    counter.send(Increment).await.unwrap();
+
+    // A display which only cares about the latest value keeps a buffer of one, dropping values it
+    // has not caught up with. It starts with the current value, even though it subscribed late.
+    let mut display = counter.subscribe_state::<Count>(1, Overflow::DropOldest);
+    let Count(value) = display.next().await.unwrap();
+    println!("Display starts at {value}");
+
+    // A log which wants every value keeps a larger buffer.
+    let log = counter.subscribe_state::<Count>(16, Overflow::DropNewest);
+
+    for _ in 0..3 {
+        counter.send(Increment).await.unwrap();
+    }
+
+    let Count(value) = display.next().await.unwrap();
+    println!("Display shows {value}");
+
+    // Once the counter stops, the subscriptions end after their buffered values.
+    drop(counter);
+    let values = log.map(|Count(value)| value).collect::<Vec<_>>().await;
+    println!("Log received {values:?}");
+}
@@ -1,19 +1,34 @@
 //! An address to an actor is a way to send it a message. An address allows an actor to be sent any
 //! kind of message that it can receive.
 
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::fmt::{self, Debug, Formatter};
 use std::future::Future;
 use std::hash::{Hash, Hasher};
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use event_listener::EventListener;
-use futures_util::FutureExt;
+use futures_core::future::BoxFuture;
+use futures_util::{future, FutureExt, StreamExt};
 
+use crate::buffered::BufferedAddress;
+use crate::chan::{MailboxFull, MessageToOne};
+use crate::envelope::ReturningEnvelope;
+use crate::inspect::{self, Inspect, InspectReport};
+use crate::keyed::Key;
+use crate::message_channel::MessageChannel;
+use crate::monitor::Down;
+use crate::rate_limit::{Quota, RateLimitedAddress};
 use crate::refcount::{Either, RefCounter, Strong, Weak};
-use crate::send_future::{ActorNamedBroadcasting, Broadcast, ResolveToHandlerReturn};
-use crate::{chan, ActorNamedSending, Handler, SendFuture};
+use crate::reply_stream::{ReplyStream, Streaming};
+use crate::send_future::{ActorNamedBroadcasting, Broadcast, Forget, ResolveToHandlerReturn};
+use crate::subscription::{Overflow, Subscription};
+use crate::{
+    chan, Actor, ActorId, ActorNamedSending, DisconnectReason, Error, Handler, SendFuture,
+};
 
 /// An [`Address`] is a reference to an actor through which messages can be sent.
 ///
@@ -62,6 +77,26 @@ use crate::{chan, ActorNamedSending, Handler, SendFuture};
 /// of their priority. All actors must handle a message for it to be removed from the mailbox and
 /// the length to decrease. This means that the backpressure provided by [`Address::broadcast`] will
 /// wait for the slowest actor.
+///
+/// ## Ordering
+///
+/// Messages of equal priority which are sent to an actor are handled in the order in which they
+/// were queued, regardless of their types and of whether they were sent with [`Address::send`],
+/// [`Address::send_and_forget`], [`SendFuture::detach`] or through a
+/// [`MessageChannel`]. A message is queued when its [`SendFuture`] is first polled or, if the
+/// mailbox is full, once there is room for it, in the order in which its senders started waiting.
+/// Hence, messages which one task sends one after another are handled in that order. If several
+/// actors run on the same address, they start handling the messages in that order.
+///
+/// The order only changes where this is asked for:
+/// - Messages of a higher [priority](SendFuture::priority) are handled first, also with
+///   [`Mailbox::with_priority_fairness`](crate::Mailbox::with_priority_fairness), which only
+///   changes the order between priorities.
+/// - Broadcasts are queued separately, so they are not ordered with messages to one actor of
+///   equal priority.
+/// - Messages are set aside while [`Handler::can_handle`] returns `false`, and put back with
+///   [`Context::requeue`](crate::Context::requeue).
+/// - Messages sent through a [`BufferedAddress`] are only queued once the buffer is flushed.
 pub struct Address<A, Rc: RefCounter = Strong>(pub(crate) chan::Ptr<A, Rc>);
 
 impl<A, Rc: RefCounter> Debug for Address<A, Rc> {
@@ -72,6 +107,7 @@ impl<A, Rc: RefCounter> Debug for Address<A, Rc> {
             .replace("Tx", "");
 
         f.debug_struct(&format!("Address<{}, {}>", actor_type, rc_type))
+            .field("id", &self.0.id())
             .field("addresses", &self.0.sender_count())
             .field("mailboxes", &self.0.receiver_count())
             .finish()
@@ -91,6 +127,31 @@ impl<A> WeakAddress<A> {
     pub fn try_upgrade(&self) -> Option<Address<A>> {
         Some(Address(self.0.try_to_tx_strong()?))
     }
+
+    /// Send a message to the actor if it is still alive, returning `None` if it is not.
+    ///
+    /// Unlike upgrading the address with [`WeakAddress::try_upgrade`] and sending the message
+    /// separately, this is a single call. The actor is only kept alive until the message has been
+    /// sent, not until it has been handled.
+    #[allow(clippy::type_complexity)]
+    pub fn send_if_alive<M>(
+        &self,
+        message: M,
+    ) -> Option<
+        SendFuture<ActorNamedSending<A, Strong>, ResolveToHandlerReturn<<A as Handler<M>>::Return>>,
+    >
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        let address = self.try_upgrade()?;
+
+        if !address.is_connected() {
+            return None;
+        }
+
+        Some(address.send(message))
+    }
 }
 
 /// Functions which apply only to strong addresses (the default kind).
@@ -120,6 +181,48 @@ where
     pub fn as_either(&self) -> Address<A, Either> {
         Address(self.0.to_tx_either())
     }
+
+    /// Erase the type of the actor behind a clone of this address, returning a [`MessageChannel`]
+    /// for the message type `M` with the same reference count as this address.
+    ///
+    /// This is a shorthand for `MessageChannel::new(address.clone())`. Since [`MessageChannel`]
+    /// implements `From<Address>`, APIs can accept either kind of handle as
+    /// `impl Into<MessageChannel<M, R>>`, and a channel passed to them is used as is.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Logger;
+    /// # impl Actor for Logger { type Stop = (); async fn stopped(self) {} }
+    /// struct Log(&'static str);
+    ///
+    /// impl Handler<Log> for Logger {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Log, _: &mut Context<Self>) {}
+    /// }
+    ///
+    /// fn log_to(sink: impl Into<MessageChannel<Log, ()>>) -> MessageChannel<Log, ()> {
+    ///     sink.into()
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let logger = xtra::spawn_smol(Logger, Mailbox::unbounded());
+    ///
+    ///     let typed = log_to(logger.clone());
+    ///     let erased = log_to(logger.channel::<Log>());
+    ///
+    ///     assert_eq!(typed, erased);
+    ///     erased.send(Log("hello")).await.unwrap();
+    /// })
+    /// ```
+    pub fn channel<M>(&self) -> MessageChannel<M, <A as Handler<M>>::Return, Rc>
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        MessageChannel::new(self.clone())
+    }
 }
 
 /// Functions which apply to any kind of address, be they strong or weak.
@@ -155,6 +258,16 @@ impl<A, Rc: RefCounter> Address<A, Rc> {
         self.0.is_connected()
     }
 
+    /// Why the actor is disconnected from this address, if it is and the reason is known.
+    ///
+    /// The reason is recorded by the event loop of the actor once it stops, so that it can be looked
+    /// up once sending to the address fails with [`Error::Disconnected`]. It is unknown if the actor was never
+    /// run, or if it was run by a custom event loop rather than [`run`](crate::run) or one of the
+    /// `spawn` functions of xtra.
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.0.disconnect_reason()
+    }
+
     /// Returns the number of messages in the actor's mailbox. This will be the sum of broadcast
     /// messages, priority messages, and ordered messages. It can be up to three times the capacity,
     /// as the capacity is for each send type (broadcast, priority, and ordered).
@@ -162,6 +275,12 @@ impl<A, Rc: RefCounter> Address<A, Rc> {
         self.0.len()
     }
 
+    /// Returns the number of messages of the given [`priority`](crate::SendFuture::priority) which
+    /// are waiting in the actor's mailbox. Broadcast messages are not counted.
+    pub fn len_with_priority(&self, priority: u32) -> usize {
+        self.0.len_with_priority(priority)
+    }
+
     /// The capacity of the actor's mailbox per send type (broadcast, priority, and ordered).
     pub fn capacity(&self) -> Option<usize> {
         self.0.capacity()
@@ -172,13 +291,114 @@ impl<A, Rc: RefCounter> Address<A, Rc> {
         self.len() == 0
     }
 
+    /// Returns whether an actor on this address is currently handling a message, as opposed to
+    /// waiting for the next message to arrive in its mailbox.
+    ///
+    /// Together with [`Address::len`], this can be used to estimate the load of an actor, e.g. to
+    /// pick the least busy one out of a pool of workers. As the actor may start or finish handling
+    /// a message at any time, the returned value should only be treated as a hint.
+    pub fn is_busy(&self) -> bool {
+        self.0.is_busy()
+    }
+
+    /// Waits until the actor is idle: no actor on this address is handling a message, and none are
+    /// waiting in its mailbox. If the actor is busy, this resolves the next time it becomes idle,
+    /// which includes handling all messages it sends to itself along the way, e.g. with
+    /// [`Context::notify`](crate::Context::notify). If it is idle already, this resolves right away.
+    ///
+    /// This is meant for tests and readiness checks, to know when a cascade of messages has
+    /// settled. Messages scheduled for later, e.g. with
+    /// [`Context::notify_after`](crate::Context::notify_after), do not keep the actor busy until
+    /// they are due. Fails like sending a message if the actor stops before becoming idle.
+    pub async fn wait_until_idle(&self) -> Result<(), Error> {
+        let idle = self.0.idle_listener();
+
+        if !self.is_connected() {
+            return Err(Error::Disconnected);
+        }
+
+        if self.0.is_idle() {
+            return Ok(());
+        }
+
+        match future::select(idle, self.join()).await {
+            future::Either::Left(((), _)) => Ok(()),
+            future::Either::Right(((), _)) => Err(Error::Disconnected),
+        }
+    }
+
+    /// The unique identifier of the actor referred to by this address.
+    ///
+    /// Unlike names, identifiers never collide: two addresses have the same identifier exactly if
+    /// [`Address::same_actor`] returns `true` for them.
+    pub fn id(&self) -> ActorId {
+        self.0.id()
+    }
+
+    /// The name of the actor referred to by this address, as returned by [`Actor::name`].
+    ///
+    /// The name is captured when the actor is started. Until then, this returns the name of the
+    /// actor's type.
+    ///
+    /// ```rust
+    /// # use std::borrow::Cow;
+    /// # use xtra::prelude::*;
+    /// struct Connection(u32);
+    ///
+    /// impl Actor for Connection {
+    ///     type Stop = ();
+    ///
+    ///     fn name(&self) -> Cow<'static, str> {
+    ///         format!("Connection#{}", self.0).into()
+    ///     }
+    ///
+    ///     async fn stopped(self) {}
+    /// }
+    ///
+    /// # struct Ping;
+    /// # impl Handler<Ping> for Connection {
+    /// #     type Return = ();
+    /// #     async fn handle(&mut self, _: Ping, _: &mut Context<Self>) {}
+    /// # }
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let (addr, mailbox) = Mailbox::unbounded();
+    ///     smol::spawn(xtra::run(mailbox, Connection(42))).detach();
+    ///     # addr.send(Ping).await.unwrap(); // Make sure the actor has started.
+    ///     assert_eq!(addr.name(), "Connection#42");
+    /// })
+    /// ```
+    pub fn name(&self) -> Cow<'static, str> {
+        self.0.name()
+    }
+
+    /// Take a snapshot of the state of the actor with [`Inspect::inspect`].
+    ///
+    /// The request is queued like a message sent with [`Address::send`], so the snapshot is taken
+    /// in between two handlers. See the [`inspect`] module for an example.
+    pub fn inspect(
+        &self,
+    ) -> SendFuture<ActorNamedSending<A, Rc>, ResolveToHandlerReturn<InspectReport>>
+    where
+        A: Inspect,
+    {
+        SendFuture::sending_named(inspect::Request, self.0.clone())
+    }
+
     /// Send a message to the actor. The message will, by default, have a priority of 0 and be sent
     /// into the ordered queue. This can be configured through [`SendFuture::priority`].
     ///
     /// The actor must implement [`Handler<Message>`] for this to work.
     ///
     /// This function returns a [`Future`](SendFuture) that resolves to the [`Return`](crate::Handler::Return) value of the handler.
-    /// The [`SendFuture`] will resolve to [`Err(Disconnected)`] in case the actor is stopped and not accepting messages.
+    /// The [`SendFuture`] will resolve to [`Err(Disconnected)`] in case the actor is stopped and not accepting messages,
+    /// which carries the [`DisconnectReason`] for which the actor stopped if it is known.
+    ///
+    /// A handler must not await the reply to a message sent to its own actor, as the actor cannot
+    /// handle the message before the handler returns. Awaiting such a reply resolves to
+    /// [`Error::WouldDeadlock`] instead of deadlocking. The same applies to a cycle of actors
+    /// awaiting replies from each other, which panics with debug assertions enabled. Both are only
+    /// detected if there is a single actor on the address.
     #[allow(clippy::type_complexity)]
     pub fn send<M>(
         &self,
@@ -191,11 +411,694 @@ impl<A, Rc: RefCounter> Address<A, Rc> {
         SendFuture::sending_named(message, self.0.clone())
     }
 
+    /// Send a message to the actor and resolve to the [`Return`](crate::Handler::Return) value of
+    /// its handler, or to an [`Error`] if it is not accepting messages.
+    ///
+    /// This is an alias of [`Address::send`] for request-response style code. As [`Error`]
+    /// implements [`std::error::Error`], the result composes with `?` in functions returning e.g.
+    /// `anyhow::Result` or an error type derived with `thiserror`.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # #[derive(Default, xtra::Actor)]
+    /// # struct Counter(u32);
+    /// struct Get;
+    ///
+    /// impl Handler<Get> for Counter {
+    ///     type Return = u32;
+    ///
+    ///     async fn handle(&mut self, _: Get, _: &mut Context<Self>) -> u32 {
+    ///         self.0
+    ///     }
+    /// }
+    ///
+    /// async fn count(counter: &Address<Counter>) -> Result<u32, Box<dyn std::error::Error>> {
+    ///     Ok(counter.ask(Get).await?)
+    /// }
+    /// # #[cfg(feature = "smol")]
+    /// # smol::block_on(async {
+    /// #     let counter = xtra::spawn_smol(Counter(3), Mailbox::unbounded());
+    /// #     assert_eq!(count(&counter).await.unwrap(), 3);
+    /// # })
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn ask<M>(
+        &self,
+        message: M,
+    ) -> SendFuture<ActorNamedSending<A, Rc>, ResolveToHandlerReturn<<A as Handler<M>>::Return>>
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        self.send(message)
+    }
+
+    /// Send a message to the actor, explicitly discarding the [`Return`](crate::Handler::Return)
+    /// value of the handler.
+    ///
+    /// The returned future resolves once the message has been queued, or to
+    /// [`Error::Disconnected`] if the actor is not accepting messages. Unlike dropping the
+    /// [`Receiver`](crate::Receiver) of a [`detached`](SendFuture::detach) send, this makes it
+    /// obvious at the call site that the return value is intentionally ignored. The priority of
+    /// the message can be set through [`SendFuture::priority`]. It is handled in order with the
+    /// messages sent through [`Address::send`], see [the ordering guarantee](Address#ordering).
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Cache;
+    /// # impl Actor for Cache { type Stop = (); async fn stopped(self) {} }
+    /// struct Evict(&'static str);
+    ///
+    /// impl Handler<Evict> for Cache {
+    ///     type Return = bool; // Whether the key was present.
+    ///
+    ///     async fn handle(&mut self, _: Evict, _: &mut Context<Self>) -> bool {
+    ///         true
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let address = xtra::spawn_smol(Cache, Mailbox::unbounded());
+    ///     address.send_and_forget(Evict("key")).await.unwrap();
+    /// })
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn send_and_forget<M>(&self, message: M) -> SendFuture<ActorNamedSending<A, Rc>, Forget>
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        SendFuture::sending_named(message, self.0.clone()).forget()
+    }
+
+    /// Send a message to the actor, handing out the oneshot receiver of the reply directly rather
+    /// than wrapping it in a [`SendFuture`] or [`Receiver`](crate::Receiver).
+    ///
+    /// This is a low-level escape hatch for callers which poll the reply themselves, e.g. in their
+    /// own `select!` or state machine. The returned [`SendFuture`] queues the message like
+    /// [`Address::send_and_forget`], waiting for room in a bounded mailbox, and its priority can
+    /// be set through [`SendFuture::priority`]. The message is only sent once it is awaited.
+    ///
+    /// The receiver resolves to `Ok` with the result of the handler, which is an [`Error`] if the
+    /// actor stopped itself during handling. It resolves to `Err(`[`catty::Disconnected`]`)` if
+    /// the message was dropped before it could be handled, i.e. in the cases in which a
+    /// [`Receiver`](crate::Receiver) resolves to [`Error::Interrupted`]: the actor disconnected or
+    /// panicked, or the returned [`SendFuture`] failed or was dropped before queuing the message.
+    /// Unlike a [`Receiver`](crate::Receiver), the raw receiver is not tracked: awaiting it from
+    /// the actor's own handler is not detected and deadlocks, and
+    /// [`Context::reply_wanted`](crate::Context::reply_wanted) reports that no reply is wanted.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Greeter;
+    /// # impl Actor for Greeter { type Stop = (); async fn stopped(self) {} }
+    /// # struct Hello;
+    /// # impl Handler<Hello> for Greeter { type Return = &'static str; async fn handle(&mut self, _: Hello, _: &mut Context<Self>) -> &'static str { "hello" } }
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let address = xtra::spawn_smol(Greeter, Mailbox::unbounded());
+    ///
+    ///     let (queued, reply) = address.send_raw(Hello);
+    ///     queued.await.unwrap();
+    ///
+    ///     assert_eq!(reply.await, Ok(Ok("hello")));
+    /// })
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn send_raw<M>(
+        &self,
+        message: M,
+    ) -> (
+        SendFuture<ActorNamedSending<A, Rc>, Forget>,
+        catty::Receiver<Result<<A as Handler<M>>::Return, Error>>,
+    )
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        SendFuture::sending_raw(message, self.0.clone())
+    }
+
+    /// Send a message to the actor without waiting for its reply, and forward the
+    /// [`Return`](crate::Handler::Return) value of the handler to the actor behind `reply_to`
+    /// instead, e.g. to have another actor, or this one later, handle the answer to a query.
+    ///
+    /// The returned future resolves once the message has been queued, like the one returned by
+    /// [`Address::send_and_forget`]. The reply is forwarded by a task spawned with the
+    /// [`Spawner`](crate::runtime::Spawner) of this actor, which holds `reply_to` until then, so
+    /// a weak channel keeps the actor behind it from being kept alive by a pending reply. If that
+    /// actor has stopped by the time the reply is ready, the reply is dead-lettered like any
+    /// message sent to a stopped actor. Nothing is forwarded if the message is never handled.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Catalog;
+    /// # impl Actor for Catalog { type Stop = (); async fn stopped(self) {} }
+    /// # #[derive(Default)]
+    /// # struct Cart(Vec<u32>);
+    /// # impl Actor for Cart { type Stop = (); async fn stopped(self) {} }
+    /// # struct Items;
+    /// # impl Handler<Items> for Cart { type Return = Vec<u32>; async fn handle(&mut self, _: Items, _: &mut Context<Self>) -> Vec<u32> { self.0.clone() } }
+    /// struct Lookup(&'static str);
+    /// struct Price(u32);
+    ///
+    /// impl Handler<Lookup> for Catalog {
+    ///     type Return = Price;
+    ///
+    ///     async fn handle(&mut self, Lookup(item): Lookup, _: &mut Context<Self>) -> Price {
+    ///         Price(item.len() as u32)
+    ///     }
+    /// }
+    ///
+    /// impl Handler<Price> for Cart {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, Price(price): Price, _: &mut Context<Self>) {
+    ///         self.0.push(price);
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let catalog = xtra::spawn_smol(Catalog, Mailbox::unbounded());
+    ///     let cart = xtra::spawn_smol(Cart::default(), Mailbox::unbounded());
+    ///
+    ///     catalog
+    ///         .send_detached(Lookup("apple"), MessageChannel::new(cart.clone()))
+    ///         .await
+    ///         .unwrap();
+    /// #   while cart.send(Items).await.unwrap().is_empty() { smol::future::yield_now().await; }
+    ///
+    ///     assert_eq!(cart.send(Items).await.unwrap(), [5]);
+    /// })
+    /// ```
+    ///
+    /// The reply is forwarded on a task spawned with the [`Spawner`](crate::runtime::Spawner) of
+    /// the [`Mailbox`](crate::Mailbox) of this actor. If none is configured, it is forwarded on an
+    /// executor thread shared by all actors instead.
+    pub fn send_detached<M, Rc2>(
+        &self,
+        message: M,
+        reply_to: MessageChannel<<A as Handler<M>>::Return, (), Rc2>,
+    ) -> SendFuture<ActorNamedSending<A, Rc>, Forget>
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+        Rc2: RefCounter,
+    {
+        let spawner = self.0.spawner_or_fallback();
+        let (queued, reply) = self.send_raw(message);
+        let name = format!(
+            "{}::reply",
+            crate::runtime::task_name(&self.name(), self.id())
+        );
+
+        spawner.spawn(
+            &name,
+            Box::pin(async move {
+                if let Ok(Ok(reply)) = reply.await {
+                    // A disconnected channel dead-letters the reply.
+                    let _ = reply_to.send_and_forget(reply).await;
+                }
+            }),
+        );
+
+        queued
+    }
+
+    /// Deliver a [`Down`] message to `notify` once this actor has stopped, so that another actor can
+    /// react to it without polling [`Address::is_connected`].
+    ///
+    /// The message carries the [`DisconnectReason`] which the actor stopped for, and is delivered
+    /// right away if the actor has already stopped. Monitoring does not keep the actor alive, and
+    /// is dropped without a message if `notify` is disconnected before this actor stops.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// use xtra::monitor::Down;
+    /// use xtra::DisconnectReason;
+    ///
+    /// # struct Worker;
+    /// # impl Actor for Worker { type Stop = (); async fn stopped(self) {} }
+    /// # struct Crash;
+    /// # impl Handler<Crash> for Worker { type Return = (); async fn handle(&mut self, _: Crash, ctx: &mut Context<Self>) { ctx.stop_self() } }
+    /// # #[derive(Default)]
+    /// # struct Supervisor(Vec<Down>);
+    /// # impl Actor for Supervisor { type Stop = (); async fn stopped(self) {} }
+    /// # struct Downs;
+    /// # impl Handler<Downs> for Supervisor { type Return = Vec<Down>; async fn handle(&mut self, _: Downs, _: &mut Context<Self>) -> Vec<Down> { self.0.clone() } }
+    /// impl Handler<Down> for Supervisor {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, down: Down, _: &mut Context<Self>) {
+    ///         self.0.push(down);
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let worker = xtra::spawn_smol(Worker, Mailbox::unbounded());
+    ///     let supervisor = xtra::spawn_smol(Supervisor::default(), Mailbox::unbounded());
+    ///
+    ///     worker.monitor(MessageChannel::new(supervisor.clone()));
+    ///     worker.send(Crash).await.unwrap();
+    /// #   while supervisor.send(Downs).await.unwrap().is_empty() { smol::future::yield_now().await; }
+    ///
+    ///     let downs = supervisor.send(Downs).await.unwrap();
+    ///     assert_eq!(downs[0].reason(), Some(DisconnectReason::StoppedSelf));
+    /// })
+    /// ```
+    ///
+    /// The actor is monitored on a task spawned with the [`Spawner`](crate::runtime::Spawner) of
+    /// the [`Mailbox`](crate::Mailbox) of this actor. If none is configured, it is monitored on an
+    /// executor thread shared by all actors instead.
+    pub fn monitor<Rc2>(&self, notify: MessageChannel<Down, (), Rc2>)
+    where
+        A: Actor,
+        Rc2: RefCounter,
+    {
+        let spawner = self.0.spawner_or_fallback();
+        let monitored = Address(self.0.to_tx_weak());
+        let name = format!(
+            "{}::monitor",
+            crate::runtime::task_name(&self.name(), self.id())
+        );
+
+        spawner.spawn(
+            &name,
+            Box::pin(async move {
+                // Nobody is left to notify once the monitoring actor has stopped.
+                if let future::Either::Left(_) =
+                    future::select(monitored.join(), notify.join()).await
+                {
+                    let down = Down::new(monitored.0.actor(), monitored.disconnect_reason());
+                    let _ = notify.send_and_forget(down).await;
+                }
+            }),
+        );
+    }
+
+    /// Run a closure against the state of the actor on its task, as if it were the handler of a
+    /// message, and resolve to what it returns.
+    ///
+    /// This is meant for one-off interactions, e.g. in tests or admin tooling, for which defining a
+    /// message type would be heavy. Like a message, the closure is only run once the actor gets to
+    /// it in its mailbox, and the returned future resolves to an [`Error`] if the actor is not
+    /// accepting messages. Its priority can be set through [`SendFuture::priority`].
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Counter(u64);
+    /// # impl Actor for Counter { type Stop = (); async fn stopped(self) {} }
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let address = xtra::spawn_smol(Counter(0), Mailbox::unbounded());
+    ///
+    ///     address.exec(|counter, _| counter.0 += 2).await.unwrap();
+    ///     assert_eq!(address.exec(|counter, _| counter.0).await, Ok(2));
+    /// })
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn exec<F, R>(
+        &self,
+        f: F,
+    ) -> SendFuture<ActorNamedSending<A, Rc>, ResolveToHandlerReturn<R>>
+    where
+        A: Actor,
+        F: FnOnce(&mut A, &mut crate::Context<A>) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        SendFuture::exec(
+            Box::new(|act, ctx| Box::pin(future::ready(f(act, ctx)))),
+            self.0.clone(),
+        )
+    }
+
+    /// Like [`Address::exec`], but for a closure returning a future, which may borrow the actor
+    /// and its context.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use xtra::prelude::*;
+    /// # struct Counter(u64);
+    /// # impl Actor for Counter { type Stop = (); async fn stopped(self) {} }
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let address = xtra::spawn_smol(Counter(0), Mailbox::unbounded());
+    ///
+    ///     let count = address
+    ///         .exec_async(|counter, _| {
+    ///             Box::pin(async move {
+    ///                 smol::Timer::after(Duration::from_millis(1)).await;
+    ///                 counter.0 += 1;
+    ///                 counter.0
+    ///             })
+    ///         })
+    ///         .await;
+    ///
+    ///     assert_eq!(count, Ok(1));
+    /// })
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn exec_async<F, R>(
+        &self,
+        f: F,
+    ) -> SendFuture<ActorNamedSending<A, Rc>, ResolveToHandlerReturn<R>>
+    where
+        A: Actor,
+        F: for<'a> FnOnce(&'a mut A, &'a mut crate::Context<A>) -> BoxFuture<'a, R>
+            + Send
+            + 'static,
+        R: Send + 'static,
+    {
+        SendFuture::exec(Box::new(f), self.0.clone())
+    }
+
+    /// Send a message to the actor with the given key, replacing all messages sent with an equal
+    /// key which are still in the mailbox. Like with [`Address::send_and_forget`], the returned
+    /// future resolves once the message has been queued, and the return value of the handler is
+    /// discarded.
+    ///
+    /// Replacing only applies to messages which have not been taken out of the mailbox yet. Once
+    /// the actor starts handling a message, a message sent with the same key is handled after it.
+    /// A replaced message which was waiting for room in a full mailbox counts as queued for its
+    /// sender. Keys of different types never compare equal.
+    ///
+    /// This enables debouncing, e.g. of saving a document while it is being edited:
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # #[derive(Default)]
+    /// # struct Document(Vec<String>);
+    /// # impl Actor for Document { type Stop = Vec<String>; async fn stopped(self) -> Vec<String> { self.0 } }
+    /// struct Save(String);
+    ///
+    /// impl Handler<Save> for Document {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, Save(text): Save, _: &mut Context<Self>) {
+    ///         self.0.push(text);
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let (address, mailbox) = Mailbox::unbounded();
+    ///
+    ///     for text in ["h", "he", "hey"] {
+    ///         address.send_keyed("save", Save(text.to_owned())).await.unwrap();
+    ///     }
+    ///
+    ///     let document = smol::spawn(xtra::run(mailbox, Document::default()));
+    ///     drop(address);
+    ///
+    ///     assert_eq!(document.await, ["hey"]);
+    /// })
+    /// ```
+    pub async fn send_keyed<K, M>(&self, key: K, message: M) -> Result<(), Error>
+    where
+        K: Hash + Eq + Send + Sync + 'static,
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        let envelope = ReturningEnvelope::<A, M, <A as Handler<M>>::Return>::new(message, 0).0;
+
+        match self.0.try_send_keyed(Key::new(key), Box::new(envelope))? {
+            Ok(()) => Ok(()),
+            Err(MailboxFull(waiting)) => waiting.await,
+        }
+    }
+
+    /// Take all messages sent with the given key by [`Address::send_keyed`] out of the mailbox,
+    /// returning whether there were any. Messages which the actor has started handling are not
+    /// affected.
+    pub fn cancel_keyed<K>(&self, key: K) -> bool
+    where
+        K: Hash + Eq + Send + Sync + 'static,
+    {
+        self.0.cancel_keyed(&Key::new(key))
+    }
+
+    /// Send a message to the actor as a [`Streaming`] request, to which the handler replies with
+    /// any number of items of type `T`.
+    ///
+    /// The message is sent once the returned stream is first polled. Up to `buffer` items which
+    /// have not been received yet are buffered, after which the handler waits for the caller. The
+    /// stream ends once the handler is done, or if the actor stops before handling the message.
+    /// See the [`reply_stream`](crate::reply_stream) module for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is zero.
+    pub fn send_stream<M, T>(&self, message: M, buffer: usize) -> ReplyStream<T>
+    where
+        M: Send + 'static,
+        T: Send + 'static,
+        A: Handler<Streaming<M, T>>,
+    {
+        let address = self.clone();
+
+        ReplyStream::new(buffer, move |replies| {
+            Box::pin(address.send_and_forget(Streaming { message, replies }))
+        })
+    }
+
+    /// Turn this address into a [`BufferedAddress`], which buffers messages of type `M` and sends
+    /// them to the actor in batches of up to `max` messages. A batch is sent once it is full, or
+    /// once `max_delay` has elapsed since its first message was pushed. See the
+    /// [`buffered`](crate::buffered) module for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn buffered<M>(self, max: usize, max_delay: Duration) -> BufferedAddress<A, M, Rc>
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        BufferedAddress::new(self, max, max_delay)
+    }
+
+    /// Turn this address into a [`RateLimitedAddress`], which sends messages to the actor no faster
+    /// than the given [`Quota`] allows. The actor and other addresses to it are not affected. See
+    /// the [`rate_limit`](crate::rate_limit) module for details.
+    pub fn rate_limited(self, quota: Quota) -> RateLimitedAddress<A, Rc> {
+        RateLimitedAddress::new(self, quota)
+    }
+
+    /// Subscribe to the events of type `E` which the actor publishes with
+    /// [`Context::publish`](crate::Context::publish).
+    ///
+    /// Up to `buffer` events which have not been received yet are buffered, after which events are
+    /// dropped according to `overflow`. The subscription does not keep the actor alive, and ends
+    /// once the actor has stopped. See the [`subscription`](crate::subscription) module for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is zero.
+    pub fn subscribe<E>(&self, buffer: usize, overflow: Overflow) -> Subscription<E>
+    where
+        E: Send + 'static,
+    {
+        self.0.topics().subscribe(buffer, overflow)
+    }
+
+    /// Subscribe to the state of type `S` which the actor publishes with
+    /// [`Context::publish_state`](crate::Context::publish_state).
+    ///
+    /// The subscription starts with the latest state which the actor has published, if any, and
+    /// then receives every change like a subscription created by [`Address::subscribe`]. With
+    /// [`Overflow::DropOldest`] and a buffer of one, a slow subscriber skips straight to the latest
+    /// state.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is zero.
+    pub fn subscribe_state<S>(&self, buffer: usize, overflow: Overflow) -> Subscription<S>
+    where
+        S: Clone + Send + 'static,
+    {
+        self.0.topics().subscribe_state(buffer, overflow)
+    }
+
+    /// Subscribe the actor behind the given channel to the events of type `E` which this actor
+    /// publishes with [`Context::publish`](crate::Context::publish).
+    ///
+    /// Like with [`Address::subscribe`], up to `buffer` events are buffered, after which events are
+    /// dropped according to `overflow`. The buffered events are sent to the channel one at a time
+    /// by a task spawned with the [`Spawner`](crate::runtime::Spawner) of this actor, each once the
+    /// previous one has been queued in the mailbox of the subscriber. The task only holds a weak
+    /// reference to either actor, so it keeps neither of them alive, and ends once either of them
+    /// has stopped. If no [`Spawner`](crate::runtime::Spawner) is configured, the task runs on an
+    /// executor thread shared by all actors instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is zero.
+    pub fn subscribe_with_channel<E, Rc2>(
+        &self,
+        channel: MessageChannel<E, (), Rc2>,
+        buffer: usize,
+        overflow: Overflow,
+    ) where
+        E: Send + 'static,
+        Rc2: RefCounter,
+    {
+        let spawner = self.0.spawner_or_fallback();
+        let mut events = self.subscribe::<E>(buffer, overflow);
+        let channel = channel.as_either().downgrade();
+        let name = format!(
+            "{}::subscription",
+            crate::runtime::task_name(&self.name(), self.id())
+        );
+
+        spawner.spawn(
+            &name,
+            Box::pin(async move {
+                let mut join = channel.join();
+
+                while let future::Either::Left((Some(event), _)) =
+                    future::select(events.next(), &mut join).await
+                {
+                    if channel.send_and_forget(event).await.is_err() {
+                        break;
+                    }
+                }
+            }),
+        );
+    }
+
+    /// Send a message to the actor, attaching a [`MessageChannel`] through which the handler can
+    /// reply with a new message instead of (or in addition to) its [`Return`](crate::Handler::Return)
+    /// value. Inside of the handler, the channel is available via [`Context::reply_to`](crate::Context::reply_to).
+    ///
+    /// This enables conversation patterns such as ping-pong between actors, where the replying
+    /// actor does not know the type of the actor which sent it the message.
+    ///
+    /// Apart from the attached channel, this behaves exactly like [`Address::send`].
+    #[allow(clippy::type_complexity)]
+    pub fn send_from<M, Reply>(
+        &self,
+        from: MessageChannel<Reply, ()>,
+        message: M,
+    ) -> SendFuture<ActorNamedSending<A, Rc>, ResolveToHandlerReturn<<A as Handler<M>>::Return>>
+    where
+        M: Send + 'static,
+        Reply: Send + 'static,
+        A: Handler<M>,
+    {
+        SendFuture::sending_named_from(message, Box::new(from), self.0.clone())
+    }
+
+    /// Send a message to the actor with the given correlation id, which is available inside of the
+    /// handler via [`Context::correlation_id`](crate::Context::correlation_id).
+    ///
+    /// Messages sent with [`Address::send`] or [`Address::broadcast`] from within a handler inherit
+    /// the correlation id of the message being handled, so this only needs to be called where a
+    /// request enters the system.
+    ///
+    /// Apart from the correlation id, this behaves exactly like [`Address::send`].
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Tracer;
+    /// # impl Actor for Tracer { type Stop = (); async fn stopped(self) {} }
+    /// struct WhoAmI;
+    ///
+    /// impl Handler<WhoAmI> for Tracer {
+    ///     type Return = Option<u128>;
+    ///
+    ///     async fn handle(&mut self, _: WhoAmI, ctx: &mut Context<Self>) -> Option<u128> {
+    ///         ctx.correlation_id()
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let addr = xtra::spawn_smol(Tracer, Mailbox::unbounded());
+    ///     assert_eq!(addr.send_with_correlation(WhoAmI, 7).await, Ok(Some(7)));
+    ///     assert_eq!(addr.send(WhoAmI).await, Ok(None));
+    /// })
+    /// ```
+    #[allow(clippy::type_complexity)]
+    pub fn send_with_correlation<M>(
+        &self,
+        message: M,
+        correlation_id: u128,
+    ) -> SendFuture<ActorNamedSending<A, Rc>, ResolveToHandlerReturn<<A as Handler<M>>::Return>>
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        SendFuture::sending_named_correlated(message, correlation_id, self.0.clone())
+    }
+
+    /// Send a batch of messages to the actor, resolving to the [`Return`](crate::Handler::Return)
+    /// values of the handler in the order the messages were sent.
+    ///
+    /// All messages are enqueued before any of the replies are awaited. This pipelines the
+    /// requests, i.e. the actor can work through the entire batch without waiting for the caller
+    /// to pick up each reply. Enqueuing is still subject to the back-pressure of a bounded mailbox.
+    ///
+    /// The returned future resolves to [`Error::Disconnected`] if the
+    /// actor stops before all replies have been received.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Doubler;
+    /// # impl Actor for Doubler { type Stop = (); async fn stopped(self) {} }
+    /// impl Handler<u32> for Doubler {
+    ///     type Return = u32;
+    ///
+    ///     async fn handle(&mut self, n: u32, _: &mut Context<Self>) -> u32 {
+    ///         n * 2
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let addr = xtra::spawn_smol(Doubler, Mailbox::unbounded());
+    ///     assert_eq!(addr.request_all(1..=3).await, Ok(vec![2, 4, 6]));
+    /// })
+    /// ```
+    pub fn request_all<M, I>(
+        &self,
+        messages: I,
+    ) -> impl Future<Output = Result<Vec<<A as Handler<M>>::Return>, Error>>
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+        I: IntoIterator<Item = M>,
+    {
+        let address = self.clone();
+
+        async move {
+            let mut receivers = Vec::new();
+
+            for message in messages {
+                receivers.push(address.send(message).detach().await?);
+            }
+
+            let mut returns = Vec::with_capacity(receivers.len());
+
+            for receiver in receivers {
+                returns.push(receiver.await?);
+            }
+
+            Ok(returns)
+        }
+    }
+
     /// Send a message to all actors on this address. The message will, by default, have a priority
     /// of 0. This can be configured through [`SendFuture::priority`].
     ///
     /// The actor must implement [`Handler<Message>`] for this to work where [`Handler::Return`] is
     /// set to `()`.
+    ///
+    /// Each actor handles its own clone of the message. To share a large message between actors
+    /// instead, broadcast it as an `Arc<M>` to actors implementing `Handler<Arc<M>>`, like
+    /// [`EventBus::publish_shared`](crate::event_bus::EventBus::publish_shared) does.
     pub fn broadcast<M>(&self, msg: M) -> SendFuture<ActorNamedBroadcasting<A, Rc>, Broadcast>
     where
         M: Clone + Send + Sync + 'static,
@@ -218,6 +1121,53 @@ impl<A, Rc: RefCounter> Address<A, Rc> {
         self.0.inner_ptr() == other.0.inner_ptr()
     }
 
+    /// Stop all actors on this address and take back all messages which they have not started to
+    /// handle yet, e.g. to hand them over to a new instance of the actor.
+    ///
+    /// The messages can be given to another actor with [`Mailbox::inject`](crate::Mailbox::inject).
+    /// Senders which are awaiting a reply will receive it from the actor that eventually handles the
+    /// message. The messages are only recovered for actors of the same type, as a message can only
+    /// be handled by an actor which implements [`Handler`] for it.
+    ///
+    /// Like [`Context::stop_all`](crate::Context::stop_all), the actors stop as soon as they finish
+    /// handling their current message. Messages sent with [`Address::broadcast`], messages deferred
+    /// by [`Handler::can_handle`] and messages sent after this call
+    /// are not recovered.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Counter(u32);
+    /// # impl Actor for Counter { type Stop = (); async fn stopped(self) {} }
+    /// struct Increment;
+    ///
+    /// impl Handler<Increment> for Counter {
+    ///     type Return = u32;
+    ///
+    ///     async fn handle(&mut self, _: Increment, _: &mut Context<Self>) -> u32 {
+    ///         self.0 += 1;
+    ///         self.0
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let (old, _mailbox) = Mailbox::<Counter>::unbounded();
+    ///     let reply = old.send(Increment).detach().await.unwrap();
+    ///
+    ///     let (new, mailbox) = Mailbox::unbounded();
+    ///     mailbox.inject(old.stop_and_recover());
+    ///     xtra::spawn_smol(Counter(41), (new, mailbox));
+    ///
+    ///     assert_eq!(reply.await, Ok(42));
+    /// })
+    /// ```
+    pub fn stop_and_recover(self) -> RecoveredMessages<A>
+    where
+        A: Actor,
+    {
+        RecoveredMessages(self.0.shutdown_and_drain())
+    }
+
     /// Converts this address into a sink that can be used to send messages to the actor. These
     /// messages will have default priority and will be handled in send order.
     ///
@@ -231,6 +1181,17 @@ impl<A, Rc: RefCounter> Address<A, Rc> {
     /// [`Stream`](futures_util::stream::Stream) is being polled. Depending on your usecase, you
     /// may want to use a [`WeakAddress`] instead.
     ///
+    /// The sink accepts an item once the previous one has been queued in the mailbox. If the
+    /// mailbox is bounded and full, the sink is thus not ready until the actor has made room,
+    /// which throttles e.g. a [`Stream::forward`](futures_util::stream::StreamExt::forward) into
+    /// it. Flushing or closing the sink waits until all items have been queued, not until they
+    /// have been handled.
+    ///
+    /// Once the actor has stopped, the sink fails with [`Error::Disconnected`], even while it is
+    /// idle. A [`Stream::forward`](futures_util::stream::StreamExt::forward) into a sink created
+    /// from a [`WeakAddress`] thus ends as soon as the actor stops, rather than when the next item
+    /// of the stream is sent.
+    ///
     /// Because [`Sink`]s do not return anything, this function is only available for messages with
     /// a [`Handler`] implementation that sets [`Return`](Handler::Return) to `()`.
     ///
@@ -241,14 +1202,41 @@ impl<A, Rc: RefCounter> Address<A, Rc> {
         A: Handler<M, Return = ()>,
         M: Send + 'static,
     {
-        futures_util::sink::unfold((), move |(), message| self.send(message))
+        MailboxSink::new(self)
+    }
+}
+
+/// Messages taken back from a stopped actor with [`Address::stop_and_recover`].
+#[must_use = "Recovered messages are dropped unless they are injected into another actor"]
+pub struct RecoveredMessages<A>(pub(crate) Vec<MessageToOne<A>>);
+
+impl<A> RecoveredMessages<A> {
+    /// The number of recovered messages.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether no messages were recovered.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<A> Debug for RecoveredMessages<A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct(&format!(
+            "RecoveredMessages<{}>",
+            std::any::type_name::<A>()
+        ))
+        .field("len", &self.len())
+        .finish()
     }
 }
 
 /// A future which will complete when the corresponding actor stops and its address becomes
 /// disconnected.
 #[must_use = "Futures do nothing unless polled"]
-pub struct ActorJoinHandle(Option<EventListener>);
+pub struct ActorJoinHandle(pub(crate) Option<EventListener>);
 
 impl Future for ActorJoinHandle {
     type Output = ();
@@ -267,6 +1255,124 @@ impl Future for ActorJoinHandle {
     }
 }
 
+/// Something a [`MailboxSink`] sends its items to.
+#[cfg(feature = "sink")]
+pub(crate) trait SinkTarget<M> {
+    type Sending: Future<Output = Result<(), Error>> + Unpin;
+
+    fn is_connected(&self) -> bool;
+
+    fn join(&self) -> ActorJoinHandle;
+
+    fn send_and_forget(&self, message: M) -> Self::Sending;
+}
+
+#[cfg(feature = "sink")]
+impl<A, M, Rc> SinkTarget<M> for Address<A, Rc>
+where
+    A: Handler<M, Return = ()>,
+    M: Send + 'static,
+    Rc: RefCounter,
+{
+    type Sending = SendFuture<ActorNamedSending<A, Rc>, Forget>;
+
+    fn is_connected(&self) -> bool {
+        Address::is_connected(self)
+    }
+
+    fn join(&self) -> ActorJoinHandle {
+        Address::join(self)
+    }
+
+    fn send_and_forget(&self, message: M) -> Self::Sending {
+        Address::send_and_forget(self, message)
+    }
+}
+
+/// A [`Sink`](futures_sink::Sink) which queues its items in the mailbox of an actor.
+///
+/// An item is accepted once the previous one has been queued, so a full mailbox exercises
+/// backpressure on whatever sends into the sink. Flushing waits until all items have been queued,
+/// not until they have been handled. Once the actor has stopped, the sink fails with
+/// [`Error::Disconnected`], even if no item is being sent into it.
+#[cfg(feature = "sink")]
+pub(crate) struct MailboxSink<M, T: SinkTarget<M>> {
+    target: T,
+    sending: Option<T::Sending>,
+    join: ActorJoinHandle,
+}
+
+#[cfg(feature = "sink")]
+impl<M, T: SinkTarget<M>> MailboxSink<M, T> {
+    pub(crate) fn new(target: T) -> Self {
+        MailboxSink {
+            join: target.join(),
+            target,
+            sending: None,
+        }
+    }
+
+    /// Wait until the item which is being sent, if any, has been queued.
+    fn poll_sending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        if let Some(sending) = &mut self.sending {
+            let result = futures_util::ready!(sending.poll_unpin(cx));
+            self.sending = None;
+            result?;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "sink")]
+impl<M, T> futures_sink::Sink<M> for MailboxSink<M, T>
+where
+    T: SinkTarget<M> + Unpin,
+{
+    type Error = Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        futures_util::ready!(this.poll_sending(cx))?;
+
+        if this.join.poll_unpin(cx).is_ready() {
+            return Poll::Ready(Err(Error::Disconnected));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: M) -> Result<(), Error> {
+        let this = self.get_mut();
+
+        if !this.target.is_connected() {
+            return Err(Error::Disconnected);
+        }
+
+        debug_assert!(this.sending.is_none(), "`poll_ready` to be called first");
+        this.sending = Some(this.target.send_and_forget(item));
+
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let this = self.get_mut();
+        futures_util::ready!(this.poll_sending(cx))?;
+
+        // Polling the join handle registers the task to be woken once the actor stops, so that an
+        // idle sink, e.g. one which is waiting for the next item of a stream, fails right away.
+        if this.join.poll_unpin(cx).is_ready() {
+            return Poll::Ready(Err(Error::Disconnected));
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        self.poll_flush(cx)
+    }
+}
+
 // Required because #[derive] adds an A: Clone bound
 impl<A, Rc: RefCounter> Clone for Address<A, Rc> {
     fn clone(&self) -> Self {
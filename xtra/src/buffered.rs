@@ -0,0 +1,251 @@
+//! Batching messages which are produced one at a time, so that they are queued in the mailbox of
+//! an actor together rather than one by one.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fmt, mem};
+
+use crate::chan::MailboxFull;
+use crate::envelope::BatchEnvelope;
+use crate::refcount::{RefCounter, Strong};
+use crate::{Address, Error, Handler};
+
+/// An [`Address`] which buffers messages of type `M` and sends them to the actor in batches.
+/// Created by [`Address::buffered`].
+///
+/// A batch is sent once it holds `max` messages, once `max_delay` has elapsed since its first
+/// message was pushed, or when [`BufferedAddress::flush`] is called, whichever comes first. The
+/// messages of a batch take up a single slot in the actor's mailbox and are handled one after
+/// another, in the order they were pushed, without other messages in between. Their
+/// [`Return`](Handler::Return) values are discarded, like with [`Address::send_and_forget`].
+///
+/// Dropping a [`BufferedAddress`] sends the messages which are still buffered right away,
+/// regardless of the capacity of the mailbox. If the actor has stopped, they are counted as dead
+/// letters like any other message sent to a stopped actor.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use xtra::prelude::*;
+/// # #[derive(Default)]
+/// # struct Metrics(u64);
+/// # impl Actor for Metrics { type Stop = (); async fn stopped(self) {} }
+/// struct Sample(u64);
+///
+/// impl Handler<Sample> for Metrics {
+///     type Return = ();
+///
+///     async fn handle(&mut self, Sample(value): Sample, _: &mut Context<Self>) {
+///         self.0 += value;
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let address = xtra::spawn_smol(Metrics::default(), Mailbox::unbounded());
+///     let samples = address.buffered(64, Duration::from_millis(10));
+///
+///     for value in 0..100 {
+///         samples.push(Sample(value)).await.unwrap();
+///     }
+///
+///     samples.flush().await.unwrap();
+/// })
+/// ```
+pub struct BufferedAddress<A, M, Rc: RefCounter = Strong> {
+    address: Address<A, Rc>,
+    max: usize,
+    max_delay: Duration,
+    batch: Arc<spin::Mutex<Batch<M>>>,
+    /// Sends a batch regardless of the capacity of the mailbox, see [`force_send`].
+    force_send: fn(&Address<A, Rc>, Vec<M>),
+}
+
+struct Batch<M> {
+    messages: Vec<M>,
+    /// Incremented whenever the batch is sent, so that a pending delay does not send a later one.
+    generation: u64,
+}
+
+impl<A, M, Rc> BufferedAddress<A, M, Rc>
+where
+    A: Handler<M>,
+    M: Send + 'static,
+    Rc: RefCounter,
+{
+    pub(crate) fn new(address: Address<A, Rc>, max: usize, max_delay: Duration) -> Self {
+        assert!(max > 0, "a batch needs room for at least one message");
+
+        BufferedAddress {
+            address,
+            max,
+            max_delay,
+            batch: Arc::new(spin::Mutex::new(Batch {
+                messages: Vec::with_capacity(max),
+                generation: 0,
+            })),
+            force_send: force_send::<A, M, Rc>,
+        }
+    }
+
+    /// Buffer a message, sending the batch if it is full.
+    ///
+    /// This only waits if the batch is sent, until there is room for it in the mailbox. It fails
+    /// with [`Error::Disconnected`] if the batch could not be sent because the actor has stopped.
+    ///
+    /// A new batch is sent after `max_delay` by a task spawned with the
+    /// [`Spawner`](crate::runtime::Spawner) and [`Timer`](crate::runtime::Timer) of the actor's
+    /// [`Mailbox`](crate::Mailbox). Without them, the executor and timer threads shared by all
+    /// actors are used.
+    pub async fn push(&self, message: M) -> Result<(), Error> {
+        let mut batch = self.batch.lock();
+        batch.messages.push(message);
+
+        if batch.messages.len() >= self.max {
+            let queued = queue(&self.address, &mut batch, self.max);
+            drop(batch);
+
+            return queued.await;
+        }
+
+        if batch.messages.len() == 1 {
+            let generation = batch.generation;
+            drop(batch);
+
+            self.send_after_delay(generation);
+        }
+
+        Ok(())
+    }
+
+    /// Send the messages which are buffered, if any, waiting until there is room for them in the
+    /// mailbox.
+    pub async fn flush(&self) -> Result<(), Error> {
+        let mut batch = self.batch.lock();
+
+        if batch.messages.is_empty() {
+            return Ok(());
+        }
+
+        let queued = queue(&self.address, &mut batch, self.max);
+        drop(batch);
+
+        queued.await
+    }
+
+    /// The number of messages which are buffered.
+    pub fn len(&self) -> usize {
+        self.batch.lock().messages.len()
+    }
+
+    /// Whether no messages are buffered.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The address of the actor to which the batches are sent.
+    pub fn address(&self) -> &Address<A, Rc> {
+        &self.address
+    }
+
+    /// Send the batch of the given generation once `max_delay` has elapsed, unless it has been sent
+    /// already by then.
+    fn send_after_delay(&self, generation: u64) {
+        let spawner = self.address.0.spawner_or_fallback();
+        let sleep = self.address.0.timer_or_fallback().sleep(self.max_delay);
+        // A pending batch must not keep the actor alive. If this buffer is dropped before the
+        // delay has elapsed, it sends the batch itself.
+        let address = Address(self.address.0.to_tx_weak());
+        let batch = self.batch.clone();
+        let max = self.max;
+        let name = format!(
+            "{}::batch",
+            crate::runtime::task_name(&self.address.name(), self.address.id())
+        );
+
+        spawner.spawn(
+            &name,
+            Box::pin(async move {
+                sleep.await;
+
+                let mut batch = batch.lock();
+
+                if batch.generation != generation || batch.messages.is_empty() {
+                    return;
+                }
+
+                let queued = queue(&address, &mut batch, max);
+                drop(batch);
+
+                // Nobody is waiting for the batch, so an actor which has stopped is not an error.
+                let _ = queued.await;
+            }),
+        );
+    }
+}
+
+impl<A, M, Rc: RefCounter> Drop for BufferedAddress<A, M, Rc> {
+    fn drop(&mut self) {
+        let mut batch = self.batch.lock();
+
+        if batch.messages.is_empty() {
+            return;
+        }
+
+        let messages = mem::take(&mut batch.messages);
+        batch.generation += 1;
+        drop(batch);
+
+        (self.force_send)(&self.address, messages);
+    }
+}
+
+/// Take the batch out of the buffer and queue it in the mailbox, or among the senders which wait
+/// for room in it. This happens while the buffer is locked, so that batches are queued in the order
+/// they were taken. The returned future resolves once the batch has been queued.
+fn queue<A, M, Rc>(
+    address: &Address<A, Rc>,
+    batch: &mut Batch<M>,
+    max: usize,
+) -> impl Future<Output = Result<(), Error>>
+where
+    A: Handler<M>,
+    M: Send + 'static,
+    Rc: RefCounter,
+{
+    let messages = mem::replace(&mut batch.messages, Vec::with_capacity(max));
+    batch.generation += 1;
+
+    let envelope = BatchEnvelope::<A, M, A::Return>::new(messages);
+    let queued = address.0.try_send_to_one(Box::new(envelope));
+
+    async move {
+        match queued? {
+            Ok(()) => Ok(()),
+            Err(MailboxFull(waiting)) => waiting.await,
+        }
+    }
+}
+
+/// Queue a batch in the mailbox right away, regardless of its capacity, e.g. when a
+/// [`BufferedAddress`] is dropped and cannot wait for room.
+fn force_send<A, M, Rc>(address: &Address<A, Rc>, messages: Vec<M>)
+where
+    A: Handler<M>,
+    M: Send + 'static,
+    Rc: RefCounter,
+{
+    let envelope = BatchEnvelope::<A, M, A::Return>::new(messages);
+    let _ = address.0.force_send_to_one(Box::new(envelope));
+}
+
+impl<A, M, Rc: RefCounter> fmt::Debug for BufferedAddress<A, M, Rc> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferedAddress")
+            .field("address", &self.address)
+            .field("buffered", &self.batch.lock().messages.len())
+            .field("max", &self.max)
+            .field("max_delay", &self.max_delay)
+            .finish()
+    }
+}
@@ -3,11 +3,13 @@
 
 mod priority;
 mod ptr;
+mod queue;
 mod waiting_receiver;
 mod waiting_sender;
 
+use std::borrow::Cow;
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, VecDeque};
+use std::collections::{BTreeMap, BinaryHeap, VecDeque};
 use std::sync::atomic::AtomicUsize;
 use std::sync::{atomic, Arc, Mutex, Weak};
 use std::{cmp, mem};
@@ -15,11 +17,16 @@ use std::{cmp, mem};
 use event_listener::{Event, EventListener};
 pub use priority::{ByPriority, HasPriority, Priority};
 pub use ptr::{Ptr, RefCounter, Rx, TxEither, TxStrong, TxWeak};
+use queue::UnicastQueue;
+pub use queue::{MailboxBacking, MailboxOrder};
 pub use waiting_receiver::WaitingReceiver;
 pub use waiting_sender::WaitingSender;
 
 use crate::envelope::{BroadcastEnvelope, MessageEnvelope, Shutdown};
-use crate::{Actor, Error};
+use crate::keyed::{Key, KeyedEnvelope, Keys};
+use crate::runtime::{Spawner, Timer};
+use crate::subscription::Topics;
+use crate::{Actor, ActorId, DisconnectReason, Disconnected, Error};
 
 pub type MessageToOne<A> = Box<dyn MessageEnvelope<Actor = A>>;
 pub type MessageToAll<A> = Arc<dyn BroadcastEnvelope<Actor = A>>;
@@ -40,21 +47,180 @@ pub fn new<A>(capacity: Option<usize>) -> (Ptr<A, TxStrong>, Ptr<A, Rx>) {
 // Public because of private::RefCounterInner. This should never actually be exported, though.
 pub struct Chan<A> {
     chan: Mutex<Inner<A>>,
+    id: ActorId,
+    name: spin::Mutex<Cow<'static, str>>,
     on_shutdown: Event,
+    on_capacity: Event,
+    /// Notified whenever the last busy actor finishes handling a message with the mailbox empty.
+    on_idle: Event,
     sender_count: AtomicUsize,
     receiver_count: AtomicUsize,
+    busy_count: AtomicUsize,
+    /// The spawner and timer configured for the actor, for use by its addresses.
+    runtime: spin::Mutex<Runtime>,
+    /// Why the last actor receiving from this channel stopped, as recorded by its event loop.
+    disconnect_reason: spin::Mutex<Option<DisconnectReason>>,
+    /// The keys of the messages sent with a key which have not been handled yet.
+    keys: Arc<Keys>,
+    /// The subscribers to the events published by the actor.
+    topics: Topics,
+}
+
+/// Stands in for the name of the actor where it is not needed, see [`Chan::metrics_name`].
+#[allow(dead_code)] // Unused if both the `metrics` and `instrumentation` features are enabled.
+pub struct Unnamed;
+
+impl std::ops::Deref for Unnamed {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        ""
+    }
+}
+
+#[derive(Default)]
+struct Runtime {
+    spawner: Option<Arc<dyn Spawner>>,
+    timer: Option<Arc<dyn Timer>>,
 }
 
 impl<A> Chan<A> {
     pub fn new(capacity: Option<usize>) -> Self {
         Self {
             chan: Mutex::new(Inner::new(capacity)),
+            id: ActorId::next(),
+            name: spin::Mutex::new(Cow::Borrowed(std::any::type_name::<A>())),
             on_shutdown: Event::new(),
+            on_capacity: Event::new(),
+            on_idle: Event::new(),
             sender_count: AtomicUsize::new(0),
             receiver_count: AtomicUsize::new(0),
+            busy_count: AtomicUsize::new(0),
+            runtime: spin::Mutex::default(),
+            disconnect_reason: spin::Mutex::new(None),
+            keys: Arc::default(),
+            topics: Topics::default(),
+        }
+    }
+
+    pub fn id(&self) -> ActorId {
+        self.id
+    }
+
+    /// The name of the actor, as last set by [`Chan::set_name`].
+    pub fn name(&self) -> Cow<'static, str> {
+        self.name.lock().clone()
+    }
+
+    pub fn set_name(&self, name: Cow<'static, str>) {
+        *self.name.lock() = name;
+    }
+
+    /// The name of the actor to label metrics with. It is only looked up if the `metrics` feature
+    /// is enabled, so that sending and receiving do not take the lock for nothing otherwise.
+    #[cfg(feature = "metrics")]
+    pub fn metrics_name(&self) -> spin::MutexGuard<'_, Cow<'static, str>> {
+        self.name.lock()
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    pub fn metrics_name(&self) -> Unnamed {
+        Unnamed
+    }
+
+    /// The name of the actor to start the spans of messages with, only looked up if the
+    /// `instrumentation` feature is enabled like [`Chan::metrics_name`].
+    #[cfg(feature = "instrumentation")]
+    fn span_name(&self) -> spin::MutexGuard<'_, Cow<'static, str>> {
+        self.name.lock()
+    }
+
+    #[cfg(not(feature = "instrumentation"))]
+    fn span_name(&self) -> Unnamed {
+        Unnamed
+    }
+
+    /// The spawner configured for the actor, as last set by [`Chan::set_spawner`].
+    pub fn spawner(&self) -> Option<Arc<dyn Spawner>> {
+        self.runtime.lock().spawner.clone()
+    }
+
+    /// The spawner configured for the actor, or a fallback which runs every task on an executor
+    /// thread shared by all actors if there is none.
+    pub fn spawner_or_fallback(&self) -> Arc<dyn Spawner> {
+        self.spawner()
+            .unwrap_or_else(|| Arc::new(crate::runtime::Fallback))
+    }
+
+    pub fn set_spawner(&self, spawner: Option<Arc<dyn Spawner>>) {
+        self.runtime.lock().spawner = spawner;
+    }
+
+    /// The timer configured for the actor, as last set by [`Chan::set_timer`].
+    pub fn timer(&self) -> Option<Arc<dyn Timer>> {
+        self.runtime.lock().timer.clone()
+    }
+
+    /// The timer configured for the actor, or a fallback which is woken by a timer thread shared by
+    /// all actors if there is none.
+    pub fn timer_or_fallback(&self) -> Arc<dyn Timer> {
+        self.timer()
+            .unwrap_or_else(|| Arc::new(crate::runtime::Fallback))
+    }
+
+    pub fn set_timer(&self, timer: Option<Arc<dyn Timer>>) {
+        self.runtime.lock().timer = timer;
+    }
+
+    /// Let messages of lower priority through once `ratio` messages of the next higher priority
+    /// have been received in a row while they were waiting.
+    pub fn set_priority_fairness(&self, ratio: u32) {
+        self.chan.lock().unwrap().fairness = Some(ratio);
+    }
+
+    /// Store the messages to one actor in the given data structure from now on.
+    pub fn set_backing(&self, backing: MailboxBacking) {
+        self.chan.lock().unwrap().unicast_queue.set_backing(backing);
+    }
+
+    /// Receive the messages of equal priority to one actor in the given order from now on.
+    pub fn set_order(&self, order: MailboxOrder) {
+        self.chan.lock().unwrap().unicast_queue.set_order(order);
+    }
+
+    /// The subscribers to the events published by the actor.
+    pub(crate) fn topics(&self) -> &Topics {
+        &self.topics
+    }
+
+    /// Callback to be invoked every time an actor starts handling a message.
+    pub fn on_handler_started(&self) {
+        self.busy_count.fetch_add(1, atomic::Ordering::Relaxed);
+    }
+
+    /// Callback to be invoked every time an actor finished handling a message.
+    pub fn on_handler_finished(&self) {
+        if self.busy_count.fetch_sub(1, atomic::Ordering::Relaxed) == 1 && self.len() == 0 {
+            self.on_idle.notify(usize::MAX);
         }
     }
 
+    /// Whether any actor is currently handling a message.
+    pub fn is_busy(&self) -> bool {
+        self.busy_count.load(atomic::Ordering::Relaxed) > 0
+    }
+
+    /// Whether no actor is handling a message and none are waiting to be handled.
+    pub fn is_idle(&self) -> bool {
+        !self.is_busy() && self.len() == 0
+    }
+
+    /// Listen for the next time that the actors become idle. The listener must be created before
+    /// checking [`Chan::is_idle`], so that the transition cannot be missed in between.
+    pub fn idle_listener(&self) -> EventListener {
+        self.on_idle.listen()
+    }
+
     /// Callback to be invoked every time a receiver is created
     pub fn on_receiver_created(&self) {
         self.receiver_count.fetch_add(1, atomic::Ordering::Relaxed);
@@ -70,6 +236,7 @@ impl<A> Chan<A> {
         atomic::fence(atomic::Ordering::Acquire);
 
         self.shutdown_waiting_senders();
+        self.topics.close();
     }
 
     /// Callback to be invoked every time a sender is created.
@@ -104,32 +271,100 @@ impl<A> Chan<A> {
 
     pub fn try_send_to_one(
         &self,
-        mut message: MessageToOne<A>,
+        message: MessageToOne<A>,
     ) -> Result<Result<(), MailboxFull<MessageToOne<A>>>, Error> {
+        self.try_queue_to_one(message).map_err(|_| {
+            crate::metrics::message_dead_lettered(&self.metrics_name());
+            Error::Disconnected
+        })
+    }
+
+    /// Like [`Chan::try_send_to_one`], but give the message back if the actor has stopped.
+    pub fn try_queue_to_one(
+        &self,
+        mut message: MessageToOne<A>,
+    ) -> Result<Result<(), MailboxFull<MessageToOne<A>>>, MessageToOne<A>> {
         if !self.is_connected() {
+            return Err(message);
+        }
+
+        message.start_span(&self.span_name(), self.id);
+
+        let mut inner = self.chan.lock().unwrap();
+
+        Ok(self.send_to_one(&mut inner, message))
+    }
+
+    /// Like [`Chan::try_send_to_one`], but first takes all messages with the same key which have
+    /// not been received yet out of the channel.
+    pub fn try_send_keyed(
+        &self,
+        key: Key,
+        mut message: MessageToOne<A>,
+    ) -> Result<Result<(), MailboxFull<MessageToOne<A>>>, Error>
+    where
+        A: 'static,
+    {
+        if !self.is_connected() {
+            crate::metrics::message_dead_lettered(&self.metrics_name());
             return Err(Error::Disconnected);
         }
 
-        message.start_span();
+        message.start_span(&self.span_name(), self.id);
 
         let mut inner = self.chan.lock().unwrap();
+        self.remove_keyed(&mut inner, &key);
+
+        let message = Box::new(KeyedEnvelope::new(message, self.keys.insert(key)));
 
+        Ok(self.send_to_one(&mut inner, message))
+    }
+
+    /// Take all messages with the given key which have not been received yet out of the channel,
+    /// returning whether there were any.
+    pub fn cancel_keyed(&self, key: &Key) -> bool {
+        let mut inner = self.chan.lock().unwrap();
+
+        self.remove_keyed(&mut inner, key) > 0
+    }
+
+    fn remove_keyed(&self, inner: &mut Inner<A>, key: &Key) -> usize {
+        if !self.keys.contains(key) {
+            return 0;
+        }
+
+        let removed = inner.remove_keyed(key);
+
+        if removed > 0 {
+            self.on_capacity.notify(removed);
+            crate::metrics::mailbox_depth(&self.metrics_name(), inner.len());
+        }
+
+        removed
+    }
+
+    fn send_to_one(
+        &self,
+        inner: &mut Inner<A>,
+        message: MessageToOne<A>,
+    ) -> Result<(), MailboxFull<MessageToOne<A>>> {
         let unfulfilled_msg = if let Err(msg) = inner.try_fulfill_receiver(message) {
             msg
         } else {
-            return Ok(Ok(()));
+            return Ok(());
         };
 
         if inner.is_unicast_full() {
             let (handle, waiting) = WaitingSender::new(unfulfilled_msg);
             inner.waiting_send_to_one.push_back(handle);
 
-            return Ok(Err(MailboxFull(waiting)));
+            return Err(MailboxFull(waiting));
         }
 
-        inner.unicast_queue.push(ByPriority(unfulfilled_msg));
+        inner.unicast_queue.push(unfulfilled_msg);
+        crate::metrics::mailbox_depth(&self.metrics_name(), inner.len());
 
-        Ok(Ok(()))
+        Ok(())
     }
 
     pub fn try_send_to_all(
@@ -137,12 +372,13 @@ impl<A> Chan<A> {
         mut message: MessageToAll<A>,
     ) -> Result<Result<(), MailboxFull<MessageToAll<A>>>, Error> {
         if !self.is_connected() {
+            crate::metrics::message_dead_lettered(&self.metrics_name());
             return Err(Error::Disconnected);
         }
 
         Arc::get_mut(&mut message)
             .expect("calling after try_send not supported")
-            .start_span();
+            .start_span(&self.span_name(), self.id);
 
         let mut inner = self.chan.lock().unwrap();
 
@@ -154,6 +390,7 @@ impl<A> Chan<A> {
         }
 
         inner.send_broadcast(message);
+        crate::metrics::mailbox_depth(&self.metrics_name(), inner.len());
 
         Ok(Ok(()))
     }
@@ -171,16 +408,23 @@ impl<A> Chan<A> {
             broadcast_mailbox.lock().peek().map(|it| it.priority())
         };
 
-        let shared_priority: Option<Priority> = inner.unicast_queue.peek().map(|it| it.priority());
+        let shared_priority: Option<Priority> = inner.unicast_queue.peek_priority();
 
         // Choose which priority channel to take from
         match shared_priority.cmp(&broadcast_priority) {
             // Shared priority is greater or equal (and it is not empty)
             Ordering::Greater | Ordering::Equal if shared_priority.is_some() => {
-                Ok(inner.pop_unicast().unwrap().into())
+                let message = inner.pop_unicast().unwrap();
+                self.on_capacity.notify(1);
+                crate::metrics::mailbox_depth(&self.metrics_name(), inner.len());
+                Ok(message.into())
             }
             // Shared priority is less - take from broadcast
-            Ordering::Less => Ok(inner.pop_broadcast(broadcast_mailbox).unwrap().into()),
+            Ordering::Less => {
+                let message = inner.pop_broadcast(broadcast_mailbox).unwrap();
+                crate::metrics::mailbox_depth(&self.metrics_name(), inner.len());
+                Ok(message.into())
+            }
             // Equal, but both are empty, so wait or exit if shutdown
             _ => {
                 // on_shutdown is only notified with inner locked, and it's locked here, so no race
@@ -196,14 +440,80 @@ impl<A> Chan<A> {
         }
     }
 
+    /// The type name of the message which would be received next with the given broadcast mailbox,
+    /// without receiving it. A pending shutdown is not a message and is skipped.
+    pub fn peek_message_type(&self, broadcast_mailbox: &BroadcastQueue<A>) -> Option<&'static str> {
+        // Lock `ChanInner` first, like `try_recv`.
+        let inner = self.chan.lock().unwrap();
+        let broadcast_mailbox = broadcast_mailbox.lock();
+
+        let unicast = inner.unicast_queue.peek();
+        let broadcast = broadcast_mailbox
+            .iter()
+            .filter(|message| message.priority() != Priority::Shutdown)
+            .max();
+
+        match (unicast, broadcast) {
+            (Some(unicast), Some(broadcast)) if unicast.priority() >= broadcast.priority() => {
+                Some(unicast.message_type())
+            }
+            (_, Some(broadcast)) => Some(broadcast.0.message_type()),
+            (unicast, None) => unicast.map(|unicast| unicast.message_type()),
+        }
+    }
+
     pub fn is_connected(&self) -> bool {
         self.receiver_count.load(atomic::Ordering::SeqCst) > 0
             && self.sender_count.load(atomic::Ordering::SeqCst) > 0
     }
 
+    /// Record why an actor receiving from this channel stopped.
+    pub fn set_disconnect_reason(&self, reason: DisconnectReason) {
+        *self.disconnect_reason.lock() = Some(reason);
+    }
+
+    /// Why this channel is disconnected, if it is and the reason is known.
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        if self.is_connected() {
+            return None;
+        }
+
+        // Sending fails as soon as all senders are dropped, possibly before the actor has stopped.
+        self.disconnect_reason.lock().or_else(|| {
+            (self.sender_count.load(atomic::Ordering::SeqCst) == 0)
+                .then_some(DisconnectReason::AddressesDropped)
+        })
+    }
+
+    /// The actor of this channel, as reported once it is disconnected.
+    pub fn actor(&self) -> Disconnected {
+        Disconnected::new(std::any::type_name::<A>(), self.id)
+    }
+
     pub fn len(&self) -> usize {
-        let inner = self.chan.lock().unwrap();
-        inner.broadcast_tail + inner.unicast_queue.len()
+        self.chan.lock().unwrap().len()
+    }
+
+    /// The number of messages of the given priority waiting to be received by any actor.
+    pub fn len_with_priority(&self, priority: u32) -> usize {
+        self.chan
+            .lock()
+            .unwrap()
+            .unicast_queue
+            .len_with_priority(Priority::Valued(priority))
+    }
+
+    /// The number of actors receiving from this channel, each of which has its own broadcast
+    /// mailbox.
+    #[allow(dead_code)] // Only used with debug assertions.
+    pub fn actor_count(&self) -> usize {
+        self.chan
+            .lock()
+            .unwrap()
+            .broadcast_queues
+            .iter()
+            .filter(|queue| queue.strong_count() > 0)
+            .count()
     }
 
     pub fn capacity(&self) -> Option<usize> {
@@ -234,10 +544,61 @@ impl<A> Chan<A> {
     where
         A: Actor,
     {
-        self.chan
+        self.chan.lock().unwrap().send_shutdown();
+    }
+
+    /// Shut down only the receiver of the given broadcast mailbox, as soon as it has finished
+    /// handling its current message.
+    pub fn shutdown_receiver(&self, broadcast_mailbox: &BroadcastQueue<A>)
+    where
+        A: Actor,
+    {
+        let mut inner = self.chan.lock().unwrap();
+
+        broadcast_mailbox
             .lock()
-            .unwrap()
-            .send_broadcast(Arc::new(Shutdown::new()));
+            .push(ByPriority::new(Arc::new(Shutdown::new())));
+
+        // We cannot tell which waiting receiver owns the broadcast mailbox, so wake all of them.
+        for rx in mem::take(&mut inner.waiting_receivers_handles) {
+            let _ = rx.notify_new_broadcast();
+        }
+    }
+
+    /// Take all messages to one actor out of the channel, including those of waiting senders, and
+    /// shut down all receivers.
+    ///
+    /// Both happen under the same lock, so no receiver can take any of the returned messages.
+    pub fn shutdown_and_drain(&self) -> Vec<MessageToOne<A>>
+    where
+        A: Actor,
+    {
+        let mut inner = self.chan.lock().unwrap();
+        let messages = self.drain_unicast(&mut inner);
+        inner.send_shutdown();
+
+        messages
+    }
+
+    /// Take all messages to one actor out of the channel, including those of waiting senders,
+    /// without shutting down any receivers.
+    pub fn drain(&self) -> Vec<MessageToOne<A>> {
+        let mut inner = self.chan.lock().unwrap();
+
+        self.drain_unicast(&mut inner)
+    }
+
+    fn drain_unicast(&self, inner: &mut Inner<A>) -> Vec<MessageToOne<A>> {
+        let mut messages = inner.unicast_queue.drain();
+
+        while let Some(msg) = inner.try_take_waiting_unicast_message() {
+            messages.push(msg);
+        }
+
+        self.on_capacity.notify(usize::MAX);
+        crate::metrics::mailbox_depth(&self.metrics_name(), inner.len());
+
+        messages
     }
 
     /// Shutdown all [`WaitingSender`]s in this channel.
@@ -248,6 +609,14 @@ impl<A> Chan<A> {
         };
 
         self.on_shutdown.notify(usize::MAX);
+        self.on_capacity.notify(usize::MAX);
+
+        crate::metrics::messages_dropped(
+            &self.metrics_name(),
+            inner.unicast_queue.len()
+                + inner.waiting_send_to_one.len()
+                + inner.waiting_send_to_all.len(),
+        );
 
         // Let any outstanding messages drop
         inner.unicast_queue.clear();
@@ -276,6 +645,63 @@ impl<A> Chan<A> {
         }
     }
 
+    /// Whether the queue for messages to one actor is full. This is never the case for unbounded
+    /// channels.
+    #[allow(dead_code)] // Unused if the `tower` feature is disabled.
+    pub fn is_full(&self) -> bool {
+        self.chan.lock().unwrap().is_unicast_full()
+    }
+
+    /// Listen for a message being taken out of the queue for messages to one actor, or for the
+    /// channel being disconnected.
+    #[allow(dead_code)] // Unused if the `tower` feature is disabled.
+    pub fn capacity_listener(&self) -> EventListener {
+        self.on_capacity.listen()
+    }
+
+    /// Queue a message to one actor regardless of the capacity of the channel, e.g. for an actor
+    /// sending a message to itself, which must never wait for its own mailbox.
+    ///
+    /// The message is queued behind all messages of equal or higher priority which are already
+    /// queued. Messages of waiting senders are not queued yet, so it is queued ahead of them.
+    pub fn force_send_to_one(&self, mut message: MessageToOne<A>) -> Result<(), Error> {
+        if !self.is_connected() {
+            crate::metrics::message_dead_lettered(&self.metrics_name());
+            return Err(Error::Disconnected);
+        }
+
+        message.start_span(&self.span_name(), self.id);
+
+        let mut inner = self.chan.lock().unwrap();
+
+        if let Err(message) = inner.try_fulfill_receiver(message) {
+            inner.unicast_queue.push(message);
+            crate::metrics::mailbox_depth(&self.metrics_name(), inner.len());
+        }
+
+        Ok(())
+    }
+
+    /// Queue a broadcast message again for the receiver of the given broadcast mailbox only.
+    pub fn requeue_broadcast(
+        &self,
+        broadcast_mailbox: &BroadcastQueue<A>,
+        message: MessageToAll<A>,
+    ) {
+        if !self.is_connected() {
+            crate::metrics::message_dead_lettered(&self.metrics_name());
+            return;
+        }
+
+        let mut inner = self.chan.lock().unwrap();
+        broadcast_mailbox.lock().push(ByPriority::new(message));
+
+        // We cannot tell which waiting receiver owns the broadcast mailbox, so wake all of them.
+        for rx in mem::take(&mut inner.waiting_receivers_handles) {
+            let _ = rx.notify_new_broadcast();
+        }
+    }
+
     /// Re-queue the given message.
     ///
     /// Normally, messages are delivered from the inbox straight to the actor. It can however happen
@@ -290,7 +716,7 @@ impl<A> Chan<A> {
         };
 
         if let Err(msg) = inner.try_fulfill_receiver(msg) {
-            inner.unicast_queue.push(ByPriority(msg));
+            inner.unicast_queue.push(msg);
         }
     }
 
@@ -307,9 +733,14 @@ struct Inner<A> {
     waiting_send_to_one: VecDeque<waiting_sender::Handle<MessageToOne<A>>>,
     waiting_send_to_all: VecDeque<waiting_sender::Handle<MessageToAll<A>>>,
     waiting_receivers_handles: VecDeque<waiting_receiver::Handle<A>>,
-    unicast_queue: BinaryHeap<ByPriority<MessageToOne<A>>>,
+    unicast_queue: UnicastQueue<MessageToOne<A>>,
     broadcast_queues: Vec<Weak<BroadcastQueue<A>>>,
     broadcast_tail: usize,
+    /// The ratio set with [`Chan::set_priority_fairness`], if any.
+    fairness: Option<u32>,
+    /// The number of messages received in a row per priority since a message of the next lower
+    /// priority was last received.
+    served: BTreeMap<Priority, u32>,
 }
 
 impl<A> Inner<A> {
@@ -319,24 +750,79 @@ impl<A> Inner<A> {
             waiting_send_to_one: VecDeque::default(),
             waiting_send_to_all: VecDeque::default(),
             waiting_receivers_handles: VecDeque::default(),
-            unicast_queue: BinaryHeap::default(),
+            unicast_queue: UnicastQueue::new(MailboxBacking::default(), MailboxOrder::default()),
             broadcast_queues: Vec::default(),
             broadcast_tail: 0,
+            fairness: None,
+            served: BTreeMap::default(),
         }
     }
 
+    /// The number of messages waiting to be received by the slowest actor.
+    fn len(&self) -> usize {
+        self.broadcast_tail + self.unicast_queue.len()
+    }
+
     fn pop_unicast(&mut self) -> Option<Box<dyn MessageEnvelope<Actor = A>>> {
-        let msg = self.unicast_queue.pop()?.0;
+        let msg = match self.fairness {
+            Some(ratio) => self.pop_unicast_fairly(ratio)?,
+            None => self.unicast_queue.pop()?,
+        };
 
         if !self.is_unicast_full() {
             if let Some(msg) = self.try_take_waiting_unicast_message() {
-                self.unicast_queue.push(ByPriority(msg))
+                self.unicast_queue.push(msg)
             }
         }
 
         Some(msg)
     }
 
+    /// Pop the message of the highest priority, unless `ratio` messages of that priority have been
+    /// popped in a row while messages of a lower priority were waiting. Then the next message of
+    /// the next lower priority is popped instead, subject to the same budget for its own priority.
+    fn pop_unicast_fairly(&mut self, ratio: u32) -> Option<MessageToOne<A>> {
+        let top = self.unicast_queue.peek_priority()?;
+
+        // Notifications are never held back, and do not count towards the budget.
+        if top == Priority::Notification {
+            return self.unicast_queue.pop();
+        }
+        let mut priority = top;
+
+        loop {
+            let served = self.served.entry(priority).or_default();
+
+            if *served < ratio {
+                *served += 1;
+                break;
+            }
+
+            match self.unicast_queue.highest_priority_below(priority) {
+                Some(lower) => {
+                    *served = 0;
+                    priority = lower;
+                }
+                // Nothing is starved by this priority, so it is not held back.
+                None => break,
+            }
+        }
+
+        let msg = if priority == top {
+            self.unicast_queue.pop()?
+        } else {
+            self.unicast_queue
+                .pop_with_priority(priority)
+                .expect("a message of the lower priority to be queued")
+        };
+
+        if self.unicast_queue.is_empty() {
+            self.served.clear();
+        }
+
+        Some(msg)
+    }
+
     pub fn pop_broadcast(
         &mut self,
         broadcast_mailbox: &BroadcastQueue<A>,
@@ -369,7 +855,7 @@ impl<A> Inner<A> {
     fn send_broadcast(&mut self, m: MessageToAll<A>) {
         self.broadcast_queues.retain(|queue| match queue.upgrade() {
             Some(q) => {
-                q.lock().push(ByPriority(m.clone()));
+                q.lock().push(ByPriority::new(m.clone()));
                 true
             }
             None => false, // The corresponding receiver has been dropped - remove it
@@ -382,6 +868,39 @@ impl<A> Inner<A> {
         self.broadcast_tail += 1;
     }
 
+    /// Shut down all receivers which are not already about to shut down, so that shutting down
+    /// repeatedly does not fill up the broadcast queues.
+    fn send_shutdown(&mut self)
+    where
+        A: Actor,
+    {
+        let shutdown: MessageToAll<A> = Arc::new(Shutdown::new());
+        let mut sent = false;
+
+        self.broadcast_queues.retain(|queue| match queue.upgrade() {
+            Some(q) => {
+                let mut q = q.lock();
+
+                // A pending shutdown is always at the top of the queue, as it has the highest priority.
+                if q.peek().map(|m| m.priority()) != Some(Priority::Shutdown) {
+                    q.push(ByPriority::new(shutdown.clone()));
+                    sent = true;
+                }
+
+                true
+            }
+            None => false, // The corresponding receiver has been dropped - remove it
+        });
+
+        for rx in mem::take(&mut self.waiting_receivers_handles) {
+            let _ = rx.notify_new_broadcast();
+        }
+
+        if sent {
+            self.broadcast_tail += 1;
+        }
+    }
+
     fn try_fulfill_receiver(&mut self, mut msg: MessageToOne<A>) -> Result<(), MessageToOne<A>> {
         while let Some(rx) = self.waiting_receivers_handles.pop_front() {
             match rx.notify_new_message(msg) {
@@ -393,6 +912,33 @@ impl<A> Inner<A> {
         Err(msg)
     }
 
+    /// Take all messages with the given key out of the queue and out of the waiting senders, whose
+    /// sending completes as if the message had been queued. Returns the number of messages taken.
+    fn remove_keyed(&mut self, key: &Key) -> usize {
+        let queued = self.unicast_queue.len();
+        self.unicast_queue.retain(|msg| msg.key() != Some(key));
+        let mut removed = queued - self.unicast_queue.len();
+
+        for handle in mem::take(&mut self.waiting_send_to_one) {
+            if handle.message_matches(|msg| msg.key() == Some(key)) {
+                drop(handle.take_message());
+                removed += 1;
+            } else {
+                self.waiting_send_to_one.push_back(handle);
+            }
+        }
+
+        // Waiting senders take up the room that has been made.
+        while !self.is_unicast_full() {
+            match self.try_take_waiting_unicast_message() {
+                Some(msg) => self.unicast_queue.push(msg),
+                None => break,
+            }
+        }
+
+        removed
+    }
+
     fn try_take_waiting_unicast_message(&mut self) -> Option<MessageToOne<A>> {
         loop {
             if let Some(msg) =
@@ -450,6 +996,17 @@ pub enum ActorMessage<A> {
     Shutdown,
 }
 
+impl<A> ActorMessage<A> {
+    /// Whether this message instructs the actor to shut down.
+    pub fn is_shutdown(&self) -> bool {
+        match self {
+            ActorMessage::ToOneActor(_) => false,
+            ActorMessage::ToAllActors(msg) => msg.priority() == Priority::Shutdown,
+            ActorMessage::Shutdown => true,
+        }
+    }
+}
+
 impl<A> From<MessageToOne<A>> for ActorMessage<A> {
     fn from(msg: MessageToOne<A>) -> Self {
         ActorMessage::ToOneActor(msg)
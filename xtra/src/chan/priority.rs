@@ -1,8 +1,12 @@
 use std::cmp::Ordering;
+use std::sync::atomic::{self, AtomicU64};
 
 #[derive(Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
 pub enum Priority {
     Valued(u32),
+    /// A message sent by the actor to itself with [`Context::notify`](crate::Context::notify),
+    /// which is received ahead of all other messages but a shutdown.
+    Notification,
     Shutdown,
 }
 
@@ -17,7 +21,28 @@ pub trait HasPriority {
 }
 
 /// A wrapper struct that allows comparison and ordering for anything thas has a priority, i.e. implements [`HasPriority`].
-pub struct ByPriority<T>(pub T);
+///
+/// Items of equal priority are ordered by when they were wrapped, the earliest first, so that a
+/// queue of them is first-in first-out, unless they were wrapped with [`ByPriority::newest_first`].
+pub struct ByPriority<T>(pub T, u64);
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+impl<T> ByPriority<T> {
+    pub fn new(item: T) -> Self {
+        ByPriority(item, SEQUENCE.fetch_add(1, atomic::Ordering::Relaxed))
+    }
+
+    /// Wrap the item so that it is ordered ahead of the items of equal priority which were wrapped
+    /// before it, making a queue of them last-in first-out. Items wrapped with [`ByPriority::new`]
+    /// must not be compared to it.
+    pub fn newest_first(item: T) -> Self {
+        ByPriority(
+            item,
+            u64::MAX - SEQUENCE.fetch_add(1, atomic::Ordering::Relaxed),
+        )
+    }
+}
 
 impl<T> HasPriority for ByPriority<T>
 where
@@ -33,7 +58,7 @@ where
     T: HasPriority,
 {
     fn eq(&self, other: &Self) -> bool {
-        self.0.priority().eq(&other.0.priority())
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -53,6 +78,9 @@ where
     T: HasPriority,
 {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.0.priority().cmp(&other.0.priority())
+        self.0
+            .priority()
+            .cmp(&other.0.priority())
+            .then_with(|| other.1.cmp(&self.1))
     }
 }
@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, BinaryHeap, VecDeque};
+use std::mem;
+
+use super::{ByPriority, HasPriority, Priority};
+
+/// The data structure which holds the messages sent to one actor, see
+/// [`Mailbox::with_backing`](crate::Mailbox::with_backing).
+///
+/// Either way, messages are received in order of priority, and within a priority in the
+/// [`MailboxOrder`] of the mailbox.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MailboxBacking {
+    /// A binary heap in a single growable buffer. Sending and receiving take logarithmic time in
+    /// the number of queued messages, and all messages share one allocation.
+    #[default]
+    Heap,
+    /// A ring buffer per priority. Sending and receiving take constant time however many messages
+    /// are queued, which suits actors receiving bursts of messages of the same priority, at the cost
+    /// of an allocation per priority which has messages queued.
+    Segmented,
+}
+
+/// The order in which the messages of equal priority to one actor are received, see
+/// [`Mailbox::with_order`](crate::Mailbox::with_order).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MailboxOrder {
+    /// The oldest message is received first.
+    #[default]
+    Fifo,
+    /// The newest message is received first, except for notifications sent with
+    /// [`Context::notify`](crate::Context::notify).
+    Lifo,
+}
+
+/// The queue of messages to one actor, stored according to its [`MailboxBacking`] and received in
+/// its [`MailboxOrder`].
+pub struct UnicastQueue<T> {
+    order: MailboxOrder,
+    storage: Storage<T>,
+}
+
+enum Storage<T> {
+    Heap(BinaryHeap<ByPriority<T>>),
+    Segmented {
+        segments: BTreeMap<Priority, VecDeque<T>>,
+        len: usize,
+    },
+}
+
+impl<T: HasPriority> UnicastQueue<T> {
+    pub fn new(backing: MailboxBacking, order: MailboxOrder) -> Self {
+        let storage = match backing {
+            MailboxBacking::Heap => Storage::Heap(BinaryHeap::new()),
+            MailboxBacking::Segmented => Storage::Segmented {
+                segments: BTreeMap::new(),
+                len: 0,
+            },
+        };
+
+        UnicastQueue { order, storage }
+    }
+
+    pub fn backing(&self) -> MailboxBacking {
+        match self.storage {
+            Storage::Heap(_) => MailboxBacking::Heap,
+            Storage::Segmented { .. } => MailboxBacking::Segmented,
+        }
+    }
+
+    /// Move all queued messages into a queue with the given backing, keeping their order.
+    pub fn set_backing(&mut self, backing: MailboxBacking) {
+        if self.backing() != backing {
+            self.rebuild(backing, self.order);
+        }
+    }
+
+    /// Receive the queued messages and those sent from now on in the given order.
+    pub fn set_order(&mut self, order: MailboxOrder) {
+        if self.order != order {
+            self.rebuild(self.backing(), order);
+        }
+    }
+
+    fn rebuild(&mut self, backing: MailboxBacking, order: MailboxOrder) {
+        let messages = self.drain();
+        *self = UnicastQueue::new(backing, order);
+
+        for message in messages {
+            self.push(message);
+        }
+    }
+
+    /// Take all queued messages, in order of priority and within a priority in the order they were
+    /// sent, regardless of the [`MailboxOrder`].
+    pub fn drain(&mut self) -> Vec<T> {
+        let mut messages = Vec::with_capacity(self.len());
+
+        while let Some(message) = self.pop() {
+            messages.push(message);
+        }
+
+        let mut start = 0;
+
+        while start < messages.len() {
+            let priority = messages[start].priority();
+            let end = messages[start..]
+                .iter()
+                .position(|message| message.priority() != priority)
+                .map_or(messages.len(), |len| start + len);
+
+            if order_of(self.order, priority) == MailboxOrder::Lifo {
+                messages[start..end].reverse();
+            }
+
+            start = end;
+        }
+
+        messages
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Heap(heap) => heap.len(),
+            Storage::Segmented { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&mut self, message: T) {
+        match &mut self.storage {
+            Storage::Heap(heap) => heap.push(match order_of(self.order, message.priority()) {
+                MailboxOrder::Fifo => ByPriority::new(message),
+                MailboxOrder::Lifo => ByPriority::newest_first(message),
+            }),
+            Storage::Segmented { segments, len } => {
+                segments
+                    .entry(message.priority())
+                    .or_default()
+                    .push_back(message);
+                *len += 1;
+            }
+        }
+    }
+
+    /// The highest priority of any queued message.
+    pub fn peek_priority(&self) -> Option<Priority> {
+        match &self.storage {
+            Storage::Heap(heap) => heap.peek().map(|message| message.priority()),
+            Storage::Segmented { segments, .. } => segments.keys().next_back().copied(),
+        }
+    }
+
+    /// The next message of the highest priority.
+    pub fn peek(&self) -> Option<&T> {
+        match &self.storage {
+            Storage::Heap(heap) => heap.peek().map(|message| &message.0),
+            Storage::Segmented { segments, .. } => {
+                let (priority, segment) = segments.iter().next_back()?;
+
+                match order_of(self.order, *priority) {
+                    MailboxOrder::Fifo => segment.front(),
+                    MailboxOrder::Lifo => segment.back(),
+                }
+            }
+        }
+    }
+
+    /// Take the next message of the highest priority.
+    pub fn pop(&mut self) -> Option<T> {
+        let order = self.order;
+
+        match &mut self.storage {
+            Storage::Heap(heap) => heap.pop().map(|message| message.0),
+            Storage::Segmented { segments, len } => {
+                let mut segment = segments.last_entry()?;
+                let order = order_of(order, *segment.key());
+                let message = pop_next(segment.get_mut(), order);
+
+                if segment.get().is_empty() {
+                    segment.remove();
+                }
+
+                *len -= 1;
+                message
+            }
+        }
+    }
+
+    /// Take the next message of the given priority.
+    pub fn pop_with_priority(&mut self, priority: Priority) -> Option<T> {
+        let order = self.order;
+
+        match &mut self.storage {
+            Storage::Heap(heap) => {
+                // Within a priority, the greatest message is the one which is received next.
+                let mut queue = mem::take(heap).into_vec();
+                let index = queue
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, message)| message.priority() == priority)
+                    .max_by(|(_, a), (_, b)| a.cmp(b))
+                    .map(|(index, _)| index);
+                let message = index.map(|index| queue.swap_remove(index).0);
+                *heap = queue.into();
+
+                message
+            }
+            Storage::Segmented { segments, len } => {
+                let segment = segments.get_mut(&priority)?;
+                let message = pop_next(segment, order_of(order, priority));
+
+                if segment.is_empty() {
+                    segments.remove(&priority);
+                }
+
+                *len -= 1;
+                message
+            }
+        }
+    }
+
+    /// The highest priority of any queued message which is lower than the given one.
+    pub fn highest_priority_below(&self, priority: Priority) -> Option<Priority> {
+        match &self.storage {
+            Storage::Heap(heap) => heap
+                .iter()
+                .map(|message| message.priority())
+                .filter(|p| *p < priority)
+                .max(),
+            Storage::Segmented { segments, .. } => {
+                segments.range(..priority).next_back().map(|(p, _)| *p)
+            }
+        }
+    }
+
+    /// The number of queued messages of the given priority.
+    pub fn len_with_priority(&self, priority: Priority) -> usize {
+        match &self.storage {
+            Storage::Heap(heap) => heap
+                .iter()
+                .filter(|message| message.priority() == priority)
+                .count(),
+            Storage::Segmented { segments, .. } => segments.get(&priority).map_or(0, VecDeque::len),
+        }
+    }
+
+    /// Keep only the messages for which `f` returns true.
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        match &mut self.storage {
+            Storage::Heap(heap) => heap.retain(|message| f(&message.0)),
+            Storage::Segmented { segments, len } => {
+                segments.retain(|_, segment| {
+                    segment.retain(&mut f);
+                    !segment.is_empty()
+                });
+                *len = segments.values().map(VecDeque::len).sum();
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match &mut self.storage {
+            Storage::Heap(heap) => heap.clear(),
+            Storage::Segmented { segments, len } => {
+                segments.clear();
+                *len = 0;
+            }
+        }
+    }
+}
+
+/// The order in which the messages of the given priority are received. Notifications are always
+/// received in the order they were sent.
+fn order_of(order: MailboxOrder, priority: Priority) -> MailboxOrder {
+    match priority {
+        Priority::Notification => MailboxOrder::Fifo,
+        _ => order,
+    }
+}
+
+/// Take the message from the end of the segment which is received next in the given order.
+fn pop_next<T>(segment: &mut VecDeque<T>, order: MailboxOrder) -> Option<T> {
+    match order {
+        MailboxOrder::Fifo => segment.pop_front(),
+        MailboxOrder::Lifo => segment.pop_back(),
+    }
+}
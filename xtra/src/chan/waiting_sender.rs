@@ -40,13 +40,16 @@ impl<M> Handle<M> {
 
                 Some(message)
             }
-            Inner::Delivered | Inner::Closed => None,
+            Inner::Delivered | Inner::Closed(_) => None,
         }
     }
-}
 
-impl<M> Drop for Handle<M> {
-    fn drop(&mut self) {
+    /// Close the paired [`WaitingSender`], failing it with the given error.
+    pub fn close(self, error: Error) {
+        self.close_with(error);
+    }
+
+    fn close_with(&self, error: Error) {
         if let Some(inner) = self.0.upgrade() {
             let mut this = inner.lock();
 
@@ -55,15 +58,38 @@ impl<M> Drop for Handle<M> {
                     if let Some(waker) = waker {
                         waker.wake_by_ref();
                     }
-                    *this = Inner::Closed;
+                    *this = Inner::Closed(error);
                 }
                 Inner::Delivered => {}
-                Inner::Closed => {}
+                Inner::Closed(_) => {}
             }
         };
     }
 }
 
+impl<M> Drop for Handle<M> {
+    fn drop(&mut self) {
+        // Has no effect if the handle has been closed with an error already.
+        self.close_with(Error::Disconnected);
+    }
+}
+
+impl<M> Handle<M> {
+    /// Whether the paired [`WaitingSender`] is still waiting with a message which satisfies the
+    /// given predicate.
+    pub fn message_matches(&self, predicate: impl FnOnce(&M) -> bool) -> bool {
+        let Some(inner) = self.0.upgrade() else {
+            return false;
+        };
+        let this = inner.lock();
+
+        match &*this {
+            Inner::Active { message, .. } => predicate(message),
+            Inner::Closed(_) | Inner::Delivered => false,
+        }
+    }
+}
+
 impl<M> Handle<M>
 where
     M: HasPriority,
@@ -74,7 +100,7 @@ where
 
         match &*this {
             Inner::Active { message, .. } => Some(message.priority()),
-            Inner::Closed | Inner::Delivered => None,
+            Inner::Closed(_) | Inner::Delivered => None,
         }
     }
 }
@@ -91,7 +117,7 @@ impl<A> Future for WaitingSender<A> {
                 Poll::Pending
             }
             Inner::Delivered => Poll::Ready(Ok(())),
-            Inner::Closed => Poll::Ready(Err(Error::Disconnected)),
+            Inner::Closed(error) => Poll::Ready(Err(error.clone())),
         }
     }
 }
@@ -99,7 +125,7 @@ impl<A> Future for WaitingSender<A> {
 enum Inner<M> {
     Active { waker: Option<Waker>, message: M },
     Delivered,
-    Closed,
+    Closed(Error),
 }
 
 impl<M> Inner<M> {
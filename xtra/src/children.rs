@@ -0,0 +1,105 @@
+use crate::address::ActorJoinHandle;
+use crate::{Actor, WeakAddress};
+
+/// The child actors spawned with [`Context::spawn_child`](crate::Context::spawn_child), which are
+/// stopped when their parent stops.
+#[derive(Default)]
+pub(crate) struct Children {
+    children: spin::Mutex<Vec<Box<dyn Child>>>,
+}
+
+impl Children {
+    /// Track the actor of the given address as a child.
+    pub(crate) fn add<B: Actor>(&self, child: WeakAddress<B>) {
+        let mut children = self.children.lock();
+
+        // Forget children which have stopped on their own, so that they do not accumulate.
+        children.retain(|child| child.is_connected());
+        children.push(Box::new(child));
+    }
+
+    /// Stop all children, then wait until all of them have stopped.
+    pub(crate) async fn stop_all(&self) {
+        let children = std::mem::take(&mut *self.children.lock());
+        let joins = children
+            .iter()
+            .map(|child| child.join())
+            .collect::<Vec<_>>();
+
+        for child in children {
+            child.stop();
+        }
+
+        for join in joins {
+            join.await;
+        }
+    }
+}
+
+/// A child actor, with the type of the actor erased.
+trait Child: Send + 'static {
+    fn is_connected(&self) -> bool;
+
+    fn stop(&self);
+
+    fn join(&self) -> ActorJoinHandle;
+}
+
+impl<B: Actor> Child for WeakAddress<B> {
+    fn is_connected(&self) -> bool {
+        WeakAddress::is_connected(self)
+    }
+
+    fn stop(&self) {
+        // Without strong addresses, the child stops on its own.
+        if let Some(address) = self.try_upgrade() {
+            address.0.shutdown_all_receivers();
+        }
+    }
+
+    fn join(&self) -> ActorJoinHandle {
+        WeakAddress::join(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Actor, Address, Mailbox};
+
+    struct Child;
+
+    impl Actor for Child {
+        type Stop = ();
+
+        async fn stopped(self) -> Self::Stop {}
+    }
+
+    struct FailsToStart(Option<catty::Sender<Address<Child>>>);
+
+    impl Actor for FailsToStart {
+        type Stop = ();
+
+        async fn started(&mut self, mailbox: &Mailbox<Self>) -> Result<(), Self::Stop> {
+            let (child, child_mailbox) = Mailbox::unbounded();
+            tokio::spawn(crate::run(child_mailbox, Child));
+            mailbox.children.add(child.downgrade());
+
+            let _ = self.0.take().unwrap().send(child);
+
+            Err(())
+        }
+
+        async fn stopped(self) -> Self::Stop {}
+    }
+
+    #[tokio::test]
+    async fn children_spawned_in_started_stop_if_it_fails() {
+        let (tx, rx) = catty::oneshot();
+        let (_address, mailbox) = Mailbox::unbounded();
+
+        crate::run(mailbox, FailsToStart(Some(tx))).await;
+
+        let child = rx.await.unwrap();
+        assert!(!child.is_connected());
+    }
+}
@@ -0,0 +1,121 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::ControlFlow;
+use std::sync::Arc;
+
+use futures_core::future::BoxFuture;
+
+use crate::chan::{HasPriority, Priority};
+use crate::envelope::{MessageEnvelope, ReturningEnvelope};
+use crate::instrumentation::Span;
+use crate::{ActorId, Handler, Mailbox};
+
+/// The latest payload per type of the messages sent with
+/// [`Context::notify_coalesced`](crate::Context::notify_coalesced) which have not been handled yet.
+#[derive(Default)]
+pub struct Coalesced(spin::Mutex<HashMap<TypeId, Box<dyn Any + Send>>>);
+
+impl Coalesced {
+    /// Store the payload of the pending message of type `M`, returning whether there already was
+    /// one, whose payload has been replaced.
+    pub fn replace<M: Send + 'static>(&self, message: M) -> bool {
+        self.0
+            .lock()
+            .insert(TypeId::of::<M>(), Box::new(message))
+            .is_some()
+    }
+
+    /// Take the payload of the pending message of type `M`, so that the next message of that type
+    /// is queued again.
+    fn take<M: 'static>(&self) -> Option<M> {
+        let message = self.0.lock().remove(&TypeId::of::<M>())?;
+
+        message.downcast().ok().map(|message| *message)
+    }
+}
+
+/// An envelope which stands in for the pending message of type `M`, whose payload is only taken
+/// once the actor handles it, so that it can be replaced while the envelope is queued.
+pub struct CoalescedEnvelope<A, M: 'static> {
+    /// Taken when the envelope is handled, so that dropping it does not forget a newer message.
+    coalesced: Option<Arc<Coalesced>>,
+    /// The actor for which the span of the message was started, see [`MessageEnvelope::start_span`].
+    span: Option<(String, ActorId)>,
+    priority: u32,
+    phantom: PhantomData<for<'a> fn(&'a A, M)>,
+}
+
+impl<A, M: 'static> CoalescedEnvelope<A, M> {
+    pub fn new(coalesced: Arc<Coalesced>) -> Self {
+        CoalescedEnvelope {
+            coalesced: Some(coalesced),
+            span: None,
+            priority: 0,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<A, M: 'static> HasPriority for CoalescedEnvelope<A, M> {
+    fn priority(&self) -> Priority {
+        Priority::Valued(self.priority)
+    }
+}
+
+impl<A, M> MessageEnvelope for CoalescedEnvelope<A, M>
+where
+    A: Handler<M>,
+    M: Send + 'static,
+{
+    type Actor = A;
+
+    fn set_priority(&mut self, new_priority: u32) {
+        self.priority = new_priority;
+    }
+
+    fn start_span(&mut self, actor_name: &str, actor_id: ActorId) {
+        self.span = Some((actor_name.to_owned(), actor_id));
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+
+    fn message_type(&self) -> &'static str {
+        std::any::type_name::<M>()
+    }
+
+    fn handle(
+        mut self: Box<Self>,
+        act: &mut Self::Actor,
+        mailbox: Mailbox<Self::Actor>,
+    ) -> (BoxFuture<'_, ControlFlow<(), ()>>, Span) {
+        let coalesced = self.coalesced.take().expect("to only be handled once");
+
+        let Some(message) = coalesced.take::<M>() else {
+            return (Box::pin(async { ControlFlow::Continue(()) }), Span::none());
+        };
+
+        let (mut envelope, _) = ReturningEnvelope::<A, M, A::Return>::new(message, self.priority);
+
+        if let Some((actor_name, actor_id)) = self.span.take() {
+            envelope.start_span(&actor_name, actor_id);
+        }
+
+        Box::new(envelope).handle(act, mailbox)
+    }
+}
+
+impl<A, M: 'static> Drop for CoalescedEnvelope<A, M> {
+    fn drop(&mut self) {
+        // The message is dropped without being handled, so the next one is queued again.
+        if let Some(coalesced) = self.coalesced.take() {
+            drop(coalesced.take::<M>());
+        }
+    }
+}
@@ -1,19 +1,85 @@
-use crate::{Actor, Mailbox};
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::future::{poll_fn, Future};
+use std::sync::atomic::Ordering;
+use std::task::Poll;
+use std::time::{Duration, Instant};
+
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::chan::MessageToOne;
+use crate::coalesce::CoalescedEnvelope;
+use crate::envelope::ReturningEnvelope;
+use crate::message_channel::MessageChannel;
+use crate::reply::ReplyInterest;
+use crate::retry::RetryPolicy;
+use crate::scoped_task::{self, TaskHandle};
+use crate::stream::{self, Backpressure, StreamCompleted, StreamHandle};
+use crate::{Actor, ActorId, Handler, Mailbox, TimerId};
 
 /// `Context` is used to control how the actor is managed and to get the actor's address from inside
 /// of a message handler.
 pub struct Context<A> {
     pub(crate) running: bool,
     pub(crate) mailbox: Mailbox<A>,
+    pub(crate) reply_to: Option<Box<dyn Any + Send>>,
+    /// Whether the sender still waits for the reply to the current message, if it can wait for one.
+    pub(crate) reply_interest: Option<ReplyInterest>,
+    pub(crate) correlation_id: Option<u128>,
+    pub(crate) timer_deadline: Option<Instant>,
+    /// The message put back into the mailbox with [`Context::requeue`], and the delay after which.
+    pub(crate) requeued: Option<(MessageToOne<A>, Option<Duration>)>,
+}
+
+impl<A> Context<A> {
+    /// Create the context for handling a message, marking the actor as busy until it is dropped.
+    pub(crate) fn new(mailbox: Mailbox<A>, reply_to: Option<Box<dyn Any + Send>>) -> Self {
+        mailbox.inner.on_handler_started();
+        mailbox.stop_requested.store(false, Ordering::Relaxed);
+
+        Context {
+            running: true,
+            mailbox,
+            reply_to,
+            reply_interest: None,
+            correlation_id: None,
+            timer_deadline: None,
+            requeued: None,
+        }
+    }
+}
+
+impl<A> Drop for Context<A> {
+    fn drop(&mut self) {
+        self.mailbox.inner.on_handler_finished();
+    }
 }
 
 impl<A: Actor> Context<A> {
     /// Stop this actor as soon as it has finished processing current message. This means that the
     /// [`Actor::stopped`] method will be called. This will not stop all actors on the address.
+    ///
+    /// The current handler runs to completion, so it may still await e.g. a flush or its reply
+    /// after calling this. Use [`Context::stop_self_now`] to cancel it instead.
+    ///
+    /// Calling this more than once, e.g. from several handlers, has no further effect.
     pub fn stop_self(&mut self) {
         self.running = false;
     }
 
+    /// Stop this actor like [`Context::stop_self`], and cancel the current handler the next time
+    /// it awaits a pending future rather than waiting for it to return.
+    ///
+    /// If the handler is cancelled, the sender of the message receives
+    /// [`Error::ActorStoppedDuringHandling`](crate::Error::ActorStoppedDuringHandling). A handler
+    /// which returns without awaiting a pending future is not affected.
+    pub fn stop_self_now(&mut self) {
+        self.stop_self();
+        self.mailbox.stop_requested.store(true, Ordering::Relaxed);
+    }
+
     /// Stop all actors on this address.
     ///
     /// This bypasses the message queue, so it will always be handled as soon as possible by all actors.
@@ -27,6 +93,9 @@ impl<A: Actor> Context<A> {
     /// a broadcast message that would cause [`Context::stop_self`] to be called may have to wait
     /// for other broadcast messages, during which time other messages may be handled by actors (i.e
     /// the shutdown may be delayed by a lagging actor).
+    ///
+    /// Calling this again before the actors have shut down has no further effect, so it does not
+    /// take up room in the broadcast queue of the actors.
     pub fn stop_all(&self) {
         // We only need to shut down if there are still any strong senders left
         if let Some(address) = self.mailbox.address().try_upgrade() {
@@ -34,8 +103,864 @@ impl<A: Actor> Context<A> {
         }
     }
 
+    /// Stop this actor once the given duration has elapsed.
+    ///
+    /// This is a shorthand for [`Mailbox::stop_after`].
+    pub fn stop_after(&self, duration: Duration) {
+        self.mailbox.stop_after(duration);
+    }
+
+    /// Move the deadline set with [`Context::stop_after`] to the given duration from now,
+    /// returning whether there was one.
+    ///
+    /// This is a shorthand for [`Mailbox::extend_deadline`].
+    pub fn extend_deadline(&self, duration: Duration) -> bool {
+        self.mailbox.extend_deadline(duration)
+    }
+
+    /// Send a message to this actor, e.g. to continue a state machine in a later handler.
+    ///
+    /// Notifications are handled before any other message which is queued in the mailbox, in the
+    /// order in which they were sent, so that no message from elsewhere observes the state of the
+    /// actor between the steps of a state machine. This holds regardless of the
+    /// [`priority`](crate::SendFuture::priority) of the other messages and of the
+    /// [`MailboxOrder`](crate::MailboxOrder), and [`Mailbox::with_priority_fairness`] does not let
+    /// other messages through while notifications are queued. Only stopping the actor with
+    /// [`Context::stop_all`] takes precedence, which drops the notifications like any queued
+    /// message. If the actor stops because all of its addresses have been dropped, it first
+    /// handles the notifications which were queued by then, like all other queued messages.
+    ///
+    /// Unlike sending the message through the actor's own address, this never waits for room in
+    /// the mailbox, so a handler cannot block on its own full mailbox. The message is dropped if
+    /// all addresses to the actor have been dropped. Like any other message sent to the address,
+    /// the message may be handled by another actor running on the same address.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # #[derive(Default)]
+    /// # struct Connection { attempts: u32 }
+    /// # impl Actor for Connection { type Stop = (); async fn stopped(self) {} }
+    /// struct Connect;
+    ///
+    /// impl Handler<Connect> for Connection {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Connect, ctx: &mut Context<Self>) {
+    ///         self.attempts += 1;
+    ///
+    ///         if self.attempts < 3 {
+    ///             ctx.notify(Connect);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn notify<M>(&self, message: M)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        let (envelope, _) = ReturningEnvelope::<A, M, A::Return>::new(message, 0);
+        let _ = self
+            .mailbox
+            .inner
+            .force_send_to_one(Box::new(envelope.into_notification()));
+    }
+
+    /// Send a message to this actor unless a message of the same type sent through this function
+    /// is still waiting in the mailbox, in which case the pending message is handled with the
+    /// latest payload instead. At most one message of type `M` is pending at a time, e.g. to
+    /// recompute some derived state once for a burst of changes rather than once per change.
+    ///
+    /// Unlike [`Context::notify`], the message is queued at the tail of the mailbox like a message
+    /// sent through the actor's address, so that the messages which are already queued are handled
+    /// first and can coalesce into it. Once the actor starts handling the message, the next call
+    /// queues a new one. Like with [`Context::notify`], this never waits for room in the mailbox,
+    /// and the message is dropped if all addresses to the actor have been dropped.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # #[derive(Default)]
+    /// # struct Spreadsheet { cells: Vec<u32>, total: u32 }
+    /// # impl Actor for Spreadsheet { type Stop = (); async fn stopped(self) {} }
+    /// struct SetCell(usize, u32);
+    /// struct Recompute;
+    ///
+    /// impl Handler<SetCell> for Spreadsheet {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, SetCell(cell, value): SetCell, ctx: &mut Context<Self>) {
+    ///         self.cells[cell] = value;
+    ///         ctx.notify_coalesced(Recompute);
+    ///     }
+    /// }
+    ///
+    /// impl Handler<Recompute> for Spreadsheet {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Recompute, _: &mut Context<Self>) {
+    ///         self.total = self.cells.iter().sum();
+    ///     }
+    /// }
+    /// ```
+    pub fn notify_coalesced<M>(&self, message: M)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        if self.mailbox.coalesced.replace(message) {
+            return;
+        }
+
+        // If the message cannot be queued, dropping the envelope forgets its payload again.
+        let envelope = CoalescedEnvelope::<A, M>::new(self.mailbox.coalesced.clone());
+        let _ = self.mailbox.inner.force_send_to_one(Box::new(envelope));
+    }
+
+    /// Publish an event to everyone subscribed to events of type `E` through
+    /// [`Address::subscribe`](crate::Address::subscribe) or
+    /// [`Address::subscribe_with_channel`](crate::Address::subscribe_with_channel), returning the
+    /// number of subscribers it was published to.
+    ///
+    /// This never waits: the event is buffered for every subscriber, dropping events of those whose
+    /// buffer is full according to their [`Overflow`](crate::subscription::Overflow) policy.
+    /// Subscriptions which have been dropped are removed. See the
+    /// [`subscription`](crate::subscription) module for details.
+    pub fn publish<E>(&self, event: E) -> usize
+    where
+        E: Clone + Send + 'static,
+    {
+        self.mailbox.inner.topics().publish(event)
+    }
+
+    /// Publish the latest state of the actor, e.g. a snapshot of what a UI displays, to everyone
+    /// subscribed to it through [`Address::subscribe_state`](crate::Address::subscribe_state),
+    /// returning the number of subscribers it was published to.
+    ///
+    /// The state is published like an event with [`Context::publish`], and is kept, so that those
+    /// who subscribe later start with it rather than waiting for the next change. See the
+    /// [`subscription`](crate::subscription) module for details.
+    pub fn publish_state<S>(&self, state: S) -> usize
+    where
+        S: Clone + Send + 'static,
+    {
+        self.mailbox.inner.topics().publish_state(state)
+    }
+
+    /// Put the message which is currently being handled back at the tail of the mailbox, to be
+    /// handled again later, e.g. because a downstream actor has no capacity for it right now.
+    ///
+    /// The message is requeued once the handler returns, like with [`Context::notify`]. Its sender
+    /// is not notified until the message has been handled without being requeued: the return
+    /// value of this handler is discarded and the sender receives the return value of the handler
+    /// which eventually does not requeue it. A broadcast message is only requeued for this actor.
+    /// Calling this again within the same handler replaces the previously requeued message. If
+    /// `message` is not of the type which is being handled, it is sent like with
+    /// [`Context::notify`] instead.
+    ///
+    /// If there are no other messages in the mailbox, the message is handled again right away, so
+    /// a handler which keeps requeueing a message spins until the condition it waits for changes.
+    /// Prefer [`Context::requeue_after`] with a small delay in such cases.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Forwarder { downstream: Address<Database> }
+    /// # impl Actor for Forwarder { type Stop = (); async fn stopped(self) {} }
+    /// # struct Database;
+    /// # impl Actor for Database { type Stop = (); async fn stopped(self) {} }
+    /// # impl Handler<Write> for Database { type Return = (); async fn handle(&mut self, _: Write, _: &mut Context<Self>) {} }
+    /// struct Write(Vec<u8>);
+    ///
+    /// impl Handler<Write> for Forwarder {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, write: Write, ctx: &mut Context<Self>) {
+    ///         if self.downstream.len() >= 100 {
+    ///             ctx.requeue(write);
+    ///             return;
+    ///         }
+    ///
+    ///         let _ = self.downstream.send(write).detach().await;
+    ///     }
+    /// }
+    /// ```
+    pub fn requeue<M>(&mut self, message: M)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        let (envelope, _) = ReturningEnvelope::<A, M, A::Return>::new(message, 0);
+        self.requeued = Some((Box::new(envelope), None));
+    }
+
+    /// Like [`Context::requeue`], but only put the message back into the mailbox once the given
+    /// duration has elapsed. Other messages are handled in the meantime.
+    ///
+    /// The message is dropped if the actor stops before the duration has elapsed.
+    ///
+    /// The duration is measured with the [`Timer`](crate::runtime::Timer) of the actor's
+    /// [`Mailbox`]. Without one, it is measured by a timer thread shared by all actors.
+    pub fn requeue_after<M>(&mut self, message: M, delay: Duration)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        let (envelope, _) = ReturningEnvelope::<A, M, A::Return>::new(message, 0);
+        self.requeued = Some((Box::new(envelope), Some(delay)));
+    }
+
+    /// Like [`Context::notify`], but only queue the message once the given duration has elapsed,
+    /// e.g. to time out a request. Returns a [`TimerId`] to cancel it with
+    /// [`Context::cancel_timer`].
+    ///
+    /// The timers of an actor are kept in a single queue, which is driven by one sleep until the
+    /// earliest deadline while the actor waits for its next message, so an actor can have any
+    /// number of them without spawning a task for each. A timer which becomes due while the actor
+    /// is busy handling a message fires as soon as that handler has returned. The handler of the
+    /// message can tell how late it is from [`Context::timer_deadline`].
+    ///
+    /// The message is dropped if this actor stops before the duration has elapsed.
+    ///
+    /// ```rust
+    /// # use std::collections::HashMap;
+    /// # use std::time::Duration;
+    /// # use xtra::prelude::*;
+    /// # use xtra::TimerId;
+    /// # #[derive(Default)]
+    /// # struct Session { pending: HashMap<u64, TimerId> }
+    /// # impl Actor for Session { type Stop = (); async fn stopped(self) {} }
+    /// struct Request(u64);
+    /// struct Expired(u64);
+    ///
+    /// impl Handler<Request> for Session {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, Request(id): Request, ctx: &mut Context<Self>) {
+    ///         let timer = ctx.notify_after(Expired(id), Duration::from_secs(30));
+    ///         self.pending.insert(id, timer);
+    ///     }
+    /// }
+    ///
+    /// impl Handler<Expired> for Session {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, Expired(id): Expired, _: &mut Context<Self>) {
+    ///         self.pending.remove(&id);
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// The duration is measured with the [`Timer`](crate::runtime::Timer) of the actor's
+    /// [`Mailbox`]. Without one, it is measured by a timer thread shared by all actors.
+    pub fn notify_after<M>(&self, message: M, delay: Duration) -> TimerId
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        let deadline = self.mailbox.timer_or_fallback().now() + delay;
+
+        let (envelope, _) = ReturningEnvelope::<A, M, A::Return>::new(message, 0);
+        let envelope = envelope.with_deadline(deadline);
+        let chan = self.mailbox.inner.to_tx_weak();
+
+        self.mailbox.timers.schedule(deadline, move || {
+            // The message is dropped if all addresses to the actor have been dropped.
+            let _ = chan.force_send_to_one(Box::new(envelope));
+        })
+    }
+
+    /// Like [`Context::notify_after`], but replace the message of the same type which is still
+    /// pending from an earlier call, restarting the window. The message is only queued once no
+    /// other message of its type has been scheduled for the duration of the window, and only the
+    /// latest one is handled, e.g. to recompute some state at most once per burst of changes.
+    ///
+    /// Messages scheduled with [`Context::notify_after`] are not affected. The pending message is
+    /// dropped if this actor stops before the window has elapsed.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use xtra::prelude::*;
+    /// # struct Config;
+    /// # impl Actor for Config { type Stop = (); async fn stopped(self) {} }
+    /// struct Changed;
+    /// struct Recompute;
+    ///
+    /// impl Handler<Changed> for Config {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Changed, ctx: &mut Context<Self>) {
+    ///         ctx.notify_debounced(Recompute, Duration::from_millis(500));
+    ///     }
+    /// }
+    /// # impl Handler<Recompute> for Config {
+    /// #     type Return = ();
+    /// #     async fn handle(&mut self, _: Recompute, _: &mut Context<Self>) {}
+    /// # }
+    /// ```
+    ///
+    /// Like for [`Context::notify_after`], the window is measured by a timer thread shared by all
+    /// actors if no [`Timer`](crate::runtime::Timer) is configured for the actor's [`Mailbox`].
+    pub fn notify_debounced<M>(&self, message: M, window: Duration) -> TimerId
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        let deadline = self.mailbox.timer_or_fallback().now() + window;
+
+        let (envelope, _) = ReturningEnvelope::<A, M, A::Return>::new(message, 0);
+        let envelope = envelope.with_deadline(deadline);
+        let chan = self.mailbox.inner.to_tx_weak();
+
+        self.mailbox
+            .timers
+            .debounce(TypeId::of::<M>(), deadline, move || {
+                // The message is dropped if all addresses to the actor have been dropped.
+                let _ = chan.force_send_to_one(Box::new(envelope));
+            })
+    }
+
+    /// Cancel a message scheduled with [`Context::notify_after`] or [`Context::notify_debounced`],
+    /// returning whether it was still pending. Once the timer has fired, its message can no longer be cancelled.
+    pub fn cancel_timer(&self, id: TimerId) -> bool {
+        self.mailbox.timers.cancel(id)
+    }
+
+    /// Register a callback to clean up a resource acquired by this handler once the actor stops,
+    /// regardless of why it stops.
+    ///
+    /// The callbacks are run with the actor right before [`Actor::stopped`], in reverse
+    /// registration order, i.e. resources are released in the opposite order they were acquired
+    /// in. They are not run if the task running the actor panics or is dropped, as the actor is
+    /// not stopped in that case.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # #[derive(Default)]
+    /// # struct Uploader { files: Vec<std::path::PathBuf> }
+    /// # impl Actor for Uploader { type Stop = (); async fn stopped(self) {} }
+    /// struct Upload(std::path::PathBuf);
+    ///
+    /// impl Handler<Upload> for Uploader {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, upload: Upload, ctx: &mut Context<Self>) {
+    ///         let temp = upload.0.with_extension("part");
+    ///         ctx.on_stop(move |_| {
+    ///             let _ = std::fs::remove_file(temp);
+    ///         });
+    ///
+    ///         self.files.push(upload.0);
+    ///     }
+    /// }
+    /// ```
+    pub fn on_stop(&mut self, f: impl FnOnce(&mut A) + Send + 'static) {
+        self.mailbox.on_stop(Box::new(f));
+    }
+
+    /// Spawn a task which runs `fut` once fewer than `n` tasks spawned with `with_permit` by this
+    /// actor are running, e.g. to cap the work an actor has in flight with downstream actors.
+    ///
+    /// All tasks spawned with `with_permit` by this actor share the same permits, and each task
+    /// holds one while it runs. A task waits as long as `n` or more tasks are running, so tasks
+    /// spawned with a smaller `n` may wait behind tasks spawned with a larger one. The handler
+    /// does not wait for the task to start.
+    ///
+    /// With the `metrics` feature, the number of running tasks is recorded in the
+    /// `xtra_permits_in_flight` gauge. It is also available through
+    /// [`Context::permits_in_flight`].
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Crawler { storage: Address<Storage> }
+    /// # impl Actor for Crawler { type Stop = (); async fn stopped(self) {} }
+    /// # struct Storage;
+    /// # impl Actor for Storage { type Stop = (); async fn stopped(self) {} }
+    /// # impl Handler<Store> for Storage { type Return = (); async fn handle(&mut self, _: Store, _: &mut Context<Self>) {} }
+    /// struct Crawl(Vec<String>);
+    /// struct Store(String);
+    ///
+    /// impl Handler<Crawl> for Crawler {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, crawl: Crawl, ctx: &mut Context<Self>) {
+    ///         for url in crawl.0 {
+    ///             let storage = self.storage.clone();
+    ///
+    ///             // At most 8 pages are stored at a time.
+    ///             ctx.with_permit(8, async move {
+    ///                 let _ = storage.send(Store(url)).await;
+    ///             });
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// The task is spawned with the [`Spawner`](crate::runtime::Spawner) configured for the actor's
+    /// [`Mailbox`]. If none is configured, it runs on an executor thread shared by all actors
+    /// instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn with_permit<F>(&self, n: usize, fut: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        assert!(n > 0, "a task cannot run without permits");
+
+        let spawner = self.mailbox.spawner_or_fallback();
+        let permit = self
+            .mailbox
+            .permits
+            .clone()
+            .acquire(n, self.mailbox.inner.name());
+
+        spawner.spawn(
+            &self.mailbox.task_name("permit"),
+            Box::pin(async move {
+                let _permit = permit.await;
+                fut.await;
+            }),
+        );
+    }
+
+    /// The number of tasks spawned with [`Context::with_permit`] by this actor which are currently
+    /// running, i.e. not waiting for a permit.
+    pub fn permits_in_flight(&self) -> usize {
+        self.mailbox.permits.in_flight()
+    }
+
+    /// Run the blocking function `f` through the [`Spawner`](crate::runtime::Spawner) configured
+    /// for the actor's [`Mailbox`], e.g. on tokio's blocking thread pool, and resolve to its result.
+    ///
+    /// This keeps blocking work such as file I/O or CPU-heavy computations off the executor's
+    /// threads, so that other actors keep running. The actor itself does not: as long as the
+    /// handler awaits the result, no further messages are handled by it. To keep handling
+    /// messages, await the result in a task spawned with [`Context::with_permit`] instead, and send
+    /// it back to the actor once it is available.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Indexer;
+    /// # impl Actor for Indexer { type Stop = (); async fn stopped(self) {} }
+    /// struct Hash(Vec<u8>);
+    ///
+    /// impl Handler<Hash> for Indexer {
+    ///     type Return = u64;
+    ///
+    ///     async fn handle(&mut self, Hash(bytes): Hash, ctx: &mut Context<Self>) -> u64 {
+    ///         ctx.run_blocking(move || bytes.iter().map(|&b| b as u64).sum()).await
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// If no [`Spawner`](crate::runtime::Spawner) is configured, `f` runs on a thread of its own
+    /// instead.
+    ///
+    /// # Panics
+    ///
+    /// The returned future panics if `f` panics.
+    pub fn run_blocking<F, R>(&self, f: F) -> impl Future<Output = R> + Send + 'static
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let spawner = self.mailbox.spawner_or_fallback();
+        let (tx, rx) = catty::oneshot();
+
+        spawner.spawn_blocking(
+            &self.mailbox.task_name("blocking"),
+            Box::new(move || {
+                let _ = tx.send(f());
+            }),
+        );
+
+        async move { rx.await.expect("blocking function to not panic") }
+    }
+
+    /// Run the fallible operation returned by `f`, and run it again whenever it fails, according
+    /// to `policy`. Resolves to the first success, or to the last error once the policy allows no
+    /// further retries.
+    ///
+    /// Like any future awaited by a handler, this keeps the actor from handling further messages
+    /// until it resolves, so that the operation sees the state of the actor as it was when the
+    /// message arrived. To keep handling messages, run the retries in a task spawned with
+    /// [`Context::with_permit`] instead, and send the result back to the actor.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use xtra::prelude::*;
+    /// use xtra::retry::RetryPolicy;
+    ///
+    /// # struct Client;
+    /// # impl Client { async fn charge(&self, _: u64) -> Result<(), &'static str> { Ok(()) } }
+    /// # struct Gateway { client: Client }
+    /// # impl Actor for Gateway { type Stop = (); async fn stopped(self) {} }
+    /// struct Charge(u64);
+    ///
+    /// impl Handler<Charge> for Gateway {
+    ///     type Return = Result<(), &'static str>;
+    ///
+    ///     async fn handle(&mut self, Charge(amount): Charge, ctx: &mut Context<Self>) -> Self::Return {
+    ///         let policy = RetryPolicy::new(3).with_backoff(Duration::from_millis(100));
+    ///
+    ///         ctx.retry(policy, || self.client.charge(amount)).await
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// The policy waits between retries with the [`Timer`](crate::runtime::Timer) configured for
+    /// the actor's [`Mailbox`]. If none is configured, a timer thread shared by all actors is used.
+    pub fn retry<F, Fut, T, E>(
+        &self,
+        policy: RetryPolicy,
+        mut f: F,
+    ) -> impl Future<Output = Result<T, E>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        let timer = policy
+            .delay(0)
+            .map(|_| self.mailbox.inner.timer_or_fallback());
+
+        async move {
+            let mut retries = 0;
+
+            loop {
+                let error = match f().await {
+                    Ok(value) => return Ok(value),
+                    Err(error) => error,
+                };
+
+                if retries == policy.retries() {
+                    return Err(error);
+                }
+
+                if let (Some(timer), Some(delay)) = (&timer, policy.delay(retries)) {
+                    timer.sleep(delay).await;
+                }
+
+                retries += 1;
+            }
+        }
+    }
+
+    /// Attach a stream to this actor, which then handles every item of the stream like a message
+    /// sent to it, and receives a [`StreamCompleted`] message once the stream has ended.
+    ///
+    /// The stream is polled on a task of its own, spawned with the
+    /// [`Spawner`](crate::runtime::Spawner) configured for the actor's [`Mailbox`] or, if none is
+    /// configured, on an executor thread shared by all actors. The actor keeps handling other
+    /// messages in the meantime. What happens if the stream yields items faster than the actor handles them is
+    /// determined by `backpressure`. The stream is dropped once the actor stops, or earlier once
+    /// it is detached from the actor by dropping the returned [`StreamHandle`]. The handle can be
+    /// kept in the state of the actor to follow the stream only for a while, or
+    /// [forgotten](StreamHandle::forget) to follow it until it ends.
+    ///
+    /// ```rust
+    /// # use futures_util::stream;
+    /// # use xtra::prelude::*;
+    /// use xtra::stream::{Backpressure, StreamCompleted};
+    ///
+    /// # #[derive(Default)]
+    /// # struct Feed { prices: Vec<u64>, done: bool }
+    /// # impl Actor for Feed { type Stop = (); async fn stopped(self) {} }
+    /// struct Follow(Vec<u64>);
+    ///
+    /// impl Handler<Follow> for Feed {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, Follow(prices): Follow, ctx: &mut Context<Self>) {
+    ///         ctx.attach_stream(stream::iter(prices), Backpressure::Pause)
+    ///             .forget();
+    ///     }
+    /// }
+    ///
+    /// impl Handler<u64> for Feed {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, price: u64, _: &mut Context<Self>) {
+    ///         self.prices.push(price);
+    ///     }
+    /// }
+    ///
+    /// impl Handler<StreamCompleted> for Feed {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: StreamCompleted, _: &mut Context<Self>) {
+    ///         self.done = true;
+    ///     }
+    /// }
+    /// ```
+    pub fn attach_stream<S>(&self, stream: S, backpressure: Backpressure) -> StreamHandle
+    where
+        S: Stream + Send + 'static,
+        S::Item: Send + 'static,
+        A: Handler<S::Item> + Handler<StreamCompleted>,
+    {
+        self.attach_try_stream(StreamExt::map(stream, Ok::<_, Infallible>), backpressure)
+    }
+
+    /// Like [`Context::attach_stream`], but for a stream of results. The stream ends at the first
+    /// error, which the actor receives in [`StreamCompleted::error`].
+    pub fn attach_try_stream<S, T, E>(&self, stream: S, backpressure: Backpressure) -> StreamHandle
+    where
+        S: Stream<Item = Result<T, E>> + Send + 'static,
+        T: Send + 'static,
+        E: Send + 'static,
+        A: Handler<T> + Handler<StreamCompleted<E>>,
+    {
+        let spawner = self.mailbox.spawner_or_fallback();
+        let (handle, task) = stream::forward(self.mailbox.address(), stream, backpressure);
+
+        spawner.spawn(&self.mailbox.task_name("stream"), Box::pin(task));
+
+        handle
+    }
+
+    /// Spawn a background task, e.g. a loop reading from a socket, which is aborted when this actor
+    /// stops.
+    ///
+    /// The task runs on the [`Spawner`](crate::runtime::Spawner) configured for this actor's
+    /// [`Mailbox`] or, if none is configured, on an executor thread shared by all actors. However the actor stops, all of its tasks which are still running are aborted
+    /// before its [`on_stop`](Context::on_stop) callbacks and [`Actor::stopped`] are run, so
+    /// their futures are dropped the next time they would be polled. The returned handle can be
+    /// used to abort the task earlier.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use xtra::prelude::*;
+    /// # use xtra::scoped_task::TaskHandle;
+    /// # struct Connection { reader: Option<TaskHandle> }
+    /// # impl Actor for Connection { type Stop = (); async fn stopped(self) {} }
+    /// struct Connected;
+    ///
+    /// impl Handler<Connected> for Connection {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Connected, ctx: &mut Context<Self>) {
+    ///         let address = ctx.mailbox().address();
+    ///
+    ///         self.reader = Some(ctx.spawn_task(async move {
+    ///             // Read from the socket and forward what was read to `address`.
+    /// #           let _ = address;
+    ///         }));
+    ///     }
+    /// }
+    /// ```
+    pub fn spawn_task<F>(&self, task: F) -> TaskHandle
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let spawner = self.mailbox.spawner_or_fallback();
+        let (handle, task) = scoped_task::abortable(task);
+
+        spawner.spawn(&self.mailbox.task_name("task"), Box::pin(task));
+        self.mailbox.tasks.add(handle.clone());
+
+        handle
+    }
+
+    /// Spawn `child` as a child of this actor, returning its [`Address`](crate::Address).
+    ///
+    /// The child gets a [`Mailbox::new`] and runs on the [`Spawner`](crate::runtime::Spawner)
+    /// configured for this actor's [`Mailbox`], whose [`Timer`](crate::runtime::Timer) it shares
+    /// too. If no spawner is configured, it runs on an executor thread shared by all actors
+    /// instead. It runs independently of its parent, but when the parent stops, it stops all of its
+    /// children like [`Context::stop_all`] and waits for them to have stopped. Only then are the
+    /// parent's [`on_stop`](Context::on_stop) callbacks and [`Actor::stopped`] run, so that
+    /// children never outlive their parent. As children can spawn children of their own, this
+    /// makes up a supervision tree which is stopped from the leaves up.
+    ///
+    /// The parent only tracks its children: it keeps neither them alive nor is it notified when
+    /// they stop on their own.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Worker;
+    /// # impl Actor for Worker { type Stop = (); async fn stopped(self) {} }
+    /// struct Pool {
+    ///     workers: Vec<Address<Worker>>,
+    /// }
+    ///
+    /// impl Actor for Pool {
+    ///     type Stop = ();
+    ///
+    ///     async fn stopped(self) {
+    ///         // All workers have stopped by now.
+    ///     }
+    /// }
+    ///
+    /// struct Grow;
+    ///
+    /// impl Handler<Grow> for Pool {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Grow, ctx: &mut Context<Self>) {
+    ///         self.workers.push(ctx.spawn_child(Worker));
+    ///     }
+    /// }
+    /// ```
+    pub fn spawn_child<B: Actor>(&self, child: B) -> crate::Address<B> {
+        let spawner = self.mailbox.spawner_or_fallback();
+        let (address, mailbox) = Mailbox::<B>::new();
+        let mailbox = mailbox.with_runtime_of(&self.mailbox);
+        let name = crate::runtime::task_name(&child.name(), mailbox.id());
+
+        spawner.spawn(
+            &name,
+            Box::pin(async move {
+                crate::run(mailbox, child).await;
+            }),
+        );
+        self.mailbox.children.add(address.downgrade());
+
+        address
+    }
+
+    /// Register this actor in the global [`Registry`](crate::registry::Registry), so that it can be
+    /// looked up as a [`MessageChannel`] for messages of type `M`.
+    ///
+    /// This is a shorthand for [`Mailbox::register_as`].
+    pub fn register_as<M>(&self)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        self.mailbox.register_as::<M>();
+    }
+
+    /// Register this actor in the global [`Registry`](crate::registry::Registry) under the given
+    /// name.
+    ///
+    /// This is a shorthand for [`Mailbox::register_named_as`].
+    pub fn register_named_as<M>(&self, name: impl Into<Cow<'static, str>>)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        self.mailbox.register_named_as::<M>(name);
+    }
+
+    /// Retry all deferred messages of this actor before receiving the next message from the mailbox.
+    ///
+    /// This is a shorthand for [`Mailbox::recheck_deferred`].
+    pub fn recheck_deferred(&self) {
+        self.mailbox.recheck_deferred();
+    }
+
+    /// The unique identifier of this actor. See [`Address::id`](crate::Address::id).
+    pub fn id(&self) -> ActorId {
+        self.mailbox.id()
+    }
+
     /// Get a reference to the [`Mailbox`] of this actor.
     pub fn mailbox(&self) -> &Mailbox<A> {
         &self.mailbox
     }
+
+    /// Get the channel that the sender of the current message asked to be replied to, if any.
+    ///
+    /// A reply channel is attached by sending the message with [`Address::send_from`](crate::Address::send_from).
+    /// This returns `None` if the message was sent without a reply channel, or if the reply channel
+    /// does not accept messages of type `M`.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Ponger;
+    /// # impl Actor for Ponger { type Stop = (); async fn stopped(self) {} }
+    /// struct Ping;
+    /// struct Pong;
+    ///
+    /// impl Handler<Ping> for Ponger {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Ping, ctx: &mut Context<Self>) {
+    ///         if let Some(sender) = ctx.reply_to::<Pong>() {
+    ///             let _ = sender.send(Pong).detach().await;
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn reply_to<M>(&self) -> Option<&MessageChannel<M, ()>>
+    where
+        M: Send + 'static,
+    {
+        self.reply_to.as_ref()?.downcast_ref()
+    }
+
+    /// Whether the sender of the current message still waits for its reply.
+    ///
+    /// The reply is wanted for as long as the [`Receiver`](crate::Receiver) of the message, such
+    /// as the future returned by [`Address::send`](crate::Address::send), has not been dropped. It
+    /// is never wanted for messages whose reply nobody can wait for, such as those sent with
+    /// [`Address::send_and_forget`](crate::Address::send_and_forget), [`Context::notify`] or
+    /// [`Address::broadcast`](crate::Address::broadcast).
+    ///
+    /// Handlers doing expensive work can use this to skip it once nobody is interested in the
+    /// result. The return value of the handler is then discarded.
+    pub fn reply_wanted(&self) -> bool {
+        self.reply_interest
+            .as_ref()
+            .is_some_and(ReplyInterest::is_wanted)
+    }
+
+    /// Resolve once the reply to the current message is no longer wanted, see
+    /// [`Context::reply_wanted`]. This resolves immediately if it was never wanted.
+    ///
+    /// The future does not borrow the context, so it can be raced against the work of the handler
+    /// to abandon it when the sender goes away.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use futures_util::future::{self, Either};
+    /// # use xtra::prelude::*;
+    /// # struct Database;
+    /// # impl Actor for Database { type Stop = (); async fn stopped(self) {} }
+    /// # async fn run_query() -> Vec<u64> { vec![] }
+    /// struct Query;
+    ///
+    /// impl Handler<Query> for Database {
+    ///     type Return = Option<Vec<u64>>;
+    ///
+    ///     async fn handle(&mut self, _: Query, ctx: &mut Context<Self>) -> Option<Vec<u64>> {
+    ///         let query = std::pin::pin!(run_query());
+    ///
+    ///         match future::select(query, std::pin::pin!(ctx.reply_cancelled())).await {
+    ///             Either::Left((rows, _)) => Some(rows),
+    ///             Either::Right(((), _)) => None,
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn reply_cancelled(&self) -> impl Future<Output = ()> + Send + 'static {
+        let interest = self.reply_interest.clone();
+
+        poll_fn(move |cx| match &interest {
+            Some(interest) => interest.poll_cancelled(cx),
+            None => Poll::Ready(()),
+        })
+    }
+
+    /// Get the correlation id of the current message, if any.
+    ///
+    /// A correlation id is attached explicitly by sending the message with
+    /// [`Address::send_with_correlation`](crate::Address::send_with_correlation). Otherwise, a
+    /// message inherits the correlation id of the message whose handler sent it, which makes it
+    /// possible to trace a request as it flows through several actors. Messages sent from tasks
+    /// spawned by the handler do not inherit it.
+    pub fn correlation_id(&self) -> Option<u128> {
+        self.correlation_id
+    }
+
+    /// The deadline of the timer which queued the current message, if it was scheduled with
+    /// [`Context::notify_after`] or [`Context::notify_debounced`].
+    ///
+    /// This is the instant at which the timer was due, on the clock of the actor's
+    /// [`Timer`](crate::runtime::Timer), not when it fired: a message is only queued once the actor
+    /// waits for its next message, and handled after those queued before it. The deadline is kept
+    /// if the message is requeued.
+    pub fn timer_deadline(&self) -> Option<Instant> {
+        self.timer_deadline
+    }
 }
@@ -0,0 +1,18 @@
+//! Propagation of correlation ids from the message a handler is handling to the messages it sends.
+
+use std::cell::Cell;
+
+thread_local! {
+    /// The correlation id of the message whose handler is currently being polled on this thread.
+    static CURRENT: Cell<Option<u128>> = const { Cell::new(None) };
+}
+
+/// The correlation id which messages created on this thread inherit by default.
+pub fn current() -> Option<u128> {
+    CURRENT.with(Cell::get)
+}
+
+/// Run `f` with `id` as the current correlation id, e.g. while polling a handler.
+pub fn scope<R>(id: Option<u128>, f: impl FnOnce() -> R) -> R {
+    crate::scoped_local::set(&CURRENT, id, f)
+}
@@ -0,0 +1,52 @@
+//! Detection of handlers awaiting a reply from their own actor, either directly or through a cycle
+//! of actors awaiting replies from each other. Such a handler would otherwise wait forever without
+//! any indication of what went wrong.
+//!
+//! Awaiting a reply from the own actor is always detected and resolves to
+//! [`Error::WouldDeadlock`](crate::Error::WouldDeadlock). Cycles are only detected with debug
+//! assertions, as this requires tracking all awaited replies. Release builds use a stub for that.
+
+use std::cell::Cell;
+
+use crate::chan::{self, RefCounter};
+
+#[cfg(debug_assertions)]
+mod detect;
+
+#[cfg(debug_assertions)]
+pub use self::detect::*;
+
+#[cfg(not(debug_assertions))]
+mod stub;
+
+#[cfg(not(debug_assertions))]
+pub use self::stub::*;
+
+thread_local! {
+    /// The actor whose handler is currently being polled on this thread, if any.
+    static CURRENT_ACTOR: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Identifies the actor an address refers to.
+pub fn actor_id<A, Rc: RefCounter>(chan: &chan::Ptr<A, Rc>) -> usize {
+    chan.inner_ptr() as usize
+}
+
+/// Mark the given actor as handling a message on this thread while `f` is running.
+pub fn in_handler<R>(actor: usize, f: impl FnOnce() -> R) -> R {
+    crate::scoped_local::set(&CURRENT_ACTOR, Some(actor), f)
+}
+
+/// The actor whose handler is currently being polled on this thread, if any.
+fn current_actor() -> Option<usize> {
+    CURRENT_ACTOR.with(Cell::get)
+}
+
+/// The actor a reply is awaited from, if there is a single actor on the address. Otherwise,
+/// another actor could handle the message.
+fn single_actor<A, Rc: RefCounter>(chan: &chan::Ptr<A, Rc>) -> Option<usize> {
+    (chan.actor_count() == 1).then(|| actor_id(chan))
+}
+
+/// A reply awaited from within a handler of the same actor, which can never arrive.
+pub struct WouldDeadlock;
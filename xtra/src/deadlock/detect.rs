@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::{current_actor, single_actor, WouldDeadlock};
+use crate::chan::{self, RefCounter};
+use crate::Actor;
+
+/// Looks up the name of an actor. Names are only looked up when reporting a deadlock, as the actor
+/// may not have been started with its name yet when the message is sent.
+type ActorName = Arc<dyn Fn() -> Cow<'static, str> + Send + Sync>;
+
+/// All replies which are currently awaited from within a handler.
+static AWAITING: Mutex<Vec<Edge>> = Mutex::new(Vec::new());
+
+static NEXT_EDGE: AtomicU64 = AtomicU64::new(0);
+
+/// A handler of the actor `from` awaiting a reply from the actor `to`.
+struct Edge {
+    id: u64,
+    from: usize,
+    to: usize,
+    to_name: ActorName,
+}
+
+/// Tracks a reply awaited from the actor a message was sent to.
+pub struct AwaitingReply {
+    /// The actor the reply is awaited from. This is only tracked if there is a single actor on the
+    /// address, as another actor could handle the message otherwise.
+    target: Option<(usize, ActorName)>,
+    edge: Option<u64>,
+}
+
+impl AwaitingReply {
+    pub fn new<A: Actor, Rc: RefCounter>(chan: &chan::Ptr<A, Rc>) -> Self {
+        let target = single_actor(chan).map(|actor| {
+            let weak = chan.to_tx_weak();
+            let name: ActorName = Arc::new(move || weak.name());
+            (actor, name)
+        });
+
+        AwaitingReply { target, edge: None }
+    }
+
+    /// A reply which is not awaited from an actor.
+    pub fn none() -> Self {
+        AwaitingReply {
+            target: None,
+            edge: None,
+        }
+    }
+
+    /// Called before polling for the reply, failing if it is awaited from within a handler of the
+    /// actor it is awaited from.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this is polled from within a handler of an actor which the target is indirectly
+    /// awaiting a reply from.
+    pub fn on_poll(&mut self) -> Result<(), WouldDeadlock> {
+        let (Some((target, target_name)), None) = (&self.target, self.edge) else {
+            return Ok(());
+        };
+        let Some(current) = current_actor() else {
+            return Ok(());
+        };
+
+        if *target == current {
+            return Err(WouldDeadlock);
+        }
+
+        let mut awaiting = AWAITING.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(cycle) = find_path(&awaiting, *target, current) {
+            drop(awaiting);
+
+            let mut names = vec![target_name()];
+            names.extend(cycle.iter().map(|name| name()));
+            let current_name = names.last().unwrap().clone();
+            let chain = names
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .collect::<Vec<_>>()
+                .join(", which is awaiting a reply from ");
+
+            panic!(
+                "Deadlock detected: a handler of `{}` is awaiting a reply from {}",
+                current_name, chain
+            );
+        }
+
+        let id = NEXT_EDGE.fetch_add(1, Ordering::Relaxed);
+        awaiting.push(Edge {
+            id,
+            from: current,
+            to: *target,
+            to_name: target_name.clone(),
+        });
+        self.edge = Some(id);
+
+        Ok(())
+    }
+
+    /// Called once the reply has been received.
+    pub fn done(&mut self) {
+        if let Some(id) = self.edge.take() {
+            AWAITING
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .retain(|edge| edge.id != id);
+        }
+    }
+}
+
+impl Drop for AwaitingReply {
+    fn drop(&mut self) {
+        self.done();
+    }
+}
+
+/// Find the names of the actors on a chain of awaited replies leading from `from` to `to`, or an
+/// empty chain if they are the same actor.
+fn find_path(awaiting: &[Edge], from: usize, to: usize) -> Option<Vec<ActorName>> {
+    let mut paths = vec![(from, Vec::new())];
+    let mut visited = vec![from];
+
+    while let Some((actor, path)) = paths.pop() {
+        if actor == to {
+            return Some(path);
+        }
+
+        for edge in awaiting.iter().filter(|edge| edge.from == actor) {
+            if !visited.contains(&edge.to) {
+                visited.push(edge.to);
+
+                let mut path = path.clone();
+                path.push(edge.to_name.clone());
+                paths.push((edge.to, path));
+            }
+        }
+    }
+
+    None
+}
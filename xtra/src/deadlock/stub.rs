@@ -0,0 +1,28 @@
+use super::{current_actor, single_actor, WouldDeadlock};
+use crate::chan::{self, RefCounter};
+use crate::Actor;
+
+pub struct AwaitingReply {
+    target: Option<usize>,
+}
+
+impl AwaitingReply {
+    pub fn new<A: Actor, Rc: RefCounter>(chan: &chan::Ptr<A, Rc>) -> Self {
+        AwaitingReply {
+            target: single_actor(chan),
+        }
+    }
+
+    pub fn none() -> Self {
+        AwaitingReply { target: None }
+    }
+
+    pub fn on_poll(&mut self) -> Result<(), WouldDeadlock> {
+        match self.target {
+            Some(target) if current_actor() == Some(target) => Err(WouldDeadlock),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn done(&mut self) {}
+}
@@ -9,26 +9,85 @@ use futures_core::future::BoxFuture;
 use futures_util::FutureExt;
 
 use crate::chan::ActorMessage;
-use crate::envelope::Shutdown;
+use crate::envelope::{ReturningEnvelope, Shutdown};
 use crate::instrumentation::Span;
 use crate::mailbox::Mailbox;
-use crate::Message;
+use crate::{Actor, Handler, Message};
 
 impl<A> Message<A> {
     /// Dispatches this message to the given actor.
     pub fn dispatch_to(self, actor: &mut A) -> DispatchFuture<'_, A> {
-        DispatchFuture::new(
-            self.inner,
-            actor,
-            Mailbox::from_parts(self.channel, self.broadcast_mailbox),
-        )
+        DispatchFuture::new(self.inner, actor, self.mailbox)
+    }
+}
+
+impl<A: Actor> Message<A> {
+    /// Dispatches this message to the given actor without boxing the handler's future if it is a
+    /// message of type `M` sent to one actor. See [`run_monomorphic`](crate::run_monomorphic).
+    pub(crate) async fn dispatch_monomorphic<M>(self, actor: &mut A) -> ControlFlow<()>
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        match self.inner {
+            ActorMessage::ToOneActor(envelope)
+                if envelope
+                    .as_any()
+                    .is::<ReturningEnvelope<A, M, <A as Handler<M>>::Return>>() =>
+            {
+                let envelope = envelope
+                    .into_any()
+                    .downcast::<ReturningEnvelope<A, M, <A as Handler<M>>::Return>>()
+                    .expect("type to have been checked");
+                let (fut, _span) = envelope.handle_unboxed(actor, self.mailbox);
+
+                fut.await
+            }
+            inner => DispatchFuture::new(inner, actor, self.mailbox).await,
+        }
+    }
+}
+
+/// The strategy with which an event loop dispatches messages to the actor.
+pub(crate) trait Dispatch<A> {
+    fn dispatch(
+        message: Message<A>,
+        actor: &mut A,
+    ) -> impl Future<Output = ControlFlow<()>> + Send + '_;
+}
+
+/// Dispatch all messages through [`Message::dispatch_to`].
+pub(crate) struct Dynamic;
+
+impl<A: Actor> Dispatch<A> for Dynamic {
+    fn dispatch(
+        message: Message<A>,
+        actor: &mut A,
+    ) -> impl Future<Output = ControlFlow<()>> + Send + '_ {
+        message.dispatch_to(actor)
+    }
+}
+
+/// Dispatch messages of type `M` through [`Message::dispatch_monomorphic`].
+pub(crate) struct Monomorphic<M>(PhantomData<fn(M)>);
+
+impl<A, M> Dispatch<A> for Monomorphic<M>
+where
+    A: Handler<M>,
+    M: Send + 'static,
+{
+    fn dispatch(
+        message: Message<A>,
+        actor: &mut A,
+    ) -> impl Future<Output = ControlFlow<()>> + Send + '_ {
+        message.dispatch_monomorphic::<M>(actor)
     }
 }
 
 /// Represents the dispatch of a message to an actor.
 ///
 /// This future is **not** cancellation-safe. Dropping it will interrupt the execution of
-/// [`Handler::handle`](crate::Handler::handle) which may leave the actor in an inconsistent state.
+/// [`Handler::handle`] which may leave the actor in an inconsistent state.
 #[must_use = "Futures do nothing unless polled"]
 pub struct DispatchFuture<'a, A> {
     state: State<'a, A>,
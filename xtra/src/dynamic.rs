@@ -0,0 +1,204 @@
+//! Sending messages whose type is only known at runtime, e.g. because they are produced by a
+//! scripting layer or deserialized from a type-tagged format.
+//!
+//! A [`DynAddress`] is built from an [`Address`] by listing the message types the actor accepts.
+//! Boxed messages sent through it are downcast to the matching type and sent like any other
+//! message, so no `match` over all message types is needed at the call site.
+//!
+//! ```rust
+//! # use std::any::Any;
+//! # use xtra::prelude::*;
+//! use xtra::dynamic::{DynAddress, DynSendError};
+//!
+//! # #[derive(Default)]
+//! # struct Scripted { log: Vec<String> }
+//! # impl Actor for Scripted { type Stop = (); async fn stopped(self) {} }
+//! struct Print(String);
+//! struct Clear;
+//!
+//! impl Handler<Print> for Scripted {
+//!     type Return = ();
+//!
+//!     async fn handle(&mut self, Print(line): Print, _: &mut Context<Self>) {
+//!         self.log.push(line);
+//!     }
+//! }
+//!
+//! impl Handler<Clear> for Scripted {
+//!     type Return = ();
+//!
+//!     async fn handle(&mut self, _: Clear, _: &mut Context<Self>) {
+//!         self.log.clear();
+//!     }
+//! }
+//!
+//! # #[cfg(feature = "smol")]
+//! smol::block_on(async {
+//!     let address = xtra::spawn_smol(Scripted::default(), Mailbox::unbounded());
+//!     let dynamic = DynAddress::builder(address)
+//!         .accept::<Print>()
+//!         .accept::<Clear>()
+//!         .build();
+//!
+//!     let message: Box<dyn Any + Send> = Box::new(Print("hello".to_owned()));
+//!     dynamic.send_boxed(message).await.unwrap();
+//!
+//!     let unknown = dynamic.send_boxed(Box::new(42u32)).await;
+//!     assert!(matches!(unknown, Err(DynSendError::UnknownType(_))));
+//! })
+//! ```
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::message_channel::MessageChannel;
+use crate::refcount::{Either, RefCounter, Strong};
+use crate::send_future::Forget;
+use crate::{ActorErasedSending, ActorId, Address, Error, Handler, SendFuture};
+
+/// Sends a boxed message of a single type to the actor.
+type SendBoxed =
+    Arc<dyn Fn(Box<dyn Any + Send>) -> SendFuture<ActorErasedSending, Forget> + Send + Sync>;
+
+/// An address to which messages can be sent as `Box<dyn Any + Send>`. See the
+/// [module documentation](self) for an example.
+///
+/// Cloning a [`DynAddress`] is cheap. Like an [`Address`], a strong [`DynAddress`] keeps the actor
+/// alive.
+pub struct DynAddress<Rc = Strong> {
+    id: ActorId,
+    senders: Arc<HashMap<TypeId, (&'static str, SendBoxed)>>,
+    _rc: std::marker::PhantomData<fn() -> Rc>,
+}
+
+impl<Rc: RefCounter> DynAddress<Rc> {
+    /// Start building a [`DynAddress`] for the actor of the given address.
+    pub fn builder<A>(address: Address<A, Rc>) -> DynAddressBuilder<A, Rc> {
+        DynAddressBuilder {
+            address,
+            senders: HashMap::new(),
+        }
+    }
+
+    /// The unique identifier of the actor. See [`Address::id`].
+    pub fn id(&self) -> ActorId {
+        self.id
+    }
+
+    /// Whether the actor accepts messages of the given type through this address.
+    pub fn accepts(&self, message_type: TypeId) -> bool {
+        self.senders.contains_key(&message_type)
+    }
+
+    /// Send a boxed message to the actor, discarding the [`Return`](Handler::Return) value of the
+    /// handler like [`Address::send_and_forget`].
+    ///
+    /// The message is downcast to the type it was boxed as. If the actor does not accept messages
+    /// of that type, this fails with [`DynSendError::UnknownType`], which returns the message.
+    pub async fn send_boxed(&self, message: Box<dyn Any + Send>) -> Result<(), DynSendError> {
+        // The type of the message rather than the type of the box it is in.
+        let message_type = (*message).type_id();
+
+        let Some((_, send)) = self.senders.get(&message_type) else {
+            return Err(DynSendError::UnknownType(message));
+        };
+
+        send(message).await.map_err(DynSendError::Send)
+    }
+}
+
+impl<Rc> Clone for DynAddress<Rc> {
+    fn clone(&self) -> Self {
+        DynAddress {
+            id: self.id,
+            senders: self.senders.clone(),
+            _rc: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<Rc> fmt::Debug for DynAddress<Rc> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut accepts = self
+            .senders
+            .values()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        accepts.sort_unstable();
+
+        f.debug_struct("DynAddress")
+            .field("id", &self.id)
+            .field("accepts", &accepts)
+            .finish()
+    }
+}
+
+/// Builds a [`DynAddress`] by listing the message types the actor accepts.
+pub struct DynAddressBuilder<A, Rc: RefCounter> {
+    address: Address<A, Rc>,
+    senders: HashMap<TypeId, (&'static str, SendBoxed)>,
+}
+
+impl<A, Rc> DynAddressBuilder<A, Rc>
+where
+    Rc: RefCounter + Into<Either>,
+{
+    /// Accept boxed messages of type `M`. Accepting the same type again has no further effect.
+    pub fn accept<M>(mut self) -> Self
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        let channel = MessageChannel::<M, A::Return, Rc>::new(self.address.clone());
+        let send: SendBoxed = Arc::new(move |message| {
+            let message = message
+                .downcast::<M>()
+                .expect("messages to be looked up by their type");
+
+            channel.send_and_forget(*message)
+        });
+
+        self.senders
+            .insert(TypeId::of::<M>(), (std::any::type_name::<M>(), send));
+        self
+    }
+
+    /// Build the [`DynAddress`].
+    pub fn build(self) -> DynAddress<Rc> {
+        DynAddress {
+            id: self.address.id(),
+            senders: Arc::new(self.senders),
+            _rc: std::marker::PhantomData,
+        }
+    }
+}
+
+/// The error returned by [`DynAddress::send_boxed`].
+pub enum DynSendError {
+    /// The actor does not accept messages of this type. The message is handed back.
+    UnknownType(Box<dyn Any + Send>),
+    /// The message could not be sent to the actor.
+    Send(Error),
+}
+
+impl fmt::Debug for DynSendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DynSendError::UnknownType(_) => f.write_str("UnknownType(..)"),
+            DynSendError::Send(error) => f.debug_tuple("Send").field(error).finish(),
+        }
+    }
+}
+
+impl fmt::Display for DynSendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DynSendError::UnknownType(_) => f.write_str("Actor does not accept this message type"),
+            DynSendError::Send(error) => fmt::Display::fmt(error, f),
+        }
+    }
+}
+
+impl std::error::Error for DynSendError {}
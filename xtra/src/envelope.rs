@@ -1,15 +1,26 @@
+use std::any::Any;
+use std::future::Future;
 use std::marker::PhantomData;
 use std::ops::ControlFlow;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::task::Poll;
+use std::time::{Duration, Instant};
 
 use catty::{Receiver, Sender};
 use futures_core::future::BoxFuture;
+use futures_util::future::{self, Either};
 use futures_util::FutureExt;
 
-use crate::chan::{HasPriority, MessageToAll, MessageToOne, Priority};
+use crate::chan::{ActorMessage, HasPriority, MessageToAll, MessageToOne, Priority};
 use crate::context::Context;
-use crate::instrumentation::{Instrumentation, Span};
-use crate::{Actor, Handler, Mailbox};
+use crate::instrumentation::{Instrumentation, SlowHandlerWatchdog, Span};
+use crate::keyed::Key;
+use crate::metrics::HandlerMetrics;
+use crate::reply::{ReplyInterest, WantReply};
+use crate::{correlation, deadlock, Actor, ActorId, Error, Handler, Mailbox};
 
 /// A message envelope is a struct that encapsulates a message and its return channel sender (if applicable).
 /// Firstly, this allows us to be generic over returning and non-returning messages (as all use the
@@ -24,7 +35,21 @@ pub trait MessageEnvelope: HasPriority + Send {
     fn set_priority(&mut self, new_priority: u32);
 
     /// Starts the instrumentation of this message request. This will create the request span.
-    fn start_span(&mut self);
+    fn start_span(&mut self, actor_name: &str, actor_id: ActorId);
+
+    /// Get a reference to this envelope as [`Any`] to check its concrete type.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Convert this envelope into [`Any`] to downcast it to its concrete type.
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+
+    /// The type name of the message in this envelope.
+    fn message_type(&self) -> &'static str;
+
+    /// The key with which the message was sent by [`Address::send_keyed`](crate::Address::send_keyed), if any.
+    fn key(&self) -> Option<&Key> {
+        None
+    }
 
     /// Handle the message inside of the box by calling the relevant [`Handler::handle`] method,
     /// returning its result over a return channel if applicable. This also takes `Box<Self>` as the
@@ -37,33 +62,169 @@ pub trait MessageEnvelope: HasPriority + Send {
     ) -> (BoxFuture<ControlFlow<(), ()>>, Span);
 }
 
-/// An envelope that returns a result from a message. Constructed by the `AddressExt::do_send` method.
+/// An envelope that returns a result from a message. Constructed by [`Address::send`](crate::Address::send).
 pub struct ReturningEnvelope<A, M, R> {
     message: M,
-    result_sender: Sender<R>,
-    priority: u32,
+    result_sender: Sender<Result<R, Error>>,
+    interest: ReplyInterest,
+    reply_to: Option<Box<dyn Any + Send>>,
+    correlation_id: Option<u128>,
+    /// The deadline of the timer which queued the message, see [`Context::timer_deadline`].
+    deadline: Option<Instant>,
+    priority: Priority,
     phantom: PhantomData<for<'a> fn(&'a A)>,
     instrumentation: Instrumentation,
 }
 
 impl<A, M, R: Send + 'static> ReturningEnvelope<A, M, R> {
-    pub fn new(message: M, priority: u32) -> (Self, Receiver<R>) {
+    pub fn new(message: M, priority: u32) -> (Self, Receiver<Result<R, Error>>) {
         let (tx, rx) = catty::oneshot();
         let envelope = ReturningEnvelope {
             message,
             result_sender: tx,
-            priority,
+            interest: ReplyInterest::default(),
+            reply_to: None,
+            correlation_id: correlation::current(),
+            deadline: None,
+            priority: Priority::Valued(priority),
             phantom: PhantomData,
             instrumentation: Instrumentation::empty(),
         };
 
         (envelope, rx)
     }
+
+    /// Mark the reply to this message as wanted until the returned guard is dropped, see
+    /// [`Context::reply_wanted`].
+    pub fn want_reply(&self) -> WantReply {
+        self.interest.want()
+    }
+
+    /// Attach the channel of the sender to this envelope, so that the handler can reply to it via
+    /// [`Context::reply_to`].
+    pub fn with_reply_to(mut self, reply_to: Box<dyn Any + Send>) -> Self {
+        self.reply_to = Some(reply_to);
+        self
+    }
+
+    /// Set the correlation id of this envelope, instead of inheriting it from the handler which is
+    /// sending it.
+    pub fn with_correlation_id(mut self, correlation_id: u128) -> Self {
+        self.correlation_id = Some(correlation_id);
+        self
+    }
+
+    /// Mark the message as queued by a timer which was due at the given deadline.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Mark the message as sent by the actor to itself, to be received ahead of other messages.
+    pub fn into_notification(mut self) -> Self {
+        self.priority = Priority::Notification;
+        self
+    }
+    /// Take the message back out of an envelope which was not handled.
+    pub fn into_message(self) -> M {
+        self.message
+    }
+}
+
+impl<A, M, R> ReturningEnvelope<A, M, R>
+where
+    A: Handler<M, Return = R>,
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    /// Like [`MessageEnvelope::handle`], but returns the concrete future instead of boxing it.
+    pub fn handle_unboxed(
+        self: Box<Self>,
+        act: &mut A,
+        mailbox: Mailbox<A>,
+    ) -> (impl Future<Output = ControlFlow<()>> + Send + '_, Span) {
+        if !act.can_handle(&self.message) {
+            mailbox.defer(ActorMessage::ToOneActor(self));
+            return (
+                Either::Left(future::ready(ControlFlow::Continue(()))),
+                Span::none(),
+            );
+        }
+
+        if let Err(rejected) = act.validate(&self.message) {
+            // We don't actually care if the receiver is listening
+            let _ = self.result_sender.send(Ok(rejected));
+            return (
+                Either::Left(future::ready(ControlFlow::Continue(()))),
+                Span::none(),
+            );
+        }
+
+        mailbox.on_handled();
+        let metrics = HandlerMetrics::new::<A, M>(&mailbox);
+        let watchdog = SlowHandlerWatchdog::new::<A, M>(&mailbox);
+
+        let Self {
+            message,
+            result_sender,
+            interest,
+            reply_to,
+            correlation_id,
+            deadline,
+            priority,
+            instrumentation,
+            ..
+        } = *self;
+
+        let requeue_into = mailbox.same_actor();
+        let fut = handle_message(
+            act,
+            message,
+            mailbox,
+            reply_to,
+            Some(interest.clone()),
+            correlation_id,
+            deadline,
+        );
+        let (fut, span) = instrumentation.apply::<_>(watchdog.watch(metrics.measure(fut)));
+
+        let fut = fut.map(move |(r, flow, requeue)| {
+            match requeue {
+                // The sender keeps waiting for the result of handling the requeued message.
+                Some(Requeue {
+                    message,
+                    delay,
+                    reply_to,
+                }) => {
+                    let envelope = ReturningEnvelope {
+                        message,
+                        result_sender,
+                        interest,
+                        reply_to,
+                        correlation_id,
+                        deadline,
+                        priority,
+                        phantom: PhantomData,
+                        instrumentation: Instrumentation::empty(),
+                    };
+                    requeue_into.requeue(Box::new(envelope), delay);
+                }
+                None => {
+                    // We don't actually care if the receiver is listening
+                    let _ = result_sender.send(r.ok_or(Error::ActorStoppedDuringHandling));
+                }
+            }
+
+            flow
+        });
+
+        (Either::Right(fut), span)
+    }
 }
 
 impl<A, M, R> HasPriority for ReturningEnvelope<A, M, R> {
     fn priority(&self) -> Priority {
-        Priority::Valued(self.priority)
+        self.priority
     }
 }
 
@@ -82,12 +243,24 @@ where
     type Actor = A;
 
     fn set_priority(&mut self, new_priority: u32) {
-        self.priority = new_priority;
+        self.priority = Priority::Valued(new_priority);
     }
 
-    fn start_span(&mut self) {
+    fn start_span(&mut self, actor_name: &str, actor_id: ActorId) {
         assert!(self.instrumentation.is_parent_none());
-        self.instrumentation = Instrumentation::started::<A, M>();
+        self.instrumentation = Instrumentation::started::<A, M>(actor_name, actor_id);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+
+    fn message_type(&self) -> &'static str {
+        std::any::type_name::<M>()
     }
 
     fn handle(
@@ -95,36 +268,196 @@ where
         act: &mut Self::Actor,
         mailbox: Mailbox<Self::Actor>,
     ) -> (BoxFuture<ControlFlow<(), ()>>, Span) {
-        let Self {
-            message,
-            result_sender,
-            instrumentation,
-            ..
-        } = *self;
+        let (fut, span) = self.handle_unboxed(act, mailbox);
+
+        (Box::pin(fut), span)
+    }
+}
+
+/// An envelope which carries several messages to be handled one after another, without other
+/// messages in between. Constructed by [`BufferedAddress`](crate::buffered::BufferedAddress).
+pub struct BatchEnvelope<A, M, R> {
+    envelopes: Vec<ReturningEnvelope<A, M, R>>,
+    priority: u32,
+}
+
+impl<A, M, R> BatchEnvelope<A, M, R>
+where
+    R: Send + 'static,
+{
+    pub fn new(messages: Vec<M>) -> Self {
+        BatchEnvelope {
+            // Nobody waits for the return values of batched messages.
+            envelopes: messages
+                .into_iter()
+                .map(|message| ReturningEnvelope::new(message, 0).0)
+                .collect(),
+            priority: 0,
+        }
+    }
+}
+
+impl<A, M, R> HasPriority for BatchEnvelope<A, M, R> {
+    fn priority(&self) -> Priority {
+        Priority::Valued(self.priority)
+    }
+}
 
+impl<A, M, R> MessageEnvelope for BatchEnvelope<A, M, R>
+where
+    A: Handler<M, Return = R>,
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    type Actor = A;
+
+    fn set_priority(&mut self, new_priority: u32) {
+        self.priority = new_priority;
+    }
+
+    fn start_span(&mut self, actor_name: &str, actor_id: ActorId) {
+        for envelope in &mut self.envelopes {
+            envelope.start_span(actor_name, actor_id);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+
+    fn message_type(&self) -> &'static str {
+        std::any::type_name::<M>()
+    }
+
+    fn handle(
+        self: Box<Self>,
+        act: &mut Self::Actor,
+        mailbox: Mailbox<Self::Actor>,
+    ) -> (BoxFuture<'_, ControlFlow<(), ()>>, Span) {
         let fut = async move {
-            let mut ctx = Context {
-                running: true,
-                mailbox,
-            };
-            let r = act.handle(message, &mut ctx).await;
-
-            if ctx.running {
-                (r, ControlFlow::Continue(()))
-            } else {
-                (r, ControlFlow::Break(()))
+            for envelope in self.envelopes {
+                // Each message is instrumented like a message which is sent on its own.
+                let (fut, _) = Box::new(envelope).handle_unboxed(act, mailbox.same_actor());
+
+                // The rest of the batch is dropped along with the actor.
+                if let ControlFlow::Break(()) = fut.await {
+                    return ControlFlow::Break(());
+                }
             }
+
+            ControlFlow::Continue(())
+        };
+
+        (Box::pin(fut), Span::none())
+    }
+}
+
+/// A closure run against an actor by an [`ExecEnvelope`], which may borrow the actor and its
+/// context in the future it returns.
+pub type ExecFn<A, R> =
+    Box<dyn for<'a> FnOnce(&'a mut A, &'a mut Context<A>) -> BoxFuture<'a, R> + Send>;
+
+/// Stands in for the type of the message of an [`ExecEnvelope`] in spans and metrics.
+pub struct Exec;
+
+/// An envelope which runs a closure against the actor in place of a handler. Constructed by
+/// [`Address::exec`](crate::Address::exec).
+pub struct ExecEnvelope<A, R> {
+    f: ExecFn<A, R>,
+    result_sender: Sender<Result<R, Error>>,
+    interest: ReplyInterest,
+    correlation_id: Option<u128>,
+    priority: u32,
+    instrumentation: Instrumentation,
+}
+
+impl<A, R: Send + 'static> ExecEnvelope<A, R> {
+    pub fn new(f: ExecFn<A, R>) -> (Self, Receiver<Result<R, Error>>) {
+        let (tx, rx) = catty::oneshot();
+        let envelope = ExecEnvelope {
+            f,
+            result_sender: tx,
+            interest: ReplyInterest::default(),
+            correlation_id: correlation::current(),
+            priority: 0,
+            instrumentation: Instrumentation::empty(),
         };
 
-        let (fut, span) = instrumentation.apply::<_>(fut);
+        (envelope, rx)
+    }
+
+    /// Like [`ReturningEnvelope::want_reply`].
+    pub fn want_reply(&self) -> WantReply {
+        self.interest.want()
+    }
+}
 
-        let fut = Box::pin(fut.map(move |(r, flow)| {
+impl<A, R> HasPriority for ExecEnvelope<A, R> {
+    fn priority(&self) -> Priority {
+        Priority::Valued(self.priority)
+    }
+}
+
+impl<A, R> MessageEnvelope for ExecEnvelope<A, R>
+where
+    A: Actor,
+    R: Send + 'static,
+{
+    type Actor = A;
+
+    fn set_priority(&mut self, new_priority: u32) {
+        self.priority = new_priority;
+    }
+
+    fn start_span(&mut self, actor_name: &str, actor_id: ActorId) {
+        assert!(self.instrumentation.is_parent_none());
+        self.instrumentation = Instrumentation::started::<A, Exec>(actor_name, actor_id);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+
+    fn message_type(&self) -> &'static str {
+        std::any::type_name::<Exec>()
+    }
+
+    fn handle(
+        self: Box<Self>,
+        act: &mut Self::Actor,
+        mailbox: Mailbox<Self::Actor>,
+    ) -> (BoxFuture<'_, ControlFlow<(), ()>>, Span) {
+        mailbox.on_handled();
+        let metrics = HandlerMetrics::new::<A, Exec>(&mailbox);
+        let watchdog = SlowHandlerWatchdog::new::<A, Exec>(&mailbox);
+
+        let Self {
+            f,
+            result_sender,
+            interest,
+            correlation_id,
+            instrumentation,
+            ..
+        } = *self;
+
+        let fut = exec_closure(act, f, mailbox, interest, correlation_id);
+        let (fut, span) = instrumentation.apply::<_>(watchdog.watch(metrics.measure(fut)));
+        let fut = fut.map(move |(r, flow)| {
             // We don't actually care if the receiver is listening
-            let _ = result_sender.send(r);
+            let _ = result_sender.send(r.ok_or(Error::ActorStoppedDuringHandling));
+
             flow
-        }));
+        });
 
-        (fut, span)
+        (Box::pin(fut), span)
     }
 }
 
@@ -136,7 +469,10 @@ pub trait BroadcastEnvelope: HasPriority + Send + Sync {
 
     /// Starts the instrumentation of this message request, if this arc is unique. This will create
     /// the request span
-    fn start_span(&mut self);
+    fn start_span(&mut self, actor_name: &str, actor_id: ActorId);
+
+    /// The type name of the message in this envelope.
+    fn message_type(&self) -> &'static str;
 
     fn handle(
         self: Arc<Self>,
@@ -153,6 +489,7 @@ impl<A> HasPriority for MessageToAll<A> {
 
 pub struct BroadcastEnvelopeConcrete<A, M> {
     message: M,
+    correlation_id: Option<u128>,
     priority: u32,
     phantom: PhantomData<for<'a> fn(&'a A)>,
     instrumentation: Instrumentation,
@@ -162,6 +499,7 @@ impl<A: Actor, M> BroadcastEnvelopeConcrete<A, M> {
     pub fn new(message: M, priority: u32) -> Self {
         BroadcastEnvelopeConcrete {
             message,
+            correlation_id: correlation::current(),
             priority,
             phantom: PhantomData,
             instrumentation: Instrumentation::empty(),
@@ -180,9 +518,13 @@ where
         self.priority = new_priority;
     }
 
-    fn start_span(&mut self) {
+    fn start_span(&mut self, actor_name: &str, actor_id: ActorId) {
         assert!(self.instrumentation.is_parent_none());
-        self.instrumentation = Instrumentation::started::<A, M>();
+        self.instrumentation = Instrumentation::started::<A, M>(actor_name, actor_id);
+    }
+
+    fn message_type(&self) -> &'static str {
+        std::any::type_name::<M>()
     }
 
     fn handle(
@@ -190,22 +532,41 @@ where
         act: &mut Self::Actor,
         mailbox: Mailbox<Self::Actor>,
     ) -> (BoxFuture<ControlFlow<(), ()>>, Span) {
+        if !act.can_handle(&self.message) {
+            mailbox.defer(ActorMessage::ToAllActors(self));
+            return deferred();
+        }
+
+        // Nobody receives the result of a broadcast, so a rejected message is just skipped.
+        if act.validate(&self.message).is_err() {
+            return (Box::pin(async { ControlFlow::Continue(()) }), Span::none());
+        }
+
+        mailbox.on_handled();
+        let metrics = HandlerMetrics::new::<A, M>(&mailbox);
+        let watchdog = SlowHandlerWatchdog::new::<A, M>(&mailbox);
+
         let (msg, instrumentation) = (self.message.clone(), self.instrumentation.clone());
+        let (correlation_id, priority) = (self.correlation_id, self.priority);
         drop(self); // Drop ASAP to end the message waiting for actor span
-        let fut = async move {
-            let mut ctx = Context {
-                running: true,
-                mailbox,
-            };
-            act.handle(msg, &mut ctx).await;
-
-            if ctx.running {
-                ControlFlow::Continue(())
-            } else {
-                ControlFlow::Break(())
-            }
-        };
-        let (fut, span) = instrumentation.apply::<_>(fut);
+        let requeue_into = mailbox.same_actor();
+        let fut = handle_message(act, msg, mailbox, None, None, correlation_id, None).map(
+            move |(_, flow, requeue)| {
+                if let Some(Requeue { message, delay, .. }) = requeue {
+                    let envelope = BroadcastEnvelopeConcrete {
+                        message,
+                        correlation_id,
+                        priority,
+                        phantom: PhantomData,
+                        instrumentation: Instrumentation::empty(),
+                    };
+                    requeue_into.requeue_broadcast(Arc::new(envelope), delay);
+                }
+
+                flow
+            },
+        );
+        let (fut, span) = instrumentation.apply::<_>(watchdog.watch(metrics.measure(fut)));
         (Box::pin(fut), span)
     }
 }
@@ -216,6 +577,166 @@ impl<A, M> HasPriority for BroadcastEnvelopeConcrete<A, M> {
     }
 }
 
+/// A message which the handler put back into the mailbox with [`Context::requeue`], to be wrapped
+/// in an envelope like the original one.
+struct Requeue<M> {
+    message: M,
+    delay: Option<Duration>,
+    reply_to: Option<Box<dyn Any + Send>>,
+}
+
+/// Handle a message with a new [`Context`], returning the result of the handler, whether the
+/// actor should keep running and the message if the handler requeued it.
+///
+/// If the handler suspends after the actor was stopped with [`Context::stop_self`], it is
+/// cancelled and no result is returned.
+///
+/// Afterwards, [`Actor::after_handle`] is called, even if the handler panicked, in which case the
+/// panic is resumed once it has completed. While either is polled, the correlation id of the
+/// message is the current one, so that any messages sent from them inherit it.
+async fn handle_message<A, M>(
+    act: &mut A,
+    message: M,
+    mailbox: Mailbox<A>,
+    reply_to: Option<Box<dyn Any + Send>>,
+    interest: Option<ReplyInterest>,
+    correlation_id: Option<u128>,
+    deadline: Option<Instant>,
+) -> (Option<A::Return>, ControlFlow<()>, Option<Requeue<M>>)
+where
+    A: Handler<M>,
+    M: Send + 'static,
+{
+    let stop_requested = mailbox.stop_requested.clone();
+    let actor = deadlock::actor_id(&mailbox.inner);
+    let mut ctx = Context::new(mailbox, reply_to);
+    ctx.reply_interest = interest;
+    ctx.correlation_id = correlation_id;
+    ctx.timer_deadline = deadline;
+
+    let r = poll_handler(
+        act.handle(message, &mut ctx),
+        &stop_requested,
+        actor,
+        correlation_id,
+    )
+    .await;
+    after_handle(act, &mut ctx, actor, correlation_id).await;
+    let r = r.unwrap_or_else(|panic| panic::resume_unwind(panic));
+
+    let requeue = match ctx.requeued.take() {
+        Some((envelope, delay)) if envelope.as_any().is::<ReturningEnvelope<A, M, A::Return>>() => {
+            let envelope = envelope
+                .into_any()
+                .downcast::<ReturningEnvelope<A, M, A::Return>>()
+                .expect("envelope to be of the checked type");
+
+            Some(Requeue {
+                message: envelope.message,
+                delay,
+                reply_to: ctx.reply_to.take(),
+            })
+        }
+        // A message of another type is sent like any other message to the actor itself.
+        Some((envelope, delay)) => {
+            ctx.mailbox.requeue(envelope, delay);
+            None
+        }
+        None => None,
+    };
+
+    if ctx.running {
+        (r, ControlFlow::Continue(()), requeue)
+    } else {
+        (r, ControlFlow::Break(()), requeue)
+    }
+}
+
+/// Like [`handle_message`], but run a closure against the actor instead of its handler.
+async fn exec_closure<A, R>(
+    act: &mut A,
+    f: ExecFn<A, R>,
+    mailbox: Mailbox<A>,
+    interest: ReplyInterest,
+    correlation_id: Option<u128>,
+) -> (Option<R>, ControlFlow<()>)
+where
+    A: Actor,
+{
+    let stop_requested = mailbox.stop_requested.clone();
+    let actor = deadlock::actor_id(&mailbox.inner);
+    let mut ctx = Context::new(mailbox, None);
+    ctx.reply_interest = Some(interest);
+    ctx.correlation_id = correlation_id;
+
+    let r = poll_handler(f(act, &mut ctx), &stop_requested, actor, correlation_id).await;
+    after_handle(act, &mut ctx, actor, correlation_id).await;
+    let r = r.unwrap_or_else(|panic| panic::resume_unwind(panic));
+
+    // There is no message to put back, so a requeued message is sent like any other.
+    if let Some((envelope, delay)) = ctx.requeued.take() {
+        ctx.mailbox.requeue(envelope, delay);
+    }
+
+    if ctx.running {
+        (r, ControlFlow::Continue(()))
+    } else {
+        (r, ControlFlow::Break(()))
+    }
+}
+
+/// Poll the future of a handler until it completes, or until the actor is stopped with
+/// [`Context::stop_self_now`] while it is pending, in which case it is cancelled.
+///
+/// If the handler panics, the panic is caught and returned as the error, so that
+/// [`Actor::after_handle`] can run before it is resumed.
+async fn poll_handler<R>(
+    handling: impl Future<Output = R>,
+    stop_requested: &AtomicBool,
+    actor: usize,
+    correlation_id: Option<u128>,
+) -> std::thread::Result<Option<R>> {
+    let mut handling = pin!(handling);
+
+    future::poll_fn(|cx| {
+        let poll = panic::catch_unwind(AssertUnwindSafe(|| {
+            correlation::scope(correlation_id, || {
+                deadlock::in_handler(actor, || handling.as_mut().poll(cx))
+            })
+        }));
+
+        match poll {
+            Ok(Poll::Ready(r)) => Poll::Ready(Ok(Some(r))),
+            Ok(Poll::Pending) if stop_requested.load(Ordering::Relaxed) => Poll::Ready(Ok(None)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(panic) => Poll::Ready(Err(panic)),
+        }
+    })
+    .await
+}
+
+/// Run [`Actor::after_handle`] once a handler has returned or panicked.
+async fn after_handle<A: Actor>(
+    act: &mut A,
+    ctx: &mut Context<A>,
+    actor: usize,
+    correlation_id: Option<u128>,
+) {
+    let mut after_handle = pin!(act.after_handle(ctx));
+
+    future::poll_fn(|cx| {
+        correlation::scope(correlation_id, || {
+            deadlock::in_handler(actor, || after_handle.as_mut().poll(cx))
+        })
+    })
+    .await;
+}
+
+/// The result of handling a message which has been deferred.
+fn deferred() -> (BoxFuture<'static, ControlFlow<()>>, Span) {
+    (Box::pin(async { ControlFlow::Continue(()) }), Span::none())
+}
+
 #[derive(Copy, Clone, Default)]
 pub struct Shutdown<A>(PhantomData<for<'a> fn(&'a A)>);
 
@@ -246,7 +767,11 @@ where
     fn set_priority(&mut self, _: u32) {}
 
     // This message is not instrumented
-    fn start_span(&mut self) {}
+    fn start_span(&mut self, _: &str, _: ActorId) {}
+
+    fn message_type(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
 
     fn handle(
         self: Arc<Self>,
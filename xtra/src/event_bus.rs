@@ -0,0 +1,213 @@
+//! A typed publish/subscribe event bus, through which events can be fanned out to all actors
+//! interested in them without the publisher having to know about the subscribers.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use crate::message_channel::MessageChannel;
+use crate::refcount::Weak;
+use crate::{Handler, Mailbox};
+
+/// An event bus through which actors can subscribe to and publish events of any type.
+///
+/// Subscriptions are held through weak [`MessageChannel`]s, so subscribing does not keep an actor
+/// alive. Once a subscriber stops, it is removed from the bus the next time an event of that type
+/// is published.
+///
+/// Cloning an [`EventBus`] is cheap and yields a handle to the same bus.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// # use xtra::event_bus::EventBus;
+/// #[derive(Clone)]
+/// struct ConfigChanged;
+///
+/// struct Server {
+///     bus: EventBus,
+///     reloads: usize,
+/// }
+///
+/// impl Actor for Server {
+///     type Stop = ();
+///
+///     async fn started(&mut self, mailbox: &Mailbox<Self>) -> Result<(), Self::Stop> {
+///         self.bus.subscribe::<ConfigChanged, _>(mailbox);
+///         Ok(())
+///     }
+///
+///     async fn stopped(self) {}
+/// }
+///
+/// impl Handler<ConfigChanged> for Server {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: ConfigChanged, _: &mut Context<Self>) {
+///         self.reloads += 1;
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let bus = EventBus::new();
+///     let (addr, mailbox) = Mailbox::unbounded();
+///     smol::spawn(xtra::run(mailbox, Server { bus: bus.clone(), reloads: 0 })).detach();
+///     # while bus.subscriber_count::<ConfigChanged>() == 0 { smol::future::yield_now().await }
+///
+///     assert_eq!(bus.publish(ConfigChanged).await, 1);
+/// })
+/// ```
+#[derive(Clone, Default)]
+pub struct EventBus {
+    topics: Arc<Mutex<HashMap<TypeId, Box<dyn Any + Send>>>>,
+}
+
+impl EventBus {
+    /// Create a new, empty [`EventBus`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe the actor of the given [`Mailbox`] to events of type `E`.
+    ///
+    /// Within a handler, the mailbox can be obtained through [`Context::mailbox`](crate::Context::mailbox).
+    /// Subscribing an actor which is already subscribed to `E` has no effect.
+    pub fn subscribe<E, A>(&self, mailbox: &Mailbox<A>)
+    where
+        E: Clone + Send + 'static,
+        A: Handler<E, Return = ()>,
+    {
+        self.subscribe_channel(MessageChannel::new(mailbox.address()));
+    }
+
+    /// Subscribe the actor behind the given channel to events of type `E`.
+    ///
+    /// Subscribing a channel whose actor is already subscribed to `E` has no effect.
+    pub fn subscribe_channel<E>(&self, channel: MessageChannel<E, (), Weak>)
+    where
+        E: Clone + Send + 'static,
+    {
+        self.with_subscribers::<E, _>(|subscribers| {
+            if !subscribers.iter().any(|s| s.same_actor(&channel)) {
+                subscribers.push(channel);
+            }
+        })
+    }
+
+    /// Unsubscribe the actor of the given [`Mailbox`] from events of type `E`.
+    pub fn unsubscribe<E, A>(&self, mailbox: &Mailbox<A>)
+    where
+        E: Clone + Send + 'static,
+        A: Handler<E, Return = ()>,
+    {
+        let channel = MessageChannel::<E, (), Weak>::new(mailbox.address());
+
+        self.with_subscribers::<E, _>(|subscribers| {
+            subscribers.retain(|s| !s.same_actor(&channel));
+        })
+    }
+
+    /// Publish an event to all actors subscribed to events of type `E`, returning the number of
+    /// actors it was delivered to.
+    ///
+    /// This waits until the event has been put into the mailbox of every subscriber, but not for
+    /// the subscribers to handle it. Subscribers which have stopped are removed from the bus.
+    pub async fn publish<E>(&self, event: E) -> usize
+    where
+        E: Clone + Send + 'static,
+    {
+        let subscribers = self.with_subscribers::<E, _>(|subscribers| {
+            subscribers.retain(MessageChannel::is_connected);
+            subscribers.clone()
+        });
+
+        let mut delivered = 0;
+
+        for subscriber in subscribers {
+            if subscriber.send(event.clone()).detach().await.is_ok() {
+                delivered += 1;
+            }
+        }
+
+        delivered
+    }
+
+    /// Publish an event to all actors subscribed to events of type `Arc<E>`, returning the number
+    /// of actors it was delivered to.
+    ///
+    /// Where [`EventBus::publish`] clones the event for every subscriber, this wraps it in an
+    /// [`Arc`] once and delivers the same [`Arc`] to each subscriber, so large events such as
+    /// snapshots are not copied. Subscribers subscribe to and implement [`Handler`] for `Arc<E>`:
+    ///
+    /// ```rust
+    /// # use std::sync::Arc;
+    /// # use xtra::prelude::*;
+    /// # use xtra::event_bus::EventBus;
+    /// # struct Replica { state: Vec<u8> }
+    /// # impl Actor for Replica { type Stop = (); async fn stopped(self) {} }
+    /// struct Snapshot(Vec<u8>);
+    ///
+    /// impl Handler<Arc<Snapshot>> for Replica {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, snapshot: Arc<Snapshot>, _: &mut Context<Self>) {
+    ///         // Only the last subscriber to handle the snapshot gets to take it without copying.
+    ///         self.state = Arc::try_unwrap(snapshot).map_or_else(|s| s.0.clone(), |s| s.0);
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let bus = EventBus::new();
+    ///     let (addr, mailbox) = Mailbox::unbounded();
+    ///     bus.subscribe::<Arc<Snapshot>, _>(&mailbox);
+    ///     smol::spawn(xtra::run(mailbox, Replica { state: Vec::new() })).detach();
+    ///
+    ///     assert_eq!(bus.publish_shared(Snapshot(vec![0; 1 << 20])).await, 1);
+    /// })
+    /// ```
+    ///
+    /// Handlers which need ownership of the event can take it with [`Arc::try_unwrap`], which only
+    /// succeeds for the last subscriber holding it, and otherwise clone what they need.
+    pub async fn publish_shared<E>(&self, event: E) -> usize
+    where
+        E: Send + Sync + 'static,
+    {
+        self.publish(Arc::new(event)).await
+    }
+
+    /// The number of actors subscribed to events of type `E`, including those which have stopped
+    /// but not been removed yet.
+    pub fn subscriber_count<E>(&self) -> usize
+    where
+        E: Clone + Send + 'static,
+    {
+        self.with_subscribers::<E, _>(|subscribers| subscribers.len())
+    }
+
+    fn with_subscribers<E, R>(
+        &self,
+        f: impl FnOnce(&mut Vec<MessageChannel<E, (), Weak>>) -> R,
+    ) -> R
+    where
+        E: Send + 'static,
+    {
+        let mut topics = self.topics.lock().unwrap();
+        let subscribers = topics
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(Vec::<MessageChannel<E, (), Weak>>::new()))
+            .downcast_mut()
+            .expect("topics to be keyed by the type id of their event");
+
+        f(subscribers)
+    }
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("topics", &self.topics.lock().unwrap().len())
+            .finish()
+    }
+}
@@ -0,0 +1,120 @@
+//! Inspecting the state of running actors, e.g. for debugging them in production.
+//!
+//! An actor opts in by implementing [`Inspect`], after which a snapshot of its state can be
+//! requested with [`Address::inspect`](crate::Address::inspect). The request is sent like any
+//! other message, so the snapshot is taken in between two handlers and never observes a handler's
+//! intermediate state.
+//!
+//! ```rust
+//! # use xtra::prelude::*;
+//! use xtra::inspect::{Inspect, State};
+//!
+//! #[derive(Debug, Default)]
+//! struct Counter {
+//!     count: u32,
+//! }
+//! # impl Actor for Counter { type Stop = (); async fn stopped(self) {} }
+//!
+//! impl Inspect for Counter {
+//!     fn inspect(&self) -> State {
+//!         State::debug(self)
+//!     }
+//! }
+//!
+//! # #[cfg(feature = "smol")]
+//! smol::block_on(async {
+//!     let addr = xtra::spawn_smol(Counter::default(), Mailbox::unbounded());
+//!     let report = addr.inspect().await.unwrap();
+//!
+//!     assert_eq!(report.id(), addr.id());
+//!     assert_eq!(report.state().to_string(), "Counter {\n    count: 0,\n}");
+//! })
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+
+use crate::{Actor, ActorId, Context, Handler};
+
+/// An actor whose state can be inspected with [`Address::inspect`](crate::Address::inspect).
+pub trait Inspect: Actor {
+    /// Take a snapshot of the state of this actor.
+    ///
+    /// Use [`State::debug`] to describe the state by the [`Debug`](fmt::Debug) representation of
+    /// the actor.
+    fn inspect(&self) -> State;
+}
+
+/// A snapshot of the state of an actor, as returned by [`Inspect::inspect`].
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum State {
+    /// The state described as text, e.g. by [`State::debug`].
+    Text(String),
+    /// The state as a JSON value, for tools which process it further.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    Json(serde_json::Value),
+}
+
+impl State {
+    /// Describe the state by the pretty-printed [`Debug`](fmt::Debug) representation of `value`.
+    pub fn debug(value: &impl fmt::Debug) -> Self {
+        State::Text(format!("{:#?}", value))
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Text(text) => f.write_str(text),
+            #[cfg(feature = "json")]
+            State::Json(json) => write!(f, "{:#}", json),
+        }
+    }
+}
+
+/// The result of [`Address::inspect`](crate::Address::inspect).
+#[derive(Clone, Debug, PartialEq)]
+pub struct InspectReport {
+    id: ActorId,
+    name: Cow<'static, str>,
+    state: State,
+}
+
+impl InspectReport {
+    /// The identifier of the inspected actor. See [`Address::id`](crate::Address::id).
+    pub fn id(&self) -> ActorId {
+        self.id
+    }
+
+    /// The name of the inspected actor. See [`Address::name`](crate::Address::name).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The state of the inspected actor.
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    /// Take the state of the inspected actor out of this report.
+    pub fn into_state(self) -> State {
+        self.state
+    }
+}
+
+/// The message sent by [`Address::inspect`](crate::Address::inspect).
+pub(crate) struct Request;
+
+impl<A: Inspect> Handler<Request> for A {
+    type Return = InspectReport;
+
+    async fn handle(&mut self, _: Request, ctx: &mut Context<Self>) -> InspectReport {
+        InspectReport {
+            id: ctx.id(),
+            name: ctx.mailbox().inner.name(),
+            state: self.inspect(),
+        }
+    }
+}
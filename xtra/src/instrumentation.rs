@@ -9,3 +9,41 @@ mod stub;
 
 #[cfg(not(feature = "instrumentation"))]
 pub use self::stub::*;
+
+/// The reason for which an actor stopped.
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(not(feature = "instrumentation"), allow(dead_code))]
+pub enum StopReason {
+    /// [`Actor::started`](crate::Actor::started) returned an error.
+    StartFailed,
+    /// The actor called [`Context::stop_self`](crate::Context::stop_self).
+    StopSelf,
+    /// An actor called [`Context::stop_all`](crate::Context::stop_all).
+    StopAll,
+    /// All strong addresses to the actor were dropped.
+    Disconnected,
+}
+
+impl StopReason {
+    /// The reason for an actor stopping after handling a message, where `is_shutdown` tells
+    /// whether that message was an instruction to shut down.
+    pub fn after_message<A>(is_shutdown: bool, mailbox: &crate::Mailbox<A>) -> Self {
+        if !is_shutdown {
+            StopReason::StopSelf
+        } else if mailbox.inner.sender_count() == 0 {
+            StopReason::Disconnected
+        } else {
+            StopReason::StopAll
+        }
+    }
+
+    #[cfg_attr(not(feature = "instrumentation"), allow(dead_code))]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StopReason::StartFailed => "start_failed",
+            StopReason::StopSelf => "stop_self",
+            StopReason::StopAll => "stop_all",
+            StopReason::Disconnected => "disconnected",
+        }
+    }
+}
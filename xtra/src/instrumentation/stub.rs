@@ -1,5 +1,7 @@
 use std::future::Future;
 
+use crate::ActorId;
+
 #[derive(Clone)]
 pub struct Instrumentation {}
 
@@ -26,7 +28,7 @@ impl Instrumentation {
     }
 
     #[allow(unknown_lints, clippy::extra_unused_type_parameters)] // Needs to be consistent with non-stub impl.
-    pub fn started<A, M>() -> Self {
+    pub fn started<A, M>(_actor_name: &str, _actor_id: ActorId) -> Self {
         Self::empty()
     }
 
@@ -41,3 +43,29 @@ impl Instrumentation {
         (fut, Span(()))
     }
 }
+
+#[allow(unknown_lints, clippy::extra_unused_type_parameters)] // Needs to be consistent with non-stub impl.
+pub fn actor_span<A>(_actor_name: &str, _actor_id: ActorId) -> Span {
+    Span(())
+}
+
+pub fn instrument_actor<F>(_span: Span, fut: F) -> F {
+    fut
+}
+
+pub fn actor_started() {}
+
+pub fn actor_stopped(_reason: super::StopReason) {}
+
+pub struct SlowHandlerWatchdog {}
+
+impl SlowHandlerWatchdog {
+    #[allow(unknown_lints, clippy::extra_unused_type_parameters)] // Needs to be consistent with non-stub impl.
+    pub fn new<A, M>(_mailbox: &crate::Mailbox<A>) -> Self {
+        SlowHandlerWatchdog {}
+    }
+
+    pub fn watch<F>(self, fut: F) -> F {
+        fut
+    }
+}
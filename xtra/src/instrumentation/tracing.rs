@@ -1,7 +1,17 @@
+use std::borrow::Cow;
 use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
+use futures_core::future::BoxFuture;
 pub use tracing::Span;
 
+use super::StopReason;
+use crate::runtime::Timer;
+use crate::ActorId;
+
 #[derive(Clone)]
 pub struct Instrumentation {
     pub parent: Span,
@@ -16,10 +26,12 @@ impl Instrumentation {
         }
     }
 
-    pub fn started<A, M>() -> Self {
+    pub fn started<A, M>(actor_name: &str, actor_id: ActorId) -> Self {
         let parent = tracing::debug_span!(
             "xtra_actor_request",
             actor_type = %std::any::type_name::<A>(),
+            actor_name = %actor_name,
+            actor_id = actor_id.as_u64(),
             message_type = %std::any::type_name::<M>(),
         )
         .or_current();
@@ -45,6 +57,7 @@ impl Instrumentation {
             tracing::debug_span!("xtra_message_handler", interrupted = tracing::field::Empty)
                 .or_current()
         });
+        executing.in_scope(|| tracing::debug!("Handling message"));
 
         (
             tracing::Instrument::instrument(fut, executing.clone()),
@@ -52,3 +65,174 @@ impl Instrumentation {
         )
     }
 }
+
+/// Create the span which covers the entire lifetime of an actor.
+pub fn actor_span<A>(actor_name: &str, actor_id: ActorId) -> Span {
+    tracing::info_span!(
+        "xtra_actor",
+        actor_type = %std::any::type_name::<A>(),
+        actor_name = %actor_name,
+        actor_id = actor_id.as_u64(),
+    )
+}
+
+/// Run the event loop of an actor within its span, reporting if it panics.
+pub fn instrument_actor<F>(span: Span, fut: F) -> impl Future<Output = F::Output>
+where
+    F: Future,
+{
+    tracing::Instrument::instrument(ReportPanic { inner: fut }, span)
+}
+
+pub fn actor_started() {
+    tracing::info!("Actor started");
+}
+
+pub fn actor_stopped(reason: StopReason) {
+    tracing::info!(reason = reason.as_str(), "Actor stopped");
+}
+
+pin_project_lite::pin_project! {
+    struct ReportPanic<F> {
+        #[pin]
+        inner: F,
+    }
+}
+
+impl<F: Future> Future for ReportPanic<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // The guard is dropped while unwinding if polling the event loop panics.
+        let _guard = PanicGuard;
+        self.project().inner.poll(cx)
+    }
+}
+
+struct PanicGuard;
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            tracing::error!("Actor panicked");
+        }
+    }
+}
+
+/// Warns about a handler which takes longer than the threshold configured with
+/// [`Mailbox::warn_if_handler_exceeds`](crate::Mailbox::warn_if_handler_exceeds).
+pub struct SlowHandlerWatchdog(Option<Watch>);
+
+struct Watch {
+    threshold: Duration,
+    timer: Arc<dyn Timer>,
+    sleep: Option<BoxFuture<'static, ()>>,
+    /// When the handler was first polled, measured with `timer`.
+    started: Option<Instant>,
+    warned: bool,
+    actor_name: Cow<'static, str>,
+    message_type: &'static str,
+}
+
+impl SlowHandlerWatchdog {
+    pub fn new<A, M>(mailbox: &crate::Mailbox<A>) -> Self {
+        let watch = mailbox
+            .slow_handler_threshold()
+            .map(|(threshold, timer)| Watch {
+                threshold,
+                timer,
+                sleep: None,
+                started: None,
+                warned: false,
+                actor_name: mailbox.inner.name(),
+                message_type: std::any::type_name::<M>(),
+            });
+
+        SlowHandlerWatchdog(watch)
+    }
+
+    /// Warn once the threshold has elapsed before `fut` completes, and again every time the
+    /// threshold elapses after that, without cancelling `fut`.
+    pub fn watch<F>(self, fut: F) -> impl Future<Output = F::Output>
+    where
+        F: Future,
+    {
+        Watched {
+            inner: fut,
+            watch: self.0,
+        }
+    }
+}
+
+pin_project_lite::pin_project! {
+    struct Watched<F> {
+        #[pin]
+        inner: F,
+        watch: Option<Watch>,
+    }
+}
+
+impl<F: Future> Future for Watched<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+
+        // The timer is started before the handler is first polled, so that a handler which blocks
+        // the thread is reported too and the elapsed time includes its first poll.
+        if let Some(watch) = this.watch.as_mut().filter(|watch| watch.started.is_none()) {
+            watch.started = Some(watch.timer.now());
+            watch.sleep = Some(watch.timer.sleep(watch.threshold));
+        }
+
+        if let Poll::Ready(output) = this.inner.poll(cx) {
+            if let Some(watch) = this.watch.as_ref().filter(|watch| !watch.warned) {
+                let elapsed = watch.elapsed();
+
+                if elapsed >= watch.threshold {
+                    tracing::warn!(
+                        actor_name = %watch.actor_name,
+                        message_type = %watch.message_type,
+                        elapsed = ?elapsed,
+                        "Handler took longer than the threshold",
+                    );
+                }
+            }
+
+            return Poll::Ready(output);
+        }
+
+        let Some(watch) = this.watch else {
+            return Poll::Pending;
+        };
+
+        loop {
+            let sleep = watch
+                .sleep
+                .get_or_insert_with(|| watch.timer.sleep(watch.threshold));
+
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            watch.sleep = None;
+            watch.warned = true;
+
+            tracing::warn!(
+                actor_name = %watch.actor_name,
+                message_type = %watch.message_type,
+                elapsed = ?watch.elapsed(),
+                "Handler is still running",
+            );
+        }
+    }
+}
+
+impl Watch {
+    /// The time which has passed since the handler was first polled.
+    fn elapsed(&self) -> Duration {
+        self.started.map_or(Duration::ZERO, |started| {
+            self.timer.now().saturating_duration_since(started)
+        })
+    }
+}
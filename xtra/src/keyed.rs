@@ -0,0 +1,165 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use futures_core::future::BoxFuture;
+
+use crate::chan::{HasPriority, MessageToOne, Priority};
+use crate::envelope::MessageEnvelope;
+use crate::instrumentation::Span;
+use crate::{ActorId, Mailbox};
+
+/// The key of a message sent with [`Address::send_keyed`](crate::Address::send_keyed), with its
+/// type erased. Keys of different types are never equal.
+#[derive(Clone)]
+pub struct Key(Arc<dyn DynKey>);
+
+impl Key {
+    pub fn new<K>(key: K) -> Self
+    where
+        K: Hash + Eq + Send + Sync + 'static,
+    {
+        Key(Arc::new(key))
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        (*self.0).eq_key(&*other.0)
+    }
+}
+
+impl Eq for Key {}
+
+impl Hash for Key {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (*self.0).hash_key(state)
+    }
+}
+
+trait DynKey: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+
+    fn eq_key(&self, other: &dyn DynKey) -> bool;
+
+    fn hash_key(&self, state: &mut dyn Hasher);
+}
+
+impl<K> DynKey for K
+where
+    K: Hash + Eq + Send + Sync + 'static,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn eq_key(&self, other: &dyn DynKey) -> bool {
+        other.as_any().downcast_ref::<K>() == Some(self)
+    }
+
+    fn hash_key(&self, mut state: &mut dyn Hasher) {
+        TypeId::of::<K>().hash(&mut state);
+        self.hash(&mut state);
+    }
+}
+
+/// The number of keyed messages per key which have been sent but not handled yet, so that the
+/// queues of the channel only need to be searched for a key which is pending.
+#[derive(Default)]
+pub struct Keys(spin::Mutex<HashMap<Key, usize>>);
+
+impl Keys {
+    pub fn contains(&self, key: &Key) -> bool {
+        self.0.lock().contains_key(key)
+    }
+
+    /// Count a message with the given key as pending until the returned guard is dropped.
+    pub fn insert(self: &Arc<Self>, key: Key) -> KeyGuard {
+        *self.0.lock().entry(key.clone()).or_default() += 1;
+
+        KeyGuard {
+            key,
+            keys: self.clone(),
+        }
+    }
+}
+
+pub struct KeyGuard {
+    key: Key,
+    keys: Arc<Keys>,
+}
+
+impl Drop for KeyGuard {
+    fn drop(&mut self) {
+        let mut keys = self.keys.0.lock();
+
+        if let Some(count) = keys.get_mut(&self.key) {
+            *count -= 1;
+
+            if *count == 0 {
+                keys.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// An envelope which carries a message sent with a key, so that it can be taken out of the
+/// channel by its key until the actor starts handling it.
+pub struct KeyedEnvelope<A> {
+    envelope: MessageToOne<A>,
+    guard: KeyGuard,
+}
+
+impl<A> KeyedEnvelope<A> {
+    pub fn new(envelope: MessageToOne<A>, guard: KeyGuard) -> Self {
+        KeyedEnvelope { envelope, guard }
+    }
+}
+
+impl<A> HasPriority for KeyedEnvelope<A> {
+    fn priority(&self) -> Priority {
+        self.envelope.priority()
+    }
+}
+
+impl<A> MessageEnvelope for KeyedEnvelope<A> {
+    type Actor = A;
+
+    fn set_priority(&mut self, new_priority: u32) {
+        self.envelope.set_priority(new_priority);
+    }
+
+    fn start_span(&mut self, actor_name: &str, actor_id: ActorId) {
+        self.envelope.start_span(actor_name, actor_id);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self.envelope.as_any()
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self.envelope.into_any()
+    }
+
+    fn message_type(&self) -> &'static str {
+        self.envelope.message_type()
+    }
+
+    fn key(&self) -> Option<&Key> {
+        Some(&self.guard.key)
+    }
+
+    fn handle(
+        self: Box<Self>,
+        act: &mut Self::Actor,
+        mailbox: Mailbox<Self::Actor>,
+    ) -> (BoxFuture<'_, std::ops::ControlFlow<(), ()>>, Span) {
+        let KeyedEnvelope { envelope, guard } = *self;
+
+        // Once the actor handles the message, it can no longer be replaced or cancelled.
+        drop(guard);
+
+        envelope.handle(act, mailbox)
+    }
+}
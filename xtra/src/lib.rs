@@ -3,35 +3,80 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 #![deny(unsafe_code, missing_docs)]
 
+use std::borrow::Cow;
 use std::fmt;
 use std::future::Future;
 use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicU64, Ordering};
 
+/// The oneshot channel through which replies are received, see [`Address::send_raw`].
+#[doc(no_inline)]
+pub use catty;
 use futures_util::future::Either;
 use futures_util::{future, FutureExt};
 
 pub use self::address::{Address, WeakAddress};
+pub use self::chan::{MailboxBacking, MailboxOrder};
 pub use self::context::Context;
 pub use self::mailbox::Mailbox;
+pub use self::restart::run_restarting;
+pub use self::scope::scope;
 pub use self::scoped_task::scoped;
 pub use self::send_future::{ActorErasedSending, ActorNamedSending, Receiver, SendFuture};
+#[cfg(feature = "signal")]
+pub use self::signal::on_shutdown_signal;
 #[allow(unused_imports)]
 pub use self::spawn::*; // Star export so we don't have to write `cfg` attributes here.
+pub use self::timers::TimerId;
 
 pub mod address;
+pub mod buffered;
 mod chan;
+mod children;
+mod coalesce;
 mod context;
+mod correlation;
+mod deadlock;
 mod dispatch_future;
+pub mod dynamic;
 mod envelope;
+pub mod event_bus;
+pub mod inspect;
 mod instrumentation;
+mod keyed;
 mod mailbox;
 pub mod message_channel;
+mod metrics;
+pub mod monitor;
+mod permits;
+pub mod rate_limit;
+pub mod recipients;
 mod recv_future;
+pub mod registry;
+#[cfg(feature = "remote")]
+#[cfg_attr(docsrs, doc(cfg(feature = "remote")))]
+pub mod remote;
+mod reply;
+pub mod reply_stream;
+pub mod restart;
+pub mod retry;
+pub mod runtime;
+pub mod scope;
+mod scoped_local;
 /// This module contains a way to scope a future to the lifetime of an actor, stopping it before it
 /// completes if the actor it is associated with stops too.
 pub mod scoped_task;
 mod send_future;
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+pub mod service;
+pub mod shutdown;
+pub mod signal;
 mod spawn;
+pub mod stream;
+pub mod subscription;
+pub mod test;
+mod timers;
 
 /// Commonly used types from xtra
 pub mod prelude {
@@ -48,6 +93,49 @@ pub mod refcount {
     pub use crate::chan::{RefCounter, TxEither as Either, TxStrong as Strong, TxWeak as Weak};
 }
 
+/// Implements [`Handler`] for each handler method of an impl block, so that an actor handling many
+/// message types does not need a separate `impl Handler<M>` for each.
+///
+/// A handler method is an `async fn` which takes `&mut self`, the message and optionally the
+/// [`Context`]. Its return type becomes the [`Return`](Handler::Return) type of the handler for the
+/// type of its message. Other items of the impl block are left alone.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// #[derive(xtra::Actor)]
+/// struct Counter(u64);
+///
+/// struct Increment(u64);
+/// struct Get;
+/// struct Reset;
+///
+/// #[xtra::handlers]
+/// impl Counter {
+///     async fn increment(&mut self, Increment(by): Increment) {
+///         self.0 += by;
+///     }
+///
+///     async fn get(&mut self, _: Get) -> u64 {
+///         self.0
+///     }
+///
+///     async fn reset(&mut self, _: Reset, ctx: &mut Context<Self>) -> Result<u64, ()> {
+///         ctx.stop_self();
+///         Ok(std::mem::take(&mut self.0))
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let address = xtra::spawn_smol(Counter(0), Mailbox::unbounded());
+///
+///     address.send(Increment(2)).await.unwrap();
+///     assert_eq!(address.send(Get).await, Ok(2));
+///     assert_eq!(address.send(Reset).await, Ok(Ok(2)));
+/// })
+/// ```
+#[cfg(feature = "macros")]
+pub use macros::handlers;
 /// Provides a default implementation of the [`Actor`] trait for the given type with a [`Stop`](Actor::Stop) type of `()` and empty lifecycle functions.
 ///
 /// The [`Actor`] custom derive takes away some boilerplate for a standard actor:
@@ -81,6 +169,8 @@ pub mod refcount {
 #[cfg(feature = "macros")]
 pub use macros::Actor;
 
+use crate::dispatch_future::{Dispatch, Dynamic, Monomorphic};
+use crate::instrumentation::StopReason;
 use crate::recv_future::Message;
 
 /// Defines that an [`Actor`] can handle a given message `M`.
@@ -115,11 +205,167 @@ pub trait Handler<M>: Actor {
     type Return: Send + 'static;
 
     /// Handle a given message, returning its result.
+    ///
+    /// This is usually implemented as an `async fn`, so the returned future is not boxed by the
+    /// handler. [`run`] boxes it once per message to dispatch messages of all types dynamically,
+    /// which [`run_monomorphic`] avoids for messages of one type.
     fn handle(
         &mut self,
         message: M,
         ctx: &mut Context<Self>,
     ) -> impl Future<Output = Self::Return> + Send;
+
+    /// Whether the actor is currently in a state to handle the given message.
+    ///
+    /// If this returns `false`, the message is not handled but deferred: it is stashed away and
+    /// retried after the actor has handled another message (which may have changed its state), or
+    /// after [`Context::recheck_deferred`] has been called. Deferred messages are retried in the
+    /// order they were received and before any other message in the mailbox, regardless of
+    /// priority.
+    ///
+    /// For a [bounded](Mailbox::bounded) mailbox, the stash is bounded by its capacity. If a
+    /// message is deferred while the stash is full, it is dropped and the sender receives
+    /// [`Error::Interrupted`]. For an [unbounded](Mailbox::unbounded) mailbox, the stash is
+    /// unbounded too, so an actor which never becomes able to handle a message keeps all of them.
+    /// Deferred messages which are still stashed when the actor stops are dropped in the same way.
+    ///
+    /// Defaults to `true`, i.e. every message is handled as soon as it is received.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # #[derive(Default)] struct Connection { connected: bool }
+    /// # impl Actor for Connection { type Stop = (); async fn stopped(self) {} }
+    /// struct Connected;
+    /// struct Request;
+    ///
+    /// impl Handler<Connected> for Connection {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Connected, _: &mut Context<Self>) {
+    ///         self.connected = true;
+    ///     }
+    /// }
+    ///
+    /// impl Handler<Request> for Connection {
+    ///     type Return = &'static str;
+    ///
+    ///     fn can_handle(&self, _: &Request) -> bool {
+    ///         self.connected
+    ///     }
+    ///
+    ///     async fn handle(&mut self, _: Request, _: &mut Context<Self>) -> &'static str {
+    ///         "response"
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let addr = xtra::spawn_smol(Connection::default(), Mailbox::unbounded());
+    ///
+    ///     let request = addr.send(Request); // Deferred until the connection is established.
+    ///     addr.send(Connected).detach().await.unwrap();
+    ///
+    ///     assert_eq!(request.await, Ok("response"));
+    /// })
+    /// ```
+    #[allow(unused_variables)]
+    fn can_handle(&self, message: &M) -> bool {
+        true
+    }
+
+    /// Check the message before it is handled, e.g. to authorize or validate it in one place.
+    ///
+    /// If this returns `Err`, [`Handler::handle`] is not called and the sender receives the
+    /// contained value as the result of the message instead. Broadcast messages which are rejected
+    /// are skipped. This is called on the actor's task right before the message would be handled,
+    /// after [`Handler::can_handle`], so messages are still handled or rejected in the order in
+    /// which they are received.
+    ///
+    /// Defaults to `Ok(())`, i.e. every message is handled.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Bank { balance: u64 }
+    /// # impl Actor for Bank { type Stop = (); async fn stopped(self) {} }
+    /// struct Withdraw(u64);
+    ///
+    /// impl Handler<Withdraw> for Bank {
+    ///     type Return = Result<u64, &'static str>;
+    ///
+    ///     fn validate(&self, Withdraw(amount): &Withdraw) -> Result<(), Self::Return> {
+    ///         if *amount > self.balance {
+    ///             return Err(Err("insufficient funds"));
+    ///         }
+    ///
+    ///         Ok(())
+    ///     }
+    ///
+    ///     async fn handle(&mut self, Withdraw(amount): Withdraw, _: &mut Context<Self>) -> Self::Return {
+    ///         self.balance -= amount;
+    ///         Ok(self.balance)
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let addr = xtra::spawn_smol(Bank { balance: 10 }, Mailbox::unbounded());
+    ///
+    ///     assert_eq!(addr.send(Withdraw(20)).await, Ok(Err("insufficient funds")));
+    ///     assert_eq!(addr.send(Withdraw(5)).await, Ok(Ok(5)));
+    /// })
+    /// ```
+    #[allow(unused_variables)]
+    fn validate(&self, message: &M) -> Result<(), Self::Return> {
+        Ok(())
+    }
+}
+
+/// Fail to compile unless the actor implements [`Handler`] for every one of the listed message
+/// types, e.g. to catch a message which was added to a protocol without a handler for it.
+///
+/// The check happens entirely at compile time and generates no code which runs. The actor must be
+/// a concrete type.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// # struct Door;
+/// # impl Actor for Door { type Stop = (); async fn stopped(self) {} }
+/// struct Open;
+/// struct Close;
+///
+/// impl Handler<Open> for Door {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: Open, _: &mut Context<Self>) {}
+/// }
+///
+/// impl Handler<Close> for Door {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: Close, _: &mut Context<Self>) {}
+/// }
+///
+/// xtra::assert_impl_handler!(Door, [Open, Close]);
+/// ```
+///
+/// Listing a message type without a handler is a compile error:
+///
+/// ```compile_fail
+/// # use xtra::prelude::*;
+/// # struct Door;
+/// # impl Actor for Door { type Stop = (); async fn stopped(self) {} }
+/// struct Lock;
+///
+/// xtra::assert_impl_handler!(Door, [Lock]);
+/// ```
+#[macro_export]
+macro_rules! assert_impl_handler {
+    ($actor:ty, [$($message:ty),+ $(,)?]) => {
+        const _: fn() = || {
+            fn assert_impl_handler<A: $crate::Handler<M>, M>() {}
+            $(assert_impl_handler::<$actor, $message>();)*
+        };
+    };
 }
 
 /// An actor which can handle message one at a time. Actors can only be
@@ -176,6 +422,44 @@ pub trait Actor: 'static + Send + Sized {
     /// Value returned from the actor when [`Actor::stopped`] is called.
     type Stop: Send + 'static;
 
+    /// The capacity of the actor's mailbox when it is created with [`Mailbox::new`], or `None` for
+    /// an unbounded mailbox.
+    ///
+    /// This keeps the capacity close to the definition of actors which always want a bounded
+    /// mailbox. Creating the mailbox explicitly with [`Mailbox::bounded`] or [`Mailbox::unbounded`]
+    /// overrides it. Defaults to `None`.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// struct Worker;
+    ///
+    /// impl Actor for Worker {
+    ///     type Stop = ();
+    ///     const MAILBOX_CAPACITY: Option<usize> = Some(16);
+    ///
+    ///     async fn stopped(self) {}
+    /// }
+    ///
+    /// let (address, _mailbox) = Mailbox::<Worker>::new();
+    /// assert_eq!(address.capacity(), Some(16));
+    ///
+    /// let (address, _mailbox) = Mailbox::<Worker>::bounded(4);
+    /// assert_eq!(address.capacity(), Some(4));
+    /// ```
+    const MAILBOX_CAPACITY: Option<usize> = None;
+
+    /// The name of this actor, used for diagnostics such as [`Address::name`] and tracing spans.
+    ///
+    /// This defaults to the name of the actor's type. It can be overridden to include
+    /// instance-specific information, such as `"Connection#42"`, which is useful to tell apart many
+    /// instances of the same actor type.
+    ///
+    /// The name is captured once by [`run`] when the actor is started. If several actors are running
+    /// on the same address, the name of the actor that was started last is used.
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(std::any::type_name::<Self>())
+    }
+
     /// Called as soon as the actor has been started.
     #[allow(unused_variables)]
     fn started(
@@ -185,6 +469,63 @@ pub trait Actor: 'static + Send + Sized {
         async { Ok(()) }
     }
 
+    /// Called after each message has been handled, regardless of its type. This can be used for
+    /// cross-cutting logic such as committing a transaction or publishing a change event.
+    ///
+    /// This is also called if the handler was cancelled because the actor was stopped with
+    /// [`Context::stop_self`], or if it panicked, in which case the panic is resumed once this has
+    /// completed. It is not called for messages which were deferred by [`Handler::can_handle`].
+    /// The sender of the message receives its reply only after this has completed. Messages sent
+    /// from here inherit the correlation id of the message which was handled, and the given
+    /// [`Context`] is the one the handler was called with, so that the actor can e.g. stop itself
+    /// from here.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// #[derive(Default)]
+    /// struct Store {
+    ///     staged: Vec<String>,
+    ///     committed: Vec<String>,
+    /// }
+    ///
+    /// impl Actor for Store {
+    ///     type Stop = Vec<String>;
+    ///
+    ///     async fn after_handle(&mut self, _: &mut Context<Self>) {
+    ///         self.committed.append(&mut self.staged);
+    ///     }
+    ///
+    ///     async fn stopped(self) -> Vec<String> {
+    ///         self.committed
+    ///     }
+    /// }
+    ///
+    /// struct Put(&'static str);
+    ///
+    /// impl Handler<Put> for Store {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, Put(value): Put, _: &mut Context<Self>) {
+    ///         self.staged.push(value.to_owned());
+    ///     }
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let (addr, mailbox) = Mailbox::unbounded();
+    ///     let store = smol::spawn(xtra::run(mailbox, Store::default()));
+    ///
+    ///     addr.send(Put("a")).await.unwrap();
+    ///     drop(addr);
+    ///
+    ///     assert_eq!(store.await, vec!["a".to_owned()]);
+    /// })
+    /// ```
+    #[allow(unused_variables)]
+    fn after_handle(&mut self, ctx: &mut Context<Self>) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+
     /// Called at the end of an actor's event loop.
     ///
     /// An actor's event loop can stop for several reasons:
@@ -197,8 +538,10 @@ pub trait Actor: 'static + Send + Sized {
 
 /// An error related to the actor system
 #[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "remote", derive(serde::Serialize, serde::Deserialize))]
 pub enum Error {
-    /// The actor is no longer running and disconnected from the sending address.
+    /// The given actor is no longer running and disconnected from the sending address. See
+    /// [`Address::disconnect_reason`] for why, if it is known.
     Disconnected,
     /// The message request operation was interrupted. This happens when the message result sender
     /// is dropped. Therefore, it should never be returned from [`detached`](SendFuture::detach) [`SendFuture`]s
@@ -206,6 +549,20 @@ pub enum Error {
     /// Unlike [`Error::Disconnected`], it does not necessarily imply that any retries or further
     /// attempts to interact with the actor will result in an error.
     Interrupted,
+    /// The actor was stopped with [`Context::stop_self_now`] while handling the message, which
+    /// cancelled the handler before it returned.
+    ActorStoppedDuringHandling,
+    /// The reply was awaited from within a handler of the actor which is to reply, which can never
+    /// handle the message before the handler returns.
+    ///
+    /// The message has been queued regardless and is handled once the current handler returns.
+    /// Sending a message to the own actor without awaiting the reply, e.g. with
+    /// [`Address::send_and_forget`] or [`Context::notify`], is fine.
+    WouldDeadlock,
+    /// The message or the return value of its handler could not be serialized to send it to or
+    /// from an actor in another process, see the `remote` module. Carries the error message of the
+    /// serializer.
+    Serialization(String),
 }
 
 impl fmt::Display for Error {
@@ -213,27 +570,367 @@ impl fmt::Display for Error {
         match self {
             Error::Disconnected => f.write_str("Actor address disconnected"),
             Error::Interrupted => f.write_str("Message request interrupted"),
+            Error::ActorStoppedDuringHandling => {
+                f.write_str("Actor stopped during handling of the message")
+            }
+            Error::WouldDeadlock => {
+                f.write_str("Awaiting a reply from the own actor would deadlock")
+            }
+            Error::Serialization(e) => {
+                write!(f, "Failed to serialize the message or its reply: {}", e)
+            }
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+impl Error {
+    /// Whether this error is [`Error::Disconnected`], i.e. whether the actor is no longer running.
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self, Error::Disconnected)
+    }
+}
+
+/// An actor which is no longer running, as reported by [`Down::actor`](monitor::Down::actor) to tell
+/// which of several monitored actors stopped.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct Disconnected {
+    actor_type: &'static str,
+    actor_id: ActorId,
+}
+
+impl Disconnected {
+    pub(crate) fn new(actor_type: &'static str, actor_id: ActorId) -> Self {
+        Disconnected {
+            actor_type,
+            actor_id,
+        }
+    }
+
+    /// The type name of the actor, as returned by [`std::any::type_name`].
+    pub fn actor_type(&self) -> &'static str {
+        self.actor_type
+    }
+
+    /// The identifier of the actor.
+    pub fn actor_id(&self) -> ActorId {
+        self.actor_id
+    }
+}
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Actor address disconnected: {}{}",
+            self.actor_type, self.actor_id
+        )
+    }
+}
+
+impl std::error::Error for Disconnected {}
+
+/// The reason for which an actor is disconnected from its addresses, as reported by
+/// [`Address::disconnect_reason`].
+///
+/// If several actors run on the same address, this is the reason for which the last of them
+/// stopped.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "remote", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum DisconnectReason {
+    /// [`Actor::started`] returned an error.
+    StartFailed,
+    /// The actor called [`Context::stop_self`].
+    StoppedSelf,
+    /// An actor called [`Context::stop_all`], or the actor was stopped along with its parent or by
+    /// a [`ShutdownGroup`](crate::shutdown::ShutdownGroup).
+    StoppedAll,
+    /// All addresses with a [`Strong`](crate::refcount::Strong) reference count were dropped.
+    AddressesDropped,
+    /// The deadline set with [`Context::stop_after`] elapsed.
+    TimedOut,
+    /// The actor panicked.
+    Panicked,
+    /// The future running the actor was dropped before the actor stopped, e.g. because the task
+    /// running it was cancelled.
+    Aborted,
+}
+
+impl DisconnectReason {
+    /// The reason for an actor stopping at the end of its event loop, where `timed_out` tells
+    /// whether its deadline elapsed.
+    fn after_stop(reason: StopReason, timed_out: bool) -> Self {
+        match reason {
+            StopReason::StartFailed => DisconnectReason::StartFailed,
+            StopReason::StopSelf => DisconnectReason::StoppedSelf,
+            // The deadline shuts the actor down like `stop_all` does, but only this actor.
+            StopReason::StopAll if timed_out => DisconnectReason::TimedOut,
+            StopReason::StopAll => DisconnectReason::StoppedAll,
+            StopReason::Disconnected => DisconnectReason::AddressesDropped,
+        }
+    }
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::StartFailed => f.write_str("the actor failed to start"),
+            DisconnectReason::StoppedSelf => f.write_str("the actor stopped itself"),
+            DisconnectReason::StoppedAll => f.write_str("all actors on the address were stopped"),
+            DisconnectReason::AddressesDropped => f.write_str("all strong addresses were dropped"),
+            DisconnectReason::TimedOut => f.write_str("the deadline of the actor elapsed"),
+            DisconnectReason::Panicked => f.write_str("the actor panicked"),
+            DisconnectReason::Aborted => f.write_str("the actor was aborted"),
+        }
+    }
+}
+
+/// Records why the actor stopped once its event loop ends, including if it ends by panicking or by
+/// being dropped.
+struct Teardown<'a, A> {
+    mailbox: &'a Mailbox<A>,
+    reason: Option<StopReason>,
+}
+
+impl<A> Drop for Teardown<'_, A> {
+    fn drop(&mut self) {
+        let reason = match self.reason {
+            Some(reason) => DisconnectReason::after_stop(reason, self.mailbox.timed_out()),
+            None if std::thread::panicking() => DisconnectReason::Panicked,
+            None => DisconnectReason::Aborted,
+        };
+
+        self.mailbox.inner.set_disconnect_reason(reason);
+        self.mailbox.timers.clear();
+        self.mailbox.tasks.abort_all();
+    }
+}
+
+/// A unique identifier of an actor, assigned when its [`Mailbox`] is created.
+///
+/// Identifiers are never reused within a process. All actors running on the same address share an
+/// identifier, so two identifiers are equal exactly if their addresses refer to the same actor, as
+/// determined by [`Address::same_actor`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[cfg_attr(feature = "remote", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActorId(u64);
+
+impl ActorId {
+    /// Assign the next identifier.
+    pub(crate) fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        ActorId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// The identifier as a number, e.g. for logging.
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for ActorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
 /// Run the provided actor.
 ///
 /// This is the primary event loop of an actor which takes messages out of the mailbox and hands
 /// them to the actor.
-pub async fn run<A>(mailbox: Mailbox<A>, mut actor: A) -> A::Stop
+pub async fn run<A>(mailbox: Mailbox<A>, actor: A) -> A::Stop
 where
     A: Actor,
 {
-    if let Err(stop) = actor.started(&mailbox).await {
-        return stop;
+    match run_with::<A, Dynamic, _, _>(mailbox, actor, A::stopped).await {
+        Ok(stop) | Err(stop) => stop,
     }
+}
 
-    while let ControlFlow::Continue(()) = yield_once(&mailbox, &mut actor).await {}
+/// Run the provided actor like [`run`], but resolve to the actor itself once it has stopped instead
+/// of calling [`Actor::stopped`], e.g. to persist its final state or to check it in a test.
+///
+/// Everything else which happens when an actor stops still happens before the actor is returned:
+/// its addresses are disconnected, and its timers, tasks, children and
+/// [`Context::on_stop`] callbacks are cancelled or run. The caller can still call
+/// [`Actor::stopped`] on the returned actor. If [`Actor::started`] fails, this resolves to `Err`
+/// with the value it returned, like [`run`] does.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// # #[derive(Default)]
+/// # struct Inventory { items: Vec<&'static str> }
+/// # impl Actor for Inventory { type Stop = (); async fn stopped(self) {} }
+/// struct Add(&'static str);
+///
+/// impl Handler<Add> for Inventory {
+///     type Return = ();
+///
+///     async fn handle(&mut self, Add(item): Add, _: &mut Context<Self>) {
+///         self.items.push(item);
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let (address, mailbox) = Mailbox::unbounded();
+///     let inventory = smol::spawn(xtra::run_returning_actor(mailbox, Inventory::default()));
+///
+///     address.send(Add("apple")).await.unwrap();
+///     drop(address);
+///
+///     let inventory = inventory.await.unwrap();
+///     assert_eq!(inventory.items, ["apple"]); // e.g. persist the final state here
+///     inventory.stopped().await;
+/// })
+/// ```
+pub async fn run_returning_actor<A>(mailbox: Mailbox<A>, actor: A) -> Result<A, A::Stop>
+where
+    A: Actor,
+{
+    run_with::<A, Dynamic, _, _>(mailbox, actor, future::ready).await
+}
+
+/// Run the provided actor like [`run`], but optimised for actors which mostly handle messages of
+/// a single type `M`.
+///
+/// Messages of type `M` sent to one actor are handled without boxing the future returned by
+/// [`Handler::handle`] and without dynamic dispatch when polling it. All other messages, including
+/// broadcasts of `M`, are dispatched like in [`run`]. Note that messages are still boxed when they
+/// are sent.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// # struct Counter(u64);
+/// # impl Actor for Counter { type Stop = u64; async fn stopped(self) -> u64 { self.0 } }
+/// struct Increment;
+///
+/// impl Handler<Increment> for Counter {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: Increment, _: &mut Context<Self>) {
+///         self.0 += 1;
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let (addr, mailbox) = Mailbox::unbounded();
+///     let counter = smol::spawn(xtra::run_monomorphic::<_, Increment>(mailbox, Counter(0)));
+///
+///     addr.send(Increment).await.unwrap();
+///     drop(addr);
+///
+///     assert_eq!(counter.await, 1);
+/// })
+/// ```
+pub async fn run_monomorphic<A, M>(mailbox: Mailbox<A>, actor: A) -> A::Stop
+where
+    A: Handler<M>,
+    M: Send + 'static,
+{
+    match run_with::<A, Monomorphic<M>, _, _>(mailbox, actor, A::stopped).await {
+        Ok(stop) | Err(stop) => stop,
+    }
+}
+
+/// Build the actor with the asynchronous `init`, e.g. to open a database connection, then run it
+/// like [`run`].
+///
+/// The [`Address`] of the actor is usable right away: messages sent while `init` is still running
+/// wait in the mailbox, so the actor is fully initialized before it handles any message, and
+/// [`Actor::started`] only runs once `init` has completed. As usual, senders wait for room in a
+/// bounded mailbox which fills up in the meantime.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// # struct Database;
+/// # impl Actor for Database { type Stop = (); async fn stopped(self) {} }
+/// # impl Database { async fn connect(_: &str) -> Self { Database } }
+/// struct Query;
+///
+/// impl Handler<Query> for Database {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: Query, _: &mut Context<Self>) {
+///         // The connection is open by now.
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let (address, mailbox) = Mailbox::unbounded();
+///     smol::spawn(xtra::run_with_init(mailbox, || Database::connect("db://"))).detach();
+///
+///     address.send(Query).await.unwrap();
+/// })
+/// ```
+pub async fn run_with_init<A, F, Fut>(mailbox: Mailbox<A>, init: F) -> A::Stop
+where
+    A: Actor,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = A>,
+{
+    let actor = init().await;
+
+    run(mailbox, actor).await
+}
+
+/// The event loop of an actor, dispatching messages through `D` and handing the actor to `finish`
+/// once it has stopped. Resolves to `Err` if [`Actor::started`] failed.
+async fn run_with<A, D, F, Fut>(
+    mailbox: Mailbox<A>,
+    mut actor: A,
+    finish: F,
+) -> Result<Fut::Output, A::Stop>
+where
+    A: Actor,
+    D: Dispatch<A>,
+    F: FnOnce(A) -> Fut,
+    Fut: Future,
+{
+    let name = actor.name();
+    let span = instrumentation::actor_span::<A>(&name, mailbox.inner.id());
+    mailbox.inner.set_name(name);
+
+    instrumentation::instrument_actor(span, async move {
+        let mut teardown = Teardown {
+            mailbox: &mailbox,
+            reason: None,
+        };
+
+        if let Err(stop) = actor.started(&mailbox).await {
+            instrumentation::actor_stopped(StopReason::StartFailed);
+            teardown.reason = Some(StopReason::StartFailed);
+            mailbox.deregister();
+            mailbox.children.stop_all().await;
+            return Err(stop);
+        }
+
+        instrumentation::actor_started();
+
+        loop {
+            let message = mailbox.next().await;
+            let is_shutdown = message.inner.is_shutdown();
+
+            if let ControlFlow::Break(()) = D::dispatch(message, &mut actor).await {
+                let reason = StopReason::after_message(is_shutdown, &mailbox);
+                instrumentation::actor_stopped(reason);
+                teardown.reason = Some(reason);
+                break;
+            }
+        }
 
-    actor.stopped().await
+        mailbox.deregister();
+        mailbox.tasks.abort_all();
+        mailbox.children.stop_all().await;
+        mailbox.run_on_stop(&mut actor);
+        Ok(finish(actor).await)
+    })
+    .await
 }
 
 /// Yields to the manager to handle one message, returning the actor should be shut down or not.
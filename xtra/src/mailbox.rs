@@ -1,16 +1,58 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::atomic::{self, AtomicBool};
 use std::sync::Arc;
+use std::task::{self, Poll};
+use std::time::Duration;
 
-use crate::chan::{self, BroadcastQueue, Rx};
-use crate::recv_future::ReceiveFuture;
-use crate::{Address, WeakAddress};
+use crate::address::RecoveredMessages;
+use crate::chan::{self, ActorMessage, BroadcastQueue, MessageToAll, MessageToOne, Rx};
+use crate::children::Children;
+use crate::coalesce::Coalesced;
+use crate::message_channel::MessageChannel;
+use crate::permits::Permits;
+use crate::recv_future::{MessageStream, ReceiveFuture};
+use crate::restart::InFlight;
+use crate::runtime::{self, Spawner, Timer};
+use crate::scoped_task::Tasks;
+use crate::shutdown::ShutdownGroup;
+use crate::timers::{TimerId, Timers};
+use crate::{
+    registry, Actor, ActorId, Address, Handler, MailboxBacking, MailboxOrder, WeakAddress,
+};
 
 /// A [`Mailbox`] is the counter-part to an [`Address`].
 ///
 /// Messages sent into an [`Address`] will be received in an actor's [`Mailbox`].
 /// Think of [`Address`] and [`Mailbox`] as an MPMC channel.
 pub struct Mailbox<A> {
-    inner: chan::Ptr<A, Rx>,
-    broadcast_mailbox: Arc<BroadcastQueue<A>>,
+    pub(crate) inner: chan::Ptr<A, Rx>,
+    pub(crate) broadcast_mailbox: Arc<BroadcastQueue<A>>,
+    /// Set by [`Context::stop_self`](crate::Context::stop_self) to cancel the current handler.
+    pub(crate) stop_requested: Arc<AtomicBool>,
+    deferred: Arc<spin::Mutex<Deferred<A>>>,
+    /// The messages queued with [`Context::notify_coalesced`](crate::Context::notify_coalesced).
+    pub(crate) coalesced: Arc<Coalesced>,
+    /// The deadline set with [`Context::stop_after`](crate::Context::stop_after).
+    deadline: Arc<spin::Mutex<Deadline>>,
+    /// Whether the actor registered itself in the [`Registry`](crate::registry::Registry).
+    registered: Arc<AtomicBool>,
+    /// Callbacks registered with [`Context::on_stop`](crate::Context::on_stop).
+    on_stop: Arc<spin::Mutex<Vec<OnStop<A>>>>,
+    /// Limits the tasks spawned with [`Context::with_permit`](crate::Context::with_permit).
+    pub(crate) permits: Arc<Permits>,
+    /// The actors spawned with [`Context::spawn_child`](crate::Context::spawn_child).
+    pub(crate) children: Arc<Children>,
+    pub(crate) tasks: Arc<Tasks>,
+    /// The timers of [`Context::notify_after`](crate::Context::notify_after) and requeued messages.
+    pub(crate) timers: Arc<Timers>,
+    spawner: Option<Arc<dyn Spawner>>,
+    timer: Option<Arc<dyn Timer>>,
+    #[cfg(feature = "metrics")]
+    message_type_labels: bool,
+    #[cfg(feature = "instrumentation")]
+    slow_handler_threshold: Option<Duration>,
 }
 
 impl<A> Mailbox<A> {
@@ -18,13 +60,7 @@ impl<A> Mailbox<A> {
     pub fn bounded(capacity: usize) -> (Address<A>, Mailbox<A>) {
         let (sender, receiver) = chan::new(Some(capacity));
 
-        let address = Address(sender);
-        let mailbox = Mailbox {
-            broadcast_mailbox: receiver.new_broadcast_mailbox(),
-            inner: receiver,
-        };
-
-        (address, mailbox)
+        (Address(sender), Mailbox::for_new_actor(receiver))
     }
 
     /// Creates a new, unbounded [`Mailbox`].
@@ -33,13 +69,43 @@ impl<A> Mailbox<A> {
     pub fn unbounded() -> (Address<A>, Mailbox<A>) {
         let (sender, receiver) = crate::chan::new(None);
 
-        let address = Address(sender);
-        let mailbox = Mailbox {
-            broadcast_mailbox: receiver.new_broadcast_mailbox(),
-            inner: receiver,
-        };
+        (Address(sender), Mailbox::for_new_actor(receiver))
+    }
+
+    /// Creates a [`Mailbox`] for a new actor receiving from `inner`, with state of its own.
+    fn for_new_actor(inner: chan::Ptr<A, Rx>) -> Self {
+        Mailbox {
+            broadcast_mailbox: inner.new_broadcast_mailbox(),
+            inner,
+            stop_requested: Arc::default(),
+            deferred: Arc::default(),
+            coalesced: Arc::default(),
+            deadline: Arc::default(),
+            registered: Arc::default(),
+            on_stop: Arc::default(),
+            permits: Arc::default(),
+            children: Arc::default(),
+            tasks: Arc::default(),
+            timers: Arc::default(),
+            spawner: None,
+            timer: None,
+            #[cfg(feature = "metrics")]
+            message_type_labels: false,
+            #[cfg(feature = "instrumentation")]
+            slow_handler_threshold: None,
+        }
+    }
 
-        (address, mailbox)
+    /// Creates a new [`Mailbox`] with the capacity declared by [`Actor::MAILBOX_CAPACITY`], i.e.
+    /// a bounded mailbox if the actor declares a capacity and an unbounded one otherwise.
+    pub fn new() -> (Address<A>, Mailbox<A>)
+    where
+        A: Actor,
+    {
+        match A::MAILBOX_CAPACITY {
+            Some(capacity) => Mailbox::bounded(capacity),
+            None => Mailbox::unbounded(),
+        }
     }
 
     /// Obtain a [`WeakAddress`] to this [`Mailbox`].
@@ -50,18 +116,578 @@ impl<A> Mailbox<A> {
         Address(self.inner.to_tx_weak())
     }
 
+    /// The unique identifier of the actor this [`Mailbox`] belongs to. See [`Address::id`].
+    pub fn id(&self) -> ActorId {
+        self.inner.id()
+    }
+
+    /// Register this actor in the global [`Registry`](crate::registry::Registry), so that it can be
+    /// looked up as a [`MessageChannel`] for messages of type `M`.
+    ///
+    /// The registration is removed once the actor stops. Registering the same actor again for the
+    /// same message type has no effect.
+    pub fn register_as<M>(&self)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        self.register::<M>(None);
+    }
+
+    /// Register this actor in the global [`Registry`](crate::registry::Registry) under the given
+    /// name, so that it can be looked up with [`Registry::get_named`](crate::registry::Registry::get_named).
+    ///
+    /// This is otherwise the same as [`Mailbox::register_as`].
+    pub fn register_named_as<M>(&self, name: impl Into<Cow<'static, str>>)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        self.register::<M>(Some(name.into()));
+    }
+
+    fn register<M>(&self, name: Option<Cow<'static, str>>)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        self.registered.store(true, atomic::Ordering::SeqCst);
+        registry::register(
+            self.registry_owner(),
+            name,
+            MessageChannel::<M, A::Return, _>::new(self.address()),
+        );
+    }
+
+    /// Remove all registrations of this actor from the global registry.
+    pub(crate) fn deregister(&self) {
+        if self.registered.swap(false, atomic::Ordering::SeqCst) {
+            registry::deregister(self.registry_owner());
+        }
+    }
+
+    /// Register a callback to run right before [`Actor::stopped`].
+    pub(crate) fn on_stop(&self, f: OnStop<A>) {
+        self.on_stop.lock().push(f);
+    }
+
+    /// Run the callbacks registered with [`Mailbox::on_stop`] in reverse registration order.
+    pub(crate) fn run_on_stop(&self, actor: &mut A) {
+        let callbacks = mem::take(&mut *self.on_stop.lock());
+
+        for f in callbacks.into_iter().rev() {
+            f(actor);
+        }
+    }
+
+    /// The name of an auxiliary task spawned on behalf of this actor for the given purpose.
+    pub(crate) fn task_name(&self, task: &str) -> String {
+        format!(
+            "{}::{}",
+            runtime::task_name(&self.inner.name(), self.id()),
+            task
+        )
+    }
+
+    /// Identifies this actor, as opposed to other actors on the same address, in the registry.
+    fn registry_owner(&self) -> usize {
+        Arc::as_ptr(&self.broadcast_mailbox) as *const () as usize
+    }
+
     /// Take the next message out of the [`Mailbox`].
     pub fn next(&self) -> ReceiveFuture<A> {
-        ReceiveFuture::new(self.inner.clone(), self.broadcast_mailbox.clone())
+        ReceiveFuture::new(self.same_actor())
+    }
+
+    /// Turn the [`Mailbox`] into a [`Stream`](futures_core::Stream) of the messages to be handled
+    /// by the actor, for a receive loop which is fully under the control of the caller, e.g. one
+    /// which selects between messages, sockets and timers.
+    ///
+    /// Every message has to be dispatched to the actor with `dispatch_to`, which resolves to
+    /// [`ControlFlow::Break`](std::ops::ControlFlow::Break) once the actor should stop, be it
+    /// because it called [`Context::stop_self`](crate::Context::stop_self), all strong addresses
+    /// have been dropped or [`Context::stop_all`](crate::Context::stop_all) has been called. The
+    /// loop then has to stop the actor itself, e.g. by calling [`Actor::stopped`]. The stream ends
+    /// after the message which shuts the actor down.
+    ///
+    /// ```rust
+    /// # use std::ops::ControlFlow;
+    /// # use futures_util::StreamExt;
+    /// # use xtra::prelude::*;
+    /// # #[derive(Default)]
+    /// # struct Counter(u32);
+    /// # impl Actor for Counter { type Stop = u32; async fn stopped(self) -> u32 { self.0 } }
+    /// # struct Increment;
+    /// # impl Handler<Increment> for Counter { type Return = (); async fn handle(&mut self, _: Increment, _: &mut Context<Self>) { self.0 += 1; } }
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let (address, mailbox) = Mailbox::unbounded();
+    ///     let mut messages = mailbox.into_stream();
+    ///     let mut actor = Counter::default();
+    ///
+    ///     address.send(Increment).detach().await.unwrap();
+    ///     drop(address);
+    ///
+    ///     while let Some(message) = messages.next().await {
+    ///         if let ControlFlow::Break(()) = message.dispatch_to(&mut actor).await {
+    ///             break;
+    ///         }
+    ///     }
+    ///
+    ///     assert_eq!(actor.stopped().await, 1);
+    /// })
+    /// ```
+    pub fn into_stream(self) -> MessageStream<A> {
+        MessageStream::new(self)
     }
 
-    pub(crate) fn from_parts(
-        chan: chan::Ptr<A, Rx>,
-        broadcast_mailbox: Arc<BroadcastQueue<A>>,
-    ) -> Self {
+    /// Put the messages recovered from another actor with [`Address::stop_and_recover`] into this
+    /// [`Mailbox`], to be handled by the actor it will be run with.
+    ///
+    /// The messages are queued ahead of messages with a lower priority, regardless of the capacity
+    /// of the [`Mailbox`].
+    pub fn inject(&self, messages: RecoveredMessages<A>) {
+        for message in messages.0 {
+            self.inner.requeue_message(message);
+        }
+    }
+
+    /// Keep messages of lower [`priority`](crate::SendFuture::priority) from being starved by a
+    /// steady stream of messages of higher priority.
+    ///
+    /// Once `ratio` messages of a priority have been handled in a row while messages of a lower
+    /// priority were waiting, the next message of the next lower priority is handled before the
+    /// next one of the higher priority. This applies between every two adjacent priorities that
+    /// are waiting, so a priority which is lower still is handled once per `ratio` messages of its
+    /// next higher one. Without fairness, messages are always handled in strict order of priority.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is zero.
+    pub fn with_priority_fairness(self, ratio: u32) -> Self {
+        assert!(ratio > 0, "the fairness ratio must be at least one");
+
+        self.inner.set_priority_fairness(ratio);
+        self
+    }
+
+    /// Choose the data structure which holds the messages sent to one actor, trading memory for
+    /// the speed of sending and receiving, see [`MailboxBacking`]. Defaults to
+    /// [`MailboxBacking::Heap`].
+    ///
+    /// This applies to all actors on the address, as they share their messages.
+    pub fn with_backing(self, backing: MailboxBacking) -> Self {
+        self.inner.set_backing(backing);
+        self
+    }
+
+    /// Choose the order in which messages of equal [`priority`](crate::SendFuture::priority) are
+    /// handled. Defaults to [`MailboxOrder::Fifo`].
+    ///
+    /// With [`MailboxOrder::Lifo`], the newest message is handled first, e.g. for an actor which
+    /// renders the latest state and for which older requests are stale. This is not fair: under a
+    /// sustained load, older messages may never be handled, and their senders wait until the actor
+    /// stops. Bound the mailbox so that they are not buffered without limit, or send them with
+    /// [`Address::send_keyed`](crate::Address::send_keyed) so that each replaces the stale one
+    /// of its key. Messages of senders waiting for room in a bounded mailbox only count as sent
+    /// once they have been queued. Priorities still apply, as does
+    /// [`Mailbox::with_priority_fairness`], which then lets through the newest message of a lower
+    /// priority. Notifications sent with [`Context::notify`](crate::Context::notify) are still
+    /// handled first, in the order they were sent.
+    ///
+    /// This applies to all actors on the address, as they share their messages. Broadcasts are
+    /// always handled in the order they were sent.
+    pub fn with_order(self, order: MailboxOrder) -> Self {
+        self.inner.set_order(order);
+        self
+    }
+
+    /// Configure the [`Spawner`] that is used for spawning auxiliary tasks of the actor.
+    ///
+    /// The `spawn` functions of xtra, such as [`spawn_tokio`](crate::spawn_tokio), configure the
+    /// spawner of their respective runtime unless one has been set already.
+    pub fn with_spawner(mut self, spawner: impl Spawner) -> Self {
+        self.spawner = Some(Arc::new(spawner));
+        self.inner.set_spawner(self.spawner.clone());
+        self
+    }
+
+    /// Configure the [`Timer`] that is used by all functionality of the actor which needs to wait
+    /// for some amount of time.
+    ///
+    /// The `spawn` functions of xtra, such as [`spawn_tokio`](crate::spawn_tokio), configure the
+    /// timer of their respective runtime unless one has been set already.
+    pub fn with_timer(mut self, timer: impl Timer) -> Self {
+        self.timer = Some(Arc::new(timer));
+        self.inner.set_timer(self.timer.clone());
+        self
+    }
+
+    /// Use the same [`Spawner`] and [`Timer`] as the given [`Mailbox`], e.g. that of a parent actor.
+    pub(crate) fn with_runtime_of<B>(mut self, other: &Mailbox<B>) -> Self {
+        self.spawner = other.spawner.clone();
+        self.timer = other.timer.clone();
+        self.inner.set_spawner(self.spawner.clone());
+        self.inner.set_timer(self.timer.clone());
+        self
+    }
+
+    /// Add the actor to the given [`ShutdownGroup`], to be stopped in the given phase.
+    ///
+    /// This is the same as calling [`ShutdownGroup::add`] with the address of the actor.
+    pub fn shutdown_in(self, group: &ShutdownGroup, phase: u32) -> Self
+    where
+        A: Actor,
+    {
+        group.add(phase, &self.address());
+        self
+    }
+
+    /// Label the metrics of handled messages with the type of the message, in addition to the name
+    /// of the actor.
+    ///
+    /// This is disabled by default because every message type handled by the actor creates a new
+    /// time series, which can be expensive for some metrics backends.
+    #[cfg(feature = "metrics")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "metrics")))]
+    pub fn with_message_type_labels(mut self) -> Self {
+        self.message_type_labels = true;
+        self
+    }
+
+    /// Whether metrics of handled messages are labelled with the type of the message.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn message_type_labels(&self) -> bool {
+        self.message_type_labels
+    }
+
+    /// Emit a warning whenever a handler of the actor has been running for longer than the given
+    /// threshold, and again each time the threshold elapses after that until the handler returns.
+    ///
+    /// The warning is emitted as a `tracing` event naming the actor and the message type. The
+    /// handler is not cancelled. The threshold is measured with the [`Timer`] of this [`Mailbox`]
+    /// from the moment the handler is first polled, so a handler which blocks the thread instead
+    /// of awaiting is reported too, once it returns or suspends.
+    ///
+    /// Without a [`Timer`], no warnings are emitted.
+    #[cfg(feature = "instrumentation")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "instrumentation")))]
+    pub fn warn_if_handler_exceeds(mut self, threshold: Duration) -> Self {
+        self.slow_handler_threshold = Some(threshold);
+        self
+    }
+
+    /// The threshold for warning about slow handlers and the timer to measure it with, if configured.
+    #[cfg(feature = "instrumentation")]
+    pub(crate) fn slow_handler_threshold(&self) -> Option<(Duration, Arc<dyn Timer>)> {
+        Some((self.slow_handler_threshold?, self.timer.clone()?))
+    }
+
+    /// The [`Spawner`] configured for this [`Mailbox`], if any.
+    pub fn spawner(&self) -> Option<&dyn Spawner> {
+        self.spawner.as_deref()
+    }
+
+    /// The [`Timer`] configured for this [`Mailbox`], if any.
+    pub fn timer(&self) -> Option<&dyn Timer> {
+        self.timer.as_deref()
+    }
+
+    /// Prepare this mailbox for a fresh instance of the actor after the previous one panicked, see
+    /// [`run_restarting`](crate::run_restarting).
+    ///
+    /// Callbacks and children belong to the previous instance and are discarded respectively
+    /// stopped. Queued messages are kept or dropped according to `in_flight`.
+    pub(crate) async fn reset_for_restart(&self, in_flight: InFlight) {
+        self.on_stop.lock().clear();
+        self.stop_requested.store(false, atomic::Ordering::Relaxed);
+        self.children.stop_all().await;
+
+        if in_flight == InFlight::DeadLetter {
+            let messages = self.inner.drain();
+            let deferred = mem::take(&mut *self.deferred.lock());
+            crate::metrics::messages_dropped(
+                &self.inner.metrics_name(),
+                messages.len() + deferred.len(),
+            );
+
+            // Dropping the messages tells their senders that they were interrupted.
+            drop((messages, deferred));
+        }
+    }
+
+    /// Retry all messages deferred by [`Handler::can_handle`] before
+    /// receiving the next message from the mailbox.
+    ///
+    /// Deferred messages are retried automatically after every handled message. Calling this is
+    /// only necessary if the state of the actor changes outside of a handler, e.g. in a custom
+    /// event loop.
+    pub fn recheck_deferred(&self) {
+        self.deferred.lock().recheck = true;
+    }
+
+    /// Put a message requeued with [`Context::requeue`](crate::Context::requeue) back into the
+    /// mailbox, once the delay has elapsed if there is one.
+    pub(crate) fn requeue(&self, message: MessageToOne<A>, delay: Option<Duration>)
+    where
+        A: Actor,
+    {
+        let chan = self.inner.to_tx_weak();
+
+        self.after(delay, move || {
+            // The message is dropped if the actor has stopped in the meantime.
+            let _ = chan.force_send_to_one(message);
+        });
+    }
+
+    /// Put a requeued broadcast message back into the broadcast mailbox of this actor only, once
+    /// the delay has elapsed if there is one.
+    pub(crate) fn requeue_broadcast(&self, message: MessageToAll<A>, delay: Option<Duration>)
+    where
+        A: Actor,
+    {
+        let chan = self.inner.to_tx_weak();
+        let broadcast_mailbox = Arc::downgrade(&self.broadcast_mailbox);
+
+        self.after(delay, move || {
+            if let Some(broadcast_mailbox) = broadcast_mailbox.upgrade() {
+                chan.requeue_broadcast(&broadcast_mailbox, message);
+            }
+        });
+    }
+
+    /// Call `f` right away, or on the timer queue of this actor once the delay has elapsed.
+    fn after(&self, delay: Option<Duration>, f: impl FnOnce() + Send + 'static) {
+        let Some(delay) = delay else {
+            return f();
+        };
+
+        let timer = self.timer_or_fallback();
+        self.timers.schedule(timer.now() + delay, f);
+    }
+
+    /// The [`Spawner`] configured for this [`Mailbox`], or a fallback which runs tasks on an
+    /// executor thread shared by all actors if there is none.
+    pub(crate) fn spawner_or_fallback(&self) -> &dyn Spawner {
+        self.spawner().unwrap_or(&runtime::Fallback)
+    }
+
+    /// The [`Timer`] configured for this [`Mailbox`], or a fallback which is woken by a timer
+    /// thread shared by all actors if there is none.
+    pub(crate) fn timer_or_fallback(&self) -> &dyn Timer {
+        self.timer().unwrap_or(&runtime::Fallback)
+    }
+
+    /// Fire the timers of this actor which are due, queueing their messages.
+    pub(crate) fn fire_due_timers(&self) {
+        self.timers.fire_due(self.timer_or_fallback());
+    }
+
+    /// Wait until a timer of this actor has fired, sleeping until the earliest deadline.
+    pub(crate) fn poll_timers(&self, cx: &mut task::Context<'_>) -> Poll<()> {
+        let timer = self.timer_or_fallback();
+
+        loop {
+            if self.timers.fire_due(timer) {
+                return Poll::Ready(());
+            }
+
+            futures_util::ready!(self.timers.poll_earliest(timer, cx));
+        }
+    }
+
+    /// Stash a message which the actor cannot handle in its current state.
+    ///
+    /// If the stash of a bounded mailbox is full, the message is dropped. The stash of an unbounded
+    /// mailbox is unbounded.
+    pub(crate) fn defer(&self, message: ActorMessage<A>) {
+        let mut deferred = self.deferred.lock();
+
+        if let Some(capacity) = self.inner.capacity() {
+            if deferred.len() >= capacity {
+                crate::metrics::messages_dropped(&self.inner.metrics_name(), 1);
+                return;
+            }
+        }
+
+        deferred.stash.push_back(message);
+    }
+
+    /// Notify the mailbox that a message has been handled, which may have changed the actor's state.
+    pub(crate) fn on_handled(&self) {
+        let mut deferred = self.deferred.lock();
+
+        if deferred.len() > 0 {
+            deferred.recheck = true;
+        }
+    }
+
+    /// Take the next deferred message to retry, if a retry is due.
+    pub(crate) fn next_deferred(&self) -> Option<ActorMessage<A>> {
+        let mut deferred = self.deferred.lock();
+
+        if deferred.retrying.is_empty() && mem::take(&mut deferred.recheck) {
+            let Deferred {
+                stash, retrying, ..
+            } = &mut *deferred;
+            mem::swap(stash, retrying);
+        }
+
+        deferred.retrying.pop_front()
+    }
+
+    /// Stop this actor once the given duration has elapsed, e.g. to end a session after a timeout.
+    ///
+    /// When the deadline is reached, the actor stops as soon as it has finished processing its
+    /// current message, as if it had called [`Context::stop_self`](crate::Context::stop_self).
+    /// Calling this again replaces the previous deadline, unless it has already elapsed. The
+    /// deadline is cancelled if the actor stops earlier.
+    ///
+    /// The deadline is kept in the timer queue of the actor, like the timers of
+    /// [`Context::notify_after`](crate::Context::notify_after), and is measured with the [`Timer`]
+    /// of this [`Mailbox`]. Without one, it is measured by a timer thread shared by all actors.
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use xtra::prelude::*;
+    /// struct Session;
+    ///
+    /// impl Actor for Session {
+    ///     type Stop = ();
+    ///
+    ///     async fn started(&mut self, mailbox: &Mailbox<Self>) -> Result<(), ()> {
+    ///         mailbox.stop_after(Duration::from_secs(60));
+    ///         Ok(())
+    ///     }
+    ///
+    ///     async fn stopped(self) {}
+    /// }
+    ///
+    /// struct Activity;
+    ///
+    /// impl Handler<Activity> for Session {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Activity, ctx: &mut Context<Self>) {
+    ///         // Keep the session alive for another minute.
+    ///         ctx.extend_deadline(Duration::from_secs(60));
+    ///     }
+    /// }
+    /// ```
+    pub fn stop_after(&self, duration: Duration)
+    where
+        A: Actor,
+    {
+        self.set_deadline(duration, false);
+    }
+
+    /// Whether the deadline set with [`Mailbox::stop_after`] has elapsed.
+    pub(crate) fn timed_out(&self) -> bool {
+        self.deadline.lock().fired
+    }
+
+    /// Move the deadline set with [`Mailbox::stop_after`] to the given duration from now.
+    ///
+    /// Returns `false` without setting a deadline if there was none, or if it has already elapsed.
+    pub fn extend_deadline(&self, duration: Duration) -> bool
+    where
+        A: Actor,
+    {
+        self.set_deadline(duration, true)
+    }
+
+    /// Arm the deadline to elapse after the given duration, replacing the pending one. Returns
+    /// `false` without arming it if the deadline has already elapsed, or if `only_if_pending` is
+    /// set and there is no pending deadline.
+    fn set_deadline(&self, duration: Duration, only_if_pending: bool) -> bool
+    where
+        A: Actor,
+    {
+        let mut deadline = self.deadline.lock();
+
+        // Once elapsed, the actor is stopping, so the deadline cannot be moved anymore.
+        if deadline.fired || (only_if_pending && deadline.pending.is_none()) {
+            return false;
+        }
+
+        if let Some(replaced) = deadline.pending.take() {
+            self.timers.cancel(replaced);
+        }
+
+        deadline.generation += 1;
+
+        let generation = deadline.generation;
+        let state = Arc::downgrade(&self.deadline);
+        let chan = self.inner.to_tx_weak();
+        let broadcast_mailbox = Arc::downgrade(&self.broadcast_mailbox);
+        let at = self.timer_or_fallback().now() + duration;
+
+        deadline.pending = Some(self.timers.schedule(at, move || {
+            let (Some(state), Some(broadcast_mailbox)) =
+                (state.upgrade(), broadcast_mailbox.upgrade())
+            else {
+                return;
+            };
+            let mut deadline = state.lock();
+
+            // The deadline may have been replaced while its timer fired.
+            if deadline.generation != generation || deadline.fired {
+                return;
+            }
+
+            deadline.fired = true;
+            deadline.pending = None;
+            drop(deadline);
+
+            chan.shutdown_receiver(&broadcast_mailbox);
+        }));
+
+        true
+    }
+
+    /// Configure the given runtime as spawner and timer, unless they have already been configured.
+    #[allow(dead_code)] // Unused if no runtime feature is enabled.
+    pub(crate) fn with_default_runtime<R>(mut self, runtime: R) -> Self
+    where
+        R: Spawner + Timer + Clone,
+    {
+        if self.spawner.is_none() {
+            self.spawner = Some(Arc::new(runtime.clone()));
+        }
+
+        if self.timer.is_none() {
+            self.timer = Some(Arc::new(runtime));
+        }
+
+        self.inner.set_spawner(self.spawner.clone());
+        self.inner.set_timer(self.timer.clone());
+        self
+    }
+
+    /// Create another handle to this [`Mailbox`] which shares the broadcast mailbox, i.e. is used
+    /// by the same actor.
+    ///
+    /// This is different to [`Clone`], which creates a [`Mailbox`] for a new actor on the same address.
+    pub(crate) fn same_actor(&self) -> Self {
         Self {
-            inner: chan,
-            broadcast_mailbox,
+            inner: self.inner.clone(),
+            broadcast_mailbox: self.broadcast_mailbox.clone(),
+            stop_requested: self.stop_requested.clone(),
+            deferred: self.deferred.clone(),
+            coalesced: self.coalesced.clone(),
+            deadline: self.deadline.clone(),
+            registered: self.registered.clone(),
+            on_stop: self.on_stop.clone(),
+            permits: self.permits.clone(),
+            children: self.children.clone(),
+            tasks: self.tasks.clone(),
+            timers: self.timers.clone(),
+            spawner: self.spawner.clone(),
+            timer: self.timer.clone(),
+            #[cfg(feature = "metrics")]
+            message_type_labels: self.message_type_labels,
+            #[cfg(feature = "instrumentation")]
+            slow_handler_threshold: self.slow_handler_threshold,
         }
     }
 }
@@ -69,8 +695,55 @@ impl<A> Mailbox<A> {
 impl<A> Clone for Mailbox<A> {
     fn clone(&self) -> Self {
         Mailbox {
-            inner: self.inner.clone(),
-            broadcast_mailbox: self.inner.new_broadcast_mailbox(),
+            spawner: self.spawner.clone(),
+            timer: self.timer.clone(),
+            #[cfg(feature = "metrics")]
+            message_type_labels: self.message_type_labels,
+            #[cfg(feature = "instrumentation")]
+            slow_handler_threshold: self.slow_handler_threshold,
+            ..Mailbox::for_new_actor(self.inner.clone())
+        }
+    }
+}
+
+/// The state of the deadline set with [`Context::stop_after`](crate::Context::stop_after), which is
+/// checked and updated under a single lock.
+#[derive(Default)]
+struct Deadline {
+    /// The timer of the pending deadline, cancelled when the deadline is replaced.
+    pending: Option<TimerId>,
+    /// Incremented every time the deadline is armed, so that a replaced deadline which elapses
+    /// concurrently does not stop the actor.
+    generation: u64,
+    /// Set once the deadline has elapsed, so that the actor is known to have timed out.
+    fired: bool,
+}
+
+/// A callback registered with [`Context::on_stop`](crate::Context::on_stop).
+type OnStop<A> = Box<dyn FnOnce(&mut A) + Send>;
+
+/// Messages which have been deferred by [`Handler::can_handle`].
+struct Deferred<A> {
+    /// Messages waiting for the actor's state to change.
+    stash: VecDeque<ActorMessage<A>>,
+    /// Messages which are currently being retried.
+    retrying: VecDeque<ActorMessage<A>>,
+    /// Whether the stash should be retried once the current retries are exhausted.
+    recheck: bool,
+}
+
+impl<A> Deferred<A> {
+    fn len(&self) -> usize {
+        self.stash.len() + self.retrying.len()
+    }
+}
+
+impl<A> Default for Deferred<A> {
+    fn default() -> Self {
+        Deferred {
+            stash: VecDeque::new(),
+            retrying: VecDeque::new(),
+            recheck: false,
         }
     }
 }
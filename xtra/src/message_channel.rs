@@ -2,14 +2,20 @@
 //! any actor that can handle it. It is like [`Address`], but associated with
 //! the message type rather than the actor type.
 
+use std::any::Any;
+use std::borrow::Cow;
 use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+#[cfg(feature = "tower")]
+use event_listener::EventListener;
 
 use crate::address::{ActorJoinHandle, Address};
 use crate::chan::RefCounter;
 use crate::refcount::{Either, Strong, Weak};
-use crate::send_future::{ActorErasedSending, ResolveToHandlerReturn, SendFuture};
-use crate::Handler;
+use crate::send_future::{ActorErasedSending, Forget, ResolveToHandlerReturn, SendFuture};
+use crate::{ActorId, DisconnectReason, Handler};
 
 /// A message channel is a channel through which you can send only one kind of message, but to
 /// any actor that can handle it. It is like [`Address`], but associated with the message type rather
@@ -88,11 +94,24 @@ where
         }
     }
 
+    /// Construct a [`MessageChannel`] which is not backed by an [`Address`].
+    pub(crate) fn from_inner(
+        inner: Box<dyn MessageChannelTrait<M, Rc, Return = R> + Send + Sync + 'static>,
+    ) -> Self {
+        Self { inner }
+    }
+
     /// Returns whether the actor referred to by this message channel is running and accepting messages.
     pub fn is_connected(&self) -> bool {
         self.inner.is_connected()
     }
 
+    /// Why the actor referred to by this message channel is disconnected, if it is and the reason
+    /// is known. See [`Address::disconnect_reason`].
+    pub fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.inner.disconnect_reason()
+    }
+
     /// Returns the number of messages in the actor's mailbox.
     ///
     /// Note that this does **not** differentiate between types of messages; it will return the
@@ -115,14 +134,52 @@ where
         self.len() == 0
     }
 
+    /// Listen for the actor's mailbox to have room for another message, if it is bounded.
+    #[cfg(feature = "tower")]
+    pub(crate) fn capacity_listener(&self) -> Option<EventListener> {
+        self.inner.capacity_listener()
+    }
+
+    /// The unique identifier of the actor behind this channel.
+    ///
+    /// See [`Address::id`] for details.
+    pub fn id(&self) -> ActorId {
+        self.inner.id()
+    }
+
+    /// The name of the actor behind this channel, as returned by [`Actor::name`](crate::Actor::name).
+    ///
+    /// See [`Address::name`] for details.
+    pub fn name(&self) -> Cow<'static, str> {
+        self.inner.name()
+    }
+
     /// Send a message to the actor.
     ///
     /// This function returns a [`Future`](SendFuture) that resolves to the [`Return`](crate::Handler::Return) value of the handler.
-    /// The [`SendFuture`] will resolve to [`Err(Disconnected)`] in case the actor is stopped and not accepting messages.
+    /// The [`SendFuture`] will resolve to [`Err(Disconnected)`] in case the actor is stopped and not accepting messages,
+    /// which carries the reason for which the actor stopped if it is known.
     pub fn send(&self, message: M) -> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>> {
         self.inner.send(message)
     }
 
+    /// Queue a message to the actor right away, giving it back if the actor has stopped, e.g. to
+    /// send it to another actor instead.
+    pub(crate) fn try_send(
+        &self,
+        message: M,
+    ) -> Result<SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>>, M> {
+        self.inner.try_send(message)
+    }
+
+    /// Send a message to the actor, explicitly discarding the [`Return`](crate::Handler::Return)
+    /// value of the handler.
+    ///
+    /// See [`Address::send_and_forget`] for details.
+    pub fn send_and_forget(&self, message: M) -> SendFuture<ActorErasedSending, Forget> {
+        self.inner.send(message).forget()
+    }
+
     /// Waits until this [`MessageChannel`] becomes disconnected.
     pub fn join(&self) -> ActorJoinHandle {
         self.inner.join()
@@ -135,6 +192,44 @@ where
     {
         self.inner.to_inner_ptr() == other.inner.to_inner_ptr()
     }
+
+    /// Recover the [`Address`] this channel was built from, e.g. to send a message type the
+    /// channel does not cover. This returns `None` if the actor behind the channel is not of type
+    /// `A`, or if the channel is not backed by an [`Address`] at all.
+    ///
+    /// A weak channel yields a [`WeakAddress`](crate::WeakAddress) and a strong one an [`Address`].
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Printer;
+    /// # impl Actor for Printer { type Stop = (); async fn stopped(self) {} }
+    /// # struct Scanner;
+    /// # impl Actor for Scanner { type Stop = (); async fn stopped(self) {} }
+    /// # impl Handler<Print> for Scanner { type Return = (); async fn handle(&mut self, _: Print, _: &mut Context<Self>) {} }
+    /// struct Print;
+    ///
+    /// impl Handler<Print> for Printer {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Print, _: &mut Context<Self>) {}
+    /// }
+    ///
+    /// let (address, _mailbox) = Mailbox::<Printer>::unbounded();
+    /// let channel = MessageChannel::<Print, ()>::new(address.clone());
+    ///
+    /// assert_eq!(channel.downcast::<Printer>(), Some(address));
+    /// assert!(channel.downcast::<Scanner>().is_none());
+    /// ```
+    pub fn downcast<A>(&self) -> Option<Address<A, Rc>>
+    where
+        A: 'static,
+        Rc: RefCounter,
+    {
+        self.inner
+            .as_any()
+            .downcast_ref::<Address<A, Rc>>()
+            .cloned()
+    }
 }
 
 #[cfg(feature = "sink")]
@@ -150,12 +245,35 @@ where
     /// To create such a [`MessageChannel`] use an [`Address`] that points to an actor where the
     /// [`Handler`] of a given message has [`Return`](Handler::Return) set to `()`.
     ///
-    /// The provided [`Sink`] will process one message at a time completely and thus enforces
-    /// back-pressure according to the bounds of the actor's mailbox.
+    /// Like the sink of [`Address::into_sink`], the provided [`Sink`] accepts an item once the
+    /// previous one has been queued, thereby enforcing back-pressure according to the bounds of
+    /// the actor's mailbox. Flushing it waits until all items have been queued, and it fails with
+    /// [`Error::Disconnected`](crate::Error::Disconnected) once the actor has stopped, even while
+    /// it is idle.
     ///
     /// [`Sink`]: futures_sink::Sink
     pub fn into_sink(self) -> impl futures_sink::Sink<M, Error = crate::Error> {
-        futures_util::sink::unfold((), move |(), message| self.send(message))
+        crate::address::MailboxSink::new(self)
+    }
+}
+
+#[cfg(feature = "sink")]
+impl<M, Rc> crate::address::SinkTarget<M> for MessageChannel<M, (), Rc>
+where
+    M: Send + 'static,
+{
+    type Sending = SendFuture<ActorErasedSending, Forget>;
+
+    fn is_connected(&self) -> bool {
+        MessageChannel::is_connected(self)
+    }
+
+    fn join(&self) -> ActorJoinHandle {
+        MessageChannel::join(self)
+    }
+
+    fn send_and_forget(&self, message: M) -> Self::Sending {
+        MessageChannel::send_and_forget(self, message)
     }
 }
 
@@ -187,6 +305,7 @@ where
             "MessageChannel<{}, {}, {}, {}>",
             actor_type, message_type, return_type, rc_type
         ))
+        .field("id", &self.inner.id())
         .field("addresses", &self.inner.sender_count())
         .field("mailboxes", &self.inner.receiver_count())
         .finish()
@@ -256,6 +375,62 @@ where
     }
 }
 
+impl<M, R> MessageChannel<M, R, Weak>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    /// Try to upgrade this [`MessageChannel`] to a [`Strong`] one.
+    ///
+    /// This will yield `None` if there are no more other strong channels or addresses around.
+    pub fn try_upgrade(&self) -> Option<MessageChannel<M, R>> {
+        Some(MessageChannel {
+            inner: self.inner.try_upgrade()?,
+        })
+    }
+
+    /// Send a message to the actor if it is still alive, returning `None` if it is not.
+    ///
+    /// Unlike upgrading the channel with [`MessageChannel::try_upgrade`] and sending the message
+    /// separately, this is a single call. The actor is only kept alive until the message has been
+    /// sent, not until it has been handled.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # struct Cache;
+    /// # impl Actor for Cache { type Stop = (); async fn stopped(self) {} }
+    /// struct Invalidate;
+    ///
+    /// impl Handler<Invalidate> for Cache {
+    ///     type Return = ();
+    ///
+    ///     async fn handle(&mut self, _: Invalidate, _: &mut Context<Self>) {}
+    /// }
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let addr = xtra::spawn_smol(Cache, Mailbox::unbounded());
+    ///     let channel = MessageChannel::new(addr.clone()).downgrade();
+    ///     assert_eq!(channel.send_if_alive(Invalidate).unwrap().await, Ok(()));
+    ///
+    ///     drop(addr);
+    ///     assert!(channel.send_if_alive(Invalidate).is_none());
+    /// })
+    /// ```
+    pub fn send_if_alive(
+        &self,
+        message: M,
+    ) -> Option<SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>>> {
+        let channel = self.try_upgrade()?;
+
+        if !channel.is_connected() {
+            return None;
+        }
+
+        Some(channel.send(message))
+    }
+}
+
 /// Functions which apply to any kind of [`MessageChannel`], be they strong or weak.
 impl<M, R, Rc> MessageChannel<M, R, Rc>
 where
@@ -268,22 +443,96 @@ where
             inner: self.inner.to_either(),
         }
     }
+
+    /// Adapt this [`MessageChannel`] to accept messages of type `N`, which are converted to `M`
+    /// with the given function before they are sent to the actor.
+    ///
+    /// This allows a module to send its own messages to an actor which handles a different type of
+    /// message, without either of them knowing about the other's vocabulary. The function is called
+    /// once for every message, when it is sent. The returned channel refers to the same actor, so
+    /// e.g. [`MessageChannel::is_connected`] and [`MessageChannel::name`] behave as on the original
+    /// channel.
+    ///
+    /// ```rust
+    /// # use xtra::prelude::*;
+    /// # #[derive(Default)]
+    /// # struct Log(Vec<String>);
+    /// # impl Actor for Log { type Stop = (); async fn stopped(self) {} }
+    /// struct Append(String);
+    ///
+    /// impl Handler<Append> for Log {
+    ///     type Return = usize;
+    ///
+    ///     async fn handle(&mut self, Append(line): Append, _: &mut Context<Self>) -> usize {
+    ///         self.0.push(line);
+    ///         self.0.len()
+    ///     }
+    /// }
+    ///
+    /// struct Connected(u16);
+    ///
+    /// # #[cfg(feature = "smol")]
+    /// smol::block_on(async {
+    ///     let log = xtra::spawn_smol(Log::default(), Mailbox::unbounded());
+    ///     let events: MessageChannel<Connected, usize> = MessageChannel::new(log)
+    ///         .contramap(|Connected(port)| Append(format!("connected on port {port}")));
+    ///
+    ///     assert_eq!(events.send(Connected(8080)).await, Ok(1));
+    /// })
+    /// ```
+    pub fn contramap<N, F>(self, f: F) -> MessageChannel<N, R, Rc>
+    where
+        N: Send + 'static,
+        F: Fn(N) -> M + Send + Sync + 'static,
+        Rc: 'static,
+    {
+        MessageChannel {
+            inner: Box::new(Contramap {
+                inner: self.inner,
+                f: Arc::new(f),
+            }),
+        }
+    }
 }
 
-trait MessageChannelTrait<M, Rc> {
+pub(crate) trait MessageChannelTrait<M, Rc> {
     type Return: Send + 'static;
 
     fn is_connected(&self) -> bool;
 
+    fn disconnect_reason(&self) -> Option<DisconnectReason>;
+
     fn len(&self) -> usize;
 
     fn capacity(&self) -> Option<usize>;
 
+    /// Listen for the mailbox to have room for another message, if it is bounded.
+    #[cfg(feature = "tower")]
+    fn capacity_listener(&self) -> Option<EventListener> {
+        None
+    }
+
+    fn id(&self) -> ActorId;
+
+    fn name(&self) -> Cow<'static, str>;
+
     fn send(
         &self,
         message: M,
     ) -> SendFuture<ActorErasedSending, ResolveToHandlerReturn<Self::Return>>;
 
+    /// Queue the message right away, giving it back if the actor has stopped.
+    fn try_send(
+        &self,
+        message: M,
+    ) -> Result<SendFuture<ActorErasedSending, ResolveToHandlerReturn<Self::Return>>, M> {
+        if !self.is_connected() {
+            return Err(message);
+        }
+
+        Ok(self.send(message))
+    }
+
     fn clone_channel(
         &self,
     ) -> Box<dyn MessageChannelTrait<M, Rc, Return = Self::Return> + Send + Sync + 'static>;
@@ -298,11 +547,20 @@ trait MessageChannelTrait<M, Rc> {
         &self,
     ) -> Box<dyn MessageChannelTrait<M, Weak, Return = Self::Return> + Send + Sync + 'static>;
 
+    fn try_upgrade(
+        &self,
+    ) -> Option<
+        Box<dyn MessageChannelTrait<M, Strong, Return = Self::Return> + Send + Sync + 'static>,
+    >;
+
     fn sender_count(&self) -> usize;
 
     fn receiver_count(&self) -> usize;
 
-    fn actor_type(&self) -> &str;
+    fn actor_type(&self) -> &'static str;
+
+    /// The channel as [`Any`], so that it can be downcast to the [`Address`] it was built from.
+    fn as_any(&self) -> &dyn Any;
 
     fn to_either(
         &self,
@@ -324,6 +582,10 @@ where
         self.is_connected()
     }
 
+    fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.disconnect_reason()
+    }
+
     fn len(&self) -> usize {
         self.len()
     }
@@ -332,10 +594,30 @@ where
         self.capacity()
     }
 
+    #[cfg(feature = "tower")]
+    fn capacity_listener(&self) -> Option<EventListener> {
+        self.capacity().map(|_| self.0.capacity_listener())
+    }
+
+    fn id(&self) -> ActorId {
+        self.id()
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        self.name()
+    }
+
     fn send(&self, message: M) -> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>> {
         SendFuture::sending_erased(message, self.0.clone())
     }
 
+    fn try_send(
+        &self,
+        message: M,
+    ) -> Result<SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>>, M> {
+        SendFuture::try_sending_erased(message, self.0.clone())
+    }
+
     fn clone_channel(
         &self,
     ) -> Box<dyn MessageChannelTrait<M, Rc, Return = Self::Return> + Send + Sync + 'static> {
@@ -360,6 +642,14 @@ where
         Box::new(Address(self.0.to_tx_weak()))
     }
 
+    fn try_upgrade(
+        &self,
+    ) -> Option<
+        Box<dyn MessageChannelTrait<M, Strong, Return = Self::Return> + Send + Sync + 'static>,
+    > {
+        Some(Box::new(Address(self.0.try_to_tx_strong()?)))
+    }
+
     fn sender_count(&self) -> usize {
         self.0.sender_count()
     }
@@ -368,10 +658,14 @@ where
         self.0.receiver_count()
     }
 
-    fn actor_type(&self) -> &str {
+    fn actor_type(&self) -> &'static str {
         std::any::type_name::<A>()
     }
 
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
     fn to_either(
         &self,
     ) -> Box<dyn MessageChannelTrait<M, Either, Return = Self::Return> + Send + Sync + 'static>
@@ -388,6 +682,128 @@ where
     }
 }
 
+/// A [`MessageChannelTrait`] for messages of type `N`, which converts them to `M` for the channel
+/// it wraps. See [`MessageChannel::contramap`].
+struct Contramap<M, N, R, Rc> {
+    inner: Box<dyn MessageChannelTrait<M, Rc, Return = R> + Send + Sync + 'static>,
+    f: Arc<dyn Fn(N) -> M + Send + Sync>,
+}
+
+impl<M, N, R, Rc> Contramap<M, N, R, Rc> {
+    /// Wrap a channel derived from the one this wraps, e.g. a weak one, with the same function.
+    fn wrap<Rc2>(
+        &self,
+        inner: Box<dyn MessageChannelTrait<M, Rc2, Return = R> + Send + Sync + 'static>,
+    ) -> Box<Contramap<M, N, R, Rc2>> {
+        Box::new(Contramap {
+            inner,
+            f: self.f.clone(),
+        })
+    }
+}
+
+impl<M, N, R, Rc> MessageChannelTrait<N, Rc> for Contramap<M, N, R, Rc>
+where
+    M: Send + 'static,
+    N: Send + 'static,
+    R: Send + 'static,
+    Rc: 'static,
+{
+    type Return = R;
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        self.inner.disconnect_reason()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        self.inner.capacity()
+    }
+
+    #[cfg(feature = "tower")]
+    fn capacity_listener(&self) -> Option<EventListener> {
+        self.inner.capacity_listener()
+    }
+
+    fn id(&self) -> ActorId {
+        self.inner.id()
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        self.inner.name()
+    }
+
+    fn send(&self, message: N) -> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>> {
+        self.inner.send((self.f)(message))
+    }
+
+    fn clone_channel(
+        &self,
+    ) -> Box<dyn MessageChannelTrait<N, Rc, Return = Self::Return> + Send + Sync + 'static> {
+        self.wrap(self.inner.clone_channel())
+    }
+
+    fn join(&self) -> ActorJoinHandle {
+        self.inner.join()
+    }
+
+    fn to_inner_ptr(&self) -> *const () {
+        self.inner.to_inner_ptr()
+    }
+
+    fn is_strong(&self) -> bool {
+        self.inner.is_strong()
+    }
+
+    fn to_weak(
+        &self,
+    ) -> Box<dyn MessageChannelTrait<N, Weak, Return = Self::Return> + Send + Sync + 'static> {
+        self.wrap(self.inner.to_weak())
+    }
+
+    fn try_upgrade(
+        &self,
+    ) -> Option<
+        Box<dyn MessageChannelTrait<N, Strong, Return = Self::Return> + Send + Sync + 'static>,
+    > {
+        Some(self.wrap(self.inner.try_upgrade()?))
+    }
+
+    fn sender_count(&self) -> usize {
+        self.inner.sender_count()
+    }
+
+    fn receiver_count(&self) -> usize {
+        self.inner.receiver_count()
+    }
+
+    fn actor_type(&self) -> &'static str {
+        self.inner.actor_type()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self.inner.as_any()
+    }
+
+    fn to_either(
+        &self,
+    ) -> Box<dyn MessageChannelTrait<N, Either, Return = Self::Return> + Send + Sync + 'static>
+    {
+        self.wrap(self.inner.to_either())
+    }
+
+    fn hash(&self, state: &mut dyn Hasher) {
+        self.inner.hash(state)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::hash::{Hash, Hasher};
@@ -477,4 +893,19 @@ mod test {
             "channels created against different addresses should differ"
         );
     }
+
+    #[test]
+    fn try_send_gives_message_back_once_disconnected() {
+        let (address, mailbox) = Mailbox::<TestActor>::unbounded();
+        let channel = TestMessageChannel::new(address);
+
+        assert!(channel.try_send(TestMessage).is_ok());
+
+        drop(mailbox);
+
+        assert!(
+            matches!(channel.try_send(TestMessage), Err(TestMessage)),
+            "the message should be given back"
+        );
+    }
 }
@@ -0,0 +1,11 @@
+#[cfg(feature = "metrics")]
+mod facade;
+
+#[cfg(feature = "metrics")]
+pub use self::facade::*;
+
+#[cfg(not(feature = "metrics"))]
+mod stub;
+
+#[cfg(not(feature = "metrics"))]
+pub use self::stub::*;
@@ -0,0 +1,80 @@
+use std::borrow::Cow;
+use std::future::Future;
+use std::time::Instant;
+
+/// Number of messages waiting in the mailbox of an actor.
+const MAILBOX_DEPTH: &str = "xtra_mailbox_depth";
+/// Number of messages which have been handled by an actor.
+const MESSAGES_HANDLED: &str = "xtra_messages_handled_total";
+/// Number of messages which were accepted into the mailbox, but dropped before being handled.
+const MESSAGES_DROPPED: &str = "xtra_messages_dropped_total";
+/// Number of messages which were sent to an actor that is no longer running.
+const MESSAGES_DEAD_LETTERED: &str = "xtra_messages_dead_lettered_total";
+/// Number of tasks spawned with [`Context::with_permit`](crate::Context::with_permit) which are
+/// currently running.
+const PERMITS_IN_FLIGHT: &str = "xtra_permits_in_flight";
+/// Time spent in [`Handler::handle`](crate::Handler::handle), in seconds.
+const HANDLER_DURATION: &str = "xtra_handler_duration_seconds";
+
+/// Records the metrics of handling a single message.
+pub struct HandlerMetrics {
+    actor_name: Cow<'static, str>,
+    message_type: Option<&'static str>,
+}
+
+impl HandlerMetrics {
+    pub fn new<A, M>(mailbox: &crate::Mailbox<A>) -> Self {
+        HandlerMetrics {
+            actor_name: mailbox.inner.name(),
+            message_type: mailbox.message_type_labels().then(std::any::type_name::<M>),
+        }
+    }
+
+    /// Record the duration of the handler and count the message as handled once `fut` completes.
+    pub async fn measure<F>(self, fut: F) -> F::Output
+    where
+        F: Future,
+    {
+        let start = Instant::now();
+        let output = fut.await;
+        let elapsed = start.elapsed();
+
+        match self.message_type {
+            Some(message_type) => {
+                let labels = [
+                    ("actor_name", self.actor_name),
+                    ("message_type", Cow::Borrowed(message_type)),
+                ];
+                ::metrics::histogram!(HANDLER_DURATION, &labels).record(elapsed);
+                ::metrics::counter!(MESSAGES_HANDLED, &labels).increment(1);
+            }
+            None => {
+                let labels = [("actor_name", self.actor_name)];
+                ::metrics::histogram!(HANDLER_DURATION, &labels).record(elapsed);
+                ::metrics::counter!(MESSAGES_HANDLED, &labels).increment(1);
+            }
+        }
+
+        output
+    }
+}
+
+pub fn mailbox_depth(actor_name: &str, depth: usize) {
+    ::metrics::gauge!(MAILBOX_DEPTH, "actor_name" => actor_name.to_owned()).set(depth as f64);
+}
+
+pub fn messages_dropped(actor_name: &str, count: usize) {
+    if count > 0 {
+        ::metrics::counter!(MESSAGES_DROPPED, "actor_name" => actor_name.to_owned())
+            .increment(count as u64);
+    }
+}
+
+pub fn message_dead_lettered(actor_name: &str) {
+    ::metrics::counter!(MESSAGES_DEAD_LETTERED, "actor_name" => actor_name.to_owned()).increment(1);
+}
+
+pub fn permits_in_flight(actor_name: &str, in_flight: usize) {
+    ::metrics::gauge!(PERMITS_IN_FLIGHT, "actor_name" => actor_name.to_owned())
+        .set(in_flight as f64);
+}
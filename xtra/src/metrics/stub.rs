@@ -0,0 +1,20 @@
+pub struct HandlerMetrics {}
+
+impl HandlerMetrics {
+    #[allow(unknown_lints, clippy::extra_unused_type_parameters)] // Needs to be consistent with non-stub impl.
+    pub fn new<A, M>(_mailbox: &crate::Mailbox<A>) -> Self {
+        HandlerMetrics {}
+    }
+
+    pub fn measure<F>(self, fut: F) -> F {
+        fut
+    }
+}
+
+pub fn mailbox_depth(_actor_name: &str, _depth: usize) {}
+
+pub fn messages_dropped(_actor_name: &str, _count: usize) {}
+
+pub fn message_dead_lettered(_actor_name: &str) {}
+
+pub fn permits_in_flight(_actor_name: &str, _in_flight: usize) {}
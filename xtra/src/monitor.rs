@@ -0,0 +1,33 @@
+//! Reacting to another actor stopping, like a monitor in Erlang. See
+//! [`Address::monitor`](crate::Address::monitor).
+
+use crate::{ActorId, DisconnectReason, Disconnected};
+
+/// The message which [`Address::monitor`](crate::Address::monitor) delivers once the monitored
+/// actor has stopped.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Down {
+    actor: Disconnected,
+    reason: Option<DisconnectReason>,
+}
+
+impl Down {
+    pub(crate) fn new(actor: Disconnected, reason: Option<DisconnectReason>) -> Self {
+        Down { actor, reason }
+    }
+
+    /// The actor which stopped.
+    pub fn actor(&self) -> &Disconnected {
+        &self.actor
+    }
+
+    /// The identifier of the actor which stopped, to tell apart several monitored actors.
+    pub fn actor_id(&self) -> ActorId {
+        self.actor.actor_id()
+    }
+
+    /// Why the actor stopped, as reported by [`Address::disconnect_reason`](crate::Address::disconnect_reason).
+    pub fn reason(&self) -> Option<DisconnectReason> {
+        self.reason
+    }
+}
@@ -0,0 +1,72 @@
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use event_listener::Event;
+
+/// Limits the number of tasks spawned with [`Context::with_permit`](crate::Context::with_permit)
+/// which run at the same time.
+#[derive(Default)]
+pub(crate) struct Permits {
+    in_flight: AtomicUsize,
+    released: Event,
+}
+
+impl Permits {
+    /// Wait until fewer than `limit` permits are held, then take one.
+    pub(crate) async fn acquire(
+        self: Arc<Self>,
+        limit: usize,
+        actor_name: Cow<'static, str>,
+    ) -> Permit {
+        loop {
+            let in_flight = self.in_flight.load(Ordering::SeqCst);
+
+            if in_flight < limit {
+                if self
+                    .in_flight
+                    .compare_exchange(in_flight, in_flight + 1, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    crate::metrics::permits_in_flight(&actor_name, in_flight + 1);
+                    return Permit {
+                        permits: self,
+                        actor_name,
+                    };
+                }
+
+                continue;
+            }
+
+            // Register before checking again, so that a permit released in between is not missed.
+            let released = self.released.listen();
+
+            if self.in_flight.load(Ordering::SeqCst) < limit {
+                continue;
+            }
+
+            released.await;
+        }
+    }
+
+    /// The number of permits which are currently held.
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// A permit taken from [`Permits`], which is released when dropped.
+pub(crate) struct Permit {
+    permits: Arc<Permits>,
+    actor_name: Cow<'static, str>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let in_flight = self.permits.in_flight.fetch_sub(1, Ordering::SeqCst) - 1;
+        crate::metrics::permits_in_flight(&self.actor_name, in_flight);
+
+        // Waiters may wait with different limits, so all of them have to check again.
+        self.permits.released.notify(usize::MAX);
+    }
+}
@@ -0,0 +1,308 @@
+//! Limiting the rate at which one sender sends messages to an actor, e.g. a client which may only
+//! issue so many requests per second to a shared service.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{cmp, fmt};
+
+use crate::refcount::{RefCounter, Strong};
+use crate::send_future::{Forget, ResolveToHandlerReturn};
+use crate::{ActorNamedSending, Address, Error, Handler, SendFuture};
+
+/// How many messages a [`RateLimitedAddress`] may send, and how quickly.
+///
+/// The quota is enforced with a token bucket: every message takes a token, and one token is added
+/// per period, up to the size of the burst. The bucket starts out full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Quota {
+    period: Duration,
+    burst: u32,
+}
+
+impl Quota {
+    /// Allow `n` messages per second, all of which may be sent at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn per_second(n: u32) -> Self {
+        assert!(n > 0, "a quota must allow at least one message");
+
+        Quota {
+            period: Duration::from_secs(1) / n,
+            burst: n,
+        }
+    }
+
+    /// Allow one message per `period`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn with_period(period: Duration) -> Self {
+        assert!(!period.is_zero(), "the period of a quota must not be zero");
+
+        Quota { period, burst: 1 }
+    }
+
+    /// Allow up to `burst` messages to be sent at once, after no messages have been sent for long
+    /// enough.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `burst` is zero.
+    pub fn allow_burst(self, burst: u32) -> Self {
+        assert!(burst > 0, "a quota must allow at least one message");
+
+        Quota { burst, ..self }
+    }
+}
+
+/// An [`Address`] which limits the rate at which messages are sent through it according to a
+/// [`Quota`]. Created by [`Address::rate_limited`].
+///
+/// Clones of a [`RateLimitedAddress`] share its quota. The actor and other addresses to it are not
+/// limited, so a quota applies to a sender rather than to the actor.
+///
+/// Waiting for the quota and measuring the time since messages were sent uses the
+/// [`Timer`](crate::runtime::Timer) configured for the actor's [`Mailbox`](crate::Mailbox). Without
+/// one, a timer thread shared by all actors is used.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// use xtra::rate_limit::Quota;
+///
+/// # struct Service;
+/// # impl Actor for Service { type Stop = (); async fn stopped(self) {} }
+/// struct Request;
+///
+/// impl Handler<Request> for Service {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: Request, _: &mut Context<Self>) {}
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let address = xtra::spawn_smol(Service, Mailbox::unbounded());
+///     let client = address.rate_limited(Quota::per_second(2));
+///
+///     client.send(Request).await.unwrap();
+///     client.send(Request).await.unwrap();
+///     assert!(client.try_send(Request).is_err());
+/// })
+/// ```
+pub struct RateLimitedAddress<A, Rc: RefCounter = Strong> {
+    address: Address<A, Rc>,
+    quota: Quota,
+    bucket: Arc<spin::Mutex<Bucket>>,
+}
+
+struct Bucket {
+    tokens: u32,
+    /// When the last token was added, or since when the bucket is full.
+    refilled: Option<Instant>,
+}
+
+impl<A, Rc: RefCounter> RateLimitedAddress<A, Rc> {
+    pub(crate) fn new(address: Address<A, Rc>, quota: Quota) -> Self {
+        RateLimitedAddress {
+            address,
+            quota,
+            bucket: Arc::new(spin::Mutex::new(Bucket {
+                tokens: quota.burst,
+                refilled: None,
+            })),
+        }
+    }
+
+    /// Send a message to the actor like [`Address::send`], once the quota allows it, and resolve
+    /// to the [`Return`](Handler::Return) value of the handler.
+    pub async fn send<M>(&self, message: M) -> Result<<A as Handler<M>>::Return, Error>
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        self.acquire().await;
+        self.address.send(message).await
+    }
+
+    /// Send a message to the actor like [`Address::send_and_forget`], once the quota allows it,
+    /// and resolve once the message has been queued.
+    pub async fn send_and_forget<M>(&self, message: M) -> Result<(), Error>
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        self.acquire().await;
+        self.address.send_and_forget(message).await
+    }
+
+    /// Send a message to the actor like [`Address::send`] if the quota allows it right away, or
+    /// give it back in a [`RateLimited`] error.
+    #[allow(clippy::type_complexity)]
+    pub fn try_send<M>(
+        &self,
+        message: M,
+    ) -> Result<
+        SendFuture<ActorNamedSending<A, Rc>, ResolveToHandlerReturn<<A as Handler<M>>::Return>>,
+        RateLimited<M>,
+    >
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        match self.try_acquire() {
+            Ok(()) => Ok(self.address.send(message)),
+            Err(retry_after) => Err(RateLimited {
+                message,
+                retry_after,
+            }),
+        }
+    }
+
+    /// Like [`RateLimitedAddress::try_send`], but discard the [`Return`](Handler::Return) value
+    /// like [`Address::send_and_forget`].
+    pub fn try_send_and_forget<M>(
+        &self,
+        message: M,
+    ) -> Result<SendFuture<ActorNamedSending<A, Rc>, Forget>, RateLimited<M>>
+    where
+        M: Send + 'static,
+        A: Handler<M>,
+    {
+        match self.try_acquire() {
+            Ok(()) => Ok(self.address.send_and_forget(message)),
+            Err(retry_after) => Err(RateLimited {
+                message,
+                retry_after,
+            }),
+        }
+    }
+
+    /// The number of messages which the quota allows to be sent right away.
+    pub fn available(&self) -> u32 {
+        let now = self.now();
+        let mut bucket = self.bucket.lock();
+        bucket.refill(self.quota, now);
+
+        bucket.tokens
+    }
+
+    /// The address of the actor to which the messages are sent.
+    pub fn address(&self) -> &Address<A, Rc> {
+        &self.address
+    }
+
+    /// Wait until the quota allows a message to be sent, and take it from the quota.
+    async fn acquire(&self) {
+        while let Err(retry_after) = self.try_acquire() {
+            self.address.0.timer_or_fallback().sleep(retry_after).await;
+        }
+    }
+
+    /// Take a message from the quota, or return how long it takes until the quota allows one.
+    fn try_acquire(&self) -> Result<(), Duration> {
+        let now = self.now();
+        let mut bucket = self.bucket.lock();
+        bucket.refill(self.quota, now);
+
+        if bucket.tokens == 0 {
+            let refilled = bucket.refilled.unwrap_or(now);
+            return Err(self.quota.period - now.saturating_duration_since(refilled));
+        }
+
+        bucket.tokens -= 1;
+
+        Ok(())
+    }
+
+    fn now(&self) -> Instant {
+        self.address.0.timer_or_fallback().now()
+    }
+}
+
+impl Bucket {
+    /// Add the tokens for all periods which have passed since the last token was added.
+    fn refill(&mut self, quota: Quota, now: Instant) {
+        let Some(refilled) = self.refilled.filter(|_| self.tokens < quota.burst) else {
+            // The period until the next token only starts once a token has been taken.
+            self.refilled = Some(now);
+            return;
+        };
+
+        let elapsed = now.saturating_duration_since(refilled);
+        let periods = elapsed.as_nanos() / quota.period.as_nanos();
+
+        if periods == 0 {
+            return;
+        }
+
+        let missing = quota.burst - self.tokens;
+
+        if periods >= u128::from(missing) {
+            self.tokens = quota.burst;
+            self.refilled = Some(now);
+        } else {
+            // Less than `missing` periods, so this fits into a u32.
+            let periods = periods as u32;
+            self.tokens = cmp::min(quota.burst, self.tokens + periods);
+            self.refilled = Some(refilled + quota.period * periods);
+        }
+    }
+}
+
+impl<A, Rc: RefCounter> Clone for RateLimitedAddress<A, Rc> {
+    fn clone(&self) -> Self {
+        RateLimitedAddress {
+            address: self.address.clone(),
+            quota: self.quota,
+            bucket: self.bucket.clone(),
+        }
+    }
+}
+
+impl<A, Rc: RefCounter> fmt::Debug for RateLimitedAddress<A, Rc> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitedAddress")
+            .field("address", &self.address)
+            .field("quota", &self.quota)
+            .field("tokens", &self.bucket.lock().tokens)
+            .finish()
+    }
+}
+
+/// The error returned by [`RateLimitedAddress::try_send`] if the quota does not allow sending a
+/// message right away, giving the message back.
+pub struct RateLimited<M> {
+    message: M,
+    retry_after: Duration,
+}
+
+impl<M> RateLimited<M> {
+    /// How long it takes until the quota allows the next message.
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after
+    }
+
+    /// Take back the message which could not be sent.
+    pub fn into_inner(self) -> M {
+        self.message
+    }
+}
+
+impl<M> fmt::Debug for RateLimited<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimited")
+            .field("retry_after", &self.retry_after)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<M> fmt::Display for RateLimited<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Rate limit exceeded, retry after {:?}", self.retry_after)
+    }
+}
+
+impl<M> std::error::Error for RateLimited<M> {}
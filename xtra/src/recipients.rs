@@ -0,0 +1,431 @@
+//! Sending a message to a group of actors and gathering their replies, e.g. for scatter-gather,
+//! or publishing it to a group of subscribers which come and go.
+
+use std::fmt;
+use std::future::poll_fn;
+use std::ops::ControlFlow;
+use std::task::Poll;
+
+use futures_util::FutureExt;
+
+use crate::message_channel::MessageChannel;
+use crate::refcount::{Strong, Weak};
+use crate::send_future::ResolveToHandlerReturn;
+use crate::{ActorErasedSending, Error, SendFuture};
+
+/// A group of actors which can all handle messages of type `M`, returning `R`.
+///
+/// Where [`Address::broadcast`](crate::Address::broadcast) delivers a message to all actors on a
+/// single address without replies, [`Recipients::gather`] sends a message to each actor of the
+/// group and collects their replies.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// use xtra::recipients::Recipients;
+///
+/// # struct Shard(u32);
+/// # impl Actor for Shard { type Stop = (); async fn stopped(self) {} }
+/// #[derive(Clone)]
+/// struct Count;
+///
+/// impl Handler<Count> for Shard {
+///     type Return = u32;
+///
+///     async fn handle(&mut self, _: Count, _: &mut Context<Self>) -> u32 {
+///         self.0
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let recipients = [1, 2, 3]
+///         .map(|count| MessageChannel::new(xtra::spawn_smol(Shard(count), Mailbox::unbounded())))
+///         .into_iter()
+///         .collect::<Recipients<Count, u32>>();
+///
+///     assert_eq!(recipients.gather(Count).await, [Ok(1), Ok(2), Ok(3)]);
+/// })
+/// ```
+pub struct Recipients<M, R, Rc = Strong> {
+    channels: Vec<MessageChannel<M, R, Rc>>,
+}
+
+impl<M, R, Rc> Recipients<M, R, Rc>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    /// Create an empty group of recipients.
+    pub fn new() -> Self {
+        Recipients {
+            channels: Vec::new(),
+        }
+    }
+
+    /// Add an actor to the group. Its replies are gathered at the index of this call.
+    pub fn push(&mut self, channel: MessageChannel<M, R, Rc>) {
+        self.channels.push(channel);
+    }
+
+    /// The number of actors in the group.
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Whether the group is empty.
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// The number of actors in the group which are still running, without sending them anything.
+    pub fn live_count(&self) -> usize {
+        self.channels
+            .iter()
+            .filter(|channel| channel.is_connected())
+            .count()
+    }
+
+    /// The actors in the group, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = &MessageChannel<M, R, Rc>> {
+        self.channels.iter()
+    }
+
+    /// Send a clone of `message` to every actor of the group and wait for all of their replies.
+    ///
+    /// The reply of each actor is at the index it was added at. Like with
+    /// [`MessageChannel::send`], the reply of an actor which has stopped is an [`Error`].
+    pub async fn gather(&self, message: M) -> Vec<Result<R, Error>>
+    where
+        M: Clone,
+    {
+        let mut replies = self.channels.iter().map(|_| None).collect::<Vec<_>>();
+
+        self.collect(message, |index, reply| {
+            replies[index] = Some(reply);
+            ControlFlow::<()>::Continue(())
+        })
+        .await;
+
+        replies
+            .into_iter()
+            .map(|reply| reply.expect("all replies to be gathered"))
+            .collect()
+    }
+
+    /// Like [`Recipients::gather`], but return the index and error of the first actor which fails,
+    /// as soon as it fails.
+    ///
+    /// Replies which are still outstanding at that point are discarded. Their messages may still be
+    /// handled, as they have possibly been queued already.
+    pub async fn try_gather(&self, message: M) -> Result<Vec<R>, (usize, Error)>
+    where
+        M: Clone,
+    {
+        let mut replies = self.channels.iter().map(|_| None).collect::<Vec<_>>();
+
+        let failed = self
+            .collect(message, |index, reply| match reply {
+                Ok(reply) => {
+                    replies[index] = Some(reply);
+                    ControlFlow::Continue(())
+                }
+                Err(error) => ControlFlow::Break((index, error)),
+            })
+            .await;
+
+        match failed {
+            Some(failed) => Err(failed),
+            None => Ok(replies
+                .into_iter()
+                .map(|reply| reply.expect("all replies to be gathered"))
+                .collect()),
+        }
+    }
+
+    /// Like [`Recipients::gather`], but return the index and reply of the first actor which
+    /// succeeds, as soon as it succeeds, or the errors of all actors if none succeeds.
+    ///
+    /// Replies which are still outstanding at that point are discarded. Their messages may still be
+    /// handled, as they have possibly been queued already.
+    pub async fn gather_first_ok(&self, message: M) -> Result<(usize, R), Vec<Error>>
+    where
+        M: Clone,
+    {
+        let mut errors = self.channels.iter().map(|_| None).collect::<Vec<_>>();
+
+        let succeeded = self
+            .collect(message, |index, reply| match reply {
+                Ok(reply) => ControlFlow::Break((index, reply)),
+                Err(error) => {
+                    errors[index] = Some(error);
+                    ControlFlow::Continue(())
+                }
+            })
+            .await;
+
+        match succeeded {
+            Some(succeeded) => Ok(succeeded),
+            None => Err(errors
+                .into_iter()
+                .map(|error| error.expect("all errors to be gathered"))
+                .collect()),
+        }
+    }
+
+    /// Send `message` to every actor and pass each reply to `f` together with the index of the
+    /// actor as it arrives, until `f` breaks or all replies have arrived.
+    async fn collect<T>(
+        &self,
+        message: M,
+        mut f: impl FnMut(usize, Result<R, Error>) -> ControlFlow<T>,
+    ) -> Option<T>
+    where
+        M: Clone,
+    {
+        let mut pending = self
+            .channels
+            .iter()
+            .map(|channel| Some(channel.send(message.clone())))
+            .collect::<Vec<Option<SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>>>>>();
+
+        poll_fn(|cx| {
+            let mut done = true;
+
+            for (index, slot) in pending.iter_mut().enumerate() {
+                let Some(reply) = slot else {
+                    continue;
+                };
+
+                match reply.poll_unpin(cx) {
+                    Poll::Ready(reply) => {
+                        *slot = None;
+
+                        if let ControlFlow::Break(result) = f(index, reply) {
+                            return Poll::Ready(Some(result));
+                        }
+                    }
+                    Poll::Pending => done = false,
+                }
+            }
+
+            if done {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<M, R, Rc> Default for Recipients<M, R, Rc>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, R, Rc> Clone for Recipients<M, R, Rc>
+where
+    R: Send + 'static,
+{
+    fn clone(&self) -> Self {
+        Recipients {
+            channels: self.channels.clone(),
+        }
+    }
+}
+
+impl<M, R, Rc> fmt::Debug for Recipients<M, R, Rc>
+where
+    R: Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.channels).finish()
+    }
+}
+
+impl<M, R, Rc> FromIterator<MessageChannel<M, R, Rc>> for Recipients<M, R, Rc> {
+    fn from_iter<I: IntoIterator<Item = MessageChannel<M, R, Rc>>>(iter: I) -> Self {
+        Recipients {
+            channels: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<M, R, Rc> Extend<MessageChannel<M, R, Rc>> for Recipients<M, R, Rc> {
+    fn extend<I: IntoIterator<Item = MessageChannel<M, R, Rc>>>(&mut self, iter: I) {
+        self.channels.extend(iter);
+    }
+}
+
+/// A group of subscribers which can all handle messages of type `M`, held through weak
+/// [`MessageChannel`]s so that the group does not keep them alive.
+///
+/// Subscribers which have stopped are removed from the group whenever a message is published to
+/// it with [`WeakRecipients::broadcast`], so the group does not grow with stale subscribers.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// use xtra::recipients::WeakRecipients;
+///
+/// # struct Dashboard;
+/// # impl Actor for Dashboard { type Stop = (); async fn stopped(self) {} }
+/// #[derive(Clone)]
+/// struct PriceChanged(u64);
+///
+/// impl Handler<PriceChanged> for Dashboard {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: PriceChanged, _: &mut Context<Self>) {}
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let first = xtra::spawn_smol(Dashboard, Mailbox::unbounded());
+///     let second = xtra::spawn_smol(Dashboard, Mailbox::unbounded());
+///
+///     let mut subscribers = WeakRecipients::new();
+///     subscribers.push(MessageChannel::new(first.downgrade()));
+///     subscribers.push(MessageChannel::new(second.downgrade()));
+///
+///     let stopped = second.join();
+///     drop(second);
+///     stopped.await;
+///
+///     assert_eq!(subscribers.broadcast(PriceChanged(42)).await, 1);
+///     assert_eq!(subscribers.len(), 1);
+/// })
+/// ```
+pub struct WeakRecipients<M, R = ()> {
+    channels: Vec<MessageChannel<M, R, Weak>>,
+}
+
+impl<M, R> WeakRecipients<M, R>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    /// Create an empty group of subscribers.
+    pub fn new() -> Self {
+        WeakRecipients {
+            channels: Vec::new(),
+        }
+    }
+
+    /// Add a subscriber to the group.
+    pub fn push(&mut self, channel: MessageChannel<M, R, Weak>) {
+        self.channels.push(channel);
+    }
+
+    /// The number of subscribers in the group, including those which have stopped since the last
+    /// [`WeakRecipients::broadcast`].
+    pub fn len(&self) -> usize {
+        self.channels.len()
+    }
+
+    /// Whether the group is empty.
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// The number of subscribers in the group which are still running, without sending them
+    /// anything. The subscribers are not upgraded, so this does not keep any of them alive.
+    pub fn live_count(&self) -> usize {
+        self.channels
+            .iter()
+            .filter(|channel| channel.is_connected())
+            .count()
+    }
+
+    /// Send a clone of `message` to every subscriber which is still alive, removing those which
+    /// have stopped. Returns the number of subscribers the message was delivered to.
+    ///
+    /// The message is sent to all subscribers at once, and this resolves once it has been queued in
+    /// all of their mailboxes, discarding the [`Return`](crate::Handler::Return) values of their
+    /// handlers. A subscriber is only kept alive until the message has been queued.
+    pub async fn broadcast(&mut self, message: M) -> usize
+    where
+        M: Clone,
+    {
+        let mut pending = Vec::with_capacity(self.channels.len());
+
+        self.channels.retain(|channel| {
+            let Some(channel) = channel.try_upgrade().filter(|it| it.is_connected()) else {
+                return false;
+            };
+
+            pending.push(Some(channel.send_and_forget(message.clone())));
+            true
+        });
+
+        let mut delivered = vec![false; pending.len()];
+
+        poll_fn(|cx| {
+            let mut done = true;
+
+            for (index, slot) in pending.iter_mut().enumerate() {
+                let Some(send) = slot else {
+                    continue;
+                };
+
+                match send.poll_unpin(cx) {
+                    Poll::Ready(result) => {
+                        *slot = None;
+                        delivered[index] = result.is_ok();
+                    }
+                    Poll::Pending => done = false,
+                }
+            }
+
+            if done {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        // Subscribers which stopped while the message was being sent are removed as well.
+        let mut delivered = delivered.into_iter();
+        self.channels.retain(|_| delivered.next().unwrap_or(true));
+
+        self.channels.len()
+    }
+}
+
+impl<M, R> Default for WeakRecipients<M, R>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, R> fmt::Debug for WeakRecipients<M, R>
+where
+    R: Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.channels).finish()
+    }
+}
+
+impl<M, R> FromIterator<MessageChannel<M, R, Weak>> for WeakRecipients<M, R> {
+    fn from_iter<I: IntoIterator<Item = MessageChannel<M, R, Weak>>>(iter: I) -> Self {
+        WeakRecipients {
+            channels: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<M, R> Extend<MessageChannel<M, R, Weak>> for WeakRecipients<M, R> {
+    fn extend<I: IntoIterator<Item = MessageChannel<M, R, Weak>>>(&mut self, iter: I) {
+        self.channels.extend(iter);
+    }
+}
@@ -1,13 +1,13 @@
 use std::future::Future;
 use std::mem;
 use std::pin::Pin;
-use std::sync::Arc;
 use std::task::{Context, Poll};
 
-use futures_core::FusedFuture;
+use futures_core::{FusedFuture, FusedStream, Stream};
 use futures_util::FutureExt;
 
-use crate::chan::{self, ActorMessage, BroadcastQueue, Rx, WaitingReceiver};
+use crate::chan::{ActorMessage, WaitingReceiver};
+use crate::Mailbox;
 
 /// A future which will resolve to the next message to be handled by the actor.
 ///
@@ -29,20 +29,12 @@ pub struct ReceiveFuture<A>(Receiving<A>);
 /// A message sent to a given actor, or a notification that it should shut down.
 pub struct Message<A> {
     pub(crate) inner: ActorMessage<A>,
-
-    pub(crate) channel: chan::Ptr<A, Rx>,
-    pub(crate) broadcast_mailbox: Arc<BroadcastQueue<A>>,
+    pub(crate) mailbox: Mailbox<A>,
 }
 
 impl<A> ReceiveFuture<A> {
-    pub(crate) fn new(
-        channel: chan::Ptr<A, Rx>,
-        broadcast_mailbox: Arc<BroadcastQueue<A>>,
-    ) -> Self {
-        Self(Receiving::New {
-            channel,
-            broadcast_mailbox,
-        })
+    pub(crate) fn new(mailbox: Mailbox<A>) -> Self {
+        Self(Receiving::New(mailbox))
     }
 }
 
@@ -60,10 +52,7 @@ impl<A> Future for ReceiveFuture<A> {
 /// implementation details like the variant names into the public API.
 #[must_use = "Futures do nothing unless polled"]
 enum Receiving<A> {
-    New {
-        channel: chan::Ptr<A, Rx>,
-        broadcast_mailbox: Arc<BroadcastQueue<A>>,
-    },
+    New(Mailbox<A>),
     Waiting(Waiting<A>),
     Done,
 }
@@ -77,42 +66,34 @@ enum Receiving<A> {
 /// mailbox in such a scenario.
 #[must_use = "Futures do nothing unless polled"]
 pub struct Waiting<A> {
-    channel: Option<chan::Ptr<A, Rx>>,
-    broadcast_mailbox: Option<Arc<BroadcastQueue<A>>>,
+    mailbox: Option<Mailbox<A>>,
     waiting_receiver: WaitingReceiver<A>,
 }
 
 impl<A> Future for Waiting<A> {
-    type Output = Result<
-        (ActorMessage<A>, chan::Ptr<A, Rx>, Arc<BroadcastQueue<A>>),
-        (chan::Ptr<A, Rx>, Arc<BroadcastQueue<A>>),
-    >;
+    type Output = Result<(ActorMessage<A>, Mailbox<A>), Mailbox<A>>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
 
+        let mailbox = this
+            .mailbox
+            .as_ref()
+            .expect("to not be polled after completion");
         let maybe_message = futures_util::ready!(this.waiting_receiver.poll(
-            this.channel
-                .as_mut()
-                .expect("to not be polled after completion"),
-            this.broadcast_mailbox
-                .as_mut()
-                .expect("to not be polled after completion"),
+            &mailbox.inner,
+            &mailbox.broadcast_mailbox,
             cx
         ));
 
-        let channel = this
-            .channel
-            .take()
-            .expect("to not be polled after completion");
         let mailbox = this
-            .broadcast_mailbox
+            .mailbox
             .take()
             .expect("to not be polled after completion");
 
         let result = match maybe_message {
-            None => Err((channel, mailbox)),
-            Some(msg) => Ok((msg, channel, mailbox)),
+            None => Err(mailbox),
+            Some(msg) => Ok((msg, mailbox)),
         };
 
         Poll::Ready(result)
@@ -122,9 +103,10 @@ impl<A> Future for Waiting<A> {
 impl<A> Drop for Waiting<A> {
     fn drop(&mut self) {
         if let Some(msg) = self.waiting_receiver.cancel() {
-            self.channel
-                .as_mut()
+            self.mailbox
+                .as_ref()
                 .expect("to not have message on drop but channel is gone")
+                .inner
                 .requeue_message(msg);
         }
     }
@@ -138,43 +120,44 @@ impl<A> Future for Receiving<A> {
 
         loop {
             match mem::replace(this, Receiving::Done) {
-                Receiving::New {
-                    channel,
-                    broadcast_mailbox,
-                } => match channel.try_recv(broadcast_mailbox.as_ref()) {
-                    Ok(inner) => {
-                        return Poll::Ready(Message {
-                            inner,
-                            channel,
-                            broadcast_mailbox,
-                        })
+                Receiving::New(mailbox) => {
+                    mailbox.fire_due_timers();
+
+                    if let Some(inner) = mailbox.next_deferred() {
+                        return Poll::Ready(Message { inner, mailbox });
                     }
-                    Err(waiting) => {
-                        *this = Receiving::Waiting(Waiting {
-                            channel: Some(channel),
-                            broadcast_mailbox: Some(broadcast_mailbox),
-                            waiting_receiver: waiting,
-                        });
+
+                    match mailbox.inner.try_recv(mailbox.broadcast_mailbox.as_ref()) {
+                        Ok(inner) => return Poll::Ready(Message { inner, mailbox }),
+                        Err(waiting) => {
+                            *this = Receiving::Waiting(Waiting {
+                                mailbox: Some(mailbox),
+                                waiting_receiver: waiting,
+                            });
+                        }
                     }
-                },
+                }
                 Receiving::Waiting(mut inner) => match inner.poll_unpin(cx) {
-                    Poll::Ready(Ok((msg, channel, broadcast_mailbox))) => {
+                    Poll::Ready(Ok((msg, mailbox))) => {
                         return Poll::Ready(Message {
                             inner: msg,
-                            channel,
-                            broadcast_mailbox,
+                            mailbox,
                         })
                     }
-                    Poll::Ready(Err((channel, broadcast_mailbox))) => {
+                    Poll::Ready(Err(mailbox)) => {
                         // False positive wake up, try receive again.
-                        *this = Receiving::New {
-                            channel,
-                            broadcast_mailbox,
-                        };
+                        *this = Receiving::New(mailbox);
                     }
                     Poll::Pending => {
+                        let mailbox = inner.mailbox.as_ref().expect("to not be completed");
+                        let timer_elapsed = mailbox.poll_timers(cx).is_ready();
                         *this = Receiving::Waiting(inner);
-                        return Poll::Pending;
+
+                        // A timer which fired while waiting queued its message, which has been
+                        // handed to this receiver if it was first in line.
+                        if !timer_elapsed {
+                            return Poll::Pending;
+                        }
                     }
                 },
                 Receiving::Done => panic!("polled after completion"),
@@ -194,3 +177,62 @@ impl<A> FusedFuture for ReceiveFuture<A> {
         self.0.is_terminated()
     }
 }
+
+/// A stream of the messages to be handled by the actor, created by
+/// [`Mailbox::into_stream`].
+///
+/// The stream ends after the message which instructs the actor to shut down, i.e. once all strong
+/// addresses have been dropped or [`Context::stop_all`](crate::Context::stop_all) has been called.
+/// Like any message, it has to be dispatched to the actor to stop it.
+///
+/// Like [`ReceiveFuture`], this stream is cancellation-safe in that no messages are lost if it is
+/// dropped while waiting for a message.
+#[must_use = "Streams do nothing unless polled"]
+pub struct MessageStream<A> {
+    mailbox: Mailbox<A>,
+    receiving: Option<ReceiveFuture<A>>,
+    terminated: bool,
+}
+
+impl<A> MessageStream<A> {
+    pub(crate) fn new(mailbox: Mailbox<A>) -> Self {
+        MessageStream {
+            mailbox,
+            receiving: None,
+            terminated: false,
+        }
+    }
+
+    /// The [`Mailbox`] from which the messages are received, e.g. to pass to
+    /// [`Actor::started`](crate::Actor::started).
+    pub fn mailbox(&self) -> &Mailbox<A> {
+        &self.mailbox
+    }
+}
+
+impl<A> Stream for MessageStream<A> {
+    type Item = Message<A>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Message<A>>> {
+        let this = self.get_mut();
+
+        if this.terminated {
+            return Poll::Ready(None);
+        }
+
+        let message = futures_util::ready!(this
+            .receiving
+            .get_or_insert_with(|| this.mailbox.next())
+            .poll_unpin(cx));
+        this.receiving = None;
+        this.terminated = message.inner.is_shutdown();
+
+        Poll::Ready(Some(message))
+    }
+}
+
+impl<A> FusedStream for MessageStream<A> {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
@@ -0,0 +1,480 @@
+//! A global registry for looking up actors by the messages they handle, without passing their
+//! addresses around.
+//!
+//! An actor registers itself with [`Mailbox::register_as`](crate::Mailbox::register_as) or
+//! [`Mailbox::register_named_as`](crate::Mailbox::register_named_as), which are also available on
+//! [`Context`](crate::Context). Registrations are keyed by the type of [`MessageChannel`] they are
+//! looked up as, i.e. the message type `M` and the return type `R`, optionally paired with a name.
+//! They are removed automatically once the actor stops.
+//!
+//! The registry does not keep actors alive. Lookups only return channels to actors which are still
+//! running. A [`ResilientChannel`] looks up the actor again once it has stopped, e.g. to reach the
+//! actor which replaced it.
+//!
+//! ```rust
+//! # use xtra::prelude::*;
+//! use xtra::registry::Registry;
+//!
+//! # struct Config;
+//! struct Get(&'static str);
+//!
+//! impl Actor for Config {
+//!     type Stop = ();
+//!
+//!     async fn started(&mut self, mailbox: &Mailbox<Self>) -> Result<(), ()> {
+//!         mailbox.register_as::<Get>();
+//!         Ok(())
+//!     }
+//!
+//!     async fn stopped(self) {}
+//! }
+//!
+//! impl Handler<Get> for Config {
+//!     type Return = Option<String>;
+//!
+//!     async fn handle(&mut self, Get(key): Get, _: &mut Context<Self>) -> Option<String> {
+//!         (key == "name").then(|| "xtra".to_owned())
+//!     }
+//! }
+//!
+//! # #[cfg(feature = "smol")]
+//! smol::block_on(async {
+//!     let addr = xtra::spawn_smol(Config, Mailbox::unbounded());
+//! #   while Registry::get::<Get, Option<String>>().is_none() { smol::future::yield_now().await }
+//!
+//!     let config = Registry::get::<Get, Option<String>>().unwrap();
+//!     assert_eq!(config.send(Get("name")).await, Ok(Some("xtra".to_owned())));
+//! #   drop(addr);
+//! })
+//! ```
+
+use std::any::{Any, TypeId};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
+
+use crate::message_channel::MessageChannel;
+use crate::refcount::Weak;
+use crate::runtime::Timer;
+use crate::send_future::{ResolveToHandlerReturn, SendFuture};
+use crate::{ActorErasedSending, Error};
+
+/// The number of shards the registry is split into, to keep lock contention low.
+const SHARDS: usize = 16;
+
+/// The global registry of actors. See the [module level documentation](self) for more.
+#[derive(Debug)]
+pub struct Registry(());
+
+/// The registrations for one type of [`MessageChannel`], guarded by the lock of their shard.
+type Shard = Mutex<HashMap<TypeId, Slot>>;
+
+#[derive(Default)]
+struct Slot {
+    unnamed: Vec<Entry>,
+    named: BTreeMap<Cow<'static, str>, Vec<Entry>>,
+}
+
+struct Entry {
+    /// Identifies the actor which registered this entry, to remove it once the actor stops.
+    owner: usize,
+    /// A [`MessageChannel`] of the type the [`Slot`] is keyed by, with a [`Weak`] reference count.
+    channel: Box<dyn Any + Send + Sync>,
+}
+
+impl Registry {
+    /// Look up an actor which registered itself for messages of type `M` without a name, returning
+    /// `None` if there is no such actor or it has stopped.
+    ///
+    /// If several actors registered themselves, the channel to the one which registered most
+    /// recently is returned.
+    pub fn get<M, R>() -> Option<MessageChannel<M, R>>
+    where
+        M: Send + 'static,
+        R: Send + 'static,
+    {
+        let mut shard = shard::<M, R>();
+        let entries = &mut shard.get_mut(&TypeId::of::<Channel<M, R>>())?.unnamed;
+
+        upgrade_latest(entries)
+    }
+
+    /// Look up an actor which registered itself for messages of type `M` with the given name,
+    /// returning `None` if there is no such actor or it has stopped.
+    ///
+    /// If several actors registered themselves with the same name, the channel to the one which
+    /// registered most recently is returned.
+    pub fn get_named<M, R>(name: &str) -> Option<MessageChannel<M, R>>
+    where
+        M: Send + 'static,
+        R: Send + 'static,
+    {
+        let mut shard = shard::<M, R>();
+        let slot = shard.get_mut(&TypeId::of::<Channel<M, R>>())?;
+
+        upgrade_latest(slot.named.get_mut(name)?)
+    }
+
+    /// Get channels to all running actors which registered themselves for messages of type `M`,
+    /// with or without a name, e.g. to broadcast a message to all of them. Each actor is only
+    /// included once, in the order in which they registered.
+    pub fn all<M, R>() -> Vec<MessageChannel<M, R>>
+    where
+        M: Send + 'static,
+        R: Send + 'static,
+    {
+        let mut shard = shard::<M, R>();
+        let Some(slot) = shard.get_mut(&TypeId::of::<Channel<M, R>>()) else {
+            return Vec::new();
+        };
+
+        let mut owners = Vec::new();
+        let mut channels = Vec::new();
+
+        for entries in std::iter::once(&mut slot.unnamed).chain(slot.named.values_mut()) {
+            entries.retain(|entry| match upgrade::<M, R>(entry) {
+                Some(channel) => {
+                    if !owners.contains(&entry.owner) {
+                        owners.push(entry.owner);
+                        channels.push(channel);
+                    }
+
+                    true
+                }
+                None => false,
+            });
+        }
+
+        channels
+    }
+}
+
+/// A [`MessageChannel`] which looks up the actor to send messages to again once it has stopped, so
+/// that messages reach the actor which replaced it, e.g. after a supervisor restarted it.
+///
+/// The actor is looked up in the [`Registry`] or with a custom function. Messages are only handed
+/// to an actor which is still running, so a message is never lost to an actor which has stopped
+/// already and `M` does not need to be [`Clone`]. If no running actor is found after the
+/// configured number of retries, the message is given back in an [`Unavailable`] error.
+///
+/// Like the registry, a [`ResilientChannel`] does not keep the actor it found alive.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use xtra::prelude::*;
+/// use xtra::registry::ResilientChannel;
+///
+/// # struct Config;
+/// struct Get(&'static str);
+/// #
+/// # impl Actor for Config {
+/// #     type Stop = ();
+/// #     async fn started(&mut self, mailbox: &Mailbox<Self>) -> Result<(), ()> {
+/// #         mailbox.register_named_as::<Get>("config");
+/// #         Ok(())
+/// #     }
+/// #     async fn stopped(self) {}
+/// # }
+/// #
+/// # impl Handler<Get> for Config {
+/// #     type Return = Option<String>;
+/// #     async fn handle(&mut self, Get(key): Get, _: &mut Context<Self>) -> Option<String> {
+/// #         (key == "name").then(|| "xtra".to_owned())
+/// #     }
+/// # }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let config = ResilientChannel::<Get, Option<String>>::named("config")
+///         .with_retries(5)
+///         .with_backoff(Duration::from_millis(10), |duration| async move {
+///             smol::Timer::after(duration).await;
+///         });
+///
+///     let addr = xtra::spawn_smol(Config, Mailbox::unbounded());
+///
+///     let name = config.send(Get("name")).await.unwrap();
+///     assert_eq!(name, Ok(Some("xtra".to_owned())));
+/// #   drop(addr);
+/// })
+/// ```
+pub struct ResilientChannel<M, R> {
+    resolve: Arc<dyn Fn() -> Option<MessageChannel<M, R>> + Send + Sync>,
+    /// The channel to the actor which was found last, looked up again once it has stopped.
+    current: spin::Mutex<Option<Channel<M, R>>>,
+    retries: u32,
+    backoff: Option<(Duration, Arc<dyn Timer>)>,
+}
+
+impl<M, R> ResilientChannel<M, R>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    /// Send messages to the actor which is returned by `resolve`, calling it again whenever that
+    /// actor has stopped or could not be found.
+    pub fn new(resolve: impl Fn() -> Option<MessageChannel<M, R>> + Send + Sync + 'static) -> Self {
+        ResilientChannel {
+            resolve: Arc::new(resolve),
+            current: spin::Mutex::new(None),
+            retries: 3,
+            backoff: None,
+        }
+    }
+
+    /// Send messages to the actor which is returned by [`Registry::get`].
+    pub fn registered() -> Self {
+        ResilientChannel::new(Registry::get)
+    }
+
+    /// Send messages to the actor which is returned by [`Registry::get_named`] for the given name.
+    pub fn named(name: impl Into<Cow<'static, str>>) -> Self {
+        let name = name.into();
+
+        ResilientChannel::new(move || Registry::get_named(&name))
+    }
+
+    /// Look up the actor up to `retries` more times if no running actor is found, before giving
+    /// up. Defaults to 3.
+    pub fn with_retries(self, retries: u32) -> Self {
+        ResilientChannel { retries, ..self }
+    }
+
+    /// Wait before each retry, starting with `initial` and doubling the time for every further
+    /// retry, using the given [`Timer`]. By default, the actor is looked up again right away, which
+    /// gives a supervisor no time to replace it.
+    pub fn with_backoff(self, initial: Duration, timer: impl Timer) -> Self {
+        ResilientChannel {
+            backoff: Some((initial, Arc::new(timer))),
+            ..self
+        }
+    }
+
+    /// Send a message to a running actor and resolve to the result of sending it with
+    /// [`MessageChannel::send`], or give the message back if no running actor was found.
+    ///
+    /// If the actor which was found stops before the message is queued, the actor is looked up
+    /// once more and the message is sent to it instead. The message is not sent again if the actor
+    /// stops after it was queued, in which case the result is an error as for any other message.
+    pub async fn send(&self, message: M) -> Result<Result<R, Error>, Unavailable<M>> {
+        Ok(self.queue(message).await?.await)
+    }
+
+    /// Like [`ResilientChannel::send`], but discard the return value of the handler like
+    /// [`MessageChannel::send_and_forget`].
+    pub async fn send_and_forget(&self, message: M) -> Result<Result<(), Error>, Unavailable<M>> {
+        Ok(self.queue(message).await?.forget().await)
+    }
+
+    /// Queue the message to a running actor, looking it up once more if the actor which was found
+    /// stops before the message is queued.
+    async fn queue(
+        &self,
+        mut message: M,
+    ) -> Result<SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>>, Unavailable<M>> {
+        for _ in 0..2 {
+            let Some(channel) = self.resolve_connected().await else {
+                break;
+            };
+
+            match channel.try_send(message) {
+                Ok(sending) => return Ok(sending),
+                Err(returned) => message = returned,
+            }
+        }
+
+        Err(Unavailable {
+            message,
+            attempts: self.retries + 1,
+        })
+    }
+
+    /// Find a running actor, retrying with the configured backoff.
+    async fn resolve_connected(&self) -> Option<MessageChannel<M, R>> {
+        let mut backoff = self.backoff.as_ref().map(|(initial, _)| *initial);
+
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                if let (Some(delay), Some((_, timer))) = (backoff, &self.backoff) {
+                    timer.sleep(delay).await;
+                    backoff = Some(delay.saturating_mul(2));
+                }
+            }
+
+            let cached = self.current.lock().as_ref().and_then(|c| c.try_upgrade());
+
+            let channel = match cached.filter(|c| c.is_connected()) {
+                Some(channel) => Some(channel),
+                None => (self.resolve)().filter(|c| c.is_connected()),
+            };
+
+            *self.current.lock() = channel.as_ref().map(|c| c.downgrade());
+
+            if channel.is_some() {
+                return channel;
+            }
+        }
+
+        None
+    }
+}
+
+impl<M, R> Clone for ResilientChannel<M, R>
+where
+    R: Send + 'static,
+{
+    fn clone(&self) -> Self {
+        ResilientChannel {
+            resolve: self.resolve.clone(),
+            current: spin::Mutex::new(self.current.lock().clone()),
+            retries: self.retries,
+            backoff: self.backoff.clone(),
+        }
+    }
+}
+
+impl<M, R> fmt::Debug for ResilientChannel<M, R>
+where
+    R: Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResilientChannel")
+            .field("current", &*self.current.lock())
+            .field("retries", &self.retries)
+            .field(
+                "backoff",
+                &self.backoff.as_ref().map(|(initial, _)| initial),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+/// The error returned by [`ResilientChannel::send`] if no running actor was found, giving the
+/// message back.
+pub struct Unavailable<M> {
+    message: M,
+    attempts: u32,
+}
+
+impl<M> Unavailable<M> {
+    /// How many times the actor was looked up.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Take back the message which could not be sent.
+    pub fn into_inner(self) -> M {
+        self.message
+    }
+}
+
+impl<M> fmt::Debug for Unavailable<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Unavailable")
+            .field("attempts", &self.attempts)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<M> fmt::Display for Unavailable<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No running actor found after {} attempts", self.attempts)
+    }
+}
+
+impl<M> std::error::Error for Unavailable<M> {}
+
+type Channel<M, R> = MessageChannel<M, R, Weak>;
+
+fn shards() -> &'static [Shard; SHARDS] {
+    static REGISTRY: OnceLock<[Shard; SHARDS]> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| std::array::from_fn(|_| Shard::default()))
+}
+
+/// Lock the shard which holds the registrations for `MessageChannel<M, R>`.
+fn shard<M: 'static, R: 'static>() -> MutexGuard<'static, HashMap<TypeId, Slot>> {
+    let mut hasher = DefaultHasher::new();
+    TypeId::of::<Channel<M, R>>().hash(&mut hasher);
+
+    lock(&shards()[hasher.finish() as usize % SHARDS])
+}
+
+fn lock(shard: &'static Shard) -> MutexGuard<'static, HashMap<TypeId, Slot>> {
+    // The registry is left in a consistent state even if a lookup panics.
+    shard
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Upgrade the most recently registered entry which is still alive, removing all dead entries
+/// which were registered after it.
+fn upgrade_latest<M, R>(entries: &mut Vec<Entry>) -> Option<MessageChannel<M, R>>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    while let Some(entry) = entries.last() {
+        if let Some(channel) = upgrade(entry) {
+            return Some(channel);
+        }
+
+        entries.pop();
+    }
+
+    None
+}
+
+fn upgrade<M, R>(entry: &Entry) -> Option<MessageChannel<M, R>>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    let channel = entry
+        .channel
+        .downcast_ref::<Channel<M, R>>()
+        .expect("entry to be keyed by its type")
+        .try_upgrade()?;
+
+    channel.is_connected().then_some(channel)
+}
+
+/// Register the channel to the actor identified by `owner` under the given name, replacing any
+/// registration of the same actor under that key.
+pub(crate) fn register<M, R>(owner: usize, name: Option<Cow<'static, str>>, channel: Channel<M, R>)
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    let mut shard = shard::<M, R>();
+    let slot = shard.entry(TypeId::of::<Channel<M, R>>()).or_default();
+    let entries = match name {
+        Some(name) => slot.named.entry(name).or_default(),
+        None => &mut slot.unnamed,
+    };
+
+    entries.retain(|entry| entry.owner != owner);
+    entries.push(Entry {
+        owner,
+        channel: Box::new(channel),
+    });
+}
+
+/// Remove all registrations of the actor identified by `owner`.
+pub(crate) fn deregister(owner: usize) {
+    for shard in shards() {
+        lock(shard).retain(|_, slot| {
+            slot.unnamed.retain(|entry| entry.owner != owner);
+            slot.named.retain(|_, entries| {
+                entries.retain(|entry| entry.owner != owner);
+                !entries.is_empty()
+            });
+
+            !slot.unnamed.is_empty() || !slot.named.is_empty()
+        });
+    }
+}
@@ -0,0 +1,613 @@
+//! Connecting actors in different processes over a transport of serialized messages.
+//!
+//! A [`RemoteSender`] hands out [`MessageChannel`]s which serialize each message and write it to a
+//! [`Sink`] of bytes, such as one end of a TCP connection. On the other end, a [`RemoteReceiver`]
+//! reads the messages from a [`Stream`] of bytes, sends them to a local actor and writes the
+//! return values of its handler back, which resolve the [`SendFuture`]s on the sending side. Code
+//! which talks to an actor through a [`MessageChannel`] therefore does not need to change when the
+//! actor is moved to another process.
+//!
+//! The transport itself is out of scope. It must deliver each `Vec<u8>` as one frame and in order,
+//! e.g. by prefixing frames with their length. Messages and return values are encoded as JSON.
+//!
+//! Each connection carries messages of a single type. To talk to an actor which handles several
+//! message types, open one connection per type.
+//!
+//! ```rust
+//! # use futures_util::{SinkExt, StreamExt};
+//! # use serde::{Deserialize, Serialize};
+//! # use xtra::prelude::*;
+//! use xtra::remote::{RemoteReceiver, RemoteSender};
+//!
+//! # struct Doubler;
+//! # impl Actor for Doubler { type Stop = (); async fn stopped(self) {} }
+//! #[derive(Serialize, Deserialize)]
+//! struct Double(u32);
+//!
+//! impl Handler<Double> for Doubler {
+//!     type Return = u32;
+//!
+//!     async fn handle(&mut self, Double(n): Double, _: &mut Context<Self>) -> u32 {
+//!         n * 2
+//!     }
+//! }
+//!
+//! # #[cfg(feature = "tokio")]
+//! # tokio::runtime::Runtime::new().unwrap().block_on(async {
+//! # fn pipe() -> (impl futures_util::Sink<Vec<u8>, Error = ()> + Send, impl futures_util::Stream<Item = Vec<u8>> + Send) {
+//! #     let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
+//! #     let sink = futures_util::sink::unfold(tx, |tx, frame| async move { tx.send(frame).map_err(|_| ())?; Ok(tx) });
+//! #     let stream = futures_util::stream::unfold(rx, |mut rx| async move { Some((rx.recv().await?, rx)) });
+//! #     (sink, stream)
+//! # }
+//! // In practice, these are both ends of a connection between two processes.
+//! let (requests_tx, requests_rx) = pipe();
+//! let (replies_tx, replies_rx) = pipe();
+//!
+//! // In the process which runs the actor:
+//! let doubler = xtra::spawn_tokio(Doubler, Mailbox::unbounded());
+//! let receiver = RemoteReceiver::new(doubler);
+//! tokio::spawn(async move { receiver.serve::<Double, _, _>(requests_rx, replies_tx).await });
+//!
+//! // In the process which sends messages to it:
+//! let (sender, connection) = RemoteSender::<Double, u32>::connect(requests_tx, replies_rx);
+//! tokio::spawn(connection);
+//!
+//! let channel: MessageChannel<Double, u32> = sender.channel();
+//! assert_eq!(channel.send(Double(21)).await, Ok(42));
+//! # })
+//! ```
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::hash::Hasher;
+use std::marker::PhantomData;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{self, AtomicBool, AtomicUsize};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::{fmt, mem};
+
+use event_listener::Event;
+use futures_core::Stream;
+use futures_sink::Sink;
+use futures_util::future::{self, Either};
+use futures_util::stream::FuturesUnordered;
+use futures_util::{SinkExt, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::address::ActorJoinHandle;
+use crate::message_channel::{MessageChannel, MessageChannelTrait};
+use crate::refcount::{Either as EitherRc, Strong, Weak};
+use crate::send_future::{ActorErasedSending, ResolveToHandlerReturn, SendFuture};
+use crate::{Actor, ActorId, Address, DisconnectReason, Error, Handler};
+
+/// A message sent to the [`RemoteReceiver`], tagged with an id to match it with its [`Reply`].
+#[derive(Serialize, Deserialize)]
+struct Request<M> {
+    id: u64,
+    message: M,
+}
+
+/// The result of handling the [`Request`] with the same id.
+#[derive(Serialize, Deserialize)]
+struct Reply<R> {
+    id: u64,
+    result: Result<R, Error>,
+}
+
+/// The sending end of a connection to an actor in another process, which hands out
+/// [`MessageChannel`]s for messages of type `M`, resolving to the return value `R` of the handler.
+///
+/// The connection is driven by the future returned from [`RemoteSender::connect`], which must be
+/// spawned. It ends once the transport is closed, or once this [`RemoteSender`] and all strong
+/// channels have been dropped and all replies have been received.
+///
+/// Channels are connected for as long as the connection is running. Once it has ended, sending a
+/// message fails with [`Error::Disconnected`], as do all messages which have been sent but not yet
+/// replied to.
+pub struct RemoteSender<M, R> {
+    connection: Arc<Connection<R>>,
+    phantom: PhantomData<fn(M)>,
+}
+
+/// The state shared between a [`RemoteSender`], its channels and the future driving the
+/// connection.
+struct Connection<R> {
+    inner: Mutex<ConnectionInner<R>>,
+    /// Identifies the connection, as the id of the remote actor is not known.
+    id: ActorId,
+    open: AtomicBool,
+    /// The number of strong channels, plus one for the [`RemoteSender`].
+    senders: AtomicUsize,
+    on_request: Event,
+    on_disconnect: Event,
+}
+
+struct ConnectionInner<R> {
+    next_id: u64,
+    /// Serialized requests which have not yet been written to the transport.
+    queue: VecDeque<Vec<u8>>,
+    /// The senders of all requests which have not yet been replied to, by id.
+    pending: HashMap<u64, catty::Sender<Result<R, Error>>>,
+}
+
+impl<M, R> RemoteSender<M, R>
+where
+    M: Serialize + Send + 'static,
+    R: DeserializeOwned + Send + 'static,
+{
+    /// Connect to a [`RemoteReceiver`] over the given transport, writing requests to `sink` and
+    /// reading replies from `stream`.
+    ///
+    /// This returns the [`RemoteSender`] and the future which drives the connection. A frame which
+    /// cannot be deserialized or an error writing to `sink` closes the connection.
+    pub fn connect<Si, St>(
+        sink: Si,
+        stream: St,
+    ) -> (Self, impl Future<Output = ()> + Send + 'static)
+    where
+        Si: Sink<Vec<u8>> + Send + 'static,
+        St: Stream<Item = Vec<u8>> + Send + 'static,
+    {
+        let connection = Arc::new(Connection {
+            inner: Mutex::new(ConnectionInner {
+                next_id: 0,
+                queue: VecDeque::new(),
+                pending: HashMap::new(),
+            }),
+            id: ActorId::next(),
+            open: AtomicBool::new(true),
+            senders: AtomicUsize::new(1),
+            on_request: Event::new(),
+            on_disconnect: Event::new(),
+        });
+
+        let driver = {
+            let connection = connection.clone();
+
+            async move {
+                let _disconnect = Disconnect(&connection);
+                let writing = pin!(write_requests(&connection, sink));
+                let reading = pin!(read_replies(&connection, stream));
+
+                // Once all senders are gone, keep reading until the outstanding replies are in.
+                if let Either::Left((true, reading)) = future::select(writing, reading).await {
+                    if !connection.is_idle() {
+                        reading.await;
+                    }
+                }
+            }
+        };
+
+        let sender = RemoteSender {
+            connection,
+            phantom: PhantomData,
+        };
+
+        (sender, driver)
+    }
+
+    /// Create a new strong channel to the remote actor. Use [`MessageChannel::downgrade`] to
+    /// obtain a weak one.
+    pub fn channel(&self) -> MessageChannel<M, R> {
+        MessageChannel::from_inner(Box::new(RemoteChannel::<M, R, Strong>::new(
+            self.connection.clone(),
+            true,
+        )))
+    }
+}
+
+impl<M, R> fmt::Debug for RemoteSender<M, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.connection.inner.lock().unwrap();
+
+        f.debug_struct("RemoteSender")
+            .field("open", &self.connection.open.load(atomic::Ordering::SeqCst))
+            .field("queued", &inner.queue.len())
+            .field("pending", &inner.pending.len())
+            .finish()
+    }
+}
+
+impl<M, R> Drop for RemoteSender<M, R> {
+    fn drop(&mut self) {
+        self.connection.release();
+    }
+}
+
+impl<R> Connection<R> {
+    fn is_connected(&self) -> bool {
+        self.open.load(atomic::Ordering::SeqCst) && self.senders.load(atomic::Ordering::SeqCst) > 0
+    }
+
+    fn is_idle(&self) -> bool {
+        self.inner.lock().unwrap().pending.is_empty()
+    }
+
+    fn release(&self) {
+        if self.senders.fetch_sub(1, atomic::Ordering::SeqCst) == 1 {
+            self.on_request.notify(usize::MAX);
+        }
+    }
+
+    fn send<M: Serialize>(
+        &self,
+        message: M,
+    ) -> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>>
+    where
+        R: Send + 'static,
+    {
+        let (tx, rx) = catty::oneshot();
+
+        {
+            let mut inner = self.inner.lock().unwrap();
+
+            if !self.is_connected() {
+                return SendFuture::resolved(Err(Error::Disconnected));
+            }
+
+            let id = inner.next_id;
+            inner.next_id += 1;
+
+            let frame = match serde_json::to_vec(&Request { id, message }) {
+                Ok(frame) => frame,
+                Err(e) => return SendFuture::resolved(Err(Error::Serialization(e.to_string()))),
+            };
+            inner.queue.push_back(frame);
+            inner.pending.insert(id, tx);
+        }
+
+        self.on_request.notify(1);
+
+        SendFuture::resolving(rx)
+    }
+
+    /// Mark the connection as closed, failing all requests which have not been replied to.
+    fn disconnect(&self) {
+        let pending = {
+            let mut inner = self.inner.lock().unwrap();
+            self.open.store(false, atomic::Ordering::SeqCst);
+            inner.queue.clear();
+            mem::take(&mut inner.pending)
+        };
+
+        for (_, tx) in pending {
+            let _ = tx.send(Err(Error::Disconnected));
+        }
+
+        self.on_disconnect.notify(usize::MAX);
+    }
+}
+
+/// Disconnects the [`Connection`] once the future driving it completes or is dropped.
+struct Disconnect<'a, R>(&'a Connection<R>);
+
+impl<R> Drop for Disconnect<'_, R> {
+    fn drop(&mut self) {
+        self.0.disconnect();
+    }
+}
+
+/// Write queued requests to the transport, returning `true` once all senders are gone and `false`
+/// if writing fails.
+async fn write_requests<R, Si>(connection: &Connection<R>, sink: Si) -> bool
+where
+    Si: Sink<Vec<u8>>,
+{
+    let mut sink = pin!(sink);
+
+    loop {
+        let listener = connection.on_request.listen();
+        let next = connection.inner.lock().unwrap().queue.pop_front();
+
+        match next {
+            Some(frame) => {
+                if sink.send(frame).await.is_err() {
+                    return false;
+                }
+            }
+            None if connection.senders.load(atomic::Ordering::SeqCst) == 0 => {
+                let _ = sink.close().await;
+                return true;
+            }
+            None => listener.await,
+        }
+    }
+}
+
+/// Read replies from the transport until it is closed, a frame cannot be deserialized, or all
+/// senders are gone and there are no outstanding replies.
+async fn read_replies<R, St>(connection: &Connection<R>, stream: St)
+where
+    R: DeserializeOwned,
+    St: Stream<Item = Vec<u8>>,
+{
+    let mut stream = pin!(stream);
+
+    while let Some(frame) = stream.next().await {
+        let Ok(reply) = serde_json::from_slice::<Reply<R>>(&frame) else {
+            return;
+        };
+
+        let mut inner = connection.inner.lock().unwrap();
+
+        if let Some(tx) = inner.pending.remove(&reply.id) {
+            let _ = tx.send(reply.result);
+        }
+
+        if connection.senders.load(atomic::Ordering::SeqCst) == 0 && inner.pending.is_empty() {
+            return;
+        }
+    }
+}
+
+/// The channel handed out by a [`RemoteSender`]. `Rc` is only used to tell apart strong and weak
+/// channels on the type level, like for [`Address`].
+struct RemoteChannel<M, R, Rc> {
+    connection: Arc<Connection<R>>,
+    strong: bool,
+    phantom: PhantomData<fn(M) -> Rc>,
+}
+
+impl<M, R, Rc> RemoteChannel<M, R, Rc> {
+    fn new(connection: Arc<Connection<R>>, strong: bool) -> Self {
+        if strong {
+            connection.senders.fetch_add(1, atomic::Ordering::SeqCst);
+        }
+
+        RemoteChannel {
+            connection,
+            strong,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<M, R, Rc> Drop for RemoteChannel<M, R, Rc> {
+    fn drop(&mut self) {
+        if self.strong {
+            self.connection.release();
+        }
+    }
+}
+
+impl<M, R, Rc> MessageChannelTrait<M, Rc> for RemoteChannel<M, R, Rc>
+where
+    M: Serialize + Send + 'static,
+    R: Send + 'static,
+    Rc: 'static,
+{
+    type Return = R;
+
+    fn is_connected(&self) -> bool {
+        self.connection.is_connected()
+    }
+
+    fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        None
+    }
+
+    fn len(&self) -> usize {
+        self.connection.inner.lock().unwrap().queue.len()
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+
+    fn id(&self) -> ActorId {
+        self.connection.id
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(std::any::type_name::<RemoteSender<M, R>>())
+    }
+
+    fn send(&self, message: M) -> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>> {
+        self.connection.send(message)
+    }
+
+    fn clone_channel(
+        &self,
+    ) -> Box<dyn MessageChannelTrait<M, Rc, Return = R> + Send + Sync + 'static> {
+        Box::new(RemoteChannel::<M, R, Rc>::new(
+            self.connection.clone(),
+            self.strong,
+        ))
+    }
+
+    fn join(&self) -> ActorJoinHandle {
+        let listener = self.connection.on_disconnect.listen();
+
+        if self.connection.open.load(atomic::Ordering::SeqCst) {
+            ActorJoinHandle(Some(listener))
+        } else {
+            ActorJoinHandle(None)
+        }
+    }
+
+    fn to_inner_ptr(&self) -> *const () {
+        Arc::as_ptr(&self.connection) as *const ()
+    }
+
+    fn is_strong(&self) -> bool {
+        self.strong
+    }
+
+    fn to_weak(&self) -> Box<dyn MessageChannelTrait<M, Weak, Return = R> + Send + Sync + 'static> {
+        Box::new(RemoteChannel::<M, R, Weak>::new(
+            self.connection.clone(),
+            false,
+        ))
+    }
+
+    fn try_upgrade(
+        &self,
+    ) -> Option<Box<dyn MessageChannelTrait<M, Strong, Return = R> + Send + Sync + 'static>> {
+        // Only upgrade if another strong channel is still around.
+        self.connection
+            .senders
+            .fetch_update(
+                atomic::Ordering::SeqCst,
+                atomic::Ordering::SeqCst,
+                |count| (count > 0).then_some(count + 1),
+            )
+            .ok()?;
+
+        Some(Box::new(RemoteChannel::<M, R, Strong> {
+            connection: self.connection.clone(),
+            strong: true,
+            phantom: PhantomData,
+        }))
+    }
+
+    fn sender_count(&self) -> usize {
+        self.connection.senders.load(atomic::Ordering::SeqCst)
+    }
+
+    fn receiver_count(&self) -> usize {
+        self.connection.open.load(atomic::Ordering::SeqCst) as usize
+    }
+
+    fn actor_type(&self) -> &'static str {
+        std::any::type_name::<RemoteSender<M, R>>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn to_either(
+        &self,
+    ) -> Box<dyn MessageChannelTrait<M, EitherRc, Return = R> + Send + Sync + 'static> {
+        Box::new(RemoteChannel::<M, R, EitherRc>::new(
+            self.connection.clone(),
+            self.strong,
+        ))
+    }
+
+    fn hash(&self, state: &mut dyn Hasher) {
+        state.write_usize(self.to_inner_ptr() as usize);
+        state.write_u8(self.strong as u8);
+    }
+}
+
+/// The receiving end of connections from [`RemoteSender`]s, which forwards messages to a local
+/// actor and writes the return values of its handler back.
+///
+/// Cloning a [`RemoteReceiver`] is cheap, as it only clones the underlying [`Address`]. This can be
+/// used to serve several connections at once.
+pub struct RemoteReceiver<A> {
+    address: Address<A>,
+}
+
+impl<A: Actor> RemoteReceiver<A> {
+    /// Create a new [`RemoteReceiver`] which forwards messages to the actor at `address`.
+    pub fn new(address: Address<A>) -> Self {
+        RemoteReceiver { address }
+    }
+
+    /// Serve a connection from a [`RemoteSender`] for messages of type `M`, reading requests from
+    /// `stream` and writing replies to `sink`.
+    ///
+    /// Messages are sent to the actor in the order in which they are read, waiting for capacity if
+    /// its mailbox is bounded. Replies are written as soon as the handler returns, which need not
+    /// be in order if several actors share the address. If the actor has stopped, the sender
+    /// receives [`Error::Disconnected`].
+    ///
+    /// This completes once `stream` ends and all outstanding replies have been written, or once a
+    /// frame cannot be deserialized or writing to `sink` fails.
+    pub async fn serve<M, St, Si>(&self, stream: St, sink: Si)
+    where
+        A: Handler<M>,
+        <A as Handler<M>>::Return: Serialize,
+        M: DeserializeOwned + Send + 'static,
+        St: Stream<Item = Vec<u8>>,
+        Si: Sink<Vec<u8>>,
+    {
+        let mut stream = pin!(stream);
+        let mut sink = pin!(sink);
+        let mut replies = FuturesUnordered::new();
+
+        loop {
+            let next = future::poll_fn(|cx| {
+                if let Poll::Ready(Some(reply)) = replies.poll_next_unpin(cx) {
+                    return Poll::Ready(Either::Left(reply));
+                }
+
+                stream.poll_next_unpin(cx).map(Either::Right)
+            })
+            .await;
+
+            match next {
+                Either::Left(reply) => {
+                    if write_reply(sink.as_mut(), reply).await.is_err() {
+                        return;
+                    }
+                }
+                Either::Right(Some(frame)) => {
+                    let Ok(Request { id, message }) = serde_json::from_slice::<Request<M>>(&frame)
+                    else {
+                        return;
+                    };
+
+                    let sent = self.address.send(message).detach().await;
+
+                    replies.push(async move {
+                        let result = match sent {
+                            Ok(receiver) => receiver.await,
+                            Err(e) => Err(e),
+                        };
+
+                        Reply { id, result }
+                    });
+                }
+                Either::Right(None) => break,
+            }
+        }
+
+        while let Some(reply) = replies.next().await {
+            if write_reply(sink.as_mut(), reply).await.is_err() {
+                return;
+            }
+        }
+
+        let _ = sink.close().await;
+    }
+}
+
+async fn write_reply<R, Si>(mut sink: Pin<&mut Si>, reply: Reply<R>) -> Result<(), Si::Error>
+where
+    R: Serialize,
+    Si: Sink<Vec<u8>>,
+{
+    let frame = serde_json::to_vec(&reply).unwrap_or_else(|e| {
+        let reply = Reply::<R> {
+            id: reply.id,
+            result: Err(Error::Serialization(e.to_string())),
+        };
+
+        serde_json::to_vec(&reply).expect("error to be serializable")
+    });
+    sink.send(frame).await
+}
+
+impl<A> Clone for RemoteReceiver<A> {
+    fn clone(&self) -> Self {
+        RemoteReceiver {
+            address: self.address.clone(),
+        }
+    }
+}
+
+impl<A> fmt::Debug for RemoteReceiver<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteReceiver")
+            .field("address", &self.address)
+            .finish()
+    }
+}
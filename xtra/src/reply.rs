@@ -0,0 +1,59 @@
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+/// Whether the sender of a message still waits for its reply, see
+/// [`Context::reply_wanted`](crate::Context::reply_wanted).
+///
+/// A reply is only wanted while a [`Receiver`](crate::Receiver) for it exists, so messages which
+/// are sent without ever waiting for their reply, such as notifications, never want one.
+#[derive(Clone, Default)]
+pub struct ReplyInterest(Arc<spin::Mutex<Interest>>);
+
+#[derive(Default)]
+struct Interest {
+    wanted: bool,
+    waker: Option<Waker>,
+}
+
+impl ReplyInterest {
+    /// Mark the reply as wanted until the returned guard is dropped.
+    pub fn want(&self) -> WantReply {
+        self.0.lock().wanted = true;
+
+        WantReply(self.clone())
+    }
+
+    pub fn is_wanted(&self) -> bool {
+        self.0.lock().wanted
+    }
+
+    /// Resolve once the reply is no longer wanted.
+    pub fn poll_cancelled(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut interest = self.0.lock();
+
+        if !interest.wanted {
+            return Poll::Ready(());
+        }
+
+        interest.waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+/// Keeps the reply to a message wanted, held by its [`Receiver`](crate::Receiver).
+pub struct WantReply(ReplyInterest);
+
+impl Drop for WantReply {
+    fn drop(&mut self) {
+        let waker = {
+            let mut interest = (self.0).0.lock();
+            interest.wanted = false;
+            interest.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
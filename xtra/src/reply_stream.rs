@@ -0,0 +1,272 @@
+//! Streaming replies, for handlers which reply with a sequence of items rather than a single
+//! value, e.g. the rows of a query or the entries of a directory.
+//!
+//! A message is sent with [`Address::send_stream`](crate::Address::send_stream), which wraps it in
+//! a [`Streaming`] request. The handler sends items through the [`Replies`] of the request, and
+//! the caller receives them through the returned [`ReplyStream`]:
+//!
+//! ```rust
+//! # use futures_util::StreamExt;
+//! # use xtra::prelude::*;
+//! use xtra::reply_stream::Streaming;
+//!
+//! # struct Table(Vec<u32>);
+//! # impl Actor for Table { type Stop = (); async fn stopped(self) {} }
+//! struct Scan { min: u32 }
+//!
+//! impl Handler<Streaming<Scan, u32>> for Table {
+//!     type Return = ();
+//!
+//!     async fn handle(&mut self, request: Streaming<Scan, u32>, _: &mut Context<Self>) {
+//!         let Streaming { message: Scan { min }, mut replies } = request;
+//!
+//!         for &row in self.0.iter().filter(|&&row| row >= min) {
+//!             if replies.send(row).await.is_err() {
+//!                 return; // The caller is no longer interested.
+//!             }
+//!         }
+//!     }
+//! }
+//!
+//! # #[cfg(feature = "smol")]
+//! smol::block_on(async {
+//!     let address = xtra::spawn_smol(Table(vec![1, 5, 2, 7]), Mailbox::unbounded());
+//!     let rows = address.send_stream(Scan { min: 3 }, 16).collect::<Vec<_>>().await;
+//!
+//!     assert_eq!(rows, [5, 7]);
+//! })
+//! ```
+//!
+//! The stream ends once the handler drops the [`Replies`], which it does when it returns, or if
+//! the actor stops before handling the message.
+//!
+//! # Backpressure
+//!
+//! At most `buffer` items, as passed to [`Address::send_stream`](crate::Address::send_stream), are
+//! buffered. Once the buffer is full, [`Replies::send`] waits until the caller has received an
+//! item. As the handler awaits this, the actor does not handle other messages while the caller is
+//! behind.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+use futures_core::future::BoxFuture;
+use futures_core::stream::FusedStream;
+use futures_core::Stream;
+use futures_util::FutureExt;
+
+use crate::Error;
+
+/// A message of type `M`, sent with [`Address::send_stream`](crate::Address::send_stream), which
+/// is replied to with items of type `T`.
+pub struct Streaming<M, T> {
+    /// The message which was sent.
+    pub message: M,
+    /// The replies to the message.
+    pub replies: Replies<T>,
+}
+
+/// Sends items to the [`ReplyStream`] of a [`Streaming`] request.
+pub struct Replies<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Replies<T> {
+    /// Send an item to the caller, waiting while the buffer of the stream is full.
+    ///
+    /// This fails with the item if the [`ReplyStream`] has been dropped.
+    pub async fn send(&mut self, item: T) -> Result<(), T> {
+        let mut item = Some(item);
+
+        poll_fn(|cx| {
+            let mut state = self.shared.state.lock();
+
+            if !state.receiver_alive {
+                return Poll::Ready(Err(item.take().expect("to not be polled after completion")));
+            }
+
+            if state.items.len() >= self.shared.buffer {
+                state.sender_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+
+            state
+                .items
+                .push_back(item.take().expect("to not be polled after completion"));
+            let receiver = state.receiver_waker.take();
+            drop(state);
+
+            if let Some(waker) = receiver {
+                waker.wake();
+            }
+
+            Poll::Ready(Ok(()))
+        })
+        .await
+    }
+
+    /// Whether the [`ReplyStream`] has been dropped, i.e. further items would not be received.
+    pub fn is_closed(&self) -> bool {
+        !self.shared.state.lock().receiver_alive
+    }
+}
+
+impl<T> Drop for Replies<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.sender_alive = false;
+        let receiver = state.receiver_waker.take();
+        drop(state);
+
+        if let Some(waker) = receiver {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Replies<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Replies")
+            .field("closed", &self.is_closed())
+            .finish()
+    }
+}
+
+/// The items a handler replies with to a [`Streaming`] request. See the
+/// [module documentation](self) for details.
+///
+/// Like a [`SendFuture`](crate::SendFuture), this does nothing unless polled: the message is only
+/// sent once the stream is first polled.
+#[must_use = "Streams do nothing unless polled"]
+pub struct ReplyStream<T> {
+    sending: Option<BoxFuture<'static, Result<(), Error>>>,
+    shared: Arc<Shared<T>>,
+    terminated: bool,
+}
+
+impl<T> ReplyStream<T> {
+    /// Create a stream which sends its request through `sending` when first polled.
+    pub(crate) fn new(
+        buffer: usize,
+        sending: impl FnOnce(Replies<T>) -> BoxFuture<'static, Result<(), Error>>,
+    ) -> Self {
+        assert!(
+            buffer > 0,
+            "a reply stream needs room for at least one item"
+        );
+
+        let shared = Arc::new(Shared {
+            buffer,
+            state: spin::Mutex::new(State {
+                items: VecDeque::new(),
+                sender_alive: true,
+                receiver_alive: true,
+                sender_waker: None,
+                receiver_waker: None,
+            }),
+        });
+        let replies = Replies {
+            shared: shared.clone(),
+        };
+
+        ReplyStream {
+            sending: Some(sending(replies)),
+            shared,
+            terminated: false,
+        }
+    }
+}
+
+impl<T> Stream for ReplyStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+
+        if this.terminated {
+            return Poll::Ready(None);
+        }
+
+        if let Some(sending) = &mut this.sending {
+            // If the message cannot be sent, its replies are dropped along with it, which ends the
+            // stream below.
+            if sending.poll_unpin(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            this.sending = None;
+        }
+
+        let mut state = this.shared.state.lock();
+
+        if let Some(item) = state.items.pop_front() {
+            let sender = state.sender_waker.take();
+            drop(state);
+
+            if let Some(waker) = sender {
+                waker.wake();
+            }
+
+            return Poll::Ready(Some(item));
+        }
+
+        if !state.sender_alive {
+            drop(state);
+            this.terminated = true;
+            return Poll::Ready(None);
+        }
+
+        state.receiver_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> FusedStream for ReplyStream<T> {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+impl<T> Drop for ReplyStream<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock();
+        state.receiver_alive = false;
+        let items = std::mem::take(&mut state.items);
+        let sender = state.sender_waker.take();
+        drop(state);
+
+        // Items are dropped outside of the lock, as dropping them may run arbitrary code.
+        drop(items);
+
+        if let Some(waker) = sender {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for ReplyStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplyStream")
+            .field("buffer", &self.shared.buffer)
+            .field("terminated", &self.terminated)
+            .finish()
+    }
+}
+
+/// The state shared between [`Replies`] and its [`ReplyStream`].
+struct Shared<T> {
+    buffer: usize,
+    state: spin::Mutex<State<T>>,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    sender_alive: bool,
+    receiver_alive: bool,
+    sender_waker: Option<Waker>,
+    receiver_waker: Option<Waker>,
+}
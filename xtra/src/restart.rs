@@ -0,0 +1,138 @@
+//! Restarting an actor in place after it panicked, so that its [`Address`](crate::Address) keeps
+//! working. See [`run_restarting`].
+
+use std::any::Any;
+use std::future::{poll_fn, Future};
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::pin;
+use std::task::Poll;
+
+use crate::{Actor, Mailbox};
+
+/// What happens to the messages waiting in the mailbox when the actor panics, see
+/// [`RestartPolicy`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InFlight {
+    /// Keep the messages, so that the restarted actor handles them.
+    Preserve,
+    /// Drop the messages, so that their senders fail with [`Error::Interrupted`](crate::Error::Interrupted).
+    DeadLetter,
+}
+
+/// How an actor run with [`run_restarting`] is restarted after it panicked.
+///
+/// The message which was being handled when the actor panicked is never handled again, and its
+/// sender fails with [`Error::Interrupted`](crate::Error::Interrupted). All other messages to one
+/// actor, including those of senders waiting for room in a bounded mailbox, are kept or dropped
+/// according to [`InFlight`]. Since they share a mailbox, this includes the messages to other
+/// actors on the same address. Broadcasts are always kept.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RestartPolicy {
+    in_flight: InFlight,
+    max_restarts: Option<u32>,
+}
+
+impl RestartPolicy {
+    /// Restart the actor every time it panics, treating the messages in its mailbox according to
+    /// `in_flight`.
+    pub fn new(in_flight: InFlight) -> Self {
+        RestartPolicy {
+            in_flight,
+            max_restarts: None,
+        }
+    }
+
+    /// Restart the actor at most `max` times. If it panics once more, the panic is propagated and
+    /// the actor stops like any actor which panics.
+    pub fn with_max_restarts(self, max: u32) -> Self {
+        RestartPolicy {
+            max_restarts: Some(max),
+            ..self
+        }
+    }
+
+    /// What happens to the messages waiting in the mailbox when the actor panics.
+    pub fn in_flight(&self) -> InFlight {
+        self.in_flight
+    }
+}
+
+/// Run the actor created by `factory` like [`run`](crate::run), replacing it with a fresh actor
+/// from `factory` whenever it panics.
+///
+/// The restarted actor keeps the [`Mailbox`] of the one it replaces, so its addresses, id and
+/// registrations stay valid and senders never learn about the restart, apart from messages which
+/// were dropped according to the [`RestartPolicy`]. Messages sent while the actor restarts wait in
+/// the mailbox. [`Actor::started`] runs again for every instance, while [`Actor::stopped`] only
+/// runs for the instance which stops. Timers, tasks, children and
+/// [`Context::on_stop`](crate::Context::on_stop) callbacks of an instance which panicked are
+/// cancelled before it is replaced.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// use xtra::restart::{InFlight, RestartPolicy};
+///
+/// #[derive(Default)]
+/// struct Parser;
+/// # impl Actor for Parser { type Stop = (); async fn stopped(self) {} }
+///
+/// struct Parse(&'static str);
+///
+/// impl Handler<Parse> for Parser {
+///     type Return = u32;
+///
+///     async fn handle(&mut self, Parse(input): Parse, _: &mut Context<Self>) -> u32 {
+///         input.parse().expect("a number")
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let (address, mailbox) = Mailbox::unbounded();
+///     let policy = RestartPolicy::new(InFlight::Preserve);
+///     smol::spawn(xtra::run_restarting(mailbox, policy, Parser::default)).detach();
+///
+///     assert_eq!(address.send(Parse("oops")).await, Err(xtra::Error::Interrupted));
+///     assert_eq!(address.send(Parse("42")).await, Ok(42));
+/// })
+/// ```
+pub async fn run_restarting<A, F>(
+    mailbox: Mailbox<A>,
+    policy: RestartPolicy,
+    mut factory: F,
+) -> A::Stop
+where
+    A: Actor,
+    F: FnMut() -> A,
+{
+    let mut restarts = 0;
+
+    loop {
+        // The mailbox is kept here, so that the channel stays connected while the actor restarts.
+        let panic = match catch_unwind(crate::run(mailbox.same_actor(), factory())).await {
+            Ok(stop) => return stop,
+            Err(panic) => panic,
+        };
+
+        if policy.max_restarts.is_some_and(|max| restarts >= max) {
+            panic::resume_unwind(panic);
+        }
+
+        restarts += 1;
+        mailbox.reset_for_restart(policy.in_flight).await;
+    }
+}
+
+/// Poll the future, catching a panic while doing so.
+async fn catch_unwind<F: Future>(future: F) -> Result<F::Output, Box<dyn Any + Send>> {
+    let mut future = pin!(future);
+
+    poll_fn(
+        |cx| match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(panic) => Poll::Ready(Err(panic)),
+        },
+    )
+    .await
+}
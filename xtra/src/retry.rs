@@ -0,0 +1,57 @@
+//! Retrying a fallible operation from within a handler, e.g. a call to a flaky downstream service.
+//! See [`Context::retry`](crate::Context::retry).
+
+use std::cmp;
+use std::time::Duration;
+
+/// How often and how quickly [`Context::retry`](crate::Context::retry) retries an operation which
+/// failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    retries: u32,
+    backoff: Option<Duration>,
+    max_backoff: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Retry the operation up to `retries` times after it failed, right away.
+    pub fn new(retries: u32) -> Self {
+        RetryPolicy {
+            retries,
+            backoff: None,
+            max_backoff: None,
+        }
+    }
+
+    /// Wait before each retry, starting with `initial` and doubling the time for every further
+    /// retry.
+    pub fn with_backoff(self, initial: Duration) -> Self {
+        RetryPolicy {
+            backoff: Some(initial),
+            ..self
+        }
+    }
+
+    /// Never wait longer than `max` before a retry, however many retries came before.
+    pub fn with_max_backoff(self, max: Duration) -> Self {
+        RetryPolicy {
+            max_backoff: Some(max),
+            ..self
+        }
+    }
+
+    /// The number of times the operation is retried after it failed.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// How long to wait before the retry which follows the given number of retries, if at all.
+    pub(crate) fn delay(&self, retries: u32) -> Option<Duration> {
+        let delay = self.backoff?.saturating_mul(2u32.saturating_pow(retries));
+
+        Some(match self.max_backoff {
+            Some(max) => cmp::min(delay, max),
+            None => delay,
+        })
+    }
+}
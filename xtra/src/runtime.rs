@@ -0,0 +1,287 @@
+//! Executor-agnostic abstractions over spawning tasks and creating timers.
+//!
+//! xtra does not depend on a specific runtime. Instead, functionality which needs to spawn
+//! auxiliary tasks or wait for some amount of time is built on top of the [`Spawner`] and [`Timer`]
+//! traits. A [`Mailbox`](crate::Mailbox) can be configured with an implementation of each through
+//! [`Mailbox::with_spawner`](crate::Mailbox::with_spawner) and [`Mailbox::with_timer`](crate::Mailbox::with_timer).
+//!
+//! The convenience `spawn` functions, such as [`spawn_tokio`](crate::spawn_tokio), configure the
+//! mailbox with the respective runtime by default.
+//!
+//! Without a runtime feature, any function of the shape `Fn(Duration) -> impl Future<Output = ()>`
+//! can be used as a [`Timer`].
+
+mod fallback;
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{self, Poll, Wake, Waker};
+use std::thread::{self, Thread};
+use std::time::{Duration, Instant};
+
+use futures_core::future::BoxFuture;
+
+use crate::ActorId;
+
+/// A way of spawning futures onto an executor.
+pub trait Spawner: Send + Sync + 'static {
+    /// Spawn the given future onto the executor, detaching it.
+    ///
+    /// The `name` is a human-readable description of the task which executors may use for
+    /// diagnostics. It can safely be ignored. Tasks spawned on behalf of an actor are named after
+    /// the actor and their purpose, e.g. `xtra::MyActor#1::monitor`.
+    fn spawn(&self, name: &str, future: BoxFuture<'static, ()>);
+
+    /// Run the given function somewhere it may block, e.g. on the executor's thread pool for
+    /// blocking work, detaching it.
+    ///
+    /// The `name` is used like for [`Spawner::spawn`]. By default, a new thread is spawned for each
+    /// function.
+    fn spawn_blocking(&self, name: &str, f: Box<dyn FnOnce() + Send>) {
+        std::thread::Builder::new()
+            .name(name.to_owned())
+            .spawn(f)
+            .expect("to be able to spawn thread");
+    }
+}
+
+/// The name of the task running the given actor, e.g. `xtra::MyActor#1`.
+///
+/// Auxiliary tasks spawned by xtra on behalf of the actor are named by appending their purpose,
+/// e.g. `xtra::MyActor#1::monitor`.
+pub(crate) fn task_name(actor_name: &str, id: ActorId) -> String {
+    format!("xtra::{}{}", actor_name, id)
+}
+
+/// A source of timers, used by all functionality which needs to wait for some amount of time.
+pub trait Timer: Send + Sync + 'static {
+    /// Create a future which completes once the given duration has elapsed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+
+    /// The current time on the clock of this timer, against which the deadlines of
+    /// [`Context::notify_after`](crate::Context::notify_after) are measured. A timer with a virtual
+    /// clock must override this, so that deadlines agree with its sleeps.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Any function returning a future which completes after the given duration can be used as a
+/// [`Timer`]. This makes it possible to use the timer of any executor, even if xtra does not have a
+/// feature for it:
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use xtra::prelude::*;
+/// # struct MyActor;
+/// # impl Actor for MyActor { type Stop = (); async fn stopped(self) {} }
+/// # #[cfg(feature = "smol")] {
+/// let (address, mailbox) = Mailbox::<MyActor>::unbounded();
+/// let mailbox = mailbox.with_timer(|duration: Duration| async move {
+///     smol::Timer::after(duration).await;
+/// });
+/// # }
+/// ```
+impl<F, Fut> Timer for F
+where
+    F: Fn(Duration) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(self(duration))
+    }
+}
+
+/// The fallback for functionality which needs a [`Spawner`] or [`Timer`] when none has been
+/// configured. Its tasks run on a single executor thread and its sleeps are woken by a single timer
+/// thread, both shared by all actors.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Fallback;
+
+impl Spawner for Fallback {
+    fn spawn(&self, _: &str, future: BoxFuture<'static, ()>) {
+        fallback::spawn(future);
+    }
+}
+
+impl Timer for Fallback {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(fallback::Sleep::until(Instant::now() + duration))
+    }
+}
+
+/// Poll the future to completion on the current thread, parking it while the future is pending.
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = task::Context::from_waker(&waker);
+    let mut future = pin!(future);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+
+        thread::park();
+    }
+}
+
+/// The [tokio](https://tokio.rs) runtime.
+///
+/// When both `tokio_unstable` and the `instrumentation` feature are enabled, spawned tasks will be
+/// named, making them identifiable in tools like [`tokio-console`](https://github.com/tokio-rs/console).
+/// The task running an actor is named like `xtra::MyActor#1` after the [name](crate::Actor::name)
+/// and [id](crate::ActorId) of the actor.
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tokio;
+
+#[cfg(feature = "tokio")]
+impl Tokio {
+    pub(crate) fn spawn_named<F>(name: &str, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        #[cfg(all(tokio_unstable, feature = "instrumentation"))]
+        {
+            tokio::task::Builder::new()
+                .name(name)
+                .spawn(future)
+                .expect("to be able to spawn task");
+        }
+
+        #[cfg(not(all(tokio_unstable, feature = "instrumentation")))]
+        {
+            let _ = name;
+            tokio::spawn(future);
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Spawner for Tokio {
+    fn spawn(&self, name: &str, future: BoxFuture<'static, ()>) {
+        Tokio::spawn_named(name, future);
+    }
+
+    fn spawn_blocking(&self, _: &str, f: Box<dyn FnOnce() + Send>) {
+        tokio::task::spawn_blocking(f);
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Timer for Tokio {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// A specific tokio runtime, such as the one on the thread of an actor spawned with
+/// [`spawn_tokio_thread`](crate::spawn_tokio_thread). Unlike [`Tokio`], tasks and timers are
+/// created on this runtime regardless of the runtime of the caller, if any.
+#[cfg(feature = "tokio")]
+impl Spawner for tokio::runtime::Handle {
+    fn spawn(&self, name: &str, future: BoxFuture<'static, ()>) {
+        #[cfg(all(tokio_unstable, feature = "instrumentation"))]
+        {
+            tokio::task::Builder::new()
+                .name(name)
+                .spawn_on(future, self)
+                .expect("to be able to spawn task");
+        }
+
+        #[cfg(not(all(tokio_unstable, feature = "instrumentation")))]
+        {
+            let _ = name;
+            tokio::runtime::Handle::spawn(self, future);
+        }
+    }
+
+    fn spawn_blocking(&self, _: &str, f: Box<dyn FnOnce() + Send>) {
+        tokio::runtime::Handle::spawn_blocking(self, f);
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Timer for tokio::runtime::Handle {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        // A sleep is registered with the timer of the runtime it is created on.
+        let _runtime = self.enter();
+
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// The [async-std](https://async.rs) runtime.
+///
+/// Spawned tasks are named, making them identifiable through [`async_std::task::current`].
+#[cfg(feature = "async_std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async_std")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AsyncStd;
+
+#[cfg(feature = "async_std")]
+impl AsyncStd {
+    pub(crate) fn spawn_named<F>(name: &str, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        async_std::task::Builder::new()
+            .name(name.to_owned())
+            .spawn(future)
+            .expect("to be able to spawn task");
+    }
+}
+
+#[cfg(feature = "async_std")]
+impl Spawner for AsyncStd {
+    fn spawn(&self, name: &str, future: BoxFuture<'static, ()>) {
+        AsyncStd::spawn_named(name, future);
+    }
+
+    fn spawn_blocking(&self, _: &str, f: Box<dyn FnOnce() + Send>) {
+        async_std::task::spawn_blocking(f);
+    }
+}
+
+#[cfg(feature = "async_std")]
+impl Timer for AsyncStd {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async_std::task::sleep(duration))
+    }
+}
+
+/// The [smol](https://github.com/smol-rs/smol) runtime.
+#[cfg(feature = "smol")]
+#[cfg_attr(docsrs, doc(cfg(feature = "smol")))]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Smol;
+
+#[cfg(feature = "smol")]
+impl Spawner for Smol {
+    fn spawn(&self, _: &str, future: BoxFuture<'static, ()>) {
+        smol::spawn(future).detach();
+    }
+
+    fn spawn_blocking(&self, _: &str, f: Box<dyn FnOnce() + Send>) {
+        smol::unblock(f).detach();
+    }
+}
+
+#[cfg(feature = "smol")]
+impl Timer for Smol {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(async move {
+            smol::Timer::after(duration).await;
+        })
+    }
+}
@@ -0,0 +1,192 @@
+//! The threads behind [`Fallback`](super::Fallback), started once and shared by all actors which
+//! have no runtime configured.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Condvar, Mutex, MutexGuard, OnceLock};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::Instant;
+
+use futures_core::future::BoxFuture;
+
+/// The sleeps which are pending, woken by a single thread once their deadline has passed.
+struct Timers {
+    /// The wakers of the pending sleeps, keyed by their deadline and an id to tell apart sleeps
+    /// with the same deadline.
+    sleeps: Mutex<BTreeMap<(Instant, u64), Waker>>,
+    next_id: AtomicU64,
+    /// Notified whenever a sleep is registered, in case it is due before all others.
+    registered: Condvar,
+}
+
+impl Timers {
+    fn get() -> &'static Timers {
+        static TIMERS: OnceLock<Timers> = OnceLock::new();
+
+        TIMERS.get_or_init(|| {
+            thread::Builder::new()
+                .name("xtra::timer".to_owned())
+                .spawn(|| Timers::get().run())
+                .expect("to be able to spawn thread");
+
+            Timers {
+                sleeps: Mutex::new(BTreeMap::new()),
+                next_id: AtomicU64::new(0),
+                registered: Condvar::new(),
+            }
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BTreeMap<(Instant, u64), Waker>> {
+        self.sleeps
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Wake every sleep once its deadline has passed, waiting for the earliest one in between.
+    fn run(&self) {
+        let mut sleeps = self.lock();
+
+        loop {
+            let now = Instant::now();
+
+            while let Some(sleep) = sleeps.first_entry() {
+                if sleep.key().0 > now {
+                    break;
+                }
+
+                sleep.remove().wake();
+            }
+
+            sleeps = match sleeps.keys().next() {
+                Some((deadline, _)) => {
+                    let timeout = deadline.saturating_duration_since(now);
+
+                    self.registered
+                        .wait_timeout(sleeps, timeout)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+                None => self
+                    .registered
+                    .wait(sleeps)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            };
+        }
+    }
+}
+
+/// A future which completes once the deadline has passed. It only registers with the timer thread
+/// once it is polled, and deregisters when it is dropped, so that sleeps which are given up do not
+/// pile up.
+pub(crate) struct Sleep {
+    deadline: Instant,
+    key: Option<(Instant, u64)>,
+}
+
+impl Sleep {
+    pub(crate) fn until(deadline: Instant) -> Self {
+        Sleep {
+            deadline,
+            key: None,
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+
+        if Instant::now() >= this.deadline {
+            if let Some(key) = this.key.take() {
+                Timers::get().lock().remove(&key);
+            }
+
+            return Poll::Ready(());
+        }
+
+        let timers = Timers::get();
+        let key = *this.key.get_or_insert_with(|| {
+            (
+                this.deadline,
+                timers.next_id.fetch_add(1, Ordering::Relaxed),
+            )
+        });
+
+        timers.lock().insert(key, cx.waker().clone());
+        timers.registered.notify_one();
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            Timers::get().lock().remove(&key);
+        }
+    }
+}
+
+/// A task spawned onto the executor thread, polled again whenever it is woken.
+struct Task {
+    future: Mutex<Option<BoxFuture<'static, ()>>>,
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        // The executor thread runs for as long as the process, so the queue is never closed.
+        let _ = tasks().send(self);
+    }
+}
+
+/// The queue of tasks which are ready to be polled, drained by a single executor thread.
+fn tasks() -> &'static mpsc::Sender<Arc<Task>> {
+    static TASKS: OnceLock<mpsc::Sender<Arc<Task>>> = OnceLock::new();
+
+    TASKS.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Arc<Task>>();
+
+        thread::Builder::new()
+            .name("xtra::executor".to_owned())
+            .spawn(move || {
+                for task in receiver {
+                    let mut future = task
+                        .future
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+                    // A task may be woken again after it has completed.
+                    let Some(pending) = future.as_mut() else {
+                        continue;
+                    };
+
+                    let waker = Waker::from(task.clone());
+
+                    if pending
+                        .as_mut()
+                        .poll(&mut Context::from_waker(&waker))
+                        .is_ready()
+                    {
+                        *future = None;
+                    }
+                }
+            })
+            .expect("to be able to spawn thread");
+
+        sender
+    })
+}
+
+/// Run the future on the executor thread.
+pub(crate) fn spawn(future: BoxFuture<'static, ()>) {
+    Arc::new(Task {
+        future: Mutex::new(Some(future)),
+    })
+    .wake();
+}
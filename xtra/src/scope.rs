@@ -0,0 +1,158 @@
+//! Actors whose lifetime is bound to a lexical scope, see [`scope`].
+
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::runtime::{Spawner, Timer};
+use crate::shutdown::ShutdownGroup;
+use crate::{Actor, Address, Mailbox};
+
+/// Run `f` with a [`Scope`] through which it spawns actors, and stop all those actors once the
+/// future returned by `f` has completed, resolving to its output once they all have stopped.
+///
+/// Leaving the scope asks every actor spawned in it to stop like
+/// [`Context::stop_all`](crate::Context::stop_all), so that it finishes its current message first.
+/// Actors which have not stopped after `grace` are stopped forcefully by dropping their queued
+/// messages, like [`ShutdownGroup::shutdown`] does. Either way, the returned future only resolves
+/// once [`Actor::stopped`] has completed for all of them, so that no actor outlives its scope.
+/// As a handler cannot be interrupted from the outside, this waits for the current handler of an
+/// actor however long it takes. Actors which are spawned in the scope while it is being left, e.g.
+/// by another actor of the scope, are stopped as well.
+///
+/// If the returned future is dropped before it has completed, the actors are stopped forcefully
+/// without waiting for them. Scopes nest: an inner scope stops its actors before the code of the
+/// outer scope which awaits it goes on.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use xtra::prelude::*;
+/// # struct Worker;
+/// # impl Actor for Worker { type Stop = (); async fn stopped(self) {} }
+/// # struct Work;
+/// # impl Handler<Work> for Worker { type Return = u32; async fn handle(&mut self, _: Work, _: &mut Context<Self>) -> u32 { 42 } }
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let grace = Duration::from_secs(5);
+///
+///     let (answer, worker) = xtra::scope(xtra::runtime::Smol, grace, |scope| async move {
+///         let worker = scope.spawn(Worker, Mailbox::unbounded());
+///
+///         (worker.send(Work).await.unwrap(), worker)
+///     })
+///     .await;
+///
+///     assert_eq!(answer, 42);
+///     assert!(!worker.is_connected()); // The worker stopped when the scope was left.
+/// })
+/// ```
+pub async fn scope<R, F, Fut>(runtime: R, grace: Duration, f: F) -> Fut::Output
+where
+    R: Spawner + Timer + Clone,
+    F: FnOnce(Scope<R>) -> Fut,
+    Fut: Future,
+{
+    let scope = Scope {
+        inner: Arc::new(Inner {
+            group: ShutdownGroup::new(runtime.clone()),
+            stopped: Mutex::new(Vec::new()),
+            runtime,
+        }),
+    };
+    let guard = LeaveOnDrop(scope.clone());
+
+    let output = f(scope.clone()).await;
+
+    // Actors may spawn further actors into the scope while it is being left.
+    loop {
+        let stopped = std::mem::take(&mut *scope.inner.stopped.lock().unwrap());
+
+        if stopped.is_empty() {
+            break;
+        }
+
+        let _ = scope.inner.group.shutdown(grace).await;
+
+        for stopped in stopped {
+            // The task of the actor may have been dropped by the runtime, which stops it all the same.
+            let _ = stopped.await;
+        }
+    }
+
+    // Nothing is left to stop, so dropping the guard does nothing.
+    drop(guard);
+
+    output
+}
+
+/// Spawns actors which are stopped once the [`scope`] which created it is left.
+///
+/// Cloning a [`Scope`] is cheap and all clones spawn into the same scope, e.g. to hand it to an
+/// actor which spawns further actors.
+pub struct Scope<R> {
+    inner: Arc<Inner<R>>,
+}
+
+struct Inner<R> {
+    runtime: R,
+    group: ShutdownGroup,
+    /// Resolve once the event loop of the respective actor has completed.
+    stopped: Mutex<Vec<catty::Receiver<()>>>,
+}
+
+impl<R> Scope<R>
+where
+    R: Spawner + Timer + Clone,
+{
+    /// Spawn the given actor onto the runtime of this scope, returning an [`Address`] to it.
+    ///
+    /// Unless configured otherwise, the actor's [`Mailbox`] uses the runtime of the scope as its
+    /// [`Spawner`] and [`Timer`]. Like with the `spawn` functions of xtra, the actor also stops
+    /// once all strong addresses to it have been dropped, even before the scope is left.
+    pub fn spawn<A>(&self, actor: A, (address, mailbox): (Address<A>, Mailbox<A>)) -> Address<A>
+    where
+        A: Actor,
+    {
+        let mailbox = mailbox.with_default_runtime(self.inner.runtime.clone());
+        let name = crate::runtime::task_name(&actor.name(), mailbox.id());
+        let (stopped, on_stopped) = catty::oneshot();
+
+        self.inner.group.add(0, &address);
+        self.inner.stopped.lock().unwrap().push(on_stopped);
+        self.inner.runtime.spawn(
+            &name,
+            Box::pin(async move {
+                crate::run(mailbox, actor).await;
+                let _ = stopped.send(());
+            }),
+        );
+
+        address
+    }
+}
+
+impl<R> Clone for Scope<R> {
+    fn clone(&self) -> Self {
+        Scope {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<R> fmt::Debug for Scope<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scope")
+            .field("actors", &self.inner.stopped.lock().unwrap().len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Stops the actors of the scope forcefully if it is dropped before it has been left.
+struct LeaveOnDrop<R>(Scope<R>);
+
+impl<R> Drop for LeaveOnDrop<R> {
+    fn drop(&mut self) {
+        self.0.inner.group.force_stop_all();
+    }
+}
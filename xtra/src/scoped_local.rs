@@ -0,0 +1,28 @@
+//! Setting a thread-local value for the duration of a call.
+
+use std::cell::Cell;
+use std::thread::LocalKey;
+
+/// Set the thread-local `key` to `value` while `f` is running, restoring the previous value
+/// afterwards, even if `f` panics.
+pub(crate) fn set<T, R>(key: &'static LocalKey<Cell<T>>, value: T, f: impl FnOnce() -> R) -> R
+where
+    T: Copy + 'static,
+{
+    struct Restore<T: Copy + 'static> {
+        key: &'static LocalKey<Cell<T>>,
+        previous: T,
+    }
+
+    impl<T: Copy + 'static> Drop for Restore<T> {
+        fn drop(&mut self) {
+            self.key.with(|current| current.set(self.previous));
+        }
+    }
+
+    let _restore = Restore {
+        key,
+        previous: key.with(|current| current.replace(value)),
+    };
+    f()
+}
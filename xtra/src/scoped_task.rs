@@ -1,6 +1,8 @@
+use std::fmt;
 use std::future::Future;
 use std::pin::Pin;
-use std::task::{Context, Poll};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
 
 use futures_util::FutureExt;
 
@@ -51,3 +53,117 @@ where
         }
     }
 }
+
+/// A handle to a task spawned with [`Context::spawn_task`](crate::Context::spawn_task), which is
+/// aborted when the actor stops.
+///
+/// Dropping the handle does not abort the task.
+#[derive(Clone)]
+pub struct TaskHandle(Arc<spin::Mutex<TaskState>>);
+
+#[derive(Default)]
+struct TaskState {
+    aborted: bool,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+impl TaskHandle {
+    /// Abort the task, dropping its future the next time it is polled. Aborting a task which has
+    /// finished already has no effect.
+    pub fn abort(&self) {
+        let mut state = self.0.lock();
+        state.aborted = true;
+        let waker = state.waker.take();
+        drop(state);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    /// Whether the task has completed or its future has been dropped after it was aborted.
+    pub fn is_finished(&self) -> bool {
+        self.0.lock().finished
+    }
+}
+
+impl fmt::Debug for TaskHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.lock();
+
+        f.debug_struct("TaskHandle")
+            .field("aborted", &state.aborted)
+            .field("finished", &state.finished)
+            .finish()
+    }
+}
+
+/// Wrap `task` so that it can be aborted through the returned handle.
+pub(crate) fn abortable<F>(task: F) -> (TaskHandle, impl Future<Output = ()> + Send + 'static)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let handle = TaskHandle(Arc::default());
+    let task = Abortable {
+        state: handle.clone(),
+        task: Box::pin(task),
+    };
+
+    (handle, task)
+}
+
+struct Abortable<F> {
+    state: TaskHandle,
+    task: Pin<Box<F>>,
+}
+
+impl<F> Future for Abortable<F>
+where
+    F: Future<Output = ()>,
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        {
+            let mut state = self.state.0.lock();
+
+            if state.aborted {
+                return Poll::Ready(());
+            }
+
+            state.waker = Some(cx.waker().clone());
+        }
+
+        self.task.as_mut().poll(cx)
+    }
+}
+
+impl<F> Drop for Abortable<F> {
+    fn drop(&mut self) {
+        self.state.0.lock().finished = true;
+    }
+}
+
+/// The tasks spawned with [`Context::spawn_task`](crate::Context::spawn_task), which are aborted
+/// when the actor stops.
+#[derive(Default)]
+pub(crate) struct Tasks(spin::Mutex<Vec<TaskHandle>>);
+
+impl Tasks {
+    pub(crate) fn add(&self, task: TaskHandle) {
+        let mut tasks = self.0.lock();
+
+        // Forget tasks which have finished on their own, so that they do not accumulate.
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(task);
+    }
+
+    pub(crate) fn abort_all(&self) {
+        let tasks = std::mem::take(&mut *self.0.lock());
+
+        for task in tasks {
+            task.abort();
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::future::Future;
 use std::mem;
 use std::pin::Pin;
@@ -8,8 +9,10 @@ use futures_core::FusedFuture;
 use futures_util::FutureExt;
 
 use crate::chan::{MailboxFull, MessageToAll, MessageToOne, RefCounter, WaitingSender};
-use crate::envelope::{BroadcastEnvelopeConcrete, ReturningEnvelope};
-use crate::{chan, Error, Handler};
+use crate::deadlock::AwaitingReply;
+use crate::envelope::{BroadcastEnvelopeConcrete, ExecEnvelope, ExecFn, ReturningEnvelope};
+use crate::reply::WantReply;
+use crate::{chan, Actor, Error, Handler};
 
 /// A [`Future`] that represents the state of sending a message to an actor.
 ///
@@ -23,6 +26,36 @@ use crate::{chan, Error, Handler};
 ///
 /// In case an actor's mailbox is bounded, [`SendFuture`] will yield `Pending` until the message is queued successfully.
 /// This allows an actor to exercise backpressure on its users.
+///
+/// [`SendFuture`] and [`Receiver`] implement [`FusedFuture`], so they can be used in `select!`
+/// without calling `fuse` on them:
+///
+/// ```rust
+/// # use futures_util::select;
+/// # use xtra::prelude::*;
+/// # struct Greeter;
+/// # impl Actor for Greeter { type Stop = (); async fn stopped(self) {} }
+/// # struct Hello;
+/// # impl Handler<Hello> for Greeter { type Return = &'static str; async fn handle(&mut self, _: Hello, _: &mut Context<Self>) -> &'static str { "hello" } }
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let address = xtra::spawn_smol(Greeter, Mailbox::unbounded());
+///
+///     let mut reply = address.send(Hello);
+///     let mut detached_reply = address.send(Hello).detach().await.unwrap();
+///     let mut replies = Vec::new();
+///
+///     loop {
+///         select! {
+///             r = reply => replies.push(r.unwrap()),
+///             r = detached_reply => replies.push(r.unwrap()),
+///             complete => break,
+///         }
+///     }
+///
+///     assert_eq!(replies, ["hello", "hello"]);
+/// })
+/// ```
 #[must_use = "Futures do nothing unless polled"]
 pub struct SendFuture<F, S> {
     sending: F,
@@ -40,6 +73,10 @@ pub struct ResolveToReceiver<R>(Option<Receiver<R>>);
 /// State-type for [`SendFuture`] to declare that it is a broadcast.
 pub struct Broadcast(());
 
+/// State-type for [`SendFuture`] to declare that the return value of the [`Handler`]
+/// is discarded, see [`Address::send_and_forget`](crate::Address::send_and_forget).
+pub struct Forget(());
+
 impl<F, R> SendFuture<F, ResolveToHandlerReturn<R>>
 where
     F: Future,
@@ -53,6 +90,14 @@ where
             state: self.state.resolve_to_receiver(),
         }
     }
+
+    /// Discard the return value of the handler, resolving once the message is queued.
+    pub(crate) fn forget(self) -> SendFuture<F, Forget> {
+        SendFuture {
+            sending: self.sending,
+            state: Forget(()),
+        }
+    }
 }
 
 impl<F, S> SendFuture<F, S>
@@ -96,6 +141,75 @@ where
     {
         let (envelope, receiver) = ReturningEnvelope::<A, M, R>::new(message, 0);
 
+        Self::sending_named_envelope(envelope, receiver, sender)
+    }
+
+    /// Construct a [`SendFuture`] like [`SendFuture::sending_named`] which carries the channel of
+    /// the sender for the handler to reply to.
+    pub(crate) fn sending_named_from<M>(
+        message: M,
+        reply_to: Box<dyn Any + Send>,
+        sender: chan::Ptr<A, Rc>,
+    ) -> Self
+    where
+        A: Handler<M, Return = R>,
+        M: Send + 'static,
+    {
+        let (envelope, receiver) = ReturningEnvelope::<A, M, R>::new(message, 0);
+
+        Self::sending_named_envelope(envelope.with_reply_to(reply_to), receiver, sender)
+    }
+
+    /// Construct a [`SendFuture`] like [`SendFuture::sending_named`] with the given correlation id
+    /// instead of the inherited one.
+    pub(crate) fn sending_named_correlated<M>(
+        message: M,
+        correlation_id: u128,
+        sender: chan::Ptr<A, Rc>,
+    ) -> Self
+    where
+        A: Handler<M, Return = R>,
+        M: Send + 'static,
+    {
+        let (envelope, receiver) = ReturningEnvelope::<A, M, R>::new(message, 0);
+        let envelope = envelope.with_correlation_id(correlation_id);
+
+        Self::sending_named_envelope(envelope, receiver, sender)
+    }
+
+    /// Construct a [`SendFuture`] which runs the given closure against the actor, see
+    /// [`Address::exec`](crate::Address::exec).
+    pub(crate) fn exec(f: ExecFn<A, R>, sender: chan::Ptr<A, Rc>) -> Self
+    where
+        A: Actor,
+    {
+        let (envelope, receiver) = ExecEnvelope::new(f);
+        let receiver = Receiver::new(receiver)
+            .awaited_from(&sender)
+            .wanting(envelope.want_reply());
+
+        Self {
+            sending: ActorNamedSending(Sending::New {
+                msg: Box::new(envelope) as MessageToOne<A>,
+                sender,
+            }),
+            state: ResolveToHandlerReturn::new(receiver),
+        }
+    }
+
+    fn sending_named_envelope<M>(
+        envelope: ReturningEnvelope<A, M, R>,
+        receiver: catty::Receiver<Result<R, Error>>,
+        sender: chan::Ptr<A, Rc>,
+    ) -> Self
+    where
+        A: Handler<M, Return = R>,
+        M: Send + 'static,
+    {
+        let receiver = Receiver::new(receiver)
+            .awaited_from(&sender)
+            .wanting(envelope.want_reply());
+
         Self {
             sending: ActorNamedSending(Sending::New {
                 msg: Box::new(envelope) as MessageToOne<A>,
@@ -106,6 +220,33 @@ where
     }
 }
 
+impl<A, Rc> SendFuture<ActorNamedSending<A, Rc>, Forget>
+where
+    Rc: RefCounter,
+{
+    /// Construct a [`SendFuture`] which resolves once the message is queued, handing out the
+    /// oneshot receiver of the reply separately, see [`Address::send_raw`](crate::Address::send_raw).
+    pub(crate) fn sending_raw<M>(
+        message: M,
+        sender: chan::Ptr<A, Rc>,
+    ) -> (Self, catty::Receiver<Result<A::Return, Error>>)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+    {
+        let (envelope, receiver) = ReturningEnvelope::<A, M, A::Return>::new(message, 0);
+        let sending = Self {
+            sending: ActorNamedSending(Sending::New {
+                msg: Box::new(envelope) as MessageToOne<A>,
+                sender,
+            }),
+            state: Forget(()),
+        };
+
+        (sending, receiver)
+    }
+}
+
 impl<R> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>> {
     pub(crate) fn sending_erased<A, M, Rc>(message: M, sender: chan::Ptr<A, Rc>) -> Self
     where
@@ -115,6 +256,9 @@ impl<R> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>> {
         R: Send + 'static,
     {
         let (envelope, receiver) = ReturningEnvelope::<A, M, R>::new(message, 0);
+        let receiver = Receiver::new(receiver)
+            .awaited_from(&sender)
+            .wanting(envelope.want_reply());
 
         Self {
             sending: ActorErasedSending(Box::new(Sending::New {
@@ -124,6 +268,44 @@ impl<R> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>> {
             state: ResolveToHandlerReturn::new(receiver),
         }
     }
+
+    /// Like [`SendFuture::sending_erased`], but queue the message right away instead of when the
+    /// future is first polled, giving it back if the actor has stopped.
+    pub(crate) fn try_sending_erased<A, M, Rc>(
+        message: M,
+        sender: chan::Ptr<A, Rc>,
+    ) -> Result<Self, M>
+    where
+        Rc: RefCounter,
+        A: Handler<M, Return = R>,
+        M: Send + 'static,
+        R: Send + 'static,
+    {
+        let (envelope, receiver) = ReturningEnvelope::<A, M, R>::new(message, 0);
+        let receiver = Receiver::new(receiver)
+            .awaited_from(&sender)
+            .wanting(envelope.want_reply());
+
+        let sending: Box<dyn private::ErasedSending> = match sender
+            .try_queue_to_one(Box::new(envelope))
+        {
+            Ok(Ok(())) => Box::new(Resolved(Some(Ok(())))),
+            Ok(Err(MailboxFull(waiting))) => Box::new(Sending::<A, _, Rc>::WaitingToSend(waiting)),
+            Err(envelope) => {
+                let envelope = envelope
+                    .into_any()
+                    .downcast::<ReturningEnvelope<A, M, R>>()
+                    .expect("envelope to be the one which was queued");
+
+                return Err(envelope.into_message());
+            }
+        };
+
+        Ok(Self {
+            sending: ActorErasedSending(sending),
+            state: ResolveToHandlerReturn::new(receiver),
+        })
+    }
 }
 
 impl<A, Rc> SendFuture<ActorNamedBroadcasting<A, Rc>, Broadcast>
@@ -147,6 +329,55 @@ where
     }
 }
 
+impl<R> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>>
+where
+    R: Send + 'static,
+{
+    /// Construct a [`SendFuture`] which does not send a message to an actor, but immediately
+    /// resolves to the given result.
+    #[cfg(feature = "remote")]
+    pub(crate) fn resolved(result: Result<R, Error>) -> Self {
+        let (sender, receiver) = catty::oneshot();
+        let sending = result.map(|r| {
+            let _ = sender.send(Ok(r));
+        });
+
+        Self {
+            sending: ActorErasedSending(Box::new(Resolved(Some(sending)))),
+            state: ResolveToHandlerReturn::new(Receiver::new(receiver)),
+        }
+    }
+}
+
+impl<R> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>>
+where
+    R: Send + 'static,
+{
+    /// Construct a [`SendFuture`] which does not send a message to an actor, but resolves to the
+    /// result of calling `f` the first time it is polled.
+    pub(crate) fn lazy<F>(f: F) -> Self
+    where
+        F: FnOnce() -> Result<R, Error> + Send + 'static,
+    {
+        let (sender, receiver) = catty::oneshot();
+
+        Self {
+            sending: ActorErasedSending(Box::new(Lazy(Some((f, sender))))),
+            state: ResolveToHandlerReturn::new(Receiver::new(receiver)),
+        }
+    }
+
+    /// Construct a [`SendFuture`] which does not send a message to an actor, but resolves to the
+    /// result received over `receiver`.
+    #[cfg(feature = "remote")]
+    pub(crate) fn resolving(receiver: catty::Receiver<Result<R, Error>>) -> Self {
+        Self {
+            sending: ActorErasedSending(Box::new(Resolved(Some(Ok(()))))),
+            state: ResolveToHandlerReturn::new(Receiver::new(receiver)),
+        }
+    }
+}
+
 #[allow(dead_code)] // This will useful later.
 impl SendFuture<ActorErasedSending, Broadcast> {
     pub(crate) fn broadcast_erased<A, M, Rc>(msg: M, sender: chan::Ptr<A, Rc>) -> Self
@@ -167,6 +398,54 @@ impl SendFuture<ActorErasedSending, Broadcast> {
     }
 }
 
+/// "Sending" state for messages which are not sent to an actor's mailbox or were queued already,
+/// see [`SendFuture::resolved`].
+struct Resolved(Option<Result<(), Error>>);
+
+impl Future for Resolved {
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Self::Output> {
+        Poll::Ready(self.get_mut().0.take().expect("polled after completion"))
+    }
+}
+
+impl FusedFuture for Resolved {
+    fn is_terminated(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+/// "Sending" state for messages which are not sent to an actor's mailbox, see [`SendFuture::lazy`].
+struct Lazy<F, R>(Option<(F, catty::Sender<Result<R, Error>>)>);
+
+// The closure is never pinned, it is moved out and called on the first poll.
+impl<F, R> Unpin for Lazy<F, R> {}
+
+impl<F, R> Future for Lazy<F, R>
+where
+    F: FnOnce() -> Result<R, Error>,
+{
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Self::Output> {
+        let (f, sender) = self.get_mut().0.take().expect("polled after completion");
+
+        Poll::Ready(f().map(|r| {
+            let _ = sender.send(Ok(r));
+        }))
+    }
+}
+
+impl<F, R> FusedFuture for Lazy<F, R>
+where
+    F: FnOnce() -> Result<R, Error>,
+{
+    fn is_terminated(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
 /// The core state machine around sending a message to an actor's mailbox.
 #[must_use = "Futures do nothing unless polled"]
 enum Sending<A, M, Rc: RefCounter> {
@@ -334,6 +613,34 @@ where
         this.state.0.poll_unpin(ctx)
     }
 }
+
+impl<R, F> FusedFuture for SendFuture<F, ResolveToHandlerReturn<R>>
+where
+    Self: Future,
+{
+    fn is_terminated(&self) -> bool {
+        self.state.0.is_terminated()
+    }
+}
+
+impl<R, F> FusedFuture for SendFuture<F, ResolveToReceiver<R>>
+where
+    Self: Future,
+{
+    fn is_terminated(&self) -> bool {
+        self.state.0.is_none()
+    }
+}
+
+impl<F> FusedFuture for SendFuture<F, Broadcast>
+where
+    Self: Future,
+    F: FusedFuture,
+{
+    fn is_terminated(&self) -> bool {
+        self.sending.is_terminated()
+    }
+}
 impl<F> Future for SendFuture<F, Broadcast>
 where
     F: Future<Output = Result<(), Error>> + Unpin,
@@ -345,26 +652,93 @@ where
     }
 }
 
+impl<F> FusedFuture for SendFuture<F, Forget>
+where
+    Self: Future,
+    F: FusedFuture,
+{
+    fn is_terminated(&self) -> bool {
+        self.sending.is_terminated()
+    }
+}
+
+impl<F> Future for SendFuture<F, Forget>
+where
+    F: Future<Output = Result<(), Error>> + Unpin,
+{
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, ctx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().sending.poll_unpin(ctx)
+    }
+}
+
 /// A [`Future`] that resolves to the [`Return`](crate::Handler::Return) value of a [`Handler`](crate::Handler).
 ///
 /// In case the actor becomes disconnected during the execution of the handler, this future will resolve to [`Error::Interrupted`].
+/// If the actor cancelled the handler with [`Context::stop_self_now`](crate::Context::stop_self_now), it will resolve to [`Error::ActorStoppedDuringHandling`].
+/// If it is awaited from within a handler of the actor which is to reply, it resolves to [`Error::WouldDeadlock`].
 #[must_use = "Futures do nothing unless polled"]
-pub struct Receiver<R>(catty::Receiver<R>);
+pub struct Receiver<R> {
+    receiver: Option<catty::Receiver<Result<R, Error>>>,
+    awaiting: AwaitingReply,
+    /// Keeps the reply wanted by the handler until this is dropped, see
+    /// [`Context::reply_wanted`](crate::Context::reply_wanted).
+    _wanted: Option<WantReply>,
+}
+
+impl<R> Receiver<R> {
+    fn new(receiver: catty::Receiver<Result<R, Error>>) -> Self {
+        Receiver {
+            receiver: Some(receiver),
+            awaiting: AwaitingReply::none(),
+            _wanted: None,
+        }
+    }
+
+    /// Tell the handler that the reply is wanted for as long as this receiver exists.
+    fn wanting(mut self, wanted: WantReply) -> Self {
+        self._wanted = Some(wanted);
+        self
+    }
+
+    /// Track that the reply is awaited from the actor of the given channel, to detect handlers
+    /// awaiting a reply from their own actor in debug builds.
+    fn awaited_from<A: Actor, Rc: RefCounter>(mut self, chan: &chan::Ptr<A, Rc>) -> Self {
+        self.awaiting = AwaitingReply::new(chan);
+        self
+    }
+}
 
 impl<R> Future for Receiver<R> {
     type Output = Result<R, Error>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        self.get_mut()
-            .0
-            .poll_unpin(cx)
-            .map_err(|_| Error::Interrupted)
+        let this = self.get_mut();
+
+        if this.awaiting.on_poll().is_err() {
+            this.receiver = None;
+            return Poll::Ready(Err(Error::WouldDeadlock));
+        }
+
+        let receiver = this.receiver.as_mut().expect("polled after completion");
+        let result = futures_util::ready!(receiver.poll_unpin(cx));
+        this.receiver = None;
+        this.awaiting.done();
+
+        Poll::Ready(result.unwrap_or(Err(Error::Interrupted)))
+    }
+}
+
+impl<R> FusedFuture for Receiver<R> {
+    fn is_terminated(&self) -> bool {
+        self.receiver.is_none()
     }
 }
 
 impl<R> ResolveToHandlerReturn<R> {
-    fn new(receiver: catty::Receiver<R>) -> Self {
-        Self(Receiver(receiver))
+    fn new(receiver: Receiver<R>) -> Self {
+        Self(receiver)
     }
 
     fn resolve_to_receiver(self) -> ResolveToReceiver<R> {
@@ -423,6 +797,14 @@ mod private {
         }
     }
 
+    impl SetPriority for Resolved {
+        fn set_priority(&mut self, _: u32) {}
+    }
+
+    impl<F, R> SetPriority for Lazy<F, R> {
+        fn set_priority(&mut self, _: u32) {}
+    }
+
     impl SetPriority for ActorErasedSending {
         fn set_priority(&mut self, priority: u32) {
             self.0.set_priority(priority)
@@ -0,0 +1,274 @@
+//! Integration with [tower](https://github.com/tower-rs/tower), allowing actors to be used as
+//! [`Service`]s.
+
+use std::fmt;
+use std::marker::PhantomData;
+use std::task::{Context, Poll};
+
+use event_listener::EventListener;
+use futures_util::FutureExt;
+use tower_service::Service;
+
+use crate::message_channel::MessageChannel;
+use crate::refcount::Strong;
+use crate::send_future::ResolveToHandlerReturn;
+use crate::{ActorErasedSending, ActorNamedSending, Address, Error, Handler, SendFuture};
+
+/// A [`Service`] which sends each request as a message of type `M` to an actor, responding with
+/// the [`Return`](Handler::Return) value of the handler.
+///
+/// For bounded mailboxes, [`Service::poll_ready`] only becomes ready once the actor's mailbox has
+/// capacity for another message. This is not a reservation: other senders may still fill up the
+/// mailbox in the meantime, in which case the response future waits for capacity before sending.
+///
+/// Cloning an [`ActorService`] is cheap, as it only clones the underlying [`Address`].
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// # use xtra::service::ActorService;
+/// # use tower_service::Service;
+/// # struct Greeter;
+/// # impl Actor for Greeter { type Stop = (); async fn stopped(self) {} }
+/// struct Greet(&'static str);
+///
+/// impl Handler<Greet> for Greeter {
+///     type Return = String;
+///
+///     async fn handle(&mut self, Greet(name): Greet, _: &mut Context<Self>) -> String {
+///         format!("Hello, {name}!")
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let addr = xtra::spawn_smol(Greeter, Mailbox::bounded(8));
+///     let mut service = ActorService::new(addr);
+///
+///     std::future::poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+///     assert_eq!(service.call(Greet("xtra")).await.unwrap(), "Hello, xtra!");
+/// })
+/// ```
+pub struct ActorService<A, M> {
+    address: Address<A>,
+    capacity: Option<EventListener>,
+    phantom: PhantomData<fn(M)>,
+}
+
+impl<A, M> ActorService<A, M>
+where
+    A: Handler<M>,
+    M: Send + 'static,
+{
+    /// Create a new [`ActorService`] sending requests to the actor behind the given address.
+    pub fn new(address: Address<A>) -> Self {
+        ActorService {
+            address,
+            capacity: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Get a reference to the [`Address`] this service sends requests to.
+    pub fn address(&self) -> &Address<A> {
+        &self.address
+    }
+
+    /// Consume this service, returning the [`Address`] it sends requests to.
+    pub fn into_address(self) -> Address<A> {
+        self.address
+    }
+}
+
+impl<A, M> Service<M> for ActorService<A, M>
+where
+    A: Handler<M>,
+    M: Send + 'static,
+{
+    type Response = <A as Handler<M>>::Return;
+    type Error = Error;
+    type Future =
+        SendFuture<ActorNamedSending<A, Strong>, ResolveToHandlerReturn<<A as Handler<M>>::Return>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let address = &self.address;
+
+        poll_capacity(
+            cx,
+            &mut self.capacity,
+            || (!address.is_connected()).then_some(Error::Disconnected),
+            || address.0.is_full(),
+            || Some(address.0.capacity_listener()),
+        )
+    }
+
+    fn call(&mut self, message: M) -> Self::Future {
+        self.address.send(message)
+    }
+}
+
+impl<A, M> Clone for ActorService<A, M> {
+    fn clone(&self) -> Self {
+        ActorService {
+            address: self.address.clone(),
+            capacity: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<A, M> fmt::Debug for ActorService<A, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ActorService")
+            .field("address", &self.address)
+            .finish()
+    }
+}
+
+/// A [`Service`] which sends each request as a message of type `M` through a [`MessageChannel`],
+/// responding with the return value `R` of the handler.
+///
+/// Like [`ActorService`], but for any actor which can handle `M`, so that the actor behind the
+/// service is not part of its type. [`Service::poll_ready`] waits for capacity in bounded mailboxes
+/// in the same way.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// # use xtra::service::ChannelService;
+/// # use tower_service::Service;
+/// # struct Greeter;
+/// # impl Actor for Greeter { type Stop = (); async fn stopped(self) {} }
+/// struct Greet(&'static str);
+///
+/// impl Handler<Greet> for Greeter {
+///     type Return = String;
+///
+///     async fn handle(&mut self, Greet(name): Greet, _: &mut Context<Self>) -> String {
+///         format!("Hello, {name}!")
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let addr = xtra::spawn_smol(Greeter, Mailbox::bounded(8));
+///     let mut service = ChannelService::new(MessageChannel::new(addr));
+///
+///     std::future::poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+///     assert_eq!(service.call(Greet("xtra")).await.unwrap(), "Hello, xtra!");
+/// })
+/// ```
+pub struct ChannelService<M, R> {
+    channel: MessageChannel<M, R>,
+    capacity: Option<EventListener>,
+}
+
+impl<M, R> ChannelService<M, R>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    /// Create a new [`ChannelService`] sending requests through the given channel.
+    pub fn new(channel: MessageChannel<M, R>) -> Self {
+        ChannelService {
+            channel,
+            capacity: None,
+        }
+    }
+
+    /// Get a reference to the [`MessageChannel`] this service sends requests through.
+    pub fn channel(&self) -> &MessageChannel<M, R> {
+        &self.channel
+    }
+
+    /// Consume this service, returning the [`MessageChannel`] it sends requests through.
+    pub fn into_channel(self) -> MessageChannel<M, R> {
+        self.channel
+    }
+}
+
+impl<M, R> Service<M> for ChannelService<M, R>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    type Response = R;
+    type Error = Error;
+    type Future = SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        let channel = &self.channel;
+
+        poll_capacity(
+            cx,
+            &mut self.capacity,
+            || (!channel.is_connected()).then_some(Error::Disconnected),
+            || {
+                channel
+                    .capacity()
+                    .is_some_and(|capacity| channel.len() >= capacity)
+            },
+            || channel.capacity_listener(),
+        )
+    }
+
+    fn call(&mut self, message: M) -> Self::Future {
+        self.channel.send(message)
+    }
+}
+
+impl<M, R> Clone for ChannelService<M, R>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    fn clone(&self) -> Self {
+        ChannelService {
+            channel: self.channel.clone(),
+            capacity: None,
+        }
+    }
+}
+
+impl<M, R> fmt::Debug for ChannelService<M, R>
+where
+    R: Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelService")
+            .field("channel", &self.channel)
+            .finish()
+    }
+}
+
+/// Wait until a mailbox has capacity for another message or the actor has disconnected.
+fn poll_capacity(
+    cx: &mut Context<'_>,
+    capacity: &mut Option<EventListener>,
+    disconnected: impl Fn() -> Option<Error>,
+    is_full: impl Fn() -> bool,
+    listen: impl Fn() -> Option<EventListener>,
+) -> Poll<Result<(), Error>> {
+    loop {
+        if let Some(error) = disconnected() {
+            *capacity = None;
+            return Poll::Ready(Err(error));
+        }
+
+        if !is_full() {
+            *capacity = None;
+            return Poll::Ready(Ok(()));
+        }
+
+        // The listener is registered before checking again to not miss a notification.
+        match capacity.as_mut() {
+            None => match listen() {
+                Some(listener) => *capacity = Some(listener),
+                // A mailbox which cannot be listened to is never full.
+                None => return Poll::Ready(Ok(())),
+            },
+            Some(listener) => {
+                futures_util::ready!(listener.poll_unpin(cx));
+                *capacity = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Coordinated shutdown of a set of actors, stopping them in phases.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_core::future::BoxFuture;
+use futures_util::future::{self, Either};
+
+use crate::refcount::{Either as EitherRc, RefCounter};
+use crate::runtime::Timer;
+use crate::{Actor, Address, Handler, WeakAddress};
+
+/// A set of actors which are stopped together, e.g. when the application shuts down.
+///
+/// Each actor is added with a phase number. [`ShutdownGroup::shutdown`] stops the actors phase by
+/// phase in ascending order, waiting for all actors of a phase to stop before moving on to the
+/// next. For example, the actors accepting requests can be stopped in an earlier phase than the
+/// actors handling them, so that no new work arrives while the latter finish.
+///
+/// Actors are added with [`ShutdownGroup::add`], or add themselves with
+/// [`Mailbox::shutdown_in`](crate::Mailbox::shutdown_in) when they are created. The group does not
+/// keep actors alive. Cloning a [`ShutdownGroup`] is cheap and all clones refer to the same group.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use xtra::prelude::*;
+/// use xtra::shutdown::ShutdownGroup;
+///
+/// # struct Server;
+/// # impl Actor for Server { type Stop = (); async fn stopped(self) {} }
+/// # struct Database;
+/// # impl Actor for Database { type Stop = (); async fn stopped(self) {} }
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let group = ShutdownGroup::new(xtra::runtime::Smol);
+///
+///     let (database, mailbox) = Mailbox::unbounded();
+///     let database = xtra::spawn_smol(Database, (database, mailbox.shutdown_in(&group, 1)));
+///
+///     let server = xtra::spawn_smol(Server, Mailbox::unbounded());
+///     group.add(0, &server);
+///
+///     // Stops the server first, then the database.
+///     let report = group.shutdown(Duration::from_secs(5)).await;
+///     assert!(report.is_clean());
+/// # drop((database, server));
+/// })
+/// ```
+#[derive(Clone)]
+pub struct ShutdownGroup {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    members: Mutex<Vec<(u32, Box<dyn Member>)>>,
+    timer: Box<dyn Timer>,
+}
+
+/// An actor in a [`ShutdownGroup`], with the type of the actor erased.
+trait Member: Send + 'static {
+    fn name(&self) -> Cow<'static, str>;
+
+    /// Ask the actor to stop gracefully.
+    fn stop(&mut self) -> BoxFuture<'static, ()>;
+
+    /// Stop the actor without handling any more of its queued messages.
+    fn force_stop(&self);
+
+    fn join(&self) -> BoxFuture<'static, ()>;
+
+    fn is_stopped(&self) -> bool;
+}
+
+/// Stops the actor like [`Context::stop_all`](crate::Context::stop_all), optionally sending it a
+/// message first.
+struct AddressMember<A> {
+    address: WeakAddress<A>,
+    stop: Option<StopFn<A>>,
+}
+
+type StopFn<A> = Box<dyn FnOnce(&WeakAddress<A>) -> BoxFuture<'static, ()> + Send>;
+
+impl<A: Actor> Member for AddressMember<A> {
+    fn name(&self) -> Cow<'static, str> {
+        self.address.name()
+    }
+
+    fn stop(&mut self) -> BoxFuture<'static, ()> {
+        match self.stop.take() {
+            Some(stop) => stop(&self.address),
+            None => {
+                self.address.0.shutdown_all_receivers();
+                Box::pin(future::ready(()))
+            }
+        }
+    }
+
+    fn force_stop(&self) {
+        // Queued messages are dropped, so that their senders are notified.
+        drop(self.address.0.shutdown_and_drain());
+    }
+
+    fn join(&self) -> BoxFuture<'static, ()> {
+        Box::pin(self.address.join())
+    }
+
+    fn is_stopped(&self) -> bool {
+        !self.address.is_connected()
+    }
+}
+
+impl ShutdownGroup {
+    /// Create a new, empty group which uses the given [`Timer`] for the timeout of
+    /// [`ShutdownGroup::shutdown`].
+    pub fn new(timer: impl Timer) -> Self {
+        ShutdownGroup {
+            inner: Arc::new(Inner {
+                members: Mutex::new(Vec::new()),
+                timer: Box::new(timer),
+            }),
+        }
+    }
+
+    /// Add the actor to this group, to be stopped in the given phase.
+    ///
+    /// The actor is stopped like with [`Context::stop_all`](crate::Context::stop_all), i.e. all
+    /// actors on the address stop once they have finished handling their current message.
+    pub fn add<A, Rc>(&self, phase: u32, address: &Address<A, Rc>)
+    where
+        A: Actor,
+        Rc: RefCounter + Into<EitherRc>,
+    {
+        self.push(phase, address.as_either().downgrade(), None);
+    }
+
+    /// Add the actor to this group, to be stopped in the given phase by sending it `message`.
+    ///
+    /// This gives the actor the chance to finish its work before it stops itself, e.g. with
+    /// [`Context::stop_self`](crate::Context::stop_self). The message is sent behind any messages
+    /// which are already queued.
+    pub fn add_with_message<A, M, Rc>(&self, phase: u32, address: &Address<A, Rc>, message: M)
+    where
+        A: Handler<M>,
+        M: Send + 'static,
+        Rc: RefCounter + Into<EitherRc>,
+    {
+        let stop = move |address: &WeakAddress<A>| -> BoxFuture<'static, ()> {
+            let sending = address.send(message).detach();
+
+            Box::pin(async move {
+                // The actor may already have stopped, in which case there is nothing to do.
+                let _ = sending.await;
+            })
+        };
+
+        self.push(phase, address.as_either().downgrade(), Some(Box::new(stop)));
+    }
+
+    fn push<A: Actor>(&self, phase: u32, address: WeakAddress<A>, stop: Option<StopFn<A>>) {
+        self.inner
+            .members
+            .lock()
+            .unwrap()
+            .push((phase, Box::new(AddressMember { address, stop })));
+    }
+
+    /// Stop all actors in this group phase by phase, in ascending order of their phase numbers.
+    ///
+    /// The actors of a phase are asked to stop gracefully, after which the group waits for all of
+    /// them to stop for at most `timeout`. Actors which have not stopped by then are stopped
+    /// forcefully by dropping all of their queued messages. As a handler cannot be interrupted from
+    /// the outside, such an actor still stops only once its current handler returns. Either way,
+    /// the next phase is started once the timeout has elapsed.
+    ///
+    /// The returned [`ShutdownReport`] names the actors which did not stop within the timeout.
+    /// All actors are removed from the group, so that it can be reused.
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        self.shutdown_or_abort(timeout, future::pending()).await
+    }
+
+    /// Like [`ShutdownGroup::shutdown`], but cut the shutdown short once `abort` completes, e.g.
+    /// when the user presses Ctrl-C a second time.
+    ///
+    /// All actors which have not stopped by then, including those of later phases, are stopped
+    /// forcefully without waiting for them. They are reported as
+    /// [`timed out`](ShutdownReport::timed_out), and the report is marked as
+    /// [`aborted`](ShutdownReport::is_aborted).
+    pub async fn shutdown_or_abort<F>(&self, timeout: Duration, abort: F) -> ShutdownReport
+    where
+        F: Future<Output = ()>,
+    {
+        let members = std::mem::take(&mut *self.inner.members.lock().unwrap());
+        let mut phases = BTreeMap::<u32, Vec<Box<dyn Member>>>::new();
+
+        for (phase, member) in members {
+            phases.entry(phase).or_default().push(member);
+        }
+
+        let mut report = ShutdownReport {
+            timed_out: Vec::new(),
+            aborted: false,
+        };
+        let mut abort = pin!(abort);
+        let mut phases = phases.into_values();
+
+        while let Some(mut members) = phases.next() {
+            let stopping = members
+                .iter_mut()
+                .map(|member| member.stop())
+                .collect::<Vec<_>>();
+            let joining = members
+                .iter()
+                .map(|member| member.join())
+                .collect::<Vec<_>>();
+
+            let stopped = pin!(async move {
+                for stop in stopping {
+                    stop.await;
+                }
+
+                for join in joining {
+                    join.await;
+                }
+            });
+            let stopped_in_time = future::select(stopped, self.inner.timer.sleep(timeout));
+
+            match future::select(stopped_in_time, abort.as_mut()).await {
+                Either::Left((Either::Left(_), _)) => {}
+                Either::Left((Either::Right(_), _)) => report.force_stop(&members),
+                Either::Right(_) => {
+                    report.force_stop(&members);
+
+                    for members in phases {
+                        report.force_stop(&members);
+                    }
+
+                    report.aborted = true;
+                    break;
+                }
+            }
+        }
+
+        report
+    }
+}
+
+impl ShutdownGroup {
+    /// Stop all actors in this group forcefully without waiting for them, removing them from the
+    /// group.
+    pub(crate) fn force_stop_all(&self) {
+        let members = std::mem::take(&mut *self.inner.members.lock().unwrap());
+
+        for (_, member) in members.iter().filter(|(_, member)| !member.is_stopped()) {
+            member.force_stop();
+        }
+    }
+}
+
+impl fmt::Debug for ShutdownGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShutdownGroup")
+            .field("members", &self.inner.members.lock().unwrap().len())
+            .finish()
+    }
+}
+
+/// The outcome of [`ShutdownGroup::shutdown`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[must_use]
+pub struct ShutdownReport {
+    timed_out: Vec<Cow<'static, str>>,
+    aborted: bool,
+}
+
+impl ShutdownReport {
+    /// Stop the members which have not stopped yet forcefully, reporting them as timed out.
+    fn force_stop(&mut self, members: &[Box<dyn Member>]) {
+        for member in members.iter().filter(|member| !member.is_stopped()) {
+            member.force_stop();
+            self.timed_out.push(member.name());
+        }
+    }
+
+    /// Whether all actors stopped within the timeout.
+    pub fn is_clean(&self) -> bool {
+        self.timed_out.is_empty()
+    }
+
+    /// The names of the actors which did not stop within the timeout, in the order of their phases.
+    pub fn timed_out(&self) -> &[Cow<'static, str>] {
+        &self.timed_out
+    }
+
+    /// Whether the shutdown was cut short with [`ShutdownGroup::shutdown_or_abort`].
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+}
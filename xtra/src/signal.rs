@@ -0,0 +1,219 @@
+//! Sending a message to actors when the process is asked to shut down, e.g. by pressing Ctrl-C.
+//!
+//! The source of the signal is pluggable through the [`ShutdownSignal`] trait, so that any runtime
+//! can be used. With the `signal` feature, [`Tokio`](crate::runtime::Tokio) implements it using
+//! `tokio::signal` and [`on_shutdown_signal`] is available as a shorthand.
+//!
+//! [`shutdown_on_signal`] stops a whole [`ShutdownGroup`] on a signal instead of notifying a
+//! single actor.
+//!
+//! # Platform differences
+//!
+//! On Unix, [`Tokio`](crate::runtime::Tokio) listens for both `SIGINT` (Ctrl-C) and `SIGTERM`, the
+//! latter of which is what service managers and container runtimes send to stop a process.
+//! Windows has no equivalent of `SIGTERM`, so only Ctrl-C is listened for there. Closing the
+//! console window or logging off is not reported as a shutdown signal on Windows.
+
+use std::future::Future;
+use std::time::Duration;
+
+use futures_core::future::BoxFuture;
+use futures_util::future::{self, Either};
+
+use crate::refcount::{Either as EitherRc, RefCounter};
+use crate::shutdown::{ShutdownGroup, ShutdownReport};
+use crate::{Address, Handler};
+
+/// A source of shutdown signals, such as the process receiving `SIGINT` or `SIGTERM`.
+pub trait ShutdownSignal: Send + Sync + 'static {
+    /// Create a future which completes once the process is asked to shut down.
+    fn recv(&self) -> BoxFuture<'static, ()>;
+}
+
+/// Any function returning a future which completes on a shutdown signal can be used as a
+/// [`ShutdownSignal`], e.g. to integrate with the signal handling of another runtime:
+///
+/// ```rust
+/// # use xtra::signal::ShutdownSignal;
+/// # #[cfg(feature = "async_std")] {
+/// let signal = || async {
+///     // Use the signal handling of your runtime here.
+///     async_std::future::pending::<()>().await;
+/// };
+/// # let _: &dyn ShutdownSignal = &signal;
+/// # }
+/// ```
+impl<F, Fut> ShutdownSignal for F
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn recv(&self) -> BoxFuture<'static, ()> {
+        Box::pin(self())
+    }
+}
+
+/// Send `message` to the actor once `signal` is received.
+///
+/// The returned future does not keep the actor alive and completes without sending the message if
+/// the actor stops before the signal is received. Dropping the future cancels it. To notify
+/// multiple actors, call this once per actor.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// # struct Server;
+/// # impl Actor for Server { type Stop = (); async fn stopped(self) {} }
+/// struct Shutdown;
+///
+/// impl Handler<Shutdown> for Server {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: Shutdown, ctx: &mut Context<Self>) {
+///         // Finish any outstanding work here.
+///         ctx.stop_self();
+///     }
+/// }
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let address = xtra::spawn_smol(Server, Mailbox::unbounded());
+///
+///     // A signal which is received immediately, for the sake of the example.
+///     let signal = || async {};
+///     xtra::signal::send_on_signal(signal, &address, Shutdown).await;
+///
+///     address.join().await;
+/// })
+/// ```
+pub fn send_on_signal<S, A, M, Rc>(
+    signal: S,
+    address: &Address<A, Rc>,
+    message: M,
+) -> impl Future<Output = ()> + Send + 'static
+where
+    S: ShutdownSignal,
+    A: Handler<M>,
+    M: Send + 'static,
+    Rc: RefCounter + Into<EitherRc>,
+{
+    let address = address.as_either().downgrade();
+
+    async move {
+        let received = signal.recv();
+        let stopped = address.join();
+
+        if let Either::Left(_) = future::select(received, stopped).await {
+            // The actor may have stopped in the meantime, in which case there is no one to notify.
+            let _ = address.send(message).detach().await;
+        }
+    }
+}
+
+/// Shut down the [`ShutdownGroup`] once `signal` is received, resolving to its [`ShutdownReport`].
+///
+/// The group is shut down like with [`ShutdownGroup::shutdown`], waiting for at most `timeout` per
+/// phase. If `signal` is received a second time in the meantime, the shutdown is cut short and all
+/// remaining actors are stopped forcefully, see [`ShutdownGroup::shutdown_or_abort`].
+///
+/// ```rust
+/// # use std::sync::atomic::{AtomicBool, Ordering};
+/// # use std::time::Duration;
+/// # use xtra::prelude::*;
+/// use xtra::shutdown::ShutdownGroup;
+///
+/// # struct Server;
+/// # impl Actor for Server { type Stop = (); async fn stopped(self) {} }
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let group = ShutdownGroup::new(xtra::runtime::Smol);
+///     let server = xtra::spawn_smol(Server, Mailbox::unbounded());
+///     group.add(0, &server);
+///
+///     // A signal which is received once right away, for the sake of the example. With the
+///     // `signal` feature, use `xtra::runtime::Tokio` to listen for Ctrl-C and `SIGTERM`.
+///     let received = AtomicBool::new(false);
+///     let signal = move || {
+///         let first = !received.swap(true, Ordering::Relaxed);
+///         async move {
+///             if !first {
+///                 std::future::pending::<()>().await;
+///             }
+///         }
+///     };
+///     let report = xtra::signal::shutdown_on_signal(signal, group, Duration::from_secs(5)).await;
+///
+///     assert!(report.is_clean() && !report.is_aborted());
+///     assert!(!server.is_connected());
+/// })
+/// ```
+pub async fn shutdown_on_signal<S>(
+    signal: S,
+    group: ShutdownGroup,
+    timeout: Duration,
+) -> ShutdownReport
+where
+    S: ShutdownSignal,
+{
+    signal.recv().await;
+    group.shutdown_or_abort(timeout, signal.recv()).await
+}
+
+/// Send `message` to the actor once the process receives a shutdown signal, i.e. `SIGINT` or
+/// `SIGTERM` on Unix and Ctrl-C on Windows.
+///
+/// This spawns [`send_on_signal`] with [`Tokio`](crate::runtime::Tokio) as the signal source onto
+/// the tokio runtime. The task ends once the message is sent or the actor stops. To notify multiple
+/// actors, call this once per actor.
+#[cfg(feature = "signal")]
+#[cfg_attr(docsrs, doc(cfg(feature = "signal")))]
+pub fn on_shutdown_signal<A, M, Rc>(address: &Address<A, Rc>, message: M)
+where
+    A: Handler<M>,
+    M: Send + 'static,
+    Rc: RefCounter + Into<EitherRc>,
+{
+    crate::runtime::Tokio::spawn_named(
+        &format!(
+            "{}::shutdown_signal",
+            crate::runtime::task_name(&address.name(), address.id())
+        ),
+        send_on_signal(crate::runtime::Tokio, address, message),
+    );
+}
+
+#[cfg(feature = "signal")]
+#[cfg_attr(docsrs, doc(cfg(feature = "signal")))]
+impl ShutdownSignal for crate::runtime::Tokio {
+    fn recv(&self) -> BoxFuture<'static, ()> {
+        Box::pin(async {
+            // If the signal handler cannot be registered, never report a shutdown signal rather
+            // than shutting down straight away.
+            #[cfg(unix)]
+            {
+                use tokio::signal::unix::{signal, SignalKind};
+
+                let interrupt = Box::pin(async {
+                    if tokio::signal::ctrl_c().await.is_err() {
+                        future::pending().await
+                    }
+                });
+                let terminate = Box::pin(async {
+                    let Ok(mut terminate) = signal(SignalKind::terminate()) else {
+                        return future::pending().await;
+                    };
+
+                    if terminate.recv().await.is_none() {
+                        future::pending().await
+                    }
+                });
+
+                future::select(interrupt, terminate).await;
+            }
+
+            #[cfg(not(unix))]
+            if tokio::signal::ctrl_c().await.is_err() {
+                future::pending().await
+            }
+        })
+    }
+}
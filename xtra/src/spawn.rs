@@ -1,4 +1,9 @@
 /// Spawns the given actor into the tokio runtime, returning an [`Address`](crate::Address) to it.
+///
+/// Unless configured otherwise, the actor's [`Mailbox`](crate::Mailbox) will use
+/// [`Tokio`](crate::runtime::Tokio) as its [`Spawner`](crate::runtime::Spawner) and
+/// [`Timer`](crate::runtime::Timer). The task running the actor is named after [`Actor::name`](crate::Actor::name)
+/// and the [`ActorId`](crate::ActorId) of the actor, e.g. `xtra::MyActor#1`.
 #[cfg(feature = "tokio")]
 #[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
 pub fn spawn_tokio<A>(
@@ -8,12 +13,19 @@ pub fn spawn_tokio<A>(
 where
     A: crate::Actor<Stop = ()>,
 {
-    tokio::spawn(crate::run(mailbox, actor));
+    let mailbox = mailbox.with_default_runtime(crate::runtime::Tokio);
+    let name = crate::runtime::task_name(&actor.name(), mailbox.id());
+    crate::runtime::Tokio::spawn_named(&name, crate::run(mailbox, actor));
 
     address
 }
 
 /// Spawns the given actor into the async_std runtime, returning an [`Address`](crate::Address) to it.
+///
+/// Unless configured otherwise, the actor's [`Mailbox`](crate::Mailbox) will use
+/// [`AsyncStd`](crate::runtime::AsyncStd) as its [`Spawner`](crate::runtime::Spawner) and
+/// [`Timer`](crate::runtime::Timer). The task running the actor is named after [`Actor::name`](crate::Actor::name)
+/// and the [`ActorId`](crate::ActorId) of the actor, e.g. `xtra::MyActor#1`.
 #[cfg(feature = "async_std")]
 #[cfg_attr(docsrs, doc(cfg(feature = "async_std")))]
 pub fn spawn_async_std<A>(
@@ -23,12 +35,18 @@ pub fn spawn_async_std<A>(
 where
     A: crate::Actor<Stop = ()>,
 {
-    async_std::task::spawn(crate::run(mailbox, actor));
+    let mailbox = mailbox.with_default_runtime(crate::runtime::AsyncStd);
+    let name = crate::runtime::task_name(&actor.name(), mailbox.id());
+    crate::runtime::AsyncStd::spawn_named(&name, crate::run(mailbox, actor));
 
     address
 }
 
 /// Spawns the given actor into the smol runtime, returning an [`Address`](crate::Address) to it.
+///
+/// Unless configured otherwise, the actor's [`Mailbox`](crate::Mailbox) will use
+/// [`Smol`](crate::runtime::Smol) as its [`Spawner`](crate::runtime::Spawner) and
+/// [`Timer`](crate::runtime::Timer).
 #[cfg(feature = "smol")]
 #[cfg_attr(docsrs, doc(cfg(feature = "smol")))]
 pub fn spawn_smol<A>(
@@ -38,6 +56,7 @@ pub fn spawn_smol<A>(
 where
     A: crate::Actor<Stop = ()>,
 {
+    let mailbox = mailbox.with_default_runtime(crate::runtime::Smol);
     smol::spawn(crate::run(mailbox, actor)).detach();
 
     address
@@ -57,3 +76,190 @@ where
 
     address
 }
+
+/// Spawns the given actor onto a dedicated OS thread, returning an [`Address`](crate::Address) to
+/// it and the handle of the thread, which can be joined for the value returned by
+/// [`Actor::stopped`](crate::Actor::stopped).
+///
+/// This is meant for actors which wrap synchronous, blocking libraries. Their handlers may block
+/// freely without stalling the tasks of an async runtime, as the actor is driven by a minimal
+/// executor which parks the thread while the actor waits for messages. Messages are sent through
+/// the returned address like to any other actor, and async handlers are driven on the thread too.
+///
+/// The thread is named after [`Actor::name`](crate::Actor::name) and the [`ActorId`](crate::ActorId)
+/// of the actor, e.g. `xtra::MyActor#1`. There is no runtime on the thread, so functionality which
+/// needs a [`Spawner`](crate::runtime::Spawner) or [`Timer`](crate::runtime::Timer) is only
+/// available if the mailbox has been configured with one.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// # struct Database;
+/// # impl Actor for Database { type Stop = (); async fn stopped(self) {} }
+/// struct Query;
+///
+/// impl Handler<Query> for Database {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: Query, _ctx: &mut Context<Self>) {
+///         std::thread::sleep(std::time::Duration::from_millis(1)); // e.g. a synchronous query
+///     }
+/// }
+///
+/// let (address, thread) = xtra::spawn_thread(Database, Mailbox::unbounded());
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(address.send(Query)).unwrap();
+///
+/// drop(address);
+/// thread.join().unwrap();
+/// ```
+pub fn spawn_thread<A>(
+    actor: A,
+    (address, mailbox): (crate::Address<A>, crate::Mailbox<A>),
+) -> (crate::Address<A>, std::thread::JoinHandle<A::Stop>)
+where
+    A: crate::Actor,
+{
+    let name = crate::runtime::task_name(&actor.name(), mailbox.id());
+    let thread = std::thread::Builder::new()
+        .name(name)
+        .spawn(move || block_on(crate::run(mailbox, actor)))
+        .expect("to be able to spawn thread");
+
+    (address, thread)
+}
+
+/// Spawns the given actor onto a dedicated OS thread running its own single-threaded tokio
+/// runtime, returning an [`Address`](crate::Address) to it and the handle of the thread, which can
+/// be joined for the value returned by [`Actor::stopped`](crate::Actor::stopped).
+///
+/// This isolates latency-critical actors from the tasks of a shared runtime, as nothing but the
+/// actor and the tasks spawned on its behalf run on the thread. Unlike with [`spawn_thread`], the
+/// actor may use tokio from its handlers, e.g. its timers and I/O types. Messages are sent through
+/// the returned address from any thread or runtime like to any other actor.
+///
+/// Every actor spawned this way costs an OS thread and a runtime, so this is not meant for large
+/// numbers of actors. The runtime is shut down once the actor has stopped, cancelling any tasks
+/// which are still running on it.
+///
+/// Unless configured otherwise, the actor's [`Mailbox`](crate::Mailbox) uses the
+/// [`Handle`](tokio::runtime::Handle) of the runtime on the thread as its
+/// [`Spawner`](crate::runtime::Spawner) and [`Timer`](crate::runtime::Timer). The thread is named
+/// like with [`spawn_thread`].
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use xtra::prelude::*;
+/// # struct Ticker;
+/// # impl Actor for Ticker { type Stop = (); async fn stopped(self) {} }
+/// struct Tick;
+///
+/// impl Handler<Tick> for Ticker {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: Tick, _ctx: &mut Context<Self>) {
+///         tokio::time::sleep(Duration::from_millis(1)).await;
+///     }
+/// }
+///
+/// # #[cfg(feature = "tokio")] {
+/// let (address, thread) = xtra::spawn_tokio_thread(Ticker, Mailbox::unbounded());
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(address.send(Tick)).unwrap();
+///
+/// drop(address);
+/// thread.join().unwrap();
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio")))]
+pub fn spawn_tokio_thread<A>(
+    actor: A,
+    (address, mailbox): (crate::Address<A>, crate::Mailbox<A>),
+) -> (crate::Address<A>, std::thread::JoinHandle<A::Stop>)
+where
+    A: crate::Actor,
+{
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .expect("to be able to build runtime");
+    let mailbox = mailbox.with_default_runtime(runtime.handle().clone());
+    let name = crate::runtime::task_name(&actor.name(), mailbox.id());
+    let thread = std::thread::Builder::new()
+        .name(name)
+        .spawn(move || runtime.block_on(crate::run(mailbox, actor)))
+        .expect("to be able to spawn thread");
+
+    (address, thread)
+}
+
+/// Drive the future to completion on the current thread, parking the thread while it is pending.
+fn block_on<F>(future: F) -> F::Output
+where
+    F: std::future::Future,
+{
+    struct Unpark(std::thread::Thread);
+
+    impl std::task::Wake for Unpark {
+        fn wake(self: std::sync::Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = std::sync::Arc::new(Unpark(std::thread::current())).into();
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+
+    loop {
+        if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+
+        std::thread::park();
+    }
+}
+
+/// Spawns an actor for every item of the `incoming` [`Stream`](futures_core::Stream), returning a
+/// [`Stream`](futures_core::Stream) of their [`Address`](crate::Address)es.
+///
+/// For every item, `factory` creates the actor and its mailbox, which are then passed to `spawn`,
+/// e.g. [`spawn_tokio`]. This is useful for servers which handle every
+/// accepted connection with its own actor.
+///
+/// The returned stream ends once `incoming` ends. Actors are only spawned while the returned stream
+/// is polled, so dropping it cancels spawning actors for any further items. Actors which have
+/// already been spawned are not affected and keep running until their addresses are dropped.
+///
+/// ```rust
+/// # use futures_util::StreamExt;
+/// # use xtra::prelude::*;
+/// # struct Connection(u32);
+/// # impl Actor for Connection { type Stop = (); async fn stopped(self) {} }
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     let incoming = futures_util::stream::iter([1, 2, 3]);
+///     let connections = xtra::spawn_stream(
+///         incoming,
+///         |id| (Connection(id), Mailbox::bounded(16)),
+///         xtra::spawn_smol,
+///     );
+///
+///     let addresses = connections.collect::<Vec<_>>().await;
+///     assert_eq!(addresses.len(), 3);
+/// })
+/// ```
+pub fn spawn_stream<S, A, F, Sp>(
+    incoming: S,
+    mut factory: F,
+    mut spawn: Sp,
+) -> impl futures_core::Stream<Item = crate::Address<A>>
+where
+    S: futures_core::Stream,
+    F: FnMut(S::Item) -> (A, (crate::Address<A>, crate::Mailbox<A>)),
+    Sp: FnMut(A, (crate::Address<A>, crate::Mailbox<A>)) -> crate::Address<A>,
+{
+    futures_util::StreamExt::map(incoming, move |item| {
+        let (actor, mailbox) = factory(item);
+        spawn(actor, mailbox)
+    })
+}
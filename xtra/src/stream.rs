@@ -0,0 +1,250 @@
+//! Streams attached to an actor with [`Context::attach_stream`](crate::Context::attach_stream), whose
+//! items are handled by the actor like any other message.
+
+use std::convert::Infallible;
+use std::fmt;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Poll, Waker};
+
+use futures_core::Stream;
+use futures_util::FutureExt;
+
+use crate::envelope::ReturningEnvelope;
+use crate::{Error, Handler, WeakAddress};
+
+/// What an attached stream does while the actor has not handled its previous item yet.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Backpressure {
+    /// Queue every item in the mailbox as soon as the stream yields it, regardless of the capacity
+    /// of the mailbox. Items are buffered without bound if the actor cannot keep up.
+    Buffer,
+    /// Only pull the next item from the stream once the actor has handled the previous one.
+    #[default]
+    Pause,
+    /// Keep pulling items from the stream, dropping those which it yields while the actor has not
+    /// handled the previous item yet. Dropped items are counted, see [`StreamHandle::dropped`].
+    Drop,
+}
+
+/// Identifies a stream attached to an actor, to tell apart the [`StreamCompleted`] messages of
+/// several streams.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct StreamId(u64);
+
+/// The message an actor receives once a stream attached to it has ended, after all of its items.
+///
+/// For streams attached with [`Context::attach_try_stream`](crate::Context::attach_try_stream),
+/// `E` is the type of the error which ends the stream. Streams attached with
+/// [`Context::attach_stream`](crate::Context::attach_stream) cannot fail.
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct StreamCompleted<E = Infallible> {
+    /// The stream which has ended.
+    pub id: StreamId,
+    /// The number of items which were dropped with [`Backpressure::Drop`].
+    pub dropped: usize,
+    /// The error which ended the stream, if any.
+    pub error: Option<E>,
+    /// Whether the stream was detached through its [`StreamHandle`] before it ended.
+    pub detached: bool,
+}
+
+/// A handle to a stream attached to an actor, which detaches the stream from the actor when it is
+/// dropped.
+///
+/// The handle is `Send`, so it can be kept in the state of the actor for as long as the actor
+/// should follow the stream. Use [`StreamHandle::forget`] to keep the stream attached until it
+/// ends or the actor stops instead.
+#[must_use = "The stream is detached right away if its handle is dropped"]
+pub struct StreamHandle {
+    id: StreamId,
+    shared: Arc<Shared>,
+    detach_on_drop: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    dropped: AtomicUsize,
+    detach: spin::Mutex<Detach>,
+}
+
+#[derive(Default)]
+struct Detach {
+    detached: bool,
+    waker: Option<Waker>,
+}
+
+impl StreamHandle {
+    /// The identifier of the stream, as in its [`StreamCompleted`] message.
+    pub fn id(&self) -> StreamId {
+        self.id
+    }
+
+    /// The number of items which have been dropped so far with [`Backpressure::Drop`].
+    pub fn dropped(&self) -> usize {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Stop pulling items from the stream, without stopping the actor. This is the same as
+    /// dropping the handle.
+    ///
+    /// Items which have been pulled already are still handled. Afterwards, the actor receives a
+    /// [`StreamCompleted`] message with [`detached`](StreamCompleted::detached) set.
+    pub fn detach(self) {
+        drop(self);
+    }
+
+    /// Drop the handle without detaching the stream, which then stays attached until it ends or
+    /// the actor stops.
+    pub fn forget(mut self) {
+        self.detach_on_drop = false;
+    }
+}
+
+impl Drop for StreamHandle {
+    fn drop(&mut self) {
+        if !self.detach_on_drop {
+            return;
+        }
+
+        let mut detach = self.shared.detach.lock();
+        detach.detached = true;
+
+        if let Some(waker) = detach.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl fmt::Debug for StreamHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamHandle")
+            .field("id", &self.id)
+            .field("dropped", &self.dropped())
+            .finish()
+    }
+}
+
+/// What the task forwarding a stream to the actor waits for.
+enum Event<T, E> {
+    Detached,
+    Stopped,
+    Handled(Result<(), Error>),
+    Next(Option<Result<T, E>>),
+}
+
+type Handling = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+
+/// Create the task which forwards the items of `stream` to the actor until the stream ends or
+/// fails, it is detached or the actor stops.
+pub(crate) fn forward<A, S, T, E>(
+    address: WeakAddress<A>,
+    stream: S,
+    backpressure: Backpressure,
+) -> (StreamHandle, impl Future<Output = ()> + Send + 'static)
+where
+    A: Handler<T> + Handler<StreamCompleted<E>>,
+    S: Stream<Item = Result<T, E>> + Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    let id = StreamId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let shared = Arc::new(Shared::default());
+    let handle = StreamHandle {
+        id,
+        shared: shared.clone(),
+        detach_on_drop: true,
+    };
+
+    let task = async move {
+        let mut stream = Box::pin(stream);
+        let mut join = address.join();
+        let mut handling: Option<Handling> = None;
+        let mut error = None;
+
+        let detached = loop {
+            let event = poll_fn(|cx| {
+                {
+                    let mut detach = shared.detach.lock();
+
+                    if detach.detached {
+                        return Poll::Ready(Event::Detached);
+                    }
+
+                    detach.waker = Some(cx.waker().clone());
+                }
+
+                if join.poll_unpin(cx).is_ready() {
+                    return Poll::Ready(Event::Stopped);
+                }
+
+                if let Some(handled) = handling.as_mut() {
+                    if let Poll::Ready(handled) = handled.poll_unpin(cx) {
+                        return Poll::Ready(Event::Handled(handled));
+                    }
+
+                    if backpressure == Backpressure::Pause {
+                        return Poll::Pending;
+                    }
+                }
+
+                stream.as_mut().poll_next(cx).map(Event::Next)
+            })
+            .await;
+
+            match event {
+                Event::Detached => break true,
+                Event::Stopped | Event::Handled(Err(_)) => return,
+                Event::Handled(Ok(())) => handling = None,
+                Event::Next(None) => break false,
+                Event::Next(Some(Err(e))) => {
+                    error = Some(e);
+                    break false;
+                }
+                Event::Next(Some(Ok(item))) => match backpressure {
+                    Backpressure::Buffer => {
+                        let (envelope, _) =
+                            ReturningEnvelope::<A, T, <A as Handler<T>>::Return>::new(item, 0);
+
+                        if address.0.force_send_to_one(Box::new(envelope)).is_err() {
+                            return;
+                        }
+                    }
+                    Backpressure::Drop if handling.is_some() => {
+                        shared.dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Backpressure::Pause | Backpressure::Drop => {
+                        let send = address.send(item);
+                        handling = Some(Box::pin(async move { send.await.map(|_| ()) }));
+                    }
+                },
+            }
+        };
+
+        // The actor is only told that the stream has ended once it has handled all of its items.
+        if let Some(handling) = handling {
+            if handling.await.is_err() {
+                return;
+            }
+        }
+
+        drop(stream);
+
+        let _ = address
+            .send(StreamCompleted {
+                id,
+                dropped: shared.dropped.load(Ordering::Relaxed),
+                error,
+                detached,
+            })
+            .detach()
+            .await;
+    };
+
+    (handle, task)
+}
@@ -0,0 +1,303 @@
+//! Events published by an actor with [`Context::publish`](crate::Context::publish), to which
+//! others subscribe through its address with [`Address::subscribe`](crate::Address::subscribe).
+//!
+//! Unlike with an [`EventBus`](crate::event_bus::EventBus), the subscribers of an actor are kept by
+//! xtra, so the actor does not need to manage a list of subscribers itself. A subscription does not
+//! keep the publishing actor alive, and ends once the actor has stopped.
+//!
+//! Every subscription buffers up to a given number of events which it has not received yet. Once
+//! its buffer is full, events are dropped according to its [`Overflow`] policy, so a slow
+//! subscriber never slows down the publisher or the other subscribers.
+//!
+//! Besides events, an actor can publish its state with
+//! [`Context::publish_state`](crate::Context::publish_state). Subscriptions created with
+//! [`Address::subscribe_state`](crate::Address::subscribe_state) start with the latest state
+//! published so far, so a subscriber always knows the current state, however late it subscribes.
+//! See `examples/state_subscription.rs` for a counter whose value is streamed this way.
+//!
+//! ```rust
+//! # use futures_util::StreamExt;
+//! # use xtra::prelude::*;
+//! # use xtra::subscription::Overflow;
+//! # struct Connection;
+//! # impl Actor for Connection { type Stop = (); async fn stopped(self) {} }
+//! #[derive(Clone, Debug, PartialEq)]
+//! struct Frame(Vec<u8>);
+//!
+//! struct Received(Vec<u8>);
+//!
+//! impl Handler<Received> for Connection {
+//!     type Return = ();
+//!
+//!     async fn handle(&mut self, Received(bytes): Received, ctx: &mut Context<Self>) {
+//!         ctx.publish(Frame(bytes));
+//!     }
+//! }
+//!
+//! # #[cfg(feature = "smol")]
+//! smol::block_on(async {
+//!     let address = xtra::spawn_smol(Connection, Mailbox::unbounded());
+//!     let mut frames = address.subscribe::<Frame>(16, Overflow::DropOldest);
+//!
+//!     address.send(Received(vec![1, 2, 3])).await.unwrap();
+//!     assert_eq!(frames.next().await, Some(Frame(vec![1, 2, 3])));
+//! })
+//! ```
+
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::{fmt, mem};
+
+use futures_core::{FusedStream, Stream};
+
+/// What a [`Subscription`] does with an event which is published while its buffer is full.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Overflow {
+    /// Drop the oldest event in the buffer to make room for the new one, so that the subscriber
+    /// always receives the most recent events.
+    DropOldest,
+    /// Drop the new event, so that the subscriber receives the events which were published first.
+    DropNewest,
+}
+
+/// A stream of the events of type `E` published by an actor, created by
+/// [`Address::subscribe`](crate::Address::subscribe).
+///
+/// The stream ends once the actor has stopped and all events published before have been
+/// received. Dropping it unsubscribes from the actor.
+#[must_use = "Streams do nothing unless polled"]
+pub struct Subscription<E>(Arc<spin::Mutex<State<E>>>);
+
+struct State<E> {
+    events: VecDeque<E>,
+    buffer: usize,
+    overflow: Overflow,
+    dropped: u64,
+    publisher_alive: bool,
+    subscriber_alive: bool,
+    waker: Option<Waker>,
+}
+
+impl<E> Subscription<E> {
+    /// The number of events which have been dropped because the buffer of this subscription was
+    /// full.
+    pub fn dropped(&self) -> u64 {
+        self.0.lock().dropped
+    }
+}
+
+impl<E> Stream for Subscription<E> {
+    type Item = E;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<E>> {
+        let mut state = self.0.lock();
+
+        if let Some(event) = state.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+
+        if !state.publisher_alive {
+            return Poll::Ready(None);
+        }
+
+        state.waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+impl<E> FusedStream for Subscription<E> {
+    fn is_terminated(&self) -> bool {
+        let state = self.0.lock();
+
+        !state.publisher_alive && state.events.is_empty()
+    }
+}
+
+impl<E> Drop for Subscription<E> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.subscriber_alive = false;
+        state.events.clear();
+    }
+}
+
+impl<E> fmt::Debug for Subscription<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.lock();
+
+        f.debug_struct("Subscription")
+            .field("buffered", &state.events.len())
+            .field("buffer", &state.buffer)
+            .field("overflow", &state.overflow)
+            .field("dropped", &state.dropped)
+            .finish()
+    }
+}
+
+/// The publishing side of a [`Subscription`], which ends the subscription when dropped.
+struct Publisher<E>(Arc<spin::Mutex<State<E>>>);
+
+impl<E> Publisher<E> {
+    /// Buffer the event for the subscriber, returning whether the subscriber is still alive.
+    fn publish(&self, event: E) -> bool {
+        let mut state = self.0.lock();
+
+        if !state.subscriber_alive {
+            return false;
+        }
+
+        if state.events.len() >= state.buffer {
+            state.dropped += 1;
+
+            match state.overflow {
+                Overflow::DropOldest => {
+                    state.events.pop_front();
+                }
+                Overflow::DropNewest => return true,
+            }
+        }
+
+        state.events.push_back(event);
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+
+        true
+    }
+}
+
+impl<E> Drop for Publisher<E> {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.publisher_alive = false;
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// The subscribers of an actor, by the type of event they subscribed to.
+#[derive(Default)]
+pub(crate) struct Topics(spin::Mutex<HashMap<TypeId, Box<dyn Any + Send>>>);
+
+/// The subscribers to events of type `E`, and the latest state of that type, if any.
+struct Topic<E> {
+    publishers: Vec<Publisher<E>>,
+    state: Option<E>,
+}
+
+impl Topics {
+    pub(crate) fn subscribe<E>(&self, buffer: usize, overflow: Overflow) -> Subscription<E>
+    where
+        E: Send + 'static,
+    {
+        let mut topics = self.0.lock();
+
+        subscribe(topic(&mut topics), VecDeque::new(), buffer, overflow)
+    }
+
+    /// Subscribe to events of type `E`, starting with the latest state of that type if any has
+    /// been published with [`Topics::publish_state`].
+    pub(crate) fn subscribe_state<E>(&self, buffer: usize, overflow: Overflow) -> Subscription<E>
+    where
+        E: Clone + Send + 'static,
+    {
+        let mut topics = self.0.lock();
+        let topic = topic(&mut topics);
+        let events = topic.state.iter().cloned().collect();
+
+        subscribe(topic, events, buffer, overflow)
+    }
+
+    /// Publish the event to all live subscribers, removing those which have been dropped. Returns
+    /// the number of subscribers the event was published to.
+    pub(crate) fn publish<E>(&self, event: E) -> usize
+    where
+        E: Clone + Send + 'static,
+    {
+        let mut topics = self.0.lock();
+        let Some(topic) = topics.get_mut(&TypeId::of::<E>()) else {
+            return 0;
+        };
+        let topic = topic
+            .downcast_mut::<Topic<E>>()
+            .expect("topics to be keyed by the type id of their event");
+
+        topic.publish(event)
+    }
+
+    /// Publish the state like [`Topics::publish`], keeping it for those who subscribe later.
+    pub(crate) fn publish_state<E>(&self, state: E) -> usize
+    where
+        E: Clone + Send + 'static,
+    {
+        let mut topics = self.0.lock();
+        let topic = topic(&mut topics);
+        topic.state = Some(state.clone());
+
+        topic.publish(state)
+    }
+
+    /// End all subscriptions.
+    pub(crate) fn close(&self) {
+        let topics = mem::take(&mut *self.0.lock());
+
+        // Subscribers are woken after the lock has been released.
+        drop(topics);
+    }
+}
+
+impl<E: Clone> Topic<E> {
+    fn publish(&mut self, event: E) -> usize {
+        self.publishers
+            .retain(|publisher| publisher.publish(event.clone()));
+
+        self.publishers.len()
+    }
+}
+
+/// The topic of events of type `E`, created if nobody has subscribed to them yet.
+fn topic<E: Send + 'static>(topics: &mut HashMap<TypeId, Box<dyn Any + Send>>) -> &mut Topic<E> {
+    topics
+        .entry(TypeId::of::<E>())
+        .or_insert_with(|| {
+            Box::new(Topic::<E> {
+                publishers: Vec::new(),
+                state: None,
+            })
+        })
+        .downcast_mut()
+        .expect("topics to be keyed by the type id of their event")
+}
+
+/// Add a subscriber to the topic, which receives the given events first.
+fn subscribe<E>(
+    topic: &mut Topic<E>,
+    events: VecDeque<E>,
+    buffer: usize,
+    overflow: Overflow,
+) -> Subscription<E> {
+    assert!(
+        buffer > 0,
+        "a subscription needs room for at least one event"
+    );
+
+    let state = Arc::new(spin::Mutex::new(State {
+        events,
+        buffer,
+        overflow,
+        dropped: 0,
+        publisher_alive: true,
+        subscriber_alive: true,
+        waker: None,
+    }));
+    topic.publishers.push(Publisher(state.clone()));
+
+    Subscription(state)
+}
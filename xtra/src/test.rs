@@ -0,0 +1,650 @@
+//! Utilities for unit-testing [`Handler`]s without spawning their actor.
+//!
+//! A [`Mailbox`] stands in for another actor and records the messages sent to it.
+//!
+//! A [`DeterministicRuntime`] runs actors on a single thread in a reproducible order and with a
+//! virtual clock.
+//!
+//! A [`TestContext`] can be passed anywhere a [`Context`] is expected and records what the handler
+//! did with it, such as stopping the actor, notifying it, setting timers or spawning tasks:
+//!
+//! ```rust
+//! # use xtra::prelude::*;
+//! use xtra::test::TestContext;
+//!
+//! # struct Counter(u32);
+//! # impl Actor for Counter { type Stop = (); async fn stopped(self) {} }
+//! struct Increment;
+//!
+//! impl Handler<Increment> for Counter {
+//!     type Return = u32;
+//!
+//!     async fn handle(&mut self, _: Increment, ctx: &mut Context<Self>) -> u32 {
+//!         self.0 += 1;
+//!
+//!         if self.0 == 2 {
+//!             ctx.stop_self();
+//!         }
+//!
+//!         self.0
+//!     }
+//! }
+//!
+//! let mut counter = Counter(0);
+//! let mut ctx = TestContext::new();
+//!
+//! assert_eq!(ctx.handle(&mut counter, Increment), 1);
+//! assert!(!ctx.is_stopped());
+//!
+//! assert_eq!(ctx.handle(&mut counter, Increment), 2);
+//! assert!(ctx.is_stopped());
+//! ```
+
+mod deterministic;
+
+use std::any::Any;
+use std::borrow::Cow;
+use std::hash::Hasher;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{self, AtomicBool, AtomicUsize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fmt, mem};
+
+use event_listener::Event;
+use futures_core::future::BoxFuture;
+
+pub use self::deterministic::{DeterministicHandle, DeterministicRuntime};
+use crate::address::ActorJoinHandle;
+use crate::chan::{HasPriority, Priority};
+use crate::message_channel::{MessageChannel, MessageChannelTrait};
+use crate::refcount::{Either, Strong, Weak};
+use crate::runtime::{block_on, Spawner, Timer};
+use crate::send_future::{ActorErasedSending, ResolveToHandlerReturn, SendFuture};
+use crate::{Actor, ActorId, Address, Context, DisconnectReason, Error, Handler};
+
+/// A [`Context`] which is not tied to a running actor, for calling [`Handler::handle`] directly.
+///
+/// [`TestContext`] dereferences to [`Context`], so `&mut TestContext<A>` can be passed to
+/// [`Handler::handle`] in place of `&mut Context<A>`. The context is shared between all messages
+/// handled with it, so calls like [`Context::stop_self`] are recorded until the [`TestContext`] is
+/// dropped.
+///
+/// Messages which the handler sends to the actor itself, including those requeued with
+/// [`Context::requeue`], are queued in its mailbox right away and never handled. They can be
+/// taken out with [`TestContext::notified`].
+///
+/// The actor's [`Mailbox`](crate::Mailbox) is configured with a [`Spawner`] which records the
+/// names of spawned tasks without running them, see [`TestContext::spawned`], and with a
+/// [`Timer`] on a virtual clock which only moves forward with [`TestContext::advance`]. Sleeping
+/// on the timer, e.g. in [`Context::retry`], completes immediately and advances the clock to the
+/// end of the sleep.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use xtra::prelude::*;
+/// use xtra::test::TestContext;
+///
+/// # struct Session;
+/// # impl Actor for Session { type Stop = (); async fn stopped(self) {} }
+/// struct Login;
+/// struct Expired;
+///
+/// impl Handler<Login> for Session {
+///     type Return = ();
+///
+///     async fn handle(&mut self, _: Login, ctx: &mut Context<Self>) {
+///         ctx.notify_after(Expired, Duration::from_secs(30));
+///     }
+/// }
+/// # impl Handler<Expired> for Session {
+/// #     type Return = ();
+/// #     async fn handle(&mut self, _: Expired, _: &mut Context<Self>) {}
+/// # }
+///
+/// let mut ctx = TestContext::new();
+/// ctx.handle(&mut Session, Login);
+/// assert_eq!(ctx.pending_timers(), 1);
+///
+/// ctx.advance(Duration::from_secs(30));
+/// assert_eq!(ctx.pending_timers(), 0);
+/// assert_eq!(ctx.notified(), [std::any::type_name::<Expired>()]);
+/// ```
+pub struct TestContext<A> {
+    address: Address<A>,
+    context: Context<A>,
+    runtime: TestRuntime,
+}
+
+impl<A: Actor> TestContext<A> {
+    /// Create a new [`TestContext`] with an unbounded mailbox.
+    pub fn new() -> Self {
+        let runtime = TestRuntime::default();
+        let (address, mailbox) = crate::Mailbox::unbounded();
+        let mailbox = mailbox
+            .with_spawner(runtime.clone())
+            .with_timer(runtime.clone());
+
+        TestContext {
+            address,
+            context: Context::new(mailbox, None),
+            runtime,
+        }
+    }
+
+    /// Handle the message with the given actor, driving the handler and [`Actor::after_handle`] to
+    /// completion on the current thread.
+    ///
+    /// This must not be called from within an async runtime if the handler depends on it, e.g.
+    /// for its timers or IO. Await [`Handler::handle`] with this context instead in such cases.
+    pub fn handle<M>(&mut self, actor: &mut A, message: M) -> A::Return
+    where
+        A: Handler<M>,
+    {
+        let context = &mut self.context;
+
+        let r = block_on(async {
+            let r = actor.handle(message, context).await;
+            actor.after_handle(context).await;
+            r
+        });
+
+        if let Some((message, delay)) = context.requeued.take() {
+            context.mailbox.requeue(message, delay);
+        }
+
+        r
+    }
+
+    /// The address of the actor, which is kept alive for as long as this [`TestContext`].
+    pub fn address(&self) -> &Address<A> {
+        &self.address
+    }
+
+    /// The type name of the message the actor would handle next, as given by
+    /// [`std::any::type_name`], without taking it out of its mailbox. Returns [`None`] if the
+    /// mailbox is empty.
+    ///
+    /// This makes it possible to assert on the order of the messages which the handler sent to the
+    /// actor itself, without handling them. A shutdown requested with [`Context::stop_all`] is not
+    /// a message, see [`TestContext::is_stopped_all`] instead.
+    pub fn peek_next_type(&self) -> Option<&'static str> {
+        self.context
+            .mailbox
+            .inner
+            .peek_message_type(&self.context.mailbox.broadcast_mailbox)
+    }
+
+    /// Take all messages out of the actor's mailbox, returning their type names as given by
+    /// [`std::any::type_name`] in the order in which the actor would handle them.
+    ///
+    /// These are the messages which the handler sent to the actor itself, e.g. with
+    /// [`Context::notify`] or [`Context::requeue`], those of timers which have fired, and those
+    /// which were sent through [`TestContext::address`]. Messages sent to all actors on the
+    /// address are not included.
+    pub fn notified(&self) -> Vec<&'static str> {
+        self.context
+            .mailbox
+            .inner
+            .drain()
+            .iter()
+            .map(|message| message.message_type())
+            .collect()
+    }
+
+    /// The names of the tasks which have been spawned through this context, e.g. with
+    /// [`Context::spawn_task`], [`Context::spawn_child`] or [`Context::run_blocking`], in the
+    /// order they were spawned.
+    ///
+    /// The tasks are never run, and are dropped together with the [`TestContext`]. In particular,
+    /// the future returned by [`Context::run_blocking`] never resolves.
+    pub fn spawned(&self) -> Vec<String> {
+        self.runtime
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// The number of timers which are still pending, e.g. those of [`Context::notify_after`]
+    /// and [`Context::requeue_after`].
+    pub fn pending_timers(&self) -> usize {
+        self.context.mailbox.timers.len()
+    }
+
+    /// Advance the virtual clock of the actor's [`Timer`] by `duration`, queueing the messages of
+    /// all timers which are due by then in its mailbox.
+    pub fn advance(&self, duration: Duration) {
+        *self.runtime.elapsed.lock().unwrap() += duration;
+        self.context.mailbox.fire_due_timers();
+    }
+
+    /// Whether the handler called [`Context::stop_self`].
+    pub fn is_stopped(&self) -> bool {
+        !self.context.running
+    }
+
+    /// Whether the handler called [`Context::stop_all`].
+    pub fn is_stopped_all(&self) -> bool {
+        self.context
+            .mailbox
+            .broadcast_mailbox
+            .lock()
+            .iter()
+            .any(|message| message.priority() == Priority::Shutdown)
+    }
+}
+
+impl<A: Actor> Default for TestContext<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<A> Deref for TestContext<A> {
+    type Target = Context<A>;
+
+    fn deref(&self) -> &Context<A> {
+        &self.context
+    }
+}
+
+impl<A> DerefMut for TestContext<A> {
+    fn deref_mut(&mut self) -> &mut Context<A> {
+        &mut self.context
+    }
+}
+
+/// Assert that the actor returns `expected` when handling the message, driving the handler to
+/// completion on the current thread with a fresh [`TestContext`].
+///
+/// Use [`TestContext::handle`] directly to also assert on what the handler did with its context.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// # struct Counter(u32);
+/// # impl Actor for Counter { type Stop = (); async fn stopped(self) {} }
+/// struct Add(u32);
+///
+/// impl Handler<Add> for Counter {
+///     type Return = u32;
+///
+///     async fn handle(&mut self, add: Add, _: &mut Context<Self>) -> u32 {
+///         self.0 += add.0;
+///         self.0
+///     }
+/// }
+///
+/// let mut counter = Counter(1);
+///
+/// xtra::assert_handles!(counter, Add(2) => 3);
+/// xtra::assert_handles!(counter, Add(4) => 7);
+/// ```
+#[macro_export]
+macro_rules! assert_handles {
+    ($actor:expr, $message:expr => $expected:expr $(,)?) => {
+        assert_eq!(
+            $crate::test::TestContext::new().handle(&mut $actor, $message),
+            $expected
+        )
+    };
+}
+
+/// A stand-in for an actor handling messages of type `M`, which records all messages sent to it.
+///
+/// This is useful for testing an actor which talks to other actors through a [`MessageChannel`].
+/// Every message sent through one of the channels of this [`Mailbox`] is recorded and replied to
+/// when the send future is first polled, without an actor or runtime being involved. A send future
+/// which is dropped without being polled records nothing.
+///
+/// Like the [`Mailbox`](crate::Mailbox) of an actor, channels are connected for as long as this
+/// [`Mailbox`] and at least one strong channel exist. Sending a message to a disconnected channel
+/// fails with [`Error::Disconnected`] and does not record it.
+///
+/// ```rust
+/// # use xtra::prelude::*;
+/// struct Lookup(&'static str);
+///
+/// let mailbox = xtra::test::Mailbox::respond_with(|lookup: &Lookup| lookup.0.len());
+/// let channel: MessageChannel<Lookup, usize> = mailbox.channel();
+///
+/// # #[cfg(feature = "smol")]
+/// smol::block_on(async {
+///     assert_eq!(channel.send(Lookup("hello")).await, Ok(5));
+///     assert_eq!(channel.send(Lookup("world!")).await, Ok(6));
+/// });
+///
+/// # #[cfg(feature = "smol")]
+/// assert_eq!(mailbox.recorded().len(), 2);
+/// ```
+pub struct Mailbox<M, R = ()> {
+    recording: Arc<Recording<M, R>>,
+}
+
+/// The state shared between a [`Mailbox`] and its channels.
+struct Recording<M, R> {
+    inner: Mutex<RecordingInner<M, R>>,
+    id: ActorId,
+    receiving: AtomicBool,
+    strong_count: AtomicUsize,
+    on_disconnect: Event,
+}
+
+struct RecordingInner<M, R> {
+    messages: Vec<M>,
+    respond: Box<dyn FnMut(&M) -> R + Send>,
+}
+
+impl<M, R> Mailbox<M, R>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+{
+    /// Create a new [`Mailbox`] which replies to every message with the default value of `R`.
+    pub fn new() -> Self
+    where
+        R: Default,
+    {
+        Self::respond_with(|_| R::default())
+    }
+
+    /// Create a new [`Mailbox`] which replies to every message with the return value of `respond`.
+    pub fn respond_with(respond: impl FnMut(&M) -> R + Send + 'static) -> Self {
+        let recording = Recording {
+            inner: Mutex::new(RecordingInner {
+                messages: Vec::new(),
+                respond: Box::new(respond),
+            }),
+            id: ActorId::next(),
+            receiving: AtomicBool::new(true),
+            strong_count: AtomicUsize::new(0),
+            on_disconnect: Event::new(),
+        };
+
+        Mailbox {
+            recording: Arc::new(recording),
+        }
+    }
+
+    /// Create a new strong channel to this [`Mailbox`]. Use [`MessageChannel::downgrade`] to
+    /// obtain a weak one.
+    pub fn channel(&self) -> MessageChannel<M, R> {
+        MessageChannel::from_inner(Box::new(RecordingChannel::<M, R, Strong>::new(
+            self.recording.clone(),
+            true,
+        )))
+    }
+
+    /// Take all messages which have been recorded since the last call to this function, in the
+    /// order in which they were sent.
+    pub fn recorded(&self) -> Vec<M> {
+        mem::take(&mut self.recording.inner.lock().unwrap().messages)
+    }
+}
+
+impl<M, R> Default for Mailbox<M, R>
+where
+    M: Send + 'static,
+    R: Default + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, R> fmt::Debug for Mailbox<M, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mailbox")
+            .field(
+                "recorded",
+                &self.recording.inner.lock().unwrap().messages.len(),
+            )
+            .field(
+                "strong_channels",
+                &self.recording.strong_count.load(atomic::Ordering::SeqCst),
+            )
+            .finish()
+    }
+}
+
+impl<M, R> Drop for Mailbox<M, R> {
+    fn drop(&mut self) {
+        self.recording
+            .receiving
+            .store(false, atomic::Ordering::SeqCst);
+        self.recording.on_disconnect.notify(usize::MAX);
+    }
+}
+
+impl<M, R> Recording<M, R> {
+    fn is_connected(&self) -> bool {
+        self.receiving.load(atomic::Ordering::SeqCst)
+            && self.strong_count.load(atomic::Ordering::SeqCst) > 0
+    }
+}
+
+/// The channel handed out by a [`Mailbox`]. `Rc` is only used to tell apart strong and weak
+/// channels on the type level, like for [`Address`].
+struct RecordingChannel<M, R, Rc> {
+    recording: Arc<Recording<M, R>>,
+    strong: bool,
+    phantom: PhantomData<fn() -> Rc>,
+}
+
+impl<M, R, Rc> RecordingChannel<M, R, Rc> {
+    fn new(recording: Arc<Recording<M, R>>, strong: bool) -> Self {
+        if strong {
+            recording
+                .strong_count
+                .fetch_add(1, atomic::Ordering::SeqCst);
+        }
+
+        RecordingChannel {
+            recording,
+            strong,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<M, R, Rc> Drop for RecordingChannel<M, R, Rc> {
+    fn drop(&mut self) {
+        if self.strong
+            && self
+                .recording
+                .strong_count
+                .fetch_sub(1, atomic::Ordering::SeqCst)
+                == 1
+        {
+            self.recording.on_disconnect.notify(usize::MAX);
+        }
+    }
+}
+
+impl<M, R, Rc> MessageChannelTrait<M, Rc> for RecordingChannel<M, R, Rc>
+where
+    M: Send + 'static,
+    R: Send + 'static,
+    Rc: 'static,
+{
+    type Return = R;
+
+    fn is_connected(&self) -> bool {
+        self.recording.is_connected()
+    }
+
+    fn disconnect_reason(&self) -> Option<DisconnectReason> {
+        None
+    }
+
+    fn len(&self) -> usize {
+        // Messages are recorded rather than queued, see `Mailbox::recorded` for those.
+        0
+    }
+
+    fn capacity(&self) -> Option<usize> {
+        None
+    }
+
+    fn id(&self) -> ActorId {
+        self.recording.id
+    }
+
+    fn name(&self) -> Cow<'static, str> {
+        Cow::Borrowed(std::any::type_name::<Mailbox<M, R>>())
+    }
+
+    fn send(&self, message: M) -> SendFuture<ActorErasedSending, ResolveToHandlerReturn<R>> {
+        let recording = self.recording.clone();
+
+        SendFuture::lazy(move || {
+            if !recording.is_connected() {
+                return Err(Error::Disconnected);
+            }
+
+            let mut inner = recording.inner.lock().unwrap();
+            let reply = (inner.respond)(&message);
+            inner.messages.push(message);
+
+            Ok(reply)
+        })
+    }
+
+    fn clone_channel(
+        &self,
+    ) -> Box<dyn MessageChannelTrait<M, Rc, Return = R> + Send + Sync + 'static> {
+        Box::new(RecordingChannel::<M, R, Rc>::new(
+            self.recording.clone(),
+            self.strong,
+        ))
+    }
+
+    fn join(&self) -> ActorJoinHandle {
+        let listener = self.recording.on_disconnect.listen();
+
+        if self.recording.is_connected() {
+            ActorJoinHandle(Some(listener))
+        } else {
+            ActorJoinHandle(None)
+        }
+    }
+
+    fn to_inner_ptr(&self) -> *const () {
+        Arc::as_ptr(&self.recording) as *const ()
+    }
+
+    fn is_strong(&self) -> bool {
+        self.strong
+    }
+
+    fn to_weak(&self) -> Box<dyn MessageChannelTrait<M, Weak, Return = R> + Send + Sync + 'static> {
+        Box::new(RecordingChannel::<M, R, Weak>::new(
+            self.recording.clone(),
+            false,
+        ))
+    }
+
+    fn try_upgrade(
+        &self,
+    ) -> Option<Box<dyn MessageChannelTrait<M, Strong, Return = R> + Send + Sync + 'static>> {
+        // Only upgrade if another strong channel is still around.
+        self.recording
+            .strong_count
+            .fetch_update(
+                atomic::Ordering::SeqCst,
+                atomic::Ordering::SeqCst,
+                |count| (count > 0).then_some(count + 1),
+            )
+            .ok()?;
+
+        Some(Box::new(RecordingChannel::<M, R, Strong> {
+            recording: self.recording.clone(),
+            strong: true,
+            phantom: PhantomData,
+        }))
+    }
+
+    fn sender_count(&self) -> usize {
+        self.recording.strong_count.load(atomic::Ordering::SeqCst)
+    }
+
+    fn receiver_count(&self) -> usize {
+        self.recording.receiving.load(atomic::Ordering::SeqCst) as usize
+    }
+
+    fn actor_type(&self) -> &'static str {
+        std::any::type_name::<Mailbox<M, R>>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn to_either(
+        &self,
+    ) -> Box<dyn MessageChannelTrait<M, Either, Return = R> + Send + Sync + 'static> {
+        Box::new(RecordingChannel::<M, R, Either>::new(
+            self.recording.clone(),
+            self.strong,
+        ))
+    }
+
+    fn hash(&self, state: &mut dyn Hasher) {
+        state.write_usize(self.to_inner_ptr() as usize);
+        state.write_u8(self.strong as u8);
+    }
+}
+
+/// The [`Spawner`] and [`Timer`] of a [`TestContext`], which records spawned tasks without running
+/// them and keeps a virtual clock.
+#[derive(Clone)]
+struct TestRuntime {
+    tasks: Arc<Mutex<Vec<SpawnedTask>>>,
+    start: Instant,
+    elapsed: Arc<Mutex<Duration>>,
+}
+
+/// A task which was spawned on a [`TestRuntime`], with its name.
+type SpawnedTask = (String, BoxFuture<'static, ()>);
+
+impl Default for TestRuntime {
+    fn default() -> Self {
+        TestRuntime {
+            tasks: Arc::default(),
+            start: Instant::now(),
+            elapsed: Arc::default(),
+        }
+    }
+}
+
+impl Spawner for TestRuntime {
+    fn spawn(&self, name: &str, future: BoxFuture<'static, ()>) {
+        self.tasks.lock().unwrap().push((name.to_owned(), future));
+    }
+
+    fn spawn_blocking(&self, name: &str, f: Box<dyn FnOnce() + Send>) {
+        self.tasks
+            .lock()
+            .unwrap()
+            .push((name.to_owned(), Box::pin(async move { f() })));
+    }
+}
+
+impl Timer for TestRuntime {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let elapsed = self.elapsed.clone();
+        let end = *elapsed.lock().unwrap() + duration;
+
+        Box::pin(async move {
+            let mut elapsed = elapsed.lock().unwrap();
+            *elapsed = (*elapsed).max(end);
+        })
+    }
+
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+}
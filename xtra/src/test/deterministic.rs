@@ -0,0 +1,394 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::future::Future;
+use std::mem;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::task::{self, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+use futures_core::future::BoxFuture;
+
+use crate::runtime::{Spawner, Timer};
+use crate::{Actor, Address, Mailbox};
+
+/// A single-threaded runtime with a virtual clock, for reproducing the interleaving of actors
+/// exactly.
+///
+/// All tasks spawned through the [`DeterministicHandle`] of the runtime, such as actors spawned with
+/// [`DeterministicRuntime::spawn_actor`], are owned by the runtime and only make progress when it is
+/// driven with [`run_one`](DeterministicRuntime::run_one), [`run_until_idle`](DeterministicRuntime::run_until_idle)
+/// or [`block_on`](DeterministicRuntime::block_on). The next task to poll is picked from all woken
+/// tasks in a pseudo-random order determined by the seed, so a failing interleaving can be replayed
+/// by running the test with the same seed again.
+///
+/// Time only passes when calling [`advance`](DeterministicRuntime::advance). All timers created
+/// through the [`DeterministicHandle`], e.g. via [`Mailbox::timer`], use this virtual clock.
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use futures_util::FutureExt;
+/// # use xtra::prelude::*;
+/// use xtra::test::DeterministicRuntime;
+///
+/// # struct Greeter;
+/// # impl Actor for Greeter { type Stop = (); async fn stopped(self) {} }
+/// struct Hello;
+///
+/// impl Handler<Hello> for Greeter {
+///     type Return = &'static str;
+///
+///     async fn handle(&mut self, _: Hello, ctx: &mut Context<Self>) -> &'static str {
+///         ctx.mailbox().timer().unwrap().sleep(Duration::from_secs(1)).await;
+///         "Hello!"
+///     }
+/// }
+///
+/// let runtime = DeterministicRuntime::new(42);
+/// let address = runtime.spawn_actor(Greeter, Mailbox::unbounded());
+///
+/// let mut reply = runtime.block_on(address.send(Hello).detach()).unwrap();
+///
+/// runtime.run_until_idle();
+/// assert!((&mut reply).now_or_never().is_none());
+///
+/// runtime.advance(Duration::from_secs(1));
+/// assert_eq!(runtime.block_on(reply), Ok("Hello!"));
+/// ```
+pub struct DeterministicRuntime {
+    scheduler: Arc<Mutex<Scheduler>>,
+}
+
+/// A handle to a [`DeterministicRuntime`], to be used as the [`Spawner`] and [`Timer`] of actors.
+///
+/// The handle does not keep the runtime alive. Tasks spawned after the runtime has been dropped
+/// are dropped immediately and its timers never complete.
+#[derive(Clone)]
+pub struct DeterministicHandle {
+    scheduler: Weak<Mutex<Scheduler>>,
+    /// The instant at which the virtual clock started, for [`Timer::now`].
+    start: Instant,
+}
+
+struct Scheduler {
+    tasks: BTreeMap<u64, BoxFuture<'static, ()>>,
+    ready: Vec<u64>,
+    next_task: u64,
+    now: Duration,
+    start: Instant,
+    timers: BinaryHeap<Reverse<TimerEntry>>,
+    /// The wakers of the pending timers, by the id of their [`TimerEntry`].
+    wakers: HashMap<u64, Waker>,
+    next_timer: u64,
+    rng: u64,
+}
+
+impl DeterministicRuntime {
+    /// Create a new runtime, scheduling tasks in the order determined by `seed`.
+    pub fn new(seed: u64) -> Self {
+        let scheduler = Scheduler {
+            tasks: BTreeMap::new(),
+            ready: Vec::new(),
+            next_task: 0,
+            now: Duration::ZERO,
+            start: Instant::now(),
+            timers: BinaryHeap::new(),
+            wakers: HashMap::new(),
+            next_timer: 0,
+            // The generator must not be seeded with zero.
+            rng: (seed ^ 0x9E37_79B9_7F4A_7C15).max(1),
+        };
+
+        DeterministicRuntime {
+            scheduler: Arc::new(Mutex::new(scheduler)),
+        }
+    }
+
+    /// Get a handle to this runtime, which implements [`Spawner`] and [`Timer`].
+    pub fn handle(&self) -> DeterministicHandle {
+        DeterministicHandle {
+            scheduler: Arc::downgrade(&self.scheduler),
+            start: self.lock().start,
+        }
+    }
+
+    /// Spawn the given actor onto this runtime, returning an [`Address`] to it.
+    ///
+    /// Unless configured otherwise, the actor's [`Mailbox`] will use the handle of this runtime as
+    /// its [`Spawner`] and [`Timer`].
+    pub fn spawn_actor<A>(
+        &self,
+        actor: A,
+        (address, mailbox): (Address<A>, Mailbox<A>),
+    ) -> Address<A>
+    where
+        A: Actor<Stop = ()>,
+    {
+        let mailbox = mailbox.with_default_runtime(self.handle());
+        let name = crate::runtime::task_name(&actor.name(), mailbox.id());
+        self.handle()
+            .spawn(&name, Box::pin(crate::run(mailbox, actor)));
+
+        address
+    }
+
+    /// Poll one of the tasks which have been woken, returning whether there was any.
+    pub fn run_one(&self) -> bool {
+        let (id, mut task) = {
+            let mut scheduler = self.lock();
+
+            loop {
+                if scheduler.ready.is_empty() {
+                    return false;
+                }
+
+                let index = (scheduler.next_random() % scheduler.ready.len() as u64) as usize;
+                let id = scheduler.ready.swap_remove(index);
+
+                // The task may have completed since it was woken.
+                if let Some(task) = scheduler.tasks.remove(&id) {
+                    break (id, task);
+                }
+            }
+        };
+
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id,
+            scheduler: Arc::downgrade(&self.scheduler),
+        }));
+
+        if task
+            .as_mut()
+            .poll(&mut task::Context::from_waker(&waker))
+            .is_pending()
+        {
+            self.lock().tasks.insert(id, task);
+        }
+
+        true
+    }
+
+    /// Poll tasks until none of them can make progress without time passing or outside events.
+    pub fn run_until_idle(&self) {
+        while self.run_one() {}
+    }
+
+    /// Drive the given future to completion on the current thread, running the tasks of this
+    /// runtime while it is pending.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the future cannot complete because all tasks are idle, e.g. because it waits for
+    /// time to pass.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        let woken = Arc::new(Woken(AtomicBool::new(true)));
+        let waker = Waker::from(woken.clone());
+        let mut future = pin!(future);
+
+        loop {
+            if woken.0.swap(false, atomic::Ordering::SeqCst) {
+                if let Poll::Ready(output) =
+                    future.as_mut().poll(&mut task::Context::from_waker(&waker))
+                {
+                    return output;
+                }
+            } else if !self.run_one() {
+                panic!("future cannot complete because all tasks of the runtime are idle");
+            }
+        }
+    }
+
+    /// Advance the virtual clock by the given duration, waking all timers which have elapsed.
+    pub fn advance(&self, duration: Duration) {
+        let elapsed = {
+            let mut scheduler = self.lock();
+            scheduler.now += duration;
+
+            let mut elapsed = Vec::new();
+            while let Some(Reverse(timer)) = scheduler.timers.peek() {
+                if timer.deadline > scheduler.now {
+                    break;
+                }
+
+                let Reverse(timer) = scheduler.timers.pop().unwrap();
+                // The timer is gone if its sleep has been dropped.
+                elapsed.extend(scheduler.wakers.remove(&timer.id));
+            }
+
+            elapsed
+        };
+
+        for waker in elapsed {
+            waker.wake();
+        }
+    }
+
+    /// The time which has passed on the virtual clock since the runtime was created.
+    pub fn now(&self) -> Duration {
+        self.lock().now
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Scheduler> {
+        self.scheduler.lock().unwrap()
+    }
+}
+
+impl Drop for DeterministicRuntime {
+    fn drop(&mut self) {
+        // Tasks are dropped outside of the lock, as dropping them may spawn or wake other tasks.
+        let tasks = mem::take(&mut self.lock().tasks);
+        drop(tasks);
+    }
+}
+
+impl Spawner for DeterministicHandle {
+    fn spawn(&self, _name: &str, future: BoxFuture<'static, ()>) {
+        if let Some(scheduler) = self.scheduler.upgrade() {
+            let mut scheduler = scheduler.lock().unwrap();
+            let id = scheduler.next_task;
+            scheduler.next_task += 1;
+            scheduler.tasks.insert(id, future);
+            scheduler.ready.push(id);
+        }
+    }
+
+    /// Blocking functions are run immediately on the calling thread, so that they do not race with
+    /// the tasks of the runtime.
+    fn spawn_blocking(&self, _name: &str, f: Box<dyn FnOnce() + Send>) {
+        f();
+    }
+}
+
+impl Timer for DeterministicHandle {
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let deadline = self
+            .scheduler
+            .upgrade()
+            .map(|scheduler| scheduler.lock().unwrap().now + duration);
+
+        Box::pin(Sleep {
+            scheduler: self.scheduler.clone(),
+            deadline,
+            id: None,
+        })
+    }
+
+    /// The instant at which the runtime was created, advanced by the time which has passed on its
+    /// virtual clock.
+    fn now(&self) -> Instant {
+        let elapsed = self
+            .scheduler
+            .upgrade()
+            .map_or(Duration::ZERO, |scheduler| scheduler.lock().unwrap().now);
+
+        self.start + elapsed
+    }
+}
+
+impl Scheduler {
+    /// The next number of the xorshift64* generator.
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        self.rng.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+struct TaskWaker {
+    id: u64,
+    scheduler: Weak<Mutex<Scheduler>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        if let Some(scheduler) = self.scheduler.upgrade() {
+            let mut scheduler = scheduler.lock().unwrap();
+
+            if !scheduler.ready.contains(&self.id) {
+                scheduler.ready.push(self.id);
+            }
+        }
+    }
+}
+
+struct Woken(AtomicBool);
+
+impl Wake for Woken {
+    fn wake(self: Arc<Self>) {
+        self.0.store(true, atomic::Ordering::SeqCst);
+    }
+}
+
+/// A timer on the virtual clock of a [`DeterministicRuntime`].
+struct Sleep {
+    scheduler: Weak<Mutex<Scheduler>>,
+    deadline: Option<Duration>,
+    /// The id of the [`TimerEntry`] of this sleep, once it has been registered.
+    id: Option<u64>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        let (Some(scheduler), Some(deadline)) = (this.scheduler.upgrade(), this.deadline) else {
+            return Poll::Pending;
+        };
+        let mut scheduler = scheduler.lock().unwrap();
+
+        if scheduler.now >= deadline {
+            return Poll::Ready(());
+        }
+
+        // Register the timer on the first poll, and only update its waker afterwards.
+        let id = *this.id.get_or_insert_with(|| {
+            let id = scheduler.next_timer;
+            scheduler.next_timer += 1;
+            scheduler.timers.push(Reverse(TimerEntry { deadline, id }));
+            id
+        });
+        scheduler.wakers.insert(id, cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        let (Some(scheduler), Some(id)) = (self.scheduler.upgrade(), self.id) else {
+            return;
+        };
+
+        // The entry is left in the heap, and skipped once it elapses.
+        let waker = scheduler.lock().unwrap().wakers.remove(&id);
+        drop(waker);
+    }
+}
+
+struct TimerEntry {
+    deadline: Duration,
+    id: u64,
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        (self.deadline, self.id) == (other.deadline, other.id)
+    }
+}
+
+impl Eq for TimerEntry {}
+
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.deadline, self.id).cmp(&(other.deadline, other.id))
+    }
+}
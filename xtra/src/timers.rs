@@ -0,0 +1,223 @@
+use std::any::TypeId;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+use futures_core::future::BoxFuture;
+use futures_util::FutureExt;
+
+use crate::runtime::Timer;
+
+/// Identifies a message scheduled with [`Context::notify_after`](crate::Context::notify_after), to
+/// cancel it with [`Context::cancel_timer`](crate::Context::cancel_timer).
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TimerId(u64);
+
+/// The timers of an actor, which are all driven by a single sleep until the earliest deadline while
+/// the actor waits for its next message.
+///
+/// Cancelled timers are only removed from the heap of deadlines once they reach its top, or once
+/// most of the heap consists of cancelled timers.
+#[derive(Default)]
+pub struct Timers(spin::Mutex<Queue>);
+
+#[derive(Default)]
+struct Queue {
+    deadlines: BinaryHeap<Reverse<(Instant, TimerId)>>,
+    pending: HashMap<TimerId, Pending>,
+    /// The pending timer of [`Context::notify_debounced`](crate::Context::notify_debounced) per
+    /// message type.
+    debounced: HashMap<TypeId, TimerId>,
+    /// The sleep until the earliest deadline, recreated whenever the earliest deadline changes.
+    sleep: Option<(Instant, BoxFuture<'static, ()>)>,
+    /// The deadline of the last sleep which completed. Timers due by then are fired even if the
+    /// clock says otherwise, so that a timer which completes early does not cause a busy loop.
+    elapsed: Option<Instant>,
+    waker: Option<Waker>,
+}
+
+struct Pending {
+    fire: Box<dyn FnOnce() + Send>,
+    debounced: Option<TypeId>,
+}
+
+impl Timers {
+    /// Call `f` once the deadline has passed and the actor waits for its next message.
+    pub fn schedule(&self, deadline: Instant, f: impl FnOnce() + Send + 'static) -> TimerId {
+        self.insert(deadline, f, None)
+    }
+
+    /// Like [`Timers::schedule`], but cancel the pending timer which was scheduled with the same
+    /// key, if any.
+    pub fn debounce(
+        &self,
+        key: TypeId,
+        deadline: Instant,
+        f: impl FnOnce() + Send + 'static,
+    ) -> TimerId {
+        self.insert(deadline, f, Some(key))
+    }
+
+    fn insert(
+        &self,
+        deadline: Instant,
+        f: impl FnOnce() + Send + 'static,
+        debounced: Option<TypeId>,
+    ) -> TimerId {
+        // Ids are unique across actors, so that cancelling the timer of another actor is a no-op.
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let id = TimerId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+
+        let mut queue = self.0.lock();
+        let replaced = debounced
+            .and_then(|key| queue.debounced.insert(key, id))
+            .and_then(|replaced| queue.pending.remove(&replaced));
+
+        queue.compact();
+        queue.deadlines.push(Reverse((deadline, id)));
+        queue.pending.insert(
+            id,
+            Pending {
+                fire: Box::new(f),
+                debounced,
+            },
+        );
+
+        // The actor has to reset its sleep if the timer is due before it completes.
+        let waker = match &queue.sleep {
+            Some((earliest, _)) if *earliest <= deadline => None,
+            _ => queue.waker.take(),
+        };
+        drop(queue);
+
+        // The replaced message is dropped outside of the lock.
+        drop(replaced);
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+
+        id
+    }
+
+    /// Cancel the timer, returning whether it was still pending.
+    pub fn cancel(&self, id: TimerId) -> bool {
+        let mut queue = self.0.lock();
+
+        let Some(cancelled) = queue.pending.remove(&id) else {
+            return false;
+        };
+
+        if let Some(key) = cancelled.debounced {
+            queue.debounced.remove(&key);
+        }
+
+        queue.compact();
+        drop(queue);
+        drop(cancelled);
+
+        true
+    }
+
+    /// The number of timers which are still pending.
+    pub fn len(&self) -> usize {
+        self.0.lock().pending.len()
+    }
+
+    /// Drop all pending timers, e.g. once the actor has stopped.
+    pub fn clear(&self) {
+        let queue = std::mem::take(&mut *self.0.lock());
+
+        // The messages of the timers are dropped outside of the lock.
+        drop(queue);
+    }
+
+    /// Fire all timers which are due, returning whether there were any.
+    pub fn fire_due(&self, timer: &dyn Timer) -> bool {
+        let mut queue = self.0.lock();
+
+        if queue.deadlines.is_empty() {
+            return false;
+        }
+
+        let now = timer.now();
+        let now = queue.elapsed.map_or(now, |elapsed| elapsed.max(now));
+        let mut due = Vec::new();
+
+        while let Some(&Reverse((deadline, id))) = queue.deadlines.peek() {
+            if deadline > now {
+                break;
+            }
+
+            queue.deadlines.pop();
+
+            if let Some(pending) = queue.pending.remove(&id) {
+                if let Some(key) = pending.debounced {
+                    queue.debounced.remove(&key);
+                }
+
+                due.push(pending.fire);
+            }
+        }
+
+        drop(queue);
+
+        let fired = !due.is_empty();
+
+        // Timers are fired outside of the lock, as they may schedule other timers.
+        for f in due {
+            f();
+        }
+
+        fired
+    }
+
+    /// Poll the sleep until the earliest deadline, resolving once a timer may be due.
+    pub fn poll_earliest(&self, timer: &dyn Timer, cx: &mut Context<'_>) -> Poll<()> {
+        let mut queue = self.0.lock();
+
+        while let Some(&Reverse((_, id))) = queue.deadlines.peek() {
+            if queue.pending.contains_key(&id) {
+                break;
+            }
+
+            queue.deadlines.pop();
+        }
+
+        let Some(&Reverse((earliest, _))) = queue.deadlines.peek() else {
+            queue.sleep = None;
+            return Poll::Pending;
+        };
+
+        if !matches!(&queue.sleep, Some((deadline, _)) if *deadline == earliest) {
+            let sleep = timer.sleep(earliest.saturating_duration_since(timer.now()));
+            queue.sleep = Some((earliest, sleep));
+        }
+
+        let (deadline, sleep) = queue.sleep.as_mut().expect("sleep to be set");
+        let deadline = *deadline;
+
+        if sleep.poll_unpin(cx).is_pending() {
+            queue.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        queue.elapsed = Some(deadline);
+        queue.sleep = None;
+
+        Poll::Ready(())
+    }
+}
+
+impl Queue {
+    /// Remove the deadlines of cancelled timers once they make up most of the heap.
+    fn compact(&mut self) {
+        if self.deadlines.len() > 2 * self.pending.len() + 16 {
+            let pending = &self.pending;
+            self.deadlines
+                .retain(|Reverse((_, id))| pending.contains_key(id));
+        }
+    }
+}
@@ -0,0 +1,11 @@
+mod common;
+
+#[async_std::test]
+async fn full_lifecycle_on_async_std_runtime() {
+    common::full_lifecycle(xtra::spawn_async_std).await;
+}
+
+#[async_std::test]
+async fn spawn_async_std_does_not_override_configured_timer() {
+    common::spawn_does_not_override_configured_timer(xtra::spawn_async_std).await;
+}
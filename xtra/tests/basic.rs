@@ -1,14 +1,23 @@
 use std::cmp::Ordering as CmpOrdering;
 use std::ops::ControlFlow;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use std::task::Poll;
 use std::time::Duration;
 
+use futures_util::future::FusedFuture;
+use futures_util::stream::FusedStream;
 use futures_util::task::noop_waker_ref;
-use futures_util::FutureExt;
+use futures_util::{FutureExt, StreamExt};
 use smol_timeout::TimeoutExt;
 use tokio::task::JoinSet;
+use xtra::event_bus::EventBus;
 use xtra::prelude::*;
-use xtra::Error;
+use xtra::runtime::{Spawner, Timer};
+use xtra::test::{DeterministicRuntime, TestContext};
+use xtra::{DisconnectReason, Error, MailboxBacking, MailboxOrder};
+
+mod common;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 struct Accumulator(usize);
@@ -73,6 +82,54 @@ async fn accumulate_to_ten() {
     assert_eq!(addr.send(Report).await.unwrap().0, 10);
 }
 
+#[tokio::test]
+async fn actor_is_returned_with_its_final_state() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let weak = addr.downgrade();
+    let handle = tokio::spawn(xtra::run_returning_actor(mailbox, Accumulator(0)));
+
+    addr.send(Inc).await.unwrap();
+    addr.send(Inc).await.unwrap();
+    drop(addr);
+
+    let actor = handle.await.unwrap().unwrap();
+
+    assert_eq!(actor, Accumulator(2));
+    assert!(!weak.is_connected());
+    assert_eq!(actor.stopped().await, 2);
+}
+
+#[tokio::test]
+async fn mailbox_stream_ends_after_shutdown() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let mut messages = mailbox.into_stream();
+    let mut actor = Accumulator(0);
+
+    let _queued = addr.send(Inc).detach().await.unwrap();
+    let _queued = addr.send(StopSelf).detach().await.unwrap();
+
+    let message = messages.next().await.unwrap();
+    assert!(message.dispatch_to(&mut actor).await.is_continue());
+
+    // Stopping itself is up to the loop, the stream goes on.
+    let message = messages.next().await.unwrap();
+    assert!(message.dispatch_to(&mut actor).await.is_break());
+    assert!(!messages.is_terminated());
+
+    let _queued = addr.send(Inc).detach().await.unwrap();
+    drop(addr);
+
+    while let Some(message) = messages.next().await {
+        if message.dispatch_to(&mut actor).await.is_break() {
+            break;
+        }
+    }
+
+    assert!(messages.is_terminated());
+    assert!(messages.next().await.is_none());
+    assert_eq!(actor.stopped().await, 2);
+}
+
 #[derive(xtra::Actor)]
 struct StopTester;
 
@@ -273,6 +330,49 @@ impl Handler<StopSelf> for ActorStopSelf {
     }
 }
 
+struct StopSelfAndHang;
+
+impl Handler<StopSelfAndHang> for ActorStopSelf {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelfAndHang, ctx: &mut Context<Self>) {
+        ctx.stop_self_now();
+        futures_util::future::pending().await
+    }
+}
+
+struct StopSelfAndFlush;
+
+impl Handler<StopSelfAndFlush> for ActorStopSelf {
+    type Return = &'static str;
+
+    async fn handle(&mut self, _: StopSelfAndFlush, ctx: &mut Context<Self>) -> &'static str {
+        ctx.stop_self();
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        "flushed"
+    }
+}
+
+#[tokio::test]
+async fn handler_runs_to_completion_after_stop_self() {
+    let address = xtra::spawn_tokio(ActorStopSelf, Mailbox::unbounded());
+
+    assert_eq!(address.send(StopSelfAndFlush).await, Ok("flushed"));
+    address.join().await;
+}
+
+#[tokio::test]
+async fn stop_self_during_handling_returns_error_to_sender() {
+    let address = xtra::spawn_tokio(ActorStopSelf, Mailbox::unbounded());
+
+    assert_eq!(
+        address.send(StopSelfAndHang).await,
+        Err(Error::ActorStoppedDuringHandling)
+    );
+    address.join().await;
+    assert!(!address.is_connected());
+}
+
 #[derive(xtra::Actor)]
 struct LongRunningHandler;
 
@@ -311,6 +411,41 @@ async fn receiving_async_on_message_channel_returns_immediately_after_dispatch()
     handler_future.await.unwrap();
 }
 
+#[tokio::test]
+async fn address_channel_keeps_reference_count_of_address() {
+    let address = xtra::spawn_tokio(LongRunningHandler, Mailbox::unbounded());
+    let weak: MessageChannel<_, _, xtra::refcount::Weak> =
+        address.downgrade().channel::<Duration>();
+    let strong: MessageChannel<Duration, ()> = address.channel();
+
+    assert_eq!(strong, MessageChannel::from(address.clone()));
+
+    // Only the strong channel keeps the actor alive.
+    drop(address);
+    strong.send(Duration::ZERO).await.unwrap();
+    drop(strong);
+    weak.join().await;
+}
+
+#[tokio::test]
+async fn send_future_is_terminated_once_resolved() {
+    let address = xtra::spawn_tokio(LongRunningHandler, Mailbox::unbounded());
+
+    let mut send_future = address.send(Duration::from_millis(1));
+    assert!(!send_future.is_terminated());
+    (&mut send_future).await.unwrap();
+    assert!(send_future.is_terminated());
+
+    let mut receiver = address
+        .send(Duration::from_millis(1))
+        .detach()
+        .await
+        .unwrap();
+    assert!(!receiver.is_terminated());
+    (&mut receiver).await.unwrap();
+    assert!(receiver.is_terminated());
+}
+
 #[derive(Eq, PartialEq, Clone, Debug)]
 enum Message {
     Broadcast { priority: u32 },
@@ -411,6 +546,122 @@ async fn handle_order() {
     assert_eq!(fut.await, expected);
 }
 
+#[tokio::test]
+async fn priority_fairness_lets_lower_priorities_through() {
+    for backing in [MailboxBacking::Heap, MailboxBacking::Segmented] {
+        let (addr, mailbox) = Mailbox::unbounded();
+        let mailbox = mailbox.with_backing(backing).with_priority_fairness(2);
+
+        for priority in [2, 2, 2, 2, 2, 2, 1, 1, 1, 0] {
+            let _ = addr
+                .send(Message::Priority { priority })
+                .priority(priority)
+                .detach()
+                .await;
+        }
+
+        assert_eq!(addr.len_with_priority(2), 6);
+        assert_eq!(addr.len_with_priority(1), 3);
+        assert_eq!(addr.len_with_priority(0), 1);
+        assert_eq!(addr.len_with_priority(3), 0);
+
+        drop(addr);
+        let handled = xtra::run(mailbox, Elephant::default())
+            .await
+            .into_iter()
+            .map(|msg| match msg {
+                Message::Priority { priority } => priority,
+                Message::Broadcast { .. } => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        // Every second message of priority 2 lets one of priority 1 through, which in turn lets the
+        // message of priority 0 through before the last one of priority 1.
+        assert_eq!(handled, vec![2, 2, 1, 2, 2, 1, 2, 2, 0, 1], "{backing:?}");
+    }
+}
+
+#[tokio::test]
+async fn changing_backing_keeps_queued_messages_in_order() {
+    let (addr, mailbox) = Mailbox::unbounded();
+
+    for priority in [0, 1, 0, 2] {
+        let _ = addr
+            .send(Message::Priority { priority })
+            .priority(priority)
+            .detach()
+            .await;
+    }
+
+    let mailbox = mailbox.with_backing(MailboxBacking::Segmented);
+
+    for priority in [1, 0] {
+        let _ = addr
+            .send(Message::Priority { priority })
+            .priority(priority)
+            .detach()
+            .await;
+    }
+
+    assert_eq!(addr.len(), 6);
+
+    drop(addr);
+    let handled = xtra::run(mailbox, Elephant::default())
+        .await
+        .into_iter()
+        .map(|msg| match msg {
+            Message::Priority { priority } => priority,
+            Message::Broadcast { .. } => unreachable!(),
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(handled, vec![2, 1, 1, 0, 0, 0]);
+}
+
+#[tokio::test]
+async fn lifo_mailbox_handles_newest_message_first() {
+    for backing in [MailboxBacking::Heap, MailboxBacking::Segmented] {
+        let (addr, mailbox) = Mailbox::unbounded();
+        let mailbox = mailbox.with_backing(backing);
+
+        for value in 0..3 {
+            let _ = addr.send(Entry(value)).priority(1).detach().await;
+        }
+
+        // Messages which are queued already are handled newest first as well.
+        let mailbox = mailbox.with_order(MailboxOrder::Lifo);
+
+        for (value, priority) in [(3, 1), (4, 2), (5, 1)] {
+            let _ = addr.send(Entry(value)).priority(priority).detach().await;
+        }
+
+        tokio::spawn(xtra::run(mailbox, Ledger::default()));
+
+        // Priorities still apply, so this is handled last.
+        assert_eq!(
+            addr.send(Entries).await.unwrap(),
+            [4, 5, 3, 2, 1, 0],
+            "{backing:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn changing_order_back_keeps_queued_messages_in_order() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let mailbox = mailbox.with_order(MailboxOrder::Lifo);
+
+    for value in 0..3 {
+        let _ = addr.send(Entry(value)).priority(1).detach().await;
+    }
+
+    let mailbox = mailbox.with_order(MailboxOrder::Fifo);
+    let _ = addr.send(Entry(3)).priority(1).detach().await;
+    tokio::spawn(xtra::run(mailbox, Ledger::default()));
+
+    assert_eq!(addr.send(Entries).await.unwrap(), [0, 1, 2, 3]);
+}
+
 #[tokio::test]
 async fn waiting_sender_order() {
     let (addr, ctx) = Mailbox::bounded(1);
@@ -795,16 +1046,18 @@ fn address_debug() {
     let addr2 = addr1.clone();
     let weak_addr = addr2.downgrade();
 
+    let id = addr1.id();
+
     assert_eq!(
         format!("{:?}", addr1),
-        "Address<basic::Greeter, Strong> { addresses: 2, mailboxes: 1 }"
+        format!("Address<basic::Greeter, Strong> {{ id: {id:?}, addresses: 2, mailboxes: 1 }}")
     );
 
     assert_eq!(format!("{:?}", addr1), format!("{:?}", addr2));
 
     assert_eq!(
         format!("{:?}", weak_addr),
-        "Address<basic::Greeter, Weak> { addresses: 2, mailboxes: 1 }"
+        format!("Address<basic::Greeter, Weak> {{ id: {id:?}, addresses: 2, mailboxes: 1 }}")
     );
 }
 
@@ -812,20 +1065,48 @@ fn address_debug() {
 fn message_channel_debug() {
     let (addr1, _ctx) = Mailbox::<Greeter>::unbounded();
 
+    let id = addr1.id();
     let mc = MessageChannel::<Hello, String>::new(addr1);
     let weak_mc = mc.downgrade();
 
     assert_eq!(
         format!("{:?}", mc),
-        "MessageChannel<basic::Greeter, basic::Hello, alloc::string::String, Strong> { addresses: 1, mailboxes: 1 }"
+        format!("MessageChannel<basic::Greeter, basic::Hello, alloc::string::String, Strong> {{ id: {id:?}, addresses: 1, mailboxes: 1 }}")
     );
 
     assert_eq!(
         format!("{:?}", weak_mc),
-        "MessageChannel<basic::Greeter, basic::Hello, alloc::string::String, Weak> { addresses: 1, mailboxes: 1 }"
+        format!("MessageChannel<basic::Greeter, basic::Hello, alloc::string::String, Weak> {{ id: {id:?}, addresses: 1, mailboxes: 1 }}")
     );
 }
 
+#[tokio::test]
+async fn actor_id_is_shared_by_everything_referring_to_the_same_actor() {
+    struct Id;
+
+    impl Handler<Id> for Greeter {
+        type Return = xtra::ActorId;
+
+        async fn handle(&mut self, _: Id, ctx: &mut Context<Self>) -> xtra::ActorId {
+            ctx.id()
+        }
+    }
+
+    let (addr, mailbox) = Mailbox::<Greeter>::unbounded();
+    let id = addr.id();
+    assert_eq!(mailbox.id(), id);
+    assert_eq!(addr.downgrade().id(), id);
+    assert_eq!(MessageChannel::<Hello, String>::new(addr.clone()).id(), id);
+
+    let other = Mailbox::<Greeter>::unbounded().0;
+    assert!(!addr.same_actor(&other));
+    assert_ne!(other.id(), id);
+
+    tokio::spawn(xtra::run(mailbox.clone(), Greeter));
+    tokio::spawn(xtra::run(mailbox, Greeter));
+    assert_eq!(addr.send(Id).await, Ok(id));
+}
+
 #[test]
 fn scoped_task() {
     // Completes when address is connected
@@ -986,3 +1267,4335 @@ async fn receive_future_can_dispatch_in_one_poll_after_it_has_been_polled() {
 
     assert!(receive_future.now_or_never().is_some())
 }
+
+#[derive(xtra::Actor)]
+struct Ponger;
+
+struct Ping;
+
+#[derive(Debug, PartialEq)]
+struct Pong;
+
+impl Handler<Ping> for Ponger {
+    type Return = ();
+
+    async fn handle(&mut self, _: Ping, ctx: &mut Context<Self>) {
+        let sender = ctx
+            .reply_to::<Pong>()
+            .expect("sender to attach a reply channel");
+        let _ = sender.send(Pong).detach().await;
+    }
+}
+
+#[derive(Default, xtra::Actor)]
+struct PongCollector(usize);
+
+impl Handler<Pong> for PongCollector {
+    type Return = ();
+
+    async fn handle(&mut self, _: Pong, _ctx: &mut Context<Self>) {
+        self.0 += 1;
+    }
+}
+
+impl Handler<Report> for PongCollector {
+    type Return = usize;
+
+    async fn handle(&mut self, _: Report, _ctx: &mut Context<Self>) -> usize {
+        self.0
+    }
+}
+
+#[tokio::test]
+async fn handler_can_reply_to_sender_channel() {
+    let ponger = xtra::spawn_tokio(Ponger, Mailbox::unbounded());
+    let collector = xtra::spawn_tokio(PongCollector::default(), Mailbox::unbounded());
+    let reply_to = MessageChannel::<Pong, ()>::new(collector.clone());
+
+    ponger.send_from(reply_to.clone(), Ping).await.unwrap();
+    ponger.send_from(reply_to, Ping).await.unwrap();
+
+    assert_eq!(collector.send(Report).await, Ok(2));
+}
+
+#[tokio::test]
+async fn reply_to_is_none_without_sender_channel() {
+    #[derive(xtra::Actor)]
+    struct NoReply;
+
+    impl Handler<Ping> for NoReply {
+        type Return = bool;
+
+        async fn handle(&mut self, _: Ping, ctx: &mut Context<Self>) -> bool {
+            ctx.reply_to::<Pong>().is_some()
+        }
+    }
+
+    let addr = xtra::spawn_tokio(NoReply, Mailbox::unbounded());
+
+    assert_eq!(addr.send(Ping).await, Ok(false));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn full_lifecycle_on_multi_threaded_tokio_runtime() {
+    common::full_lifecycle(xtra::spawn_tokio).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn spawn_tokio_does_not_override_configured_timer() {
+    common::spawn_does_not_override_configured_timer(xtra::spawn_tokio).await;
+}
+
+struct Named(u32);
+
+impl Actor for Named {
+    type Stop = ();
+
+    fn name(&self) -> std::borrow::Cow<'static, str> {
+        format!("Named#{}", self.0).into()
+    }
+
+    async fn stopped(self) {}
+}
+
+impl Handler<StopSelf> for Named {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+#[tokio::test]
+async fn address_name_is_captured_when_actor_starts() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    assert_eq!(addr.name(), std::any::type_name::<Named>());
+
+    let channel = MessageChannel::new(addr.clone());
+    let handle = tokio::spawn(xtra::run(mailbox, Named(42)));
+    addr.send(StopSelf).await.unwrap();
+    handle.await.unwrap();
+
+    assert_eq!(addr.name(), "Named#42");
+    assert_eq!(channel.name(), "Named#42");
+}
+
+#[tokio::test]
+async fn spawn_stream_spawns_an_actor_per_item() {
+    let incoming = futures_util::stream::iter([1, 2, 3]);
+    let addresses = xtra::spawn_stream(
+        incoming,
+        |id| (Named(id), Mailbox::unbounded()),
+        xtra::spawn_tokio,
+    )
+    .collect::<Vec<_>>()
+    .await;
+
+    for address in &addresses {
+        address.send(StopSelf).await.unwrap();
+        address.join().await;
+    }
+
+    let names = addresses.iter().map(|a| a.name()).collect::<Vec<_>>();
+    assert_eq!(names, ["Named#1", "Named#2", "Named#3"]);
+}
+
+#[tokio::test]
+async fn test_context_records_stop_all() {
+    let mut actor = Accumulator(0);
+    let mut ctx = TestContext::new();
+
+    actor.handle(Inc, &mut ctx).await;
+    assert!(!ctx.is_stopped_all());
+
+    actor.handle(StopAll, &mut ctx).await;
+    assert!(ctx.is_stopped_all());
+    assert!(!ctx.is_stopped());
+    assert_eq!(actor, Accumulator(1));
+}
+
+struct Schedule;
+
+impl Handler<Schedule> for Accumulator {
+    type Return = ();
+
+    async fn handle(&mut self, _: Schedule, ctx: &mut Context<Self>) {
+        ctx.notify(Inc);
+        ctx.notify_after(Report, Duration::from_secs(5));
+        ctx.spawn_task(async {});
+        ctx.spawn_child(Accumulator(0));
+    }
+}
+
+#[test]
+fn test_context_records_notifications_timers_and_tasks() {
+    let mut ctx = TestContext::new();
+
+    ctx.handle(&mut Accumulator(0), Schedule);
+    assert_eq!(ctx.notified(), [std::any::type_name::<Inc>()]);
+    assert!(ctx.notified().is_empty());
+    assert_eq!(ctx.pending_timers(), 1);
+
+    let spawned = ctx.spawned();
+    assert_eq!(spawned.len(), 2);
+    assert!(spawned[0].ends_with("::task"));
+    assert!(spawned[1].contains("Accumulator"));
+
+    ctx.advance(Duration::from_secs(4));
+    assert_eq!(ctx.pending_timers(), 1);
+    assert_eq!(ctx.peek_next_type(), None);
+
+    ctx.advance(Duration::from_secs(1));
+    assert_eq!(ctx.pending_timers(), 0);
+    assert_eq!(ctx.notified(), [std::any::type_name::<Report>()]);
+}
+
+struct StartBlocking;
+
+impl Handler<StartBlocking> for Accumulator {
+    type Return = ();
+
+    async fn handle(&mut self, _: StartBlocking, ctx: &mut Context<Self>) {
+        drop(ctx.run_blocking(|| ()));
+    }
+}
+
+#[test]
+fn test_context_records_blocking_tasks() {
+    let mut ctx = TestContext::new();
+
+    ctx.handle(&mut Accumulator(0), StartBlocking);
+
+    let spawned = ctx.spawned();
+    assert_eq!(spawned.len(), 1);
+    assert!(spawned[0].ends_with("::blocking"));
+}
+
+#[test]
+fn assert_handles_compares_return_of_handler() {
+    let mut actor = Accumulator(1);
+
+    xtra::assert_handles!(actor, Inc => ());
+    xtra::assert_handles!(actor, Report => Accumulator(2));
+}
+
+#[tokio::test]
+async fn recording_mailbox_records_messages_until_disconnected() {
+    let mailbox = xtra::test::Mailbox::<Hello, &'static str>::respond_with(|hello| hello.0);
+    let channel = mailbox.channel();
+    let weak = channel.downgrade();
+
+    assert_eq!(channel.send(Hello("alice")).await, Ok("alice"));
+    assert_eq!(weak.send(Hello("bob")).await, Ok("bob"));
+    drop(channel.send(Hello("dropped")));
+    assert_eq!(
+        mailbox.recorded().iter().map(|h| h.0).collect::<Vec<_>>(),
+        ["alice", "bob"]
+    );
+
+    drop(channel);
+    weak.join().await;
+    assert_eq!(weak.send(Hello("carol")).await, Err(Error::Disconnected));
+    assert!(mailbox.recorded().is_empty());
+}
+
+#[derive(Default)]
+struct GreetingLog(Vec<&'static str>);
+
+impl Actor for GreetingLog {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Greet(&'static str);
+
+impl Handler<Greet> for GreetingLog {
+    type Return = ();
+
+    async fn handle(&mut self, Greet(name): Greet, _: &mut Context<Self>) {
+        self.0.push(name);
+    }
+}
+
+struct Greeted;
+
+impl Handler<Greeted> for GreetingLog {
+    type Return = Vec<&'static str>;
+
+    async fn handle(&mut self, _: Greeted, _: &mut Context<Self>) -> Vec<&'static str> {
+        self.0.clone()
+    }
+}
+
+#[tokio::test]
+async fn stop_and_recover_hands_over_queued_messages() {
+    let (old, old_mailbox) = Mailbox::bounded(2);
+
+    let first = old.send(Inc).detach().await.unwrap();
+    let second = old.send(Inc).detach().await.unwrap();
+    let mut report = old.send(Report).detach();
+    assert!(
+        (&mut report).now_or_never().is_none(),
+        "mailbox should be full"
+    );
+
+    let (new, new_mailbox) = Mailbox::unbounded();
+    let recovered = old.stop_and_recover();
+    assert_eq!(recovered.len(), 3);
+    new_mailbox.inject(recovered);
+    tokio::spawn(xtra::run(new_mailbox, Accumulator(0)));
+
+    let report = report.await.unwrap();
+    first.await.unwrap();
+    second.await.unwrap();
+
+    assert!(report.await.is_ok());
+    assert_eq!(xtra::run(old_mailbox, Accumulator(100)).await, 100);
+    assert_eq!(new.send(Report).await.unwrap(), Accumulator(2));
+}
+
+/// Two tasks concurrently sending three messages each, returning the order they were handled in.
+fn interleave_senders(seed: u64) -> Vec<&'static str> {
+    let runtime = DeterministicRuntime::new(seed);
+    let address = runtime.spawn_actor(GreetingLog::default(), Mailbox::unbounded());
+
+    for name in ["alice", "bob"] {
+        let address = address.clone();
+        runtime.handle().spawn(
+            name,
+            Box::pin(async move {
+                for _ in 0..3 {
+                    address.send(Greet(name)).await.unwrap();
+                }
+            }),
+        );
+    }
+
+    runtime.run_until_idle();
+    runtime.block_on(address.send(Greeted)).unwrap()
+}
+
+#[test]
+fn deterministic_runtime_replays_interleaving_with_same_seed() {
+    for seed in 0..10 {
+        let order = interleave_senders(seed);
+
+        assert_eq!(order.len(), 6);
+        assert_eq!(order, interleave_senders(seed));
+    }
+}
+
+#[test]
+fn deterministic_runtime_timers_use_virtual_clock() {
+    let runtime = DeterministicRuntime::new(0);
+    let elapsed = Arc::new(AtomicBool::new(false));
+
+    let sleep = runtime.handle().sleep(Duration::from_secs(10));
+    let flag = elapsed.clone();
+    runtime.handle().spawn(
+        "sleeper",
+        Box::pin(async move {
+            sleep.await;
+            flag.store(true, std::sync::atomic::Ordering::SeqCst);
+        }),
+    );
+
+    runtime.run_until_idle();
+    runtime.advance(Duration::from_secs(9));
+    runtime.run_until_idle();
+    assert!(!elapsed.load(std::sync::atomic::Ordering::SeqCst));
+
+    runtime.advance(Duration::from_secs(1));
+    runtime.run_until_idle();
+    assert!(elapsed.load(std::sync::atomic::Ordering::SeqCst));
+    assert_eq!(runtime.now(), Duration::from_secs(10));
+}
+
+struct Session;
+
+impl Actor for Session {
+    type Stop = ();
+
+    async fn started(&mut self, mailbox: &Mailbox<Self>) -> Result<(), ()> {
+        mailbox.stop_after(Duration::from_secs(10));
+        Ok(())
+    }
+
+    async fn stopped(self) {}
+}
+
+struct Touch;
+
+impl Handler<Touch> for Session {
+    type Return = bool;
+
+    async fn handle(&mut self, _: Touch, ctx: &mut Context<Self>) -> bool {
+        ctx.extend_deadline(Duration::from_secs(10))
+    }
+}
+
+#[test]
+fn stop_after_stops_actor_at_deadline() {
+    let runtime = DeterministicRuntime::new(0);
+    let address = runtime.spawn_actor(Session, Mailbox::unbounded());
+    runtime.run_until_idle();
+
+    runtime.advance(Duration::from_secs(9));
+    runtime.run_until_idle();
+    assert!(address.is_connected());
+
+    runtime.advance(Duration::from_secs(1));
+    runtime.run_until_idle();
+    assert!(!address.is_connected());
+}
+
+#[test]
+fn extend_deadline_keeps_actor_alive() {
+    let runtime = DeterministicRuntime::new(0);
+    let address = runtime.spawn_actor(Session, Mailbox::unbounded());
+
+    for _ in 0..3 {
+        runtime.advance(Duration::from_secs(9));
+        assert!(runtime.block_on(address.send(Touch)).unwrap());
+    }
+
+    runtime.advance(Duration::from_secs(9));
+    runtime.run_until_idle();
+    assert!(address.is_connected());
+
+    runtime.advance(Duration::from_secs(1));
+    runtime.run_until_idle();
+    assert!(!address.is_connected());
+}
+
+#[test]
+fn extend_deadline_does_not_rearm_elapsed_deadline() {
+    let ctx = TestContext::<Session>::new();
+
+    ctx.stop_after(Duration::from_secs(10));
+    assert!(ctx.extend_deadline(Duration::from_secs(10)));
+    assert_eq!(ctx.pending_timers(), 1);
+
+    ctx.advance(Duration::from_secs(10));
+    assert!(!ctx.extend_deadline(Duration::from_secs(10)));
+    assert_eq!(ctx.pending_timers(), 0);
+}
+
+#[tokio::test]
+async fn stop_after_without_runtime_falls_back_to_shared_timer_thread() {
+    let (address, mailbox) = Mailbox::unbounded();
+    mailbox.stop_after(Duration::from_millis(10));
+    let actor = tokio::spawn(xtra::run(mailbox, ActorStopSelf));
+
+    tokio::time::timeout(Duration::from_secs(1), address.join())
+        .await
+        .expect("the actor to stop at its deadline");
+    actor.await.unwrap();
+}
+
+#[test]
+fn extend_deadline_without_deadline_does_nothing() {
+    let mut ctx = TestContext::new();
+
+    assert!(!ctx.handle(&mut Session, Touch));
+}
+
+/// Fails the given number of times before it succeeds, counting its attempts.
+struct Flaky {
+    failures: u32,
+    attempts: Arc<std::sync::atomic::AtomicU32>,
+}
+
+impl Flaky {
+    fn new(failures: u32) -> Self {
+        Flaky {
+            failures,
+            attempts: Arc::default(),
+        }
+    }
+}
+
+impl Actor for Flaky {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+/// Call the flaky operation with the given policy, resolving to the attempt which succeeded or
+/// failed last.
+struct Call(xtra::retry::RetryPolicy);
+
+impl Handler<Call> for Flaky {
+    type Return = Result<u32, u32>;
+
+    async fn handle(&mut self, Call(policy): Call, ctx: &mut Context<Self>) -> Result<u32, u32> {
+        ctx.retry(policy, || {
+            let attempt = self
+                .attempts
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+                + 1;
+
+            if attempt <= self.failures {
+                return futures_util::future::ready(Err(attempt));
+            }
+
+            futures_util::future::ready(Ok(attempt))
+        })
+        .await
+    }
+}
+
+#[test]
+fn retry_succeeds_after_failures() {
+    let mut ctx = TestContext::new();
+    let policy = xtra::retry::RetryPolicy::new(3);
+
+    assert_eq!(ctx.handle(&mut Flaky::new(2), Call(policy)), Ok(3));
+    assert_eq!(ctx.handle(&mut Flaky::new(3), Call(policy)), Ok(4));
+}
+
+#[test]
+fn retry_gives_up_with_last_error() {
+    let mut ctx = TestContext::new();
+
+    let result = ctx.handle(&mut Flaky::new(5), Call(xtra::retry::RetryPolicy::new(2)));
+    assert_eq!(result, Err(3));
+
+    let result = ctx.handle(&mut Flaky::new(1), Call(xtra::retry::RetryPolicy::new(0)));
+    assert_eq!(result, Err(1));
+}
+
+#[tokio::test]
+async fn retry_without_timer_falls_back_to_sleeping_thread() {
+    let (address, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Flaky::new(2)));
+
+    let policy = xtra::retry::RetryPolicy::new(3).with_backoff(Duration::from_millis(1));
+    assert_eq!(address.send(Call(policy)).await.unwrap(), Ok(3));
+}
+
+#[test]
+fn retry_backs_off_exponentially_up_to_max() {
+    let runtime = DeterministicRuntime::new(0);
+    let flaky = Flaky::new(3);
+    let attempts = flaky.attempts.clone();
+    let address = runtime.spawn_actor(flaky, Mailbox::unbounded());
+    let attempts = move || attempts.load(std::sync::atomic::Ordering::SeqCst);
+
+    let policy = xtra::retry::RetryPolicy::new(3)
+        .with_backoff(Duration::from_secs(1))
+        .with_max_backoff(Duration::from_secs(3));
+    let reply = runtime
+        .block_on(address.send(Call(policy)).detach())
+        .unwrap();
+    runtime.run_until_idle();
+    assert_eq!(attempts(), 1);
+
+    // The waits double from one second, but never exceed three seconds.
+    for (wait, attempt) in [(1, 2), (2, 3), (3, 4)] {
+        runtime.advance(Duration::from_secs(wait) - Duration::from_millis(1));
+        runtime.run_until_idle();
+        assert_eq!(attempts(), attempt - 1);
+
+        runtime.advance(Duration::from_millis(1));
+        runtime.run_until_idle();
+        assert_eq!(attempts(), attempt);
+    }
+
+    assert_eq!(runtime.block_on(reply), Ok(Ok(4)));
+}
+
+struct Relay {
+    name: &'static str,
+    peer: Address<Relay, xtra::refcount::Weak>,
+}
+
+impl Actor for Relay {
+    type Stop = ();
+
+    fn name(&self) -> std::borrow::Cow<'static, str> {
+        self.name.into()
+    }
+
+    async fn stopped(self) {}
+}
+
+struct Forward;
+
+impl Handler<Forward> for Relay {
+    type Return = ();
+
+    async fn handle(&mut self, _: Forward, _: &mut Context<Self>) {
+        let _ = self.peer.send(Forward).await;
+    }
+}
+
+#[cfg(debug_assertions)]
+fn panic_message(error: tokio::task::JoinError) -> String {
+    *error.into_panic().downcast::<String>().unwrap()
+}
+
+struct Nudge;
+
+impl Handler<Nudge> for Relay {
+    type Return = ();
+
+    async fn handle(&mut self, _: Nudge, _: &mut Context<Self>) {}
+}
+
+struct AskPeer;
+
+impl Handler<AskPeer> for Relay {
+    type Return = Result<(), Error>;
+
+    async fn handle(&mut self, _: AskPeer, _: &mut Context<Self>) -> Result<(), Error> {
+        self.peer.send(Nudge).await
+    }
+}
+
+#[tokio::test]
+async fn awaiting_reply_from_own_actor_would_deadlock() {
+    let (address, mailbox) = Mailbox::unbounded();
+    let relay = Relay {
+        name: "Echo",
+        peer: address.downgrade(),
+    };
+    tokio::spawn(xtra::run(mailbox, relay));
+
+    assert_eq!(address.send(AskPeer).await, Ok(Err(Error::WouldDeadlock)));
+}
+
+#[cfg(debug_assertions)]
+#[tokio::test]
+async fn awaiting_replies_in_a_cycle_panics_in_debug_builds() {
+    let (ping, ping_mailbox) = Mailbox::unbounded();
+    let (pong, pong_mailbox) = Mailbox::unbounded();
+    let ping_actor = Relay {
+        name: "Ping",
+        peer: pong.downgrade(),
+    };
+    let pong_actor = Relay {
+        name: "Pong",
+        peer: ping.downgrade(),
+    };
+    let ping_actor = tokio::spawn(xtra::run(ping_mailbox, ping_actor));
+    let pong_actor = tokio::spawn(xtra::run(pong_mailbox, pong_actor));
+
+    assert_eq!(ping.send(Forward).await, Ok(()));
+    assert_eq!(
+        panic_message(pong_actor.await.unwrap_err()),
+        "Deadlock detected: a handler of `Pong` is awaiting a reply from `Ping`, \
+            which is awaiting a reply from `Pong`"
+    );
+
+    drop((ping, pong));
+    ping_actor.await.unwrap();
+}
+
+#[tokio::test]
+async fn send_on_signal_sends_message_once_signal_is_received() {
+    let received = Arc::new(tokio::sync::Notify::new());
+    let signal = {
+        let received = received.clone();
+        move || {
+            let received = received.clone();
+            async move { received.notified().await }
+        }
+    };
+
+    let (address, mailbox) = Mailbox::unbounded();
+    let actor = tokio::spawn(xtra::run(mailbox, Accumulator(0)));
+    let forward = tokio::spawn(xtra::signal::send_on_signal(signal, &address, StopSelf));
+
+    address.send(Inc).await.unwrap();
+    assert!(address.is_connected());
+
+    received.notify_one();
+    forward.await.unwrap();
+
+    assert_eq!(actor.await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn send_on_signal_completes_once_actor_stops() {
+    let (address, mailbox) = Mailbox::unbounded();
+    let actor = tokio::spawn(xtra::run(mailbox, Accumulator(0)));
+    let forward = xtra::signal::send_on_signal(futures_util::future::pending, &address, Inc);
+
+    address.send(StopSelf).await.unwrap();
+    actor.await.unwrap();
+
+    forward
+        .timeout(Duration::from_secs(1))
+        .await
+        .expect("future to complete once the actor stopped");
+}
+
+#[tokio::test]
+async fn request_all_enqueues_all_messages_before_awaiting_replies() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let mut request = addr.request_all([Inc, Inc, Inc]).boxed();
+
+    let mut ctx = std::task::Context::from_waker(noop_waker_ref());
+    assert!(request.poll_unpin(&mut ctx).is_pending());
+    assert_eq!(addr.len(), 3);
+
+    tokio::spawn(xtra::run(mailbox, Accumulator(0)));
+
+    assert_eq!(request.await, Ok(vec![(), (), ()]));
+    assert_eq!(addr.send(Report).await, Ok(Accumulator(3)));
+}
+
+#[tokio::test]
+async fn request_all_to_disconnected_actor_fails() {
+    let (addr, mailbox) = Mailbox::<Accumulator>::unbounded();
+    drop(mailbox);
+
+    assert_eq!(addr.request_all([Inc, Inc]).await, Err(Error::Disconnected));
+}
+
+#[derive(Default)]
+struct Gate {
+    open: bool,
+    passed: Vec<u32>,
+}
+
+impl Actor for Gate {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Open;
+
+struct Pass(u32);
+
+struct Passed;
+
+impl Handler<Open> for Gate {
+    type Return = ();
+
+    async fn handle(&mut self, _: Open, _: &mut Context<Self>) {
+        self.open = true;
+    }
+}
+
+impl Handler<Pass> for Gate {
+    type Return = ();
+
+    fn can_handle(&self, _: &Pass) -> bool {
+        self.open
+    }
+
+    async fn handle(&mut self, Pass(n): Pass, _: &mut Context<Self>) {
+        self.passed.push(n);
+    }
+}
+
+impl Handler<Passed> for Gate {
+    type Return = Vec<u32>;
+
+    async fn handle(&mut self, _: Passed, _: &mut Context<Self>) -> Vec<u32> {
+        self.passed.clone()
+    }
+}
+
+#[tokio::test]
+async fn deferred_messages_are_retried_in_order_after_state_change() {
+    let addr = xtra::spawn_tokio(Gate::default(), Mailbox::unbounded());
+
+    let first = addr.send(Pass(1));
+    let second = addr.send(Pass(2));
+    let (first, second) = futures_util::join!(first.detach(), second.detach());
+
+    assert_eq!(addr.send(Passed).await, Ok(vec![]));
+    addr.send(Open).await.unwrap();
+
+    first.unwrap().await.unwrap();
+    second.unwrap().await.unwrap();
+    assert_eq!(addr.send(Passed).await, Ok(vec![1, 2]));
+}
+
+#[tokio::test]
+async fn deferring_into_full_stash_interrupts_message() {
+    let addr = xtra::spawn_tokio(Gate::default(), Mailbox::bounded(1));
+
+    let first = addr.send(Pass(1)).detach().await.unwrap();
+    let second = addr.send(Pass(2)).detach().await.unwrap();
+
+    assert_eq!(second.await, Err(Error::Interrupted));
+
+    addr.send(Open).await.unwrap();
+    first.await.unwrap();
+    assert_eq!(addr.send(Passed).await, Ok(vec![1]));
+}
+
+#[tokio::test]
+async fn recheck_deferred_retries_messages_in_custom_event_loop() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let mut gate = Gate::default();
+
+    let pass = addr.send(Pass(1)).detach().await.unwrap();
+    let _ = xtra::yield_once(&mailbox, &mut gate).await;
+
+    gate.open = true;
+    mailbox.recheck_deferred();
+    let _ = xtra::yield_once(&mailbox, &mut gate).await;
+
+    pass.await.unwrap();
+    assert_eq!(gate.passed, [1]);
+}
+
+#[derive(Clone)]
+struct ConfigChanged;
+
+#[derive(Default)]
+struct Subscriber {
+    received: usize,
+}
+
+impl Actor for Subscriber {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+impl Handler<ConfigChanged> for Subscriber {
+    type Return = ();
+
+    async fn handle(&mut self, _: ConfigChanged, _: &mut Context<Self>) {
+        self.received += 1;
+    }
+}
+
+struct Received;
+
+impl Handler<Received> for Subscriber {
+    type Return = usize;
+
+    async fn handle(&mut self, _: Received, _: &mut Context<Self>) -> usize {
+        self.received
+    }
+}
+
+impl Handler<StopSelf> for Subscriber {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+#[tokio::test]
+async fn event_bus_fans_out_to_all_subscribers_once() {
+    let bus = EventBus::new();
+    let (first, first_mailbox) = Mailbox::unbounded();
+    let (second, second_mailbox) = Mailbox::unbounded();
+
+    bus.subscribe::<ConfigChanged, Subscriber>(&first_mailbox);
+    bus.subscribe::<ConfigChanged, Subscriber>(&first_mailbox);
+    bus.subscribe::<ConfigChanged, Subscriber>(&second_mailbox);
+
+    let first = xtra::spawn_tokio(Subscriber::default(), (first, first_mailbox));
+    let second = xtra::spawn_tokio(Subscriber::default(), (second, second_mailbox));
+
+    assert_eq!(bus.publish(ConfigChanged).await, 2);
+    assert_eq!(first.send(Received).await, Ok(1));
+    assert_eq!(second.send(Received).await, Ok(1));
+}
+
+#[tokio::test]
+async fn event_bus_prunes_stopped_subscribers() {
+    let bus = EventBus::new();
+    let (addr, mailbox) = Mailbox::unbounded();
+    bus.subscribe::<ConfigChanged, Subscriber>(&mailbox);
+    let addr = xtra::spawn_tokio(Subscriber::default(), (addr, mailbox));
+
+    addr.send(StopSelf).await.unwrap();
+    addr.join().await;
+
+    assert_eq!(bus.publish(ConfigChanged).await, 0);
+    assert_eq!(bus.subscriber_count::<ConfigChanged>(), 0);
+}
+
+#[tokio::test]
+async fn event_bus_unsubscribe_stops_delivery() {
+    let bus = EventBus::new();
+    let (addr, mailbox) = Mailbox::unbounded();
+    bus.subscribe::<ConfigChanged, Subscriber>(&mailbox);
+    bus.unsubscribe::<ConfigChanged, Subscriber>(&mailbox);
+    let addr = xtra::spawn_tokio(Subscriber::default(), (addr, mailbox));
+
+    assert_eq!(bus.publish(ConfigChanged).await, 0);
+    assert_eq!(addr.send(Received).await, Ok(0));
+}
+
+#[tokio::test]
+async fn run_monomorphic_handles_all_message_types() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run_monomorphic::<_, Inc>(mailbox, Accumulator(0)));
+
+    addr.send(Inc).await.unwrap();
+    addr.send(Inc).await.unwrap();
+
+    assert_eq!(addr.send(Report).await, Ok(Accumulator(2)));
+}
+
+#[tokio::test]
+async fn run_monomorphic_defers_messages() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run_monomorphic::<_, Pass>(mailbox, Gate::default()));
+
+    let pass = addr.send(Pass(1)).detach().await.unwrap();
+    addr.send(Open).await.unwrap();
+
+    pass.await.unwrap();
+    assert_eq!(addr.send(Passed).await, Ok(vec![1]));
+}
+
+#[derive(xtra::Actor)]
+struct Blocker;
+
+struct Block(tokio::sync::oneshot::Receiver<()>);
+
+impl Handler<Block> for Blocker {
+    type Return = ();
+
+    async fn handle(&mut self, Block(unblock): Block, _: &mut Context<Self>) {
+        let _ = unblock.await;
+    }
+}
+
+#[tokio::test]
+async fn address_is_busy_while_handling_message() {
+    let addr = xtra::spawn_tokio(Blocker, Mailbox::unbounded());
+    assert!(!addr.is_busy());
+
+    let (unblock, blocked) = tokio::sync::oneshot::channel();
+    let handled = addr.send(Block(blocked)).detach().await.unwrap();
+
+    while !addr.is_busy() {
+        tokio::task::yield_now().await;
+    }
+
+    unblock.send(()).unwrap();
+    handled.await.unwrap();
+
+    while addr.is_busy() {
+        tokio::task::yield_now().await;
+    }
+}
+
+#[derive(xtra::Actor, Default)]
+struct CorrelationLog(Vec<Option<u128>>);
+
+#[derive(Clone)]
+struct Record;
+
+struct Logged;
+
+impl Handler<Record> for CorrelationLog {
+    type Return = ();
+
+    async fn handle(&mut self, _: Record, ctx: &mut Context<Self>) {
+        self.0.push(ctx.correlation_id());
+    }
+}
+
+impl Handler<Logged> for CorrelationLog {
+    type Return = Vec<Option<u128>>;
+
+    async fn handle(&mut self, _: Logged, _: &mut Context<Self>) -> Vec<Option<u128>> {
+        self.0.clone()
+    }
+}
+
+#[derive(xtra::Actor)]
+struct Frontend(Address<CorrelationLog>);
+
+struct Request;
+
+impl Handler<Request> for Frontend {
+    type Return = ();
+
+    async fn handle(&mut self, _: Request, _: &mut Context<Self>) {
+        self.0.send(Record).await.unwrap();
+        self.0.broadcast(Record).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn correlation_id_is_inherited_by_messages_sent_from_handler() {
+    let log = xtra::spawn_tokio(CorrelationLog::default(), Mailbox::unbounded());
+    let frontend = xtra::spawn_tokio(Frontend(log.clone()), Mailbox::unbounded());
+
+    frontend.send_with_correlation(Request, 42).await.unwrap();
+    frontend.send(Request).await.unwrap();
+
+    assert_eq!(
+        log.send(Logged).await,
+        Ok(vec![Some(42), Some(42), None, None])
+    );
+}
+
+#[tokio::test]
+async fn explicit_correlation_id_overrides_inherited_one() {
+    let log = xtra::spawn_tokio(CorrelationLog::default(), Mailbox::unbounded());
+
+    log.send_with_correlation(Record, 1).await.unwrap();
+    log.send(Record).await.unwrap();
+
+    assert_eq!(log.send(Logged).await, Ok(vec![Some(1), None]));
+}
+
+#[derive(Default)]
+struct Transactional {
+    staged: usize,
+    committed: usize,
+    /// The number of committed changes, observable once the actor has panicked.
+    published: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl Actor for Transactional {
+    type Stop = usize;
+
+    async fn after_handle(&mut self, _: &mut Context<Self>) {
+        self.committed += std::mem::take(&mut self.staged);
+        self.published
+            .store(self.committed, std::sync::atomic::Ordering::SeqCst);
+    }
+
+    async fn stopped(self) -> usize {
+        self.committed
+    }
+}
+
+struct Stage;
+
+struct Committed;
+
+struct StageAndStop;
+
+struct StageAndPanic;
+
+impl Handler<Stage> for Transactional {
+    type Return = ();
+
+    async fn handle(&mut self, _: Stage, _: &mut Context<Self>) {
+        self.staged += 1;
+    }
+}
+
+impl Handler<Committed> for Transactional {
+    type Return = usize;
+
+    async fn handle(&mut self, _: Committed, _: &mut Context<Self>) -> usize {
+        self.committed
+    }
+}
+
+impl Handler<StageAndStop> for Transactional {
+    type Return = ();
+
+    async fn handle(&mut self, _: StageAndStop, ctx: &mut Context<Self>) {
+        self.staged += 1;
+        ctx.stop_self_now();
+        futures_util::future::pending::<()>().await;
+    }
+}
+
+impl Handler<StageAndPanic> for Transactional {
+    type Return = ();
+
+    async fn handle(&mut self, _: StageAndPanic, _: &mut Context<Self>) {
+        self.staged += 1;
+        panic!("failed to stage");
+    }
+}
+
+#[tokio::test]
+async fn after_handle_runs_after_every_message() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let stopped = tokio::spawn(xtra::run(mailbox, Transactional::default()));
+
+    addr.send(Stage).await.unwrap();
+    addr.send(Stage).await.unwrap();
+    assert_eq!(addr.send(Committed).await, Ok(2));
+
+    assert_eq!(
+        addr.send(StageAndStop).await,
+        Err(Error::ActorStoppedDuringHandling)
+    );
+    assert_eq!(stopped.await.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn after_handle_runs_before_panic_of_handler_is_resumed() {
+    let actor = Transactional::default();
+    let published = actor.published.clone();
+    let (addr, mailbox) = Mailbox::unbounded();
+    let stopped = tokio::spawn(xtra::run(mailbox, actor));
+
+    addr.send(Stage).await.unwrap();
+    assert!(addr.send(StageAndPanic).await.is_err());
+
+    assert!(stopped.await.unwrap_err().is_panic());
+    assert_eq!(published.load(std::sync::atomic::Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_context_runs_after_handle() {
+    let mut actor = Transactional::default();
+    let mut ctx = TestContext::new();
+
+    ctx.handle(&mut actor, Stage);
+
+    assert_eq!(actor.committed, 1);
+}
+
+#[tokio::test]
+async fn weak_address_send_if_alive() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Accumulator(0)));
+    let weak = addr.downgrade();
+
+    weak.send_if_alive(Inc).unwrap().await.unwrap();
+    assert_eq!(addr.send(Report).await, Ok(Accumulator(1)));
+
+    addr.send(StopSelf).await.unwrap();
+    addr.join().await;
+    assert!(weak.send_if_alive(Inc).is_none());
+
+    drop(addr);
+    assert!(weak.send_if_alive(Inc).is_none());
+}
+
+#[tokio::test]
+async fn weak_message_channel_send_if_alive() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Accumulator(0)));
+    let weak = MessageChannel::<Inc, ()>::new(addr.clone()).downgrade();
+
+    assert!(weak.try_upgrade().is_some());
+    weak.send_if_alive(Inc).unwrap().await.unwrap();
+    assert_eq!(addr.send(Report).await, Ok(Accumulator(1)));
+
+    drop(addr);
+    assert!(weak.try_upgrade().is_none());
+    assert!(weak.send_if_alive(Inc).is_none());
+}
+
+#[tokio::test]
+async fn contramapped_message_channel_converts_messages() {
+    struct Visitor {
+        name: &'static str,
+    }
+
+    let addr = xtra::spawn_tokio(Greeter, Mailbox::unbounded());
+    let greeter = MessageChannel::<Hello, String>::new(addr.clone());
+    let visitors = greeter
+        .clone()
+        .contramap(|visitor: Visitor| Hello(visitor.name));
+
+    assert_eq!(
+        visitors.send(Visitor { name: "Alice" }).await,
+        Ok("Hello Alice".to_owned())
+    );
+    assert_eq!(
+        visitors.clone().send(Visitor { name: "Bob" }).await,
+        Ok("Hello Bob".to_owned())
+    );
+
+    let weak = visitors.downgrade();
+    assert_eq!(weak, visitors.clone().downgrade());
+    assert_eq!(
+        weak.send_if_alive(Visitor { name: "Carol" }).unwrap().await,
+        Ok("Hello Carol".to_owned())
+    );
+
+    drop((addr, greeter, visitors));
+    assert!(weak.send_if_alive(Visitor { name: "Dave" }).is_none());
+}
+
+#[tokio::test]
+async fn recording_channel_upgrades_only_while_strong_channel_exists() {
+    let mailbox = xtra::test::Mailbox::<Inc>::new();
+    let channel = mailbox.channel();
+    let weak = channel.downgrade();
+
+    assert_eq!(weak.send_if_alive(Inc).unwrap().await, Ok(()));
+    assert_eq!(mailbox.recorded().len(), 1);
+
+    drop(channel);
+    assert!(weak.send_if_alive(Inc).is_none());
+    assert!(mailbox.recorded().is_empty());
+}
+
+#[derive(xtra::Actor)]
+struct Registered(u32);
+
+struct RegisterAs(Option<&'static str>);
+
+struct Identify;
+
+impl Handler<RegisterAs> for Registered {
+    type Return = ();
+
+    async fn handle(&mut self, RegisterAs(name): RegisterAs, ctx: &mut Context<Self>) {
+        match name {
+            Some(name) => ctx.register_named_as::<Identify>(name),
+            None => ctx.register_as::<Identify>(),
+        }
+    }
+}
+
+impl Handler<Identify> for Registered {
+    type Return = u32;
+
+    async fn handle(&mut self, _: Identify, _: &mut Context<Self>) -> u32 {
+        self.0
+    }
+}
+
+impl Handler<StopSelf> for Registered {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+#[tokio::test]
+async fn registry_looks_up_actors_until_they_stop() {
+    use xtra::registry::Registry;
+
+    let first = xtra::spawn_tokio(Registered(1), Mailbox::unbounded());
+    let second = xtra::spawn_tokio(Registered(2), Mailbox::unbounded());
+
+    first.send(RegisterAs(None)).await.unwrap();
+    first.send(RegisterAs(Some("first"))).await.unwrap();
+    second.send(RegisterAs(Some("second"))).await.unwrap();
+
+    let found = Registry::get::<Identify, u32>().unwrap();
+    assert_eq!(found.send(Identify).await, Ok(1));
+    let found = Registry::get_named::<Identify, u32>("second").unwrap();
+    assert_eq!(found.send(Identify).await, Ok(2));
+    assert!(Registry::get_named::<Identify, u32>("third").is_none());
+
+    let all = Registry::all::<Identify, u32>();
+    assert_eq!(all.len(), 2);
+    drop((found, all));
+
+    // The registration is removed even though the address is still around.
+    first.send(StopSelf).await.unwrap();
+    first.join().await;
+
+    assert!(Registry::get::<Identify, u32>().is_none());
+    assert!(Registry::get_named::<Identify, u32>("first").is_none());
+    assert_eq!(Registry::all::<Identify, u32>().len(), 1);
+
+    drop(second);
+    assert!(Registry::get_named::<Identify, u32>("second").is_none());
+}
+
+/// Registers itself under the same name in every incarnation, like an actor restarted by a
+/// supervisor.
+struct Incarnation(u32);
+
+/// Deliberately not `Clone`, as the [`ResilientChannel`](xtra::registry::ResilientChannel) must
+/// give it back rather than copy it.
+struct Generation;
+
+impl Actor for Incarnation {
+    type Stop = ();
+
+    async fn started(&mut self, mailbox: &Mailbox<Self>) -> Result<(), ()> {
+        mailbox.register_named_as::<Generation>("incarnation");
+        Ok(())
+    }
+
+    async fn stopped(self) {}
+}
+
+impl Handler<Generation> for Incarnation {
+    type Return = u32;
+
+    async fn handle(&mut self, _: Generation, _: &mut Context<Self>) -> u32 {
+        self.0
+    }
+}
+
+impl Handler<StopSelf> for Incarnation {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+#[tokio::test]
+async fn resilient_channel_resends_to_replaced_actor() {
+    use xtra::registry::ResilientChannel;
+
+    let channel = ResilientChannel::<Generation, u32>::named("incarnation")
+        .with_retries(8)
+        .with_backoff(Duration::from_millis(5), xtra::runtime::Tokio);
+
+    let first = xtra::spawn_tokio(Incarnation(1), Mailbox::unbounded());
+    assert_eq!(channel.send(Generation).await.unwrap(), Ok(1));
+
+    first.send(StopSelf).await.unwrap();
+    first.join().await;
+
+    // The replacement only registers itself after the first lookups have failed.
+    let replacement = tokio::spawn(async {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        xtra::spawn_tokio(Incarnation(2), Mailbox::unbounded())
+    });
+
+    assert_eq!(channel.send(Generation).await.unwrap(), Ok(2));
+
+    let second = replacement.await.unwrap();
+    second.send(StopSelf).await.unwrap();
+    second.join().await;
+
+    let unavailable = channel.clone().with_retries(1).send(Generation).await;
+    let Err(unavailable) = unavailable else {
+        panic!("no actor should be running");
+    };
+    assert_eq!(unavailable.attempts(), 2);
+    let Generation = unavailable.into_inner();
+}
+
+struct Phased {
+    name: &'static str,
+    log: Arc<std::sync::Mutex<Vec<&'static str>>>,
+}
+
+impl Actor for Phased {
+    type Stop = ();
+
+    async fn stopped(self) {
+        self.log.lock().unwrap().push(self.name);
+    }
+}
+
+impl Handler<StopSelf> for Phased {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        self.log.lock().unwrap().push("stop requested");
+        ctx.stop_self();
+    }
+}
+
+#[tokio::test]
+async fn shutdown_group_stops_actors_phase_by_phase() {
+    use xtra::shutdown::ShutdownGroup;
+
+    let group = ShutdownGroup::new(xtra::runtime::Tokio);
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let phased = |name| Phased {
+        name,
+        log: log.clone(),
+    };
+
+    let backend = xtra::spawn_tokio(phased("backend"), Mailbox::unbounded());
+    let frontend = xtra::spawn_tokio(phased("frontend"), Mailbox::unbounded());
+    let (storage, mailbox) = Mailbox::unbounded();
+    let storage = xtra::spawn_tokio(phased("storage"), (storage, mailbox.shutdown_in(&group, 2)));
+    group.add_with_message(1, &backend, StopSelf);
+    group.add(0, &frontend);
+
+    let report = group.shutdown(Duration::from_secs(5)).await;
+
+    assert!(report.is_clean());
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["frontend", "stop requested", "backend", "storage"]
+    );
+    assert!(!backend.is_connected() && !frontend.is_connected() && !storage.is_connected());
+}
+
+#[tokio::test]
+async fn shutdown_group_reports_actors_which_do_not_stop_in_time() {
+    use xtra::shutdown::ShutdownGroup;
+
+    let group = ShutdownGroup::new(xtra::runtime::Tokio);
+    let addr = xtra::spawn_tokio(Blocker, Mailbox::unbounded());
+    group.add(0, &addr);
+
+    let (unblock, blocked) = tokio::sync::oneshot::channel();
+    let _handled = addr.send(Block(blocked)).detach().await.unwrap();
+    let (_never, queued) = tokio::sync::oneshot::channel();
+    let queued = addr.send(Block(queued)).detach().await.unwrap();
+
+    while !addr.is_busy() {
+        tokio::task::yield_now().await;
+    }
+
+    let report = group.shutdown(Duration::from_millis(10)).await;
+
+    assert_eq!(report.timed_out(), [addr.name()]);
+    assert_eq!(queued.await, Err(Error::Interrupted));
+
+    unblock.send(()).unwrap();
+    addr.join().await;
+}
+
+#[tokio::test]
+async fn second_shutdown_signal_aborts_shutdown() {
+    use xtra::shutdown::ShutdownGroup;
+
+    let signals = Arc::new(tokio::sync::Semaphore::new(0));
+    let signal = {
+        let signals = signals.clone();
+        move || {
+            let signals = signals.clone();
+            async move { signals.acquire().await.unwrap().forget() }
+        }
+    };
+
+    let group = ShutdownGroup::new(xtra::runtime::Tokio);
+    let blocker = xtra::spawn_tokio(Blocker, Mailbox::unbounded());
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let later = xtra::spawn_tokio(
+        Phased {
+            name: "later",
+            log: log.clone(),
+        },
+        Mailbox::unbounded(),
+    );
+    group.add(0, &blocker);
+    group.add_with_message(1, &later, StopSelf);
+
+    let (unblock, blocked) = tokio::sync::oneshot::channel();
+    let _handled = blocker.send(Block(blocked)).detach().await.unwrap();
+
+    let shutdown = tokio::spawn(xtra::signal::shutdown_on_signal(
+        signal,
+        group,
+        Duration::from_secs(60),
+    ));
+
+    signals.add_permits(1);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(blocker.is_connected() && later.is_connected());
+
+    signals.add_permits(1);
+    let report = shutdown
+        .timeout(Duration::from_secs(5))
+        .await
+        .expect("second signal to abort the shutdown")
+        .unwrap();
+
+    assert!(report.is_aborted());
+    assert_eq!(report.timed_out(), [blocker.name(), later.name()]);
+
+    // The later phase is stopped without being asked to stop gracefully.
+    later.join().await;
+    assert_eq!(*log.lock().unwrap(), ["later"]);
+
+    unblock.send(()).unwrap();
+    blocker.join().await;
+}
+
+#[tokio::test]
+async fn nested_scopes_stop_their_actors_when_left() {
+    let log = &Arc::new(std::sync::Mutex::new(Vec::new()));
+    let phased = |name| Phased {
+        name,
+        log: log.clone(),
+    };
+
+    let outer = xtra::scope(
+        xtra::runtime::Tokio,
+        Duration::from_secs(5),
+        |scope| async move {
+            let outer = scope.spawn(phased("outer"), Mailbox::unbounded());
+
+            let inner = xtra::scope(
+                xtra::runtime::Tokio,
+                Duration::from_secs(5),
+                |scope| async move { scope.spawn(phased("inner"), Mailbox::unbounded()) },
+            )
+            .await;
+
+            assert!(!inner.is_connected());
+            assert!(outer.is_connected());
+            assert_eq!(*log.lock().unwrap(), ["inner"]);
+
+            outer
+        },
+    )
+    .await;
+
+    assert!(!outer.is_connected());
+    assert_eq!(*log.lock().unwrap(), ["inner", "outer"]);
+}
+
+#[tokio::test]
+async fn scope_drops_queued_messages_after_grace_period() {
+    let (unblock, blocked) = tokio::sync::oneshot::channel();
+
+    let (handled, queued) = xtra::scope(xtra::runtime::Tokio, Duration::from_millis(10), |scope| {
+        async move {
+            let addr = scope.spawn(Blocker, Mailbox::unbounded());
+            let handled = addr.send(Block(blocked)).detach().await.unwrap();
+            let (_never, queued) = tokio::sync::oneshot::channel();
+            let queued = addr.send(Block(queued)).detach().await.unwrap();
+
+            while !addr.is_busy() {
+                tokio::task::yield_now().await;
+            }
+
+            // The scope waits for the current handler however long it takes.
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                unblock.send(()).unwrap();
+            });
+
+            (handled, queued)
+        }
+    })
+    .await;
+
+    assert_eq!(queued.now_or_never(), Some(Err(Error::Interrupted)));
+    assert_eq!(handled.now_or_never(), Some(Ok(())));
+}
+
+#[tokio::test]
+async fn dropping_scope_stops_its_actors() {
+    let log = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (spawned, on_spawned) = tokio::sync::oneshot::channel();
+
+    let scope = xtra::scope(xtra::runtime::Tokio, Duration::from_secs(5), |scope| {
+        let phased = Phased {
+            name: "scoped",
+            log: log.clone(),
+        };
+
+        async move {
+            let _ = spawned.send(scope.spawn(phased, Mailbox::unbounded()));
+            futures_util::future::pending::<()>().await
+        }
+    });
+
+    assert!(scope.timeout(Duration::from_millis(10)).await.is_none());
+
+    let addr = on_spawned.await.unwrap();
+    addr.join().await;
+    assert_eq!(*log.lock().unwrap(), ["scoped"]);
+}
+
+#[derive(Default)]
+struct StateMachine {
+    log: Vec<&'static str>,
+    resume: Option<tokio::sync::oneshot::Receiver<()>>,
+}
+
+impl Actor for StateMachine {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Input(&'static str);
+
+struct Transition;
+
+struct History;
+
+impl Handler<Input> for StateMachine {
+    type Return = ();
+
+    async fn handle(&mut self, Input(input): Input, ctx: &mut Context<Self>) {
+        self.log.push(input);
+
+        if input == "begin" {
+            ctx.notify(Transition);
+            ctx.notify(Transition);
+
+            if let Some(resume) = self.resume.take() {
+                let _ = resume.await;
+            }
+        }
+    }
+}
+
+impl Handler<Transition> for StateMachine {
+    type Return = ();
+
+    async fn handle(&mut self, _: Transition, _: &mut Context<Self>) {
+        self.log.push("transition");
+    }
+}
+
+impl Handler<History> for StateMachine {
+    type Return = Vec<&'static str>;
+
+    async fn handle(&mut self, _: History, _: &mut Context<Self>) -> Vec<&'static str> {
+        self.log.clone()
+    }
+}
+
+#[tokio::test]
+async fn notify_is_handled_ahead_of_queued_messages() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let mut actor = StateMachine::default();
+
+    for input in ["begin", "first", "second", "third"] {
+        let _queued = addr.send(Input(input)).detach().await.unwrap();
+    }
+
+    assert!(xtra::yield_once(&mailbox, &mut actor).await.is_continue());
+    let _later = addr.send(Input("later")).detach().await.unwrap();
+    tokio::spawn(xtra::run(mailbox, actor));
+
+    assert_eq!(
+        addr.send(History).await.unwrap(),
+        [
+            "begin",
+            "transition",
+            "transition",
+            "first",
+            "second",
+            "third",
+            "later"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn notify_is_handled_ahead_of_messages_of_any_priority() {
+    for order in [MailboxOrder::Fifo, MailboxOrder::Lifo] {
+        let (addr, mailbox) = Mailbox::unbounded();
+        let mailbox = mailbox.with_order(order).with_priority_fairness(1);
+        let mut actor = StateMachine::default();
+
+        let _queued = addr.send(Input("begin")).detach().await.unwrap();
+        assert!(xtra::yield_once(&mailbox, &mut actor).await.is_continue());
+
+        let _queued = addr.send(Input("low")).detach().await.unwrap();
+        let _queued = addr
+            .send(Input("high"))
+            .priority(10)
+            .detach()
+            .await
+            .unwrap();
+
+        for _ in 0..4 {
+            assert!(xtra::yield_once(&mailbox, &mut actor).await.is_continue());
+        }
+
+        assert_eq!(
+            actor.log,
+            ["begin", "transition", "transition", "high", "low"],
+            "{order:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn notify_is_ahead_of_messages_sent_concurrently_to_full_mailbox() {
+    let (resume, resumed) = tokio::sync::oneshot::channel();
+    let actor = StateMachine {
+        resume: Some(resumed),
+        ..Default::default()
+    };
+    let addr = xtra::spawn_tokio(actor, Mailbox::bounded(1));
+
+    let _begun = addr.send(Input("begin")).detach().await.unwrap();
+
+    while !addr.is_busy() {
+        tokio::task::yield_now().await;
+    }
+
+    // The handler has notified itself twice, filling the mailbox beyond its capacity.
+    let mut during = addr.send(Input("during")).detach();
+    assert!((&mut during).now_or_never().is_none());
+
+    resume.send(()).unwrap();
+    during.await.unwrap().await.unwrap();
+
+    assert_eq!(
+        addr.send(History).await.unwrap(),
+        ["begin", "transition", "transition", "during"]
+    );
+}
+
+/// Recomputes the total of its cells once per burst of changes.
+#[derive(Default)]
+struct Spreadsheet {
+    cells: [u32; 3],
+    /// The revision and total of every recomputation.
+    recomputed: Vec<(u32, u32)>,
+}
+
+impl Actor for Spreadsheet {
+    type Stop = ();
+
+    async fn stopped(self) -> Self::Stop {}
+}
+
+struct SetCell(usize, u32);
+
+/// Recompute the total as of the given revision.
+struct Recompute(u32);
+
+impl Handler<SetCell> for Spreadsheet {
+    type Return = ();
+
+    async fn handle(&mut self, SetCell(cell, value): SetCell, ctx: &mut Context<Self>) {
+        self.cells[cell] = value;
+        ctx.notify_coalesced(Recompute(value));
+    }
+}
+
+impl Handler<Recompute> for Spreadsheet {
+    type Return = ();
+
+    async fn handle(&mut self, Recompute(revision): Recompute, _: &mut Context<Self>) {
+        self.recomputed.push((revision, self.cells.iter().sum()));
+    }
+}
+
+#[tokio::test]
+async fn notify_coalesced_is_handled_once_with_latest_payload() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let mut actor = Spreadsheet::default();
+
+    for (cell, value) in [(0, 1), (1, 2), (2, 3)] {
+        let _queued = addr.send(SetCell(cell, value)).detach().await.unwrap();
+    }
+
+    for _ in 0..3 {
+        assert!(xtra::yield_once(&mailbox, &mut actor).await.is_continue());
+    }
+
+    // The changes were handled before the recomputation queued by the first of them.
+    assert!(actor.recomputed.is_empty());
+    assert!(xtra::yield_once(&mailbox, &mut actor).await.is_continue());
+    assert_eq!(actor.recomputed, [(3, 6)]);
+    assert!(xtra::yield_once(&mailbox, &mut actor)
+        .now_or_never()
+        .is_none());
+
+    // Once the recomputation has been handled, the next change queues another one.
+    let _queued = addr.send(SetCell(0, 4)).detach().await.unwrap();
+
+    for _ in 0..2 {
+        assert!(xtra::yield_once(&mailbox, &mut actor).await.is_continue());
+    }
+
+    assert_eq!(actor.recomputed, [(3, 6), (4, 9)]);
+}
+
+#[tokio::test]
+async fn notify_coalesced_is_queued_again_after_being_dropped() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let mut actor = Spreadsheet::default();
+
+    let _queued = addr.send(SetCell(0, 1)).detach().await.unwrap();
+    assert!(xtra::yield_once(&mailbox, &mut actor).await.is_continue());
+
+    // The pending recomputation is dropped without being handled, so the next change has to
+    // queue a new one.
+    drop(mailbox.next().await);
+
+    let _queued = addr.send(SetCell(1, 2)).detach().await.unwrap();
+
+    for _ in 0..2 {
+        assert!(xtra::yield_once(&mailbox, &mut actor).await.is_continue());
+    }
+
+    assert_eq!(actor.recomputed, [(2, 3)]);
+}
+
+/// Forwards jobs to a downstream which only has capacity for them once it is ready.
+#[derive(Default)]
+struct Backpressured {
+    ready: bool,
+    delay: Option<Duration>,
+    handled: Vec<u32>,
+}
+
+impl Actor for Backpressured {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Job(u32);
+
+struct Ready;
+
+impl Handler<Job> for Backpressured {
+    type Return = u32;
+
+    async fn handle(&mut self, Job(job): Job, ctx: &mut Context<Self>) -> u32 {
+        if !self.ready {
+            match self.delay {
+                Some(delay) => ctx.requeue_after(Job(job), delay),
+                None => ctx.requeue(Job(job)),
+            }
+
+            return 0;
+        }
+
+        self.handled.push(job);
+        job * 10
+    }
+}
+
+impl Handler<Ready> for Backpressured {
+    type Return = Vec<u32>;
+
+    async fn handle(&mut self, _: Ready, _: &mut Context<Self>) -> Vec<u32> {
+        self.ready = true;
+        self.handled.clone()
+    }
+}
+
+#[tokio::test]
+async fn requeued_message_is_handled_again_and_replied_to_once() {
+    let (addr, mailbox) = Mailbox::unbounded();
+
+    let job = addr.send(Job(1)).detach().await.unwrap();
+    let ready = addr.send(Ready).detach().await.unwrap();
+    tokio::spawn(xtra::run(mailbox, Backpressured::default()));
+
+    assert_eq!(ready.await, Ok(vec![]));
+    assert_eq!(job.await, Ok(10));
+    assert_eq!(addr.send(Job(2)).await, Ok(20));
+}
+
+#[test]
+fn requeue_after_handles_other_messages_in_the_meantime() {
+    let runtime = DeterministicRuntime::new(0);
+    let gate = Backpressured {
+        delay: Some(Duration::from_secs(10)),
+        ..Default::default()
+    };
+    let addr = runtime.spawn_actor(gate, Mailbox::unbounded());
+
+    let job = runtime.block_on(addr.send(Job(1)).detach()).unwrap();
+    runtime.run_until_idle();
+    assert_eq!(
+        addr.len(),
+        0,
+        "job should wait for the delay outside of the mailbox"
+    );
+
+    assert_eq!(runtime.block_on(addr.send(Ready)), Ok(vec![]));
+
+    runtime.advance(Duration::from_secs(10));
+    assert_eq!(runtime.block_on(job), Ok(10));
+}
+
+#[tokio::test]
+async fn requeue_after_without_timer_falls_back_to_sleeping_thread() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let gate = Backpressured {
+        delay: Some(Duration::from_millis(10)),
+        ..Default::default()
+    };
+    tokio::spawn(xtra::run(mailbox, gate));
+
+    let job = addr.send(Job(1)).detach().await.unwrap();
+    assert_eq!(addr.send(Ready).await, Ok(vec![]));
+    assert_eq!(job.await, Ok(10));
+}
+
+#[test]
+fn test_context_queues_requeued_message() {
+    let mut actor = Backpressured::default();
+    let mut ctx = TestContext::new();
+
+    ctx.handle(&mut actor, Job(1));
+
+    assert_eq!(ctx.address().len(), 1);
+}
+
+#[test]
+fn test_context_peeks_next_message_type_without_receiving_it() {
+    let mut actor = Backpressured::default();
+    let mut ctx = TestContext::new();
+    assert_eq!(ctx.peek_next_type(), None);
+
+    ctx.handle(&mut actor, Job(1));
+    assert_eq!(ctx.peek_next_type(), Some(std::any::type_name::<Job>()));
+
+    let _ = ctx
+        .address()
+        .send(Ready)
+        .priority(1)
+        .detach()
+        .now_or_never();
+    assert_eq!(ctx.peek_next_type(), Some(std::any::type_name::<Ready>()));
+    assert_eq!(ctx.address().len(), 2);
+}
+
+#[derive(Debug, Default)]
+struct Inventory {
+    items: Vec<&'static str>,
+}
+
+impl Actor for Inventory {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+impl xtra::inspect::Inspect for Inventory {
+    fn inspect(&self) -> xtra::inspect::State {
+        #[cfg(feature = "json")]
+        return xtra::inspect::State::Json(serde_json::json!({ "items": self.items }));
+
+        #[cfg(not(feature = "json"))]
+        xtra::inspect::State::debug(self)
+    }
+}
+
+struct Stock(&'static str);
+
+impl Handler<Stock> for Inventory {
+    type Return = ();
+
+    async fn handle(&mut self, Stock(item): Stock, _: &mut Context<Self>) {
+        self.items.push(item);
+    }
+}
+
+#[tokio::test]
+async fn inspect_observes_state_after_queued_messages() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let _stocked = addr.send(Stock("apples")).detach().await.unwrap();
+    let report = addr.inspect().detach().await.unwrap();
+    let _stocked = addr.send(Stock("pears")).detach().await.unwrap();
+    tokio::spawn(xtra::run(mailbox, Inventory::default()));
+
+    let report = report.await.unwrap();
+
+    assert_eq!(report.id(), addr.id());
+    assert_eq!(report.name(), addr.name());
+    #[cfg(feature = "json")]
+    assert_eq!(
+        *report.state(),
+        xtra::inspect::State::Json(serde_json::json!({ "items": ["apples"] }))
+    );
+    #[cfg(not(feature = "json"))]
+    assert_eq!(
+        report.state().to_string(),
+        "Inventory {\n    items: [\n        \"apples\",\n    ],\n}"
+    );
+
+    let weak = addr.downgrade();
+    drop(addr);
+    weak.join().await;
+    assert_eq!(weak.inspect().await, Err(Error::Disconnected));
+}
+
+/// Acquires resources with cleanup callbacks, recording the order in which they are released.
+struct Acquirer {
+    released: Arc<std::sync::Mutex<Vec<&'static str>>>,
+}
+
+impl Actor for Acquirer {
+    type Stop = ();
+
+    async fn stopped(self) {
+        self.released.lock().unwrap().push("stopped");
+    }
+}
+
+struct Acquire(&'static str);
+
+impl Handler<Acquire> for Acquirer {
+    type Return = ();
+
+    async fn handle(&mut self, Acquire(resource): Acquire, ctx: &mut Context<Self>) {
+        ctx.on_stop(move |actor| actor.released.lock().unwrap().push(resource));
+    }
+}
+
+struct AcquireAndStop(&'static str);
+
+impl Handler<AcquireAndStop> for Acquirer {
+    type Return = ();
+
+    async fn handle(&mut self, AcquireAndStop(resource): AcquireAndStop, ctx: &mut Context<Self>) {
+        ctx.on_stop(move |actor| actor.released.lock().unwrap().push(resource));
+        ctx.stop_self();
+    }
+}
+
+#[tokio::test]
+async fn on_stop_callbacks_run_in_reverse_order_before_stopped() {
+    let released = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (addr, mailbox) = Mailbox::unbounded();
+    let actor = tokio::spawn(xtra::run(
+        mailbox,
+        Acquirer {
+            released: released.clone(),
+        },
+    ));
+
+    addr.send(Acquire("subscription")).await.unwrap();
+    addr.send(Acquire("temp file")).await.unwrap();
+    addr.send(AcquireAndStop("lock")).await.unwrap();
+    actor.await.unwrap();
+
+    assert_eq!(
+        *released.lock().unwrap(),
+        ["lock", "temp file", "subscription", "stopped"]
+    );
+}
+
+#[tokio::test]
+async fn on_stop_callbacks_run_when_all_addresses_are_dropped() {
+    let released = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let (addr, mailbox) = Mailbox::unbounded();
+    let actor = tokio::spawn(xtra::run(
+        mailbox,
+        Acquirer {
+            released: released.clone(),
+        },
+    ));
+
+    addr.send(Acquire("subscription")).await.unwrap();
+    drop(addr);
+    actor.await.unwrap();
+
+    assert_eq!(*released.lock().unwrap(), ["subscription", "stopped"]);
+}
+
+/// Fetches pages in tasks which are limited by permits, each taking one second.
+struct Fetcher {
+    runtime: xtra::test::DeterministicHandle,
+    fetched: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl Actor for Fetcher {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Fetch {
+    pages: usize,
+    limit: usize,
+}
+
+impl Handler<Fetch> for Fetcher {
+    type Return = ();
+
+    async fn handle(&mut self, fetch: Fetch, ctx: &mut Context<Self>) {
+        for _ in 0..fetch.pages {
+            let runtime = self.runtime.clone();
+            let fetched = self.fetched.clone();
+
+            ctx.with_permit(fetch.limit, async move {
+                runtime.sleep(Duration::from_secs(1)).await;
+                fetched.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            });
+        }
+    }
+}
+
+struct PermitsInFlight;
+
+impl Handler<PermitsInFlight> for Fetcher {
+    type Return = usize;
+
+    async fn handle(&mut self, _: PermitsInFlight, ctx: &mut Context<Self>) -> usize {
+        ctx.permits_in_flight()
+    }
+}
+
+#[test]
+fn with_permit_limits_concurrently_running_tasks() {
+    let runtime = DeterministicRuntime::new(0);
+    let fetched = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let address = runtime.spawn_actor(
+        Fetcher {
+            runtime: runtime.handle(),
+            fetched: fetched.clone(),
+        },
+        Mailbox::unbounded(),
+    );
+
+    let _fetch = runtime
+        .block_on(address.send(Fetch { pages: 5, limit: 2 }).detach())
+        .unwrap();
+    runtime.run_until_idle();
+
+    for (in_flight, done) in [(2, 0), (2, 2), (1, 4), (0, 5)] {
+        assert_eq!(fetched.load(std::sync::atomic::Ordering::SeqCst), done);
+        assert_eq!(
+            runtime.block_on(address.send(PermitsInFlight)),
+            Ok(in_flight)
+        );
+
+        runtime.advance(Duration::from_secs(1));
+        runtime.run_until_idle();
+    }
+}
+
+/// Spawns tasks onto tokio, recording their names.
+#[derive(Clone, Default)]
+struct NameRecordingSpawner(Arc<std::sync::Mutex<Vec<String>>>);
+
+impl Spawner for NameRecordingSpawner {
+    fn spawn(&self, name: &str, future: futures_util::future::BoxFuture<'static, ()>) {
+        self.0.lock().unwrap().push(name.to_owned());
+        tokio::spawn(future);
+    }
+}
+
+struct Housekeeper;
+
+impl Actor for Housekeeper {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct SpawnAuxiliaryTasks;
+
+impl Handler<SpawnAuxiliaryTasks> for Housekeeper {
+    type Return = ();
+
+    async fn handle(&mut self, _: SpawnAuxiliaryTasks, ctx: &mut Context<Self>) {
+        ctx.spawn_task(async {});
+        ctx.with_permit(1, async {});
+    }
+}
+
+#[tokio::test]
+async fn auxiliary_tasks_are_named_after_their_actor() {
+    let spawner = NameRecordingSpawner::default();
+    let (addr, mailbox) = Mailbox::unbounded();
+    let mailbox = mailbox
+        .with_spawner(spawner.clone())
+        .with_timer(xtra::runtime::Tokio);
+    tokio::spawn(xtra::run(mailbox, Housekeeper));
+
+    addr.send(SpawnAuxiliaryTasks).await.unwrap();
+
+    let task = format!("xtra::{}{}", addr.name(), addr.id());
+    assert_eq!(
+        *spawner.0.lock().unwrap(),
+        [format!("{task}::task"), format!("{task}::permit")]
+    );
+}
+
+struct Permitted(tokio::sync::oneshot::Sender<()>);
+
+impl Handler<Permitted> for Housekeeper {
+    type Return = ();
+
+    async fn handle(&mut self, Permitted(done): Permitted, ctx: &mut Context<Self>) {
+        ctx.with_permit(1, async move {
+            let _ = done.send(());
+        });
+    }
+}
+
+#[tokio::test]
+async fn with_permit_without_spawner_runs_on_shared_executor() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Housekeeper));
+
+    let (done, ran) = tokio::sync::oneshot::channel();
+    addr.send(Permitted(done)).await.unwrap();
+
+    tokio::time::timeout(Duration::from_secs(1), ran)
+        .await
+        .expect("the task to run on the shared executor")
+        .unwrap();
+}
+
+#[cfg(feature = "sink")]
+#[tokio::test]
+async fn dropping_last_strong_address_ends_forward_into_weak_sink() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Inventory::default()));
+
+    let items =
+        futures_util::stream::iter([Ok(Stock("apples"))]).chain(futures_util::stream::pending());
+    let forward = tokio::spawn(items.forward(addr.downgrade().into_sink()));
+
+    tokio::time::sleep(Duration::from_millis(10)).await; // Let the forward become idle.
+    drop(addr);
+
+    let forwarded = forward.timeout(Duration::from_secs(1)).await;
+    assert_eq!(forwarded.unwrap().unwrap(), Err(Error::Disconnected));
+}
+
+#[test]
+fn mailbox_new_is_unbounded_unless_actor_declares_capacity() {
+    struct Declared;
+
+    impl Actor for Declared {
+        type Stop = ();
+        const MAILBOX_CAPACITY: Option<usize> = Some(2);
+
+        async fn stopped(self) {}
+    }
+
+    let (address, _mailbox) = Mailbox::<Declared>::new();
+    assert_eq!(address.capacity(), Some(2));
+
+    let (address, _mailbox) = Mailbox::<Inventory>::new();
+    assert_eq!(address.capacity(), None);
+}
+
+#[tokio::test]
+async fn send_and_forget_resolves_once_queued() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let channel = MessageChannel::<Inc, ()>::new(addr.clone());
+
+    addr.send_and_forget(Inc).await.unwrap();
+    channel.send_and_forget(Inc).await.unwrap();
+    addr.send_and_forget(Report).await.unwrap(); // The reply is discarded.
+    assert_eq!(addr.len(), 3);
+
+    let accumulator = tokio::spawn(xtra::run(mailbox, Accumulator(0)));
+    addr.send(StopSelf).await.unwrap();
+    assert_eq!(accumulator.await.unwrap(), 2);
+
+    assert_eq!(addr.send_and_forget(Inc).await, Err(Error::Disconnected));
+}
+
+struct Shard(u32);
+
+impl Actor for Shard {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+#[derive(Clone)]
+struct ShardSize;
+
+impl Handler<ShardSize> for Shard {
+    type Return = u32;
+
+    async fn handle(&mut self, _: ShardSize, _: &mut Context<Self>) -> u32 {
+        self.0
+    }
+}
+
+#[tokio::test]
+async fn gather_preserves_order_of_recipients() {
+    let (stopped, _) = Mailbox::<Shard>::unbounded();
+    let stopped = MessageChannel::new(stopped);
+    let shards = [10, 20]
+        .map(|size| MessageChannel::new(xtra::spawn_tokio(Shard(size), Mailbox::unbounded())));
+
+    let mut recipients = xtra::recipients::Recipients::<ShardSize, u32>::new();
+    recipients.push(shards[0].clone());
+    recipients.push(stopped.clone());
+    recipients.push(shards[1].clone());
+
+    assert_eq!(recipients.len(), 3);
+    assert_eq!(recipients.live_count(), 2);
+
+    assert_eq!(
+        recipients.gather(ShardSize).await,
+        [Ok(10), Err(Error::Disconnected), Ok(20)]
+    );
+    assert_eq!(
+        recipients.try_gather(ShardSize).await,
+        Err((1, Error::Disconnected))
+    );
+    assert!(matches!(
+        recipients.gather_first_ok(ShardSize).await,
+        Ok((0, 10) | (2, 20))
+    ));
+
+    let stopped = [stopped.clone(), stopped]
+        .into_iter()
+        .collect::<xtra::recipients::Recipients<_, _>>();
+    assert_eq!(
+        stopped.gather_first_ok(ShardSize).await,
+        Err(vec![Error::Disconnected, Error::Disconnected])
+    );
+}
+
+#[tokio::test]
+async fn dyn_address_downcasts_boxed_messages() {
+    use xtra::dynamic::{DynAddress, DynSendError};
+
+    let (addr, mailbox) = Mailbox::unbounded();
+    let accumulator = tokio::spawn(xtra::run(mailbox, Accumulator(0)));
+    let dynamic = DynAddress::builder(addr.clone())
+        .accept::<Inc>()
+        .accept::<StopSelf>()
+        .build();
+
+    assert_eq!(dynamic.id(), addr.id());
+    assert!(dynamic.accepts(std::any::TypeId::of::<Inc>()));
+    assert!(!dynamic.accepts(std::any::TypeId::of::<Report>()));
+
+    dynamic.send_boxed(Box::new(Inc)).await.unwrap();
+    dynamic.send_boxed(Box::new(Inc)).await.unwrap();
+
+    match dynamic.send_boxed(Box::new(Report)).await {
+        Err(DynSendError::UnknownType(message)) => assert!(message.is::<Report>()),
+        other => panic!("expected unknown type, got {:?}", other),
+    }
+
+    dynamic.send_boxed(Box::new(StopSelf)).await.unwrap();
+    assert_eq!(accumulator.await.unwrap(), 2);
+
+    assert!(matches!(
+        dynamic.send_boxed(Box::new(Inc)).await,
+        Err(DynSendError::Send(Error::Disconnected))
+    ));
+}
+
+struct Offloader;
+
+impl Actor for Offloader {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Offload(std::sync::mpsc::Receiver<u32>);
+
+impl Handler<Offload> for Offloader {
+    type Return = u32;
+
+    async fn handle(&mut self, Offload(values): Offload, ctx: &mut Context<Self>) -> u32 {
+        ctx.run_blocking(move || values.recv().unwrap()).await
+    }
+}
+
+#[tokio::test]
+async fn run_blocking_does_not_block_the_executor() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let addr = xtra::spawn_tokio(Offloader, Mailbox::unbounded());
+    let reply = tokio::spawn(addr.send(Offload(rx)));
+
+    // On this single-threaded executor, the timer only fires if the blocking function waits for
+    // the value elsewhere.
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    tx.send(42).unwrap();
+
+    assert_eq!(reply.await.unwrap(), Ok(42));
+}
+
+#[tokio::test]
+async fn run_blocking_without_spawner_runs_on_thread_of_its_own() {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Offloader));
+    let reply = tokio::spawn(addr.send(Offload(rx)));
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    tx.send(7).unwrap();
+
+    assert_eq!(reply.await.unwrap(), Ok(7));
+}
+
+struct Snapshot(#[allow(dead_code)] Vec<u8>);
+
+#[derive(Default)]
+struct Replica(Option<Arc<Snapshot>>);
+
+impl Actor for Replica {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+impl Handler<Arc<Snapshot>> for Replica {
+    type Return = ();
+
+    async fn handle(&mut self, snapshot: Arc<Snapshot>, _: &mut Context<Self>) {
+        self.0 = Some(snapshot);
+    }
+}
+
+struct Latest;
+
+impl Handler<Latest> for Replica {
+    type Return = Option<Arc<Snapshot>>;
+
+    async fn handle(&mut self, _: Latest, _: &mut Context<Self>) -> Option<Arc<Snapshot>> {
+        self.0.clone()
+    }
+}
+
+#[tokio::test]
+async fn publish_shared_delivers_the_same_event_to_all_subscribers() {
+    let bus = EventBus::new();
+    let (first, first_mailbox) = Mailbox::unbounded();
+    let (second, second_mailbox) = Mailbox::unbounded();
+
+    bus.subscribe::<Arc<Snapshot>, Replica>(&first_mailbox);
+    bus.subscribe::<Arc<Snapshot>, Replica>(&second_mailbox);
+
+    let first = xtra::spawn_tokio(Replica::default(), (first, first_mailbox));
+    let second = xtra::spawn_tokio(Replica::default(), (second, second_mailbox));
+
+    assert_eq!(bus.publish_shared(Snapshot(vec![0; 1024])).await, 2);
+
+    let first = first.send(Latest).await.unwrap().unwrap();
+    let second = second.send(Latest).await.unwrap().unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+}
+
+#[tokio::test]
+async fn message_channel_downcasts_to_the_address_it_was_built_from() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Accumulator(0)));
+    let channel = MessageChannel::<Inc, ()>::new(addr.clone());
+    let weak_channel = channel.downgrade();
+
+    assert!(channel.downcast::<Subscriber>().is_none());
+    assert_eq!(
+        weak_channel.downcast::<Accumulator>(),
+        Some(addr.downgrade())
+    );
+
+    channel.send(Inc).await.unwrap();
+    let downcast = channel.downcast::<Accumulator>().unwrap();
+    assert_eq!(downcast.send(Report).await.unwrap().0, 1);
+}
+
+/// Replies with a stream of numbers, counting how many it has sent.
+#[derive(Default)]
+struct Sequence(Arc<std::sync::atomic::AtomicUsize>);
+
+impl Actor for Sequence {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Numbers(usize);
+
+impl Handler<xtra::reply_stream::Streaming<Numbers, usize>> for Sequence {
+    type Return = ();
+
+    async fn handle(
+        &mut self,
+        request: xtra::reply_stream::Streaming<Numbers, usize>,
+        _: &mut Context<Self>,
+    ) {
+        let xtra::reply_stream::Streaming {
+            message: Numbers(n),
+            mut replies,
+        } = request;
+
+        for i in 0..n {
+            if replies.send(i).await.is_err() {
+                return;
+            }
+
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+}
+
+#[tokio::test]
+async fn reply_stream_applies_backpressure_without_losing_items() {
+    let sequence = Sequence::default();
+    let sent = sequence.0.clone();
+    let addr = xtra::spawn_tokio(sequence, Mailbox::unbounded());
+
+    let mut replies = addr.send_stream(Numbers(10_000), 4);
+    assert_eq!(replies.next().await, Some(0));
+
+    tokio::time::sleep(Duration::from_millis(10)).await; // Let the handler fill the buffer.
+    assert_eq!(sent.load(std::sync::atomic::Ordering::SeqCst), 5);
+
+    let rest = replies.by_ref().collect::<Vec<_>>().await;
+    assert_eq!(rest, (1..10_000).collect::<Vec<_>>());
+    assert!(futures_util::stream::FusedStream::is_terminated(&replies));
+    assert_eq!(replies.next().await, None);
+}
+
+#[tokio::test]
+async fn reply_stream_ends_when_actor_stops_before_handling() {
+    let (addr, mailbox) = Mailbox::<Sequence>::unbounded();
+    drop(mailbox);
+
+    let replies = addr.send_stream::<_, usize>(Numbers(10), 1);
+    let replies = replies
+        .collect::<Vec<_>>()
+        .timeout(Duration::from_secs(1))
+        .await;
+    assert_eq!(replies, Some(vec![]));
+}
+
+type StopLog = Arc<std::sync::Mutex<Vec<&'static str>>>;
+
+struct Supervisor(StopLog);
+
+impl Actor for Supervisor {
+    type Stop = ();
+
+    async fn stopped(self) {
+        self.0.lock().unwrap().push("supervisor");
+    }
+}
+
+struct Supervised(StopLog);
+
+impl Actor for Supervised {
+    type Stop = ();
+
+    async fn stopped(self) {
+        // Let the supervisor run, if it did not wait for this child.
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        self.0.lock().unwrap().push("supervised");
+    }
+}
+
+struct SpawnSupervised;
+
+impl Handler<SpawnSupervised> for Supervisor {
+    type Return = Address<Supervised>;
+
+    async fn handle(&mut self, _: SpawnSupervised, ctx: &mut Context<Self>) -> Address<Supervised> {
+        ctx.spawn_child(Supervised(self.0.clone()))
+    }
+}
+
+impl Handler<StopSelf> for Supervisor {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+#[tokio::test]
+async fn children_are_stopped_before_their_parent() {
+    let log = StopLog::default();
+    let supervisor = xtra::spawn_tokio(Supervisor(log.clone()), Mailbox::unbounded());
+
+    let first = supervisor.send(SpawnSupervised).await.unwrap();
+    let second = supervisor.send(SpawnSupervised).await.unwrap();
+
+    supervisor.send(StopSelf).await.unwrap();
+    supervisor
+        .join()
+        .timeout(Duration::from_secs(1))
+        .await
+        .unwrap();
+
+    assert!(!first.is_connected());
+    assert!(!second.is_connected());
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["supervised", "supervised", "supervisor"]
+    );
+}
+
+struct SpawnCounter;
+
+impl Handler<SpawnCounter> for Supervisor {
+    type Return = Address<Accumulator>;
+
+    async fn handle(&mut self, _: SpawnCounter, ctx: &mut Context<Self>) -> Address<Accumulator> {
+        ctx.spawn_child(Accumulator(0))
+    }
+}
+
+#[tokio::test]
+async fn children_without_spawner_run_on_shared_executor() {
+    let log = StopLog::default();
+    let (supervisor, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Supervisor(log.clone())));
+
+    let child = supervisor.send(SpawnCounter).await.unwrap();
+    child.send(Inc).await.unwrap();
+    assert_eq!(child.send(Report).await.unwrap(), Accumulator(1));
+
+    supervisor.send(StopSelf).await.unwrap();
+    supervisor
+        .join()
+        .timeout(Duration::from_secs(1))
+        .await
+        .unwrap();
+
+    assert!(!child.is_connected());
+    assert_eq!(*log.lock().unwrap(), ["supervisor"]);
+}
+
+/// Handles a message only once a permit has been added to its semaphore.
+struct Turnstile(Arc<tokio::sync::Semaphore>);
+
+impl Actor for Turnstile {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Enter;
+
+impl Handler<Enter> for Turnstile {
+    type Return = ();
+
+    async fn handle(&mut self, _: Enter, _: &mut Context<Self>) {
+        self.0.acquire().await.unwrap().forget();
+    }
+}
+
+#[cfg(feature = "sink")]
+#[tokio::test]
+async fn bounded_actor_throttles_forward_into_sink() {
+    let permits = Arc::new(tokio::sync::Semaphore::new(0));
+    let addr = xtra::spawn_tokio(Turnstile(permits.clone()), Mailbox::bounded(4));
+
+    let pulled = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let items = futures_util::stream::repeat(()).map({
+        let pulled = pulled.clone();
+        move |()| {
+            pulled.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(Enter)
+        }
+    });
+    let forward = tokio::spawn(items.forward(addr.downgrade().into_sink()));
+
+    // One message is being handled, four are queued, one is waiting for room in the mailbox and
+    // one is held by `forward` until the sink is ready.
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(pulled.load(std::sync::atomic::Ordering::SeqCst), 7);
+
+    permits.add_permits(10);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(pulled.load(std::sync::atomic::Ordering::SeqCst), 17);
+
+    forward.abort();
+}
+
+#[derive(Default)]
+struct Ledger(Vec<u32>);
+
+impl Actor for Ledger {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Entry(u32);
+
+impl Handler<Entry> for Ledger {
+    type Return = ();
+
+    async fn handle(&mut self, Entry(value): Entry, _: &mut Context<Self>) {
+        self.0.push(value);
+    }
+}
+
+struct Entries;
+
+impl Handler<Entries> for Ledger {
+    type Return = Vec<u32>;
+
+    async fn handle(&mut self, _: Entries, _: &mut Context<Self>) -> Vec<u32> {
+        self.0.clone()
+    }
+}
+
+#[tokio::test]
+async fn buffered_address_sends_full_batches_and_flushes_after_delay() {
+    let addr = xtra::spawn_tokio(Ledger::default(), Mailbox::unbounded());
+    let entries = addr.clone().buffered(3, Duration::from_millis(50));
+
+    for value in 0..4 {
+        entries.push(Entry(value)).await.unwrap();
+    }
+
+    assert_eq!(entries.len(), 1);
+    assert_eq!(addr.send(Entries).await.unwrap(), [0, 1, 2]);
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert!(entries.is_empty());
+    assert_eq!(addr.send(Entries).await.unwrap(), [0, 1, 2, 3]);
+}
+
+#[tokio::test]
+async fn buffered_address_without_runtime_flushes_on_shared_threads() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Ledger::default()));
+    let entries = addr.clone().buffered(3, Duration::from_millis(50));
+
+    entries.push(Entry(1)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert!(entries.is_empty());
+    assert_eq!(addr.send(Entries).await.unwrap(), [1]);
+}
+
+#[tokio::test]
+async fn buffered_address_flushes_explicitly_and_on_drop() {
+    let addr = xtra::spawn_tokio(Ledger::default(), Mailbox::bounded(1));
+    let entries = addr.clone().buffered(10, Duration::from_secs(60));
+
+    entries.push(Entry(1)).await.unwrap();
+    entries.flush().await.unwrap();
+    entries.push(Entry(2)).await.unwrap();
+    entries.push(Entry(3)).await.unwrap();
+    drop(entries);
+
+    assert_eq!(addr.send(Entries).await.unwrap(), [1, 2, 3]);
+}
+
+#[tokio::test]
+async fn raw_receiver_resolves_to_reply_or_disconnected() {
+    let (addr, mailbox) = Mailbox::unbounded();
+
+    let (queued, entry) = addr.send_raw(Entry(1));
+    queued.await.unwrap();
+    let (queued, entries) = addr.send_raw(Entries);
+    queued.await.unwrap();
+    let (queued, never_sent) = addr.send_raw(Entries);
+    drop(queued);
+
+    tokio::spawn(xtra::run(mailbox, Ledger::default()));
+
+    assert_eq!(entry.await, Ok(Ok(())));
+    assert_eq!(entries.await, Ok(Ok(vec![1])));
+    assert_eq!(never_sent.await, Err(xtra::catty::Disconnected));
+
+    // The message is dropped without being handled.
+    let (addr, _mailbox) = Mailbox::<Ledger>::unbounded();
+    let (queued, interrupted) = addr.send_raw(Entries);
+    queued.await.unwrap();
+    drop(addr.stop_and_recover());
+
+    assert_eq!(interrupted.await, Err(xtra::catty::Disconnected));
+}
+
+struct Fragile;
+
+impl Actor for Fragile {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Crash;
+
+impl Handler<Crash> for Fragile {
+    type Return = ();
+
+    async fn handle(&mut self, _: Crash, _: &mut Context<Self>) {
+        panic!("crashed on purpose");
+    }
+}
+
+#[tokio::test]
+async fn send_fails_with_reason_of_panicked_actor() {
+    let addr = xtra::spawn_tokio(Fragile, Mailbox::unbounded());
+
+    assert_eq!(addr.send(Crash).await, Err(Error::Interrupted));
+    addr.join().await;
+
+    assert_eq!(addr.send(Crash).await, Err(Error::Disconnected));
+    assert_eq!(addr.disconnect_reason(), Some(DisconnectReason::Panicked));
+}
+
+/// Counts the messages it handled since it was last restarted.
+#[derive(Default)]
+struct Phoenix(u32);
+
+impl Actor for Phoenix {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Rise;
+
+impl Handler<Rise> for Phoenix {
+    type Return = u32;
+
+    async fn handle(&mut self, _: Rise, _: &mut Context<Self>) -> u32 {
+        self.0 += 1;
+        self.0
+    }
+}
+
+impl Handler<Crash> for Phoenix {
+    type Return = ();
+
+    async fn handle(&mut self, _: Crash, _: &mut Context<Self>) {
+        panic!("crashed on purpose");
+    }
+}
+
+#[tokio::test]
+async fn restarted_actor_keeps_address_and_preserves_queued_messages() {
+    use xtra::restart::{InFlight, RestartPolicy};
+
+    let (addr, mailbox) = Mailbox::unbounded();
+    let policy = RestartPolicy::new(InFlight::Preserve).with_max_restarts(1);
+    let task = tokio::spawn(xtra::run_restarting(mailbox, policy, Phoenix::default));
+
+    assert_eq!(addr.send(Rise).await, Ok(1));
+
+    let crashed = addr.send(Crash).detach().await.unwrap();
+    let queued = addr.send(Rise).detach().await.unwrap();
+
+    assert_eq!(crashed.await, Err(Error::Interrupted));
+    assert_eq!(
+        queued.await,
+        Ok(1),
+        "the queued message is handled by the new instance"
+    );
+    assert!(addr.is_connected());
+
+    // The policy allows only one restart.
+    assert_eq!(addr.send(Crash).await, Err(Error::Interrupted));
+    assert!(task.await.unwrap_err().is_panic());
+
+    assert_eq!(addr.send(Rise).await, Err(Error::Disconnected));
+    assert_eq!(addr.disconnect_reason(), Some(DisconnectReason::Panicked));
+}
+
+#[tokio::test]
+async fn restarted_actor_dead_letters_queued_messages() {
+    use xtra::restart::{InFlight, RestartPolicy};
+
+    let (addr, mailbox) = Mailbox::unbounded();
+    let policy = RestartPolicy::new(InFlight::DeadLetter);
+    tokio::spawn(xtra::run_restarting(mailbox, policy, Phoenix::default));
+
+    let crashed = addr.send(Crash).detach().await.unwrap();
+    let queued = addr.send(Rise).detach().await.unwrap();
+
+    assert_eq!(crashed.await, Err(Error::Interrupted));
+    assert_eq!(queued.await, Err(Error::Interrupted));
+    assert_eq!(addr.send(Rise).await, Ok(1));
+}
+
+#[tokio::test]
+async fn aborted_actor_is_disconnected_as_aborted() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    let task = tokio::spawn(xtra::run(mailbox, Fragile));
+
+    // Let the actor start before aborting it.
+    tokio::task::yield_now().await;
+    task.abort();
+    let _ = task.await;
+
+    assert_eq!(addr.disconnect_reason(), Some(DisconnectReason::Aborted));
+}
+
+#[test]
+fn actor_stopped_at_deadline_is_disconnected_as_timed_out() {
+    let runtime = DeterministicRuntime::new(0);
+    let address = runtime.spawn_actor(Session, Mailbox::unbounded());
+    runtime.run_until_idle();
+
+    runtime.advance(Duration::from_secs(10));
+    runtime.run_until_idle();
+
+    assert_eq!(
+        address.disconnect_reason(),
+        Some(DisconnectReason::TimedOut)
+    );
+    assert_eq!(
+        runtime.block_on(address.send(Touch)),
+        Err(Error::Disconnected)
+    );
+}
+
+#[tokio::test]
+async fn disconnect_reason_is_unknown_if_actor_never_ran() {
+    let (addr, mailbox) = Mailbox::<Fragile>::unbounded();
+    assert_eq!(addr.disconnect_reason(), None);
+
+    drop(mailbox);
+
+    assert_eq!(addr.disconnect_reason(), None);
+
+    assert_eq!(addr.send(Crash).await, Err(Error::Disconnected));
+}
+
+#[tokio::test]
+async fn keyed_message_replaces_queued_message_with_same_key() {
+    let (addr, mailbox) = Mailbox::unbounded();
+
+    addr.send_keyed("save", Entry(1)).await.unwrap();
+    addr.send_keyed("load", Entry(2)).await.unwrap();
+    addr.send_keyed("save", Entry(3)).await.unwrap();
+    addr.send_keyed(1u32, Entry(4)).await.unwrap();
+    addr.send_keyed("close", Entry(5)).await.unwrap();
+
+    assert_eq!(addr.len(), 4);
+    assert!(addr.cancel_keyed("close"));
+    assert!(!addr.cancel_keyed("close"));
+    assert!(
+        !addr.cancel_keyed(1u64),
+        "keys of different types are never equal"
+    );
+
+    tokio::spawn(xtra::run(mailbox, Ledger::default()));
+
+    assert_eq!(addr.send(Entries).await.unwrap(), [2, 3, 4]);
+}
+
+#[tokio::test]
+async fn keyed_message_replaces_message_waiting_for_room() {
+    let (addr, mailbox) = Mailbox::bounded(1);
+    addr.send_and_forget(Entry(0)).await.unwrap();
+
+    let first = tokio::spawn({
+        let addr = addr.clone();
+        async move { addr.send_keyed("save", Entry(1)).await }
+    });
+    tokio::task::yield_now().await;
+
+    let second = tokio::spawn({
+        let addr = addr.clone();
+        async move { addr.send_keyed("save", Entry(2)).await }
+    });
+    tokio::task::yield_now().await;
+
+    assert!(first.await.unwrap().is_ok());
+
+    tokio::spawn(xtra::run(mailbox, Ledger::default()));
+
+    assert!(second.await.unwrap().is_ok());
+    assert_eq!(addr.send(Entries).await.unwrap(), [0, 2]);
+}
+
+struct Emitter;
+
+impl Actor for Emitter {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Tick(u32);
+
+struct Emit(std::ops::Range<u32>);
+
+impl Handler<Emit> for Emitter {
+    type Return = usize;
+
+    async fn handle(&mut self, Emit(range): Emit, ctx: &mut Context<Self>) -> usize {
+        range.map(|n| ctx.publish(Tick(n))).sum()
+    }
+}
+
+/// Publish the ticks in the range as the state of the emitter.
+struct EmitState(std::ops::Range<u32>);
+
+impl Handler<EmitState> for Emitter {
+    type Return = usize;
+
+    async fn handle(&mut self, EmitState(range): EmitState, ctx: &mut Context<Self>) -> usize {
+        range.map(|n| ctx.publish_state(Tick(n))).sum()
+    }
+}
+
+impl Handler<Tick> for Ledger {
+    type Return = ();
+
+    async fn handle(&mut self, Tick(n): Tick, _: &mut Context<Self>) {
+        self.0.push(n);
+    }
+}
+
+#[tokio::test]
+async fn subscription_drops_oldest_events_and_ends_with_actor() {
+    let addr = xtra::spawn_tokio(Emitter, Mailbox::unbounded());
+    let ticks = addr.subscribe::<Tick>(2, xtra::subscription::Overflow::DropOldest);
+
+    assert_eq!(addr.send(Emit(0..4)).await.unwrap(), 4);
+    assert_eq!(ticks.dropped(), 2);
+
+    // Subscribing does not keep the actor alive.
+    drop(addr);
+
+    assert_eq!(ticks.collect::<Vec<_>>().await, [Tick(2), Tick(3)]);
+}
+
+#[tokio::test]
+async fn dropped_subscription_is_pruned() {
+    let addr = xtra::spawn_tokio(Emitter, Mailbox::unbounded());
+    let ticks = addr.subscribe::<Tick>(2, xtra::subscription::Overflow::DropNewest);
+    let _other = addr.subscribe::<Tick>(2, xtra::subscription::Overflow::DropNewest);
+
+    assert_eq!(addr.send(Emit(0..1)).await.unwrap(), 2);
+    drop(ticks);
+    assert_eq!(addr.send(Emit(1..2)).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn state_subscription_starts_with_latest_state() {
+    let addr = xtra::spawn_tokio(Emitter, Mailbox::unbounded());
+    let early = addr.subscribe_state::<Tick>(1, xtra::subscription::Overflow::DropOldest);
+
+    assert_eq!(addr.send(EmitState(0..3)).await.unwrap(), 3);
+
+    let late = addr.subscribe_state::<Tick>(4, xtra::subscription::Overflow::DropNewest);
+    let events = addr.subscribe::<Tick>(4, xtra::subscription::Overflow::DropNewest);
+    assert_eq!(early.dropped(), 2);
+
+    // State subscriptions are pruned like any other once dropped.
+    assert_eq!(addr.send(EmitState(3..4)).await.unwrap(), 3);
+    drop(events);
+    assert_eq!(addr.send(EmitState(4..5)).await.unwrap(), 2);
+    drop(addr);
+
+    assert_eq!(early.collect::<Vec<_>>().await, [Tick(4)]);
+    assert_eq!(late.collect::<Vec<_>>().await, [Tick(2), Tick(3), Tick(4)]);
+}
+
+#[tokio::test]
+async fn subscribed_channel_receives_events_in_order() {
+    let emitter = xtra::spawn_tokio(Emitter, Mailbox::unbounded());
+    let ledger = xtra::spawn_tokio(Ledger::default(), Mailbox::unbounded());
+    emitter.subscribe_with_channel(
+        MessageChannel::<Tick, (), _>::new(ledger.downgrade()),
+        2,
+        xtra::subscription::Overflow::DropNewest,
+    );
+
+    emitter.send(Emit(0..3)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    // All events are published before any is forwarded, so the one which does not fit is dropped.
+    assert_eq!(ledger.send(Entries).await.unwrap(), [0, 1]);
+}
+
+#[tokio::test]
+async fn subscribed_channel_without_spawner_forwards_on_shared_executor() {
+    let (emitter, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Emitter));
+    let ledger = xtra::spawn_tokio(Ledger::default(), Mailbox::unbounded());
+    emitter.subscribe_with_channel(
+        MessageChannel::<Tick, (), _>::new(ledger.downgrade()),
+        2,
+        xtra::subscription::Overflow::DropNewest,
+    );
+
+    emitter.send(Emit(0..2)).await.unwrap();
+
+    let entries = tokio::time::timeout(Duration::from_secs(1), async {
+        loop {
+            let entries = ledger.send(Entries).await.unwrap();
+
+            if entries.len() == 2 {
+                return entries;
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the events to be forwarded on the shared executor");
+
+    assert_eq!(entries, [0, 1]);
+}
+
+#[tokio::test]
+async fn subscribed_channel_does_not_keep_subscriber_alive() {
+    let emitter = xtra::spawn_tokio(Emitter, Mailbox::unbounded());
+    let ledger = xtra::spawn_tokio(Ledger::default(), Mailbox::unbounded());
+    let join = ledger.join();
+    emitter.subscribe_with_channel(
+        MessageChannel::<Tick, (), _>::new(ledger),
+        2,
+        xtra::subscription::Overflow::DropNewest,
+    );
+
+    tokio::time::timeout(Duration::from_secs(1), join)
+        .await
+        .expect("the subscriber to stop once its last strong address is dropped");
+}
+
+/// Counts the times it has been asked for the time.
+struct Clock(u32);
+
+impl Actor for Clock {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Now;
+
+impl Handler<Now> for Clock {
+    type Return = Tick;
+
+    async fn handle(&mut self, _: Now, _: &mut Context<Self>) -> Tick {
+        self.0 += 1;
+        Tick(self.0)
+    }
+}
+
+#[tokio::test]
+async fn detached_reply_is_forwarded_to_channel() {
+    let clock = xtra::spawn_tokio(Clock(0), Mailbox::unbounded());
+    let ledger = xtra::spawn_tokio(Ledger::default(), Mailbox::unbounded());
+    let channel = MessageChannel::<Tick, (), _>::new(ledger.downgrade());
+
+    clock.send_detached(Now, channel.clone()).await.unwrap();
+    clock.send_detached(Now, channel.clone()).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(ledger.send(Entries).await.unwrap(), [1, 2]);
+
+    // The reply to a stopped actor is dropped without affecting the actor which sent it.
+    drop(ledger);
+    clock.send_detached(Now, channel).await.unwrap();
+    assert_eq!(clock.send(Now).await.unwrap(), Tick(4));
+}
+
+#[tokio::test]
+async fn detached_reply_without_spawner_is_forwarded_on_shared_executor() {
+    let (clock, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Clock(0)));
+    let ledger = xtra::spawn_tokio(Ledger::default(), Mailbox::unbounded());
+
+    clock
+        .send_detached(Now, MessageChannel::new(ledger.clone()))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(ledger.send(Entries).await.unwrap(), [1]);
+}
+
+/// Records the actors which it monitors stopping.
+#[derive(Default)]
+struct Watcher(Vec<xtra::monitor::Down>);
+
+impl Actor for Watcher {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Downs;
+
+impl Handler<xtra::monitor::Down> for Watcher {
+    type Return = ();
+
+    async fn handle(&mut self, down: xtra::monitor::Down, _: &mut Context<Self>) {
+        self.0.push(down);
+    }
+}
+
+impl Handler<Downs> for Watcher {
+    type Return = Vec<(xtra::ActorId, Option<DisconnectReason>)>;
+
+    async fn handle(
+        &mut self,
+        _: Downs,
+        _: &mut Context<Self>,
+    ) -> Vec<(xtra::ActorId, Option<DisconnectReason>)> {
+        self.0
+            .iter()
+            .map(|down| (down.actor_id(), down.reason()))
+            .collect()
+    }
+}
+
+struct DownActors;
+
+impl Handler<DownActors> for Watcher {
+    type Return = Vec<xtra::Disconnected>;
+
+    async fn handle(&mut self, _: DownActors, _: &mut Context<Self>) -> Vec<xtra::Disconnected> {
+        self.0.iter().map(|down| *down.actor()).collect()
+    }
+}
+
+#[tokio::test]
+async fn monitor_receives_down_once_monitored_actor_stops() {
+    let watcher = xtra::spawn_tokio(Watcher::default(), Mailbox::unbounded());
+    let stopping = xtra::spawn_tokio(ActorStopSelf, Mailbox::unbounded());
+    let dropped = xtra::spawn_tokio(ActorStopSelf, Mailbox::unbounded());
+    let (stopping_id, dropped_id) = (stopping.id(), dropped.id());
+
+    stopping.monitor(MessageChannel::new(watcher.downgrade()));
+    dropped.monitor(MessageChannel::new(watcher.downgrade()));
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(watcher.send(Downs).await.unwrap().is_empty());
+
+    stopping.send(StopSelf).await.unwrap();
+    stopping.join().await;
+    drop(dropped);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    // Monitoring an actor which has already stopped reports it right away.
+    stopping.monitor(MessageChannel::new(watcher.downgrade()));
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(
+        watcher.send(Downs).await.unwrap(),
+        [
+            (stopping_id, Some(DisconnectReason::StoppedSelf)),
+            (dropped_id, Some(DisconnectReason::AddressesDropped)),
+            (stopping_id, Some(DisconnectReason::StoppedSelf)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn down_reports_type_name_of_named_actor() {
+    let watcher = xtra::spawn_tokio(Watcher::default(), Mailbox::unbounded());
+    let named = xtra::spawn_tokio(Named(7), Mailbox::unbounded());
+    let named_id = named.id();
+
+    named.monitor(MessageChannel::new(watcher.downgrade()));
+    drop(named);
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    let actors = watcher.send(DownActors).await.unwrap();
+    assert_eq!(actors.len(), 1);
+    assert_eq!(actors[0].actor_type(), std::any::type_name::<Named>());
+    assert_eq!(actors[0].actor_id(), named_id);
+    assert_eq!(
+        actors[0].to_string(),
+        format!("Actor address disconnected: basic::Named{}", named_id)
+    );
+}
+
+#[tokio::test]
+async fn monitor_without_spawner_watches_on_shared_executor() {
+    let watcher = xtra::spawn_tokio(Watcher::default(), Mailbox::unbounded());
+    let (stopping, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, ActorStopSelf));
+    let stopping_id = stopping.id();
+
+    stopping.monitor(MessageChannel::new(watcher.downgrade()));
+    stopping.send(StopSelf).await.unwrap();
+    stopping.join().await;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(
+        watcher.send(Downs).await.unwrap(),
+        [(stopping_id, Some(DisconnectReason::StoppedSelf))]
+    );
+}
+
+#[derive(Default)]
+struct Timeouts {
+    pending: std::collections::HashMap<u32, xtra::TimerId>,
+    /// The requests which expired, with how long after their deadline they were handled.
+    expired: Vec<(u32, Duration)>,
+}
+
+impl Actor for Timeouts {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Expect(u32, Duration);
+
+impl Handler<Expect> for Timeouts {
+    type Return = ();
+
+    async fn handle(&mut self, Expect(id, timeout): Expect, ctx: &mut Context<Self>) {
+        let timer = ctx.notify_after(Expire(id), timeout);
+        self.pending.insert(id, timer);
+    }
+}
+
+struct Fulfil(u32);
+
+impl Handler<Fulfil> for Timeouts {
+    type Return = bool;
+
+    async fn handle(&mut self, Fulfil(id): Fulfil, ctx: &mut Context<Self>) -> bool {
+        ctx.cancel_timer(self.pending[&id])
+    }
+}
+
+struct Expire(u32);
+
+impl Handler<Expire> for Timeouts {
+    type Return = ();
+
+    async fn handle(&mut self, Expire(id): Expire, ctx: &mut Context<Self>) {
+        let deadline = ctx.timer_deadline().unwrap();
+        let now = ctx.mailbox().timer().unwrap().now();
+
+        self.expired.push((id, now - deadline));
+    }
+}
+
+struct Stall(Duration);
+
+impl Handler<Stall> for Timeouts {
+    type Return = ();
+
+    async fn handle(&mut self, Stall(duration): Stall, ctx: &mut Context<Self>) {
+        ctx.mailbox().timer().unwrap().sleep(duration).await;
+    }
+}
+
+struct ExpiredRequests;
+
+impl Handler<ExpiredRequests> for Timeouts {
+    type Return = Vec<(u32, Duration)>;
+
+    async fn handle(&mut self, _: ExpiredRequests, _: &mut Context<Self>) -> Vec<(u32, Duration)> {
+        self.expired.clone()
+    }
+}
+
+/// Records the alarms which went off, to check timers of actors without a configured runtime.
+#[derive(Default)]
+struct Alarms(Vec<&'static str>);
+
+impl Actor for Alarms {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct SetAlarm(&'static str, Duration);
+
+impl Handler<SetAlarm> for Alarms {
+    type Return = ();
+
+    async fn handle(&mut self, SetAlarm(name, delay): SetAlarm, ctx: &mut Context<Self>) {
+        ctx.notify_after(Ring(name), delay);
+    }
+}
+
+struct SetDebounced(&'static str, Duration);
+
+impl Handler<SetDebounced> for Alarms {
+    type Return = ();
+
+    async fn handle(&mut self, SetDebounced(name, window): SetDebounced, ctx: &mut Context<Self>) {
+        ctx.notify_debounced(Ring(name), window);
+    }
+}
+
+struct Ring(&'static str);
+
+impl Handler<Ring> for Alarms {
+    type Return = ();
+
+    async fn handle(&mut self, Ring(name): Ring, _: &mut Context<Self>) {
+        self.0.push(name);
+    }
+}
+
+struct Rung;
+
+impl Handler<Rung> for Alarms {
+    type Return = Vec<&'static str>;
+
+    async fn handle(&mut self, _: Rung, _: &mut Context<Self>) -> Vec<&'static str> {
+        self.0.clone()
+    }
+}
+
+#[tokio::test]
+async fn notify_after_without_timer_falls_back_to_shared_timer_thread() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Alarms::default()));
+
+    addr.send(SetAlarm("late", Duration::from_millis(20)))
+        .await
+        .unwrap();
+    addr.send(SetAlarm("early", Duration::from_millis(5)))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(addr.send(Rung).await.unwrap(), ["early", "late"]);
+}
+
+#[tokio::test]
+async fn notify_debounced_without_timer_falls_back_to_shared_timer_thread() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Alarms::default()));
+
+    for name in ["first", "second", "last"] {
+        addr.send(SetDebounced(name, Duration::from_millis(10)))
+            .await
+            .unwrap();
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    assert_eq!(addr.send(Rung).await.unwrap(), ["last"]);
+}
+
+#[test]
+fn timers_fire_in_deadline_order_unless_cancelled() {
+    let runtime = DeterministicRuntime::new(0);
+    let addr = runtime.spawn_actor(Timeouts::default(), Mailbox::unbounded());
+
+    for (id, secs) in [(1, 30), (2, 10), (3, 20), (4, 15)] {
+        runtime
+            .block_on(addr.send(Expect(id, Duration::from_secs(secs))))
+            .unwrap();
+    }
+
+    // Enough timers are cancelled for the queue to compact its deadlines.
+    for id in 100..200 {
+        runtime
+            .block_on(addr.send(Expect(id, Duration::from_secs(1))))
+            .unwrap();
+        assert!(runtime.block_on(addr.send(Fulfil(id))).unwrap());
+    }
+    assert!(runtime.block_on(addr.send(Fulfil(4))).unwrap());
+
+    runtime.run_until_idle();
+    runtime.advance(Duration::from_secs(10));
+    runtime.run_until_idle();
+    assert_eq!(
+        runtime.block_on(addr.send(ExpiredRequests)).unwrap(),
+        [(2, Duration::ZERO)]
+    );
+
+    // Both remaining timers are due at once and are fired in the order of their deadlines.
+    runtime.advance(Duration::from_secs(20));
+    runtime.run_until_idle();
+    assert_eq!(
+        runtime.block_on(addr.send(ExpiredRequests)).unwrap(),
+        [
+            (2, Duration::ZERO),
+            (3, Duration::from_secs(10)),
+            (1, Duration::ZERO)
+        ]
+    );
+    assert!(
+        !runtime.block_on(addr.send(Fulfil(1))).unwrap(),
+        "fired timer cannot be cancelled"
+    );
+}
+
+#[test]
+fn timer_due_while_actor_is_busy_fires_after_handler_with_original_deadline() {
+    let runtime = DeterministicRuntime::new(0);
+    let addr = runtime.spawn_actor(Timeouts::default(), Mailbox::unbounded());
+
+    runtime
+        .block_on(addr.send(Expect(1, Duration::from_secs(1))))
+        .unwrap();
+    let stall = runtime
+        .block_on(addr.send(Stall(Duration::from_secs(5))).detach())
+        .unwrap();
+    runtime.run_until_idle();
+
+    runtime.advance(Duration::from_secs(1));
+    runtime.run_until_idle();
+    runtime.advance(Duration::from_secs(4));
+    runtime.block_on(stall).unwrap();
+
+    assert_eq!(
+        runtime.block_on(addr.send(ExpiredRequests)).unwrap(),
+        [(1, Duration::from_secs(4))]
+    );
+}
+
+struct Burst(u32);
+
+impl Handler<Burst> for Timeouts {
+    type Return = ();
+
+    async fn handle(&mut self, Burst(id): Burst, ctx: &mut Context<Self>) {
+        ctx.notify_debounced(Expire(id), Duration::from_millis(500));
+    }
+}
+
+struct Linger(Arc<()>);
+
+impl Handler<Linger> for Timeouts {
+    type Return = ();
+
+    async fn handle(&mut self, Linger(held): Linger, ctx: &mut Context<Self>) {
+        ctx.notify_debounced(Linger(held), Duration::from_secs(1));
+    }
+}
+
+#[test]
+fn debounced_notification_keeps_only_latest_message() {
+    let runtime = DeterministicRuntime::new(0);
+    let addr = runtime.spawn_actor(Timeouts::default(), Mailbox::unbounded());
+
+    runtime.block_on(addr.send(Burst(1))).unwrap();
+    runtime.run_until_idle();
+    runtime.advance(Duration::from_millis(300));
+    runtime.block_on(addr.send(Burst(2))).unwrap();
+    runtime.run_until_idle();
+
+    runtime.advance(Duration::from_millis(300));
+    runtime.run_until_idle();
+    assert_eq!(
+        runtime.block_on(addr.send(ExpiredRequests)).unwrap(),
+        [],
+        "window should restart with every message"
+    );
+
+    runtime.advance(Duration::from_millis(200));
+    runtime.run_until_idle();
+    assert_eq!(
+        runtime.block_on(addr.send(ExpiredRequests)).unwrap(),
+        [(2, Duration::ZERO)]
+    );
+}
+
+#[test]
+fn debounced_notification_is_dropped_when_actor_stops() {
+    let runtime = DeterministicRuntime::new(0);
+    let addr = runtime.spawn_actor(Timeouts::default(), Mailbox::unbounded());
+    let linger = Arc::new(());
+
+    runtime.block_on(addr.send(Linger(linger.clone()))).unwrap();
+    assert_eq!(Arc::strong_count(&linger), 2);
+
+    drop(addr);
+    runtime.run_until_idle();
+    assert_eq!(Arc::strong_count(&linger), 1);
+}
+
+#[tokio::test]
+async fn weak_recipients_prune_stopped_subscribers() {
+    let ledgers = (0..4)
+        .map(|_| xtra::spawn_tokio(Ledger::default(), Mailbox::unbounded()))
+        .collect::<Vec<_>>();
+    let mut subscribers = ledgers
+        .iter()
+        .map(|ledger| MessageChannel::<Tick, (), _>::new(ledger.downgrade()))
+        .collect::<xtra::recipients::WeakRecipients<Tick>>();
+
+    let mut ledgers = ledgers.into_iter();
+    let alive = ledgers.by_ref().take(2).collect::<Vec<_>>();
+    for stopped in ledgers {
+        let join = stopped.join();
+        drop(stopped);
+        join.await;
+    }
+
+    assert_eq!(subscribers.live_count(), 2);
+    assert_eq!(subscribers.len(), 4);
+
+    assert_eq!(subscribers.broadcast(Tick(1)).await, 2);
+    assert_eq!(subscribers.len(), 2);
+
+    for ledger in &alive {
+        assert_eq!(ledger.send(Entries).await.unwrap(), [1]);
+    }
+
+    drop(alive);
+    assert_eq!(subscribers.live_count(), 0);
+    assert_eq!(subscribers.broadcast(Tick(2)).await, 0);
+    assert!(subscribers.is_empty());
+}
+
+#[derive(Default)]
+struct Follower {
+    slow: bool,
+    items: Vec<u32>,
+    /// The number of dropped items, the error and whether it was detached, per completed stream.
+    completed: Vec<(usize, Option<&'static str>, bool)>,
+    handle: Option<xtra::stream::StreamHandle>,
+}
+
+impl Actor for Follower {
+    type Stop = ();
+
+    async fn stopped(self) {}
+}
+
+struct Follow<S>(S, xtra::stream::Backpressure);
+
+impl<S> Handler<Follow<S>> for Follower
+where
+    S: futures_util::Stream<Item = Result<u32, &'static str>> + Send + 'static,
+{
+    type Return = ();
+
+    async fn handle(&mut self, Follow(stream, backpressure): Follow<S>, ctx: &mut Context<Self>) {
+        self.handle = Some(ctx.attach_try_stream(stream, backpressure));
+    }
+}
+
+struct FollowInfallible<S>(S);
+
+impl<S> Handler<FollowInfallible<S>> for Follower
+where
+    S: futures_util::Stream<Item = u32> + Send + 'static,
+{
+    type Return = ();
+
+    async fn handle(
+        &mut self,
+        FollowInfallible(stream): FollowInfallible<S>,
+        ctx: &mut Context<Self>,
+    ) {
+        self.handle = Some(ctx.attach_stream(stream, Default::default()));
+    }
+}
+
+impl Handler<u32> for Follower {
+    type Return = ();
+
+    async fn handle(&mut self, item: u32, ctx: &mut Context<Self>) {
+        if self.slow {
+            let timer = ctx.mailbox().timer().unwrap();
+            timer.sleep(Duration::from_secs(1)).await;
+        }
+
+        self.items.push(item);
+    }
+}
+
+impl Handler<xtra::stream::StreamCompleted<&'static str>> for Follower {
+    type Return = ();
+
+    async fn handle(
+        &mut self,
+        completed: xtra::stream::StreamCompleted<&'static str>,
+        _: &mut Context<Self>,
+    ) {
+        self.completed
+            .push((completed.dropped, completed.error, completed.detached));
+    }
+}
+
+impl Handler<xtra::stream::StreamCompleted> for Follower {
+    type Return = ();
+
+    async fn handle(&mut self, completed: xtra::stream::StreamCompleted, _: &mut Context<Self>) {
+        self.completed
+            .push((completed.dropped, None, completed.detached));
+    }
+}
+
+struct Unfollow;
+
+impl Handler<Unfollow> for Follower {
+    type Return = ();
+
+    async fn handle(&mut self, _: Unfollow, _: &mut Context<Self>) {
+        self.handle.take().unwrap().detach();
+    }
+}
+
+/// Drop the handle of the attached stream.
+struct DropFollowHandle;
+
+impl Handler<DropFollowHandle> for Follower {
+    type Return = ();
+
+    async fn handle(&mut self, _: DropFollowHandle, _: &mut Context<Self>) {
+        self.handle = None;
+    }
+}
+
+struct Followed;
+
+impl Handler<Followed> for Follower {
+    type Return = (Vec<u32>, Vec<(usize, Option<&'static str>, bool)>);
+
+    async fn handle(
+        &mut self,
+        _: Followed,
+        _: &mut Context<Self>,
+    ) -> (Vec<u32>, Vec<(usize, Option<&'static str>, bool)>) {
+        (self.items.clone(), self.completed.clone())
+    }
+}
+
+#[tokio::test]
+async fn attached_stream_is_handled_and_completed() {
+    let addr = xtra::spawn_tokio(Follower::default(), Mailbox::bounded(1));
+
+    addr.send(FollowInfallible(futures_util::stream::iter(0..5)))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(
+        addr.send(Followed).await.unwrap(),
+        (vec![0, 1, 2, 3, 4], vec![(0, None, false)])
+    );
+}
+
+#[tokio::test]
+async fn attached_stream_without_spawner_is_polled_on_shared_executor() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Follower::default()));
+
+    addr.send(FollowInfallible(futures_util::stream::iter(0..3)))
+        .await
+        .unwrap();
+
+    let followed = tokio::time::timeout(Duration::from_secs(1), async {
+        loop {
+            let (items, completed) = addr.send(Followed).await.unwrap();
+
+            if !completed.is_empty() {
+                return (items, completed);
+            }
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the stream to be polled on the shared executor");
+
+    assert_eq!(followed, (vec![0, 1, 2], vec![(0, None, false)]));
+}
+
+#[tokio::test]
+async fn attached_try_stream_ends_at_first_error() {
+    let addr = xtra::spawn_tokio(Follower::default(), Mailbox::unbounded());
+    let stream = futures_util::stream::iter([Ok(1), Err("boom"), Ok(3)]);
+
+    addr.send(Follow(stream, xtra::stream::Backpressure::Buffer))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(
+        addr.send(Followed).await.unwrap(),
+        (vec![1], vec![(0, Some("boom"), false)])
+    );
+}
+
+#[test]
+fn attached_stream_drops_items_while_actor_is_busy() {
+    let runtime = DeterministicRuntime::new(0);
+    let follower = Follower {
+        slow: true,
+        ..Default::default()
+    };
+    let addr = runtime.spawn_actor(follower, Mailbox::unbounded());
+    let stream = futures_util::stream::iter((0..5).map(Ok));
+
+    runtime
+        .block_on(addr.send(Follow(stream, xtra::stream::Backpressure::Drop)))
+        .unwrap();
+    runtime.run_until_idle();
+    runtime.advance(Duration::from_secs(1));
+    runtime.run_until_idle();
+
+    assert_eq!(
+        runtime.block_on(addr.send(Followed)).unwrap(),
+        (vec![0], vec![(4, None, false)])
+    );
+}
+
+#[tokio::test]
+async fn detached_stream_completes_without_stopping_actor() {
+    let addr = xtra::spawn_tokio(Follower::default(), Mailbox::unbounded());
+
+    addr.send(FollowInfallible(futures_util::stream::pending()))
+        .await
+        .unwrap();
+    addr.send(Unfollow).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(
+        addr.send(Followed).await.unwrap(),
+        (vec![], vec![(0, None, true)])
+    );
+}
+
+#[tokio::test]
+async fn dropping_stream_handle_detaches_stream() {
+    let addr = xtra::spawn_tokio(Follower::default(), Mailbox::unbounded());
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let stream = futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx));
+
+    addr.send(FollowInfallible(stream)).await.unwrap();
+    tx.send(1).unwrap();
+    tx.send(2).unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert_eq!(addr.send(Followed).await.unwrap(), (vec![1, 2], vec![]));
+
+    addr.send(DropFollowHandle).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    // The stream has been dropped, so no further items can arrive.
+    assert!(tx.send(3).is_err());
+    assert_eq!(
+        addr.send(Followed).await.unwrap(),
+        (vec![1, 2], vec![(0, None, true)])
+    );
+}
+
+struct Defensive {
+    stops: Arc<std::sync::atomic::AtomicUsize>,
+    address: Address<Self>,
+}
+
+impl Actor for Defensive {
+    type Stop = ();
+
+    async fn stopped(self) {
+        self.stops.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+        // Asking an actor which is stopping to stop again is a no-op.
+        let _ = self.address.send(StopAll).detach().await;
+        let _ = self.address.send(StopSelf).detach().await;
+    }
+}
+
+#[derive(Clone)]
+struct StopRepeatedly;
+
+impl Handler<StopRepeatedly> for Defensive {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopRepeatedly, ctx: &mut Context<Self>) {
+        for _ in 0..3 {
+            ctx.stop_all();
+            ctx.stop_self();
+        }
+    }
+}
+
+impl Handler<StopAll> for Defensive {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopAll, ctx: &mut Context<Self>) {
+        ctx.stop_all();
+    }
+}
+
+impl Handler<StopSelf> for Defensive {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+/// Schedule an item to be handled long after the test has ended.
+struct Snooze;
+
+impl Handler<Snooze> for Follower {
+    type Return = ();
+
+    async fn handle(&mut self, _: Snooze, ctx: &mut Context<Self>) {
+        ctx.notify_after(0, Duration::from_secs(60));
+    }
+}
+
+#[tokio::test]
+async fn pending_timers_streams_and_batches_do_not_keep_actor_alive() {
+    let addr = xtra::spawn_tokio(Follower::default(), Mailbox::unbounded());
+    let join = addr.join();
+
+    addr.send(FollowInfallible(futures_util::stream::pending()))
+        .await
+        .unwrap();
+    addr.send(Snooze).await.unwrap();
+    let items = addr.clone().buffered(10, Duration::from_secs(60));
+    items.push(1).await.unwrap();
+
+    drop(items);
+    drop(addr);
+
+    tokio::time::timeout(Duration::from_secs(1), join)
+        .await
+        .expect("the actor to stop once its last strong address is dropped");
+}
+
+#[tokio::test]
+async fn stopping_repeatedly_stops_each_actor_once() {
+    let stops = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let (address, mailbox) = Mailbox::unbounded();
+    let actors = [mailbox.clone(), mailbox].map(|mailbox| {
+        let actor = Defensive {
+            stops: stops.clone(),
+            address: address.clone(),
+        };
+
+        tokio::spawn(xtra::run(mailbox, actor))
+    });
+
+    address.broadcast(StopRepeatedly).await.unwrap();
+
+    for actor in actors {
+        tokio::time::timeout(Duration::from_secs(1), actor)
+            .await
+            .expect("actor to stop")
+            .unwrap();
+    }
+
+    assert_eq!(stops.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert!(!address.is_connected());
+    assert!(address.send(StopAll).await.is_err());
+}
+
+#[derive(xtra::Actor)]
+struct Reader;
+
+/// Spawn a task which holds on to the given value until it is aborted.
+struct SpawnReader(Arc<()>);
+
+impl Handler<SpawnReader> for Reader {
+    type Return = xtra::scoped_task::TaskHandle;
+
+    async fn handle(
+        &mut self,
+        SpawnReader(held): SpawnReader,
+        ctx: &mut Context<Self>,
+    ) -> xtra::scoped_task::TaskHandle {
+        ctx.spawn_task(async move {
+            let _held = held;
+            futures_util::future::pending::<()>().await
+        })
+    }
+}
+
+impl Handler<StopSelf> for Reader {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+#[tokio::test]
+async fn spawned_tasks_are_aborted_when_actor_stops() {
+    let held = Arc::new(());
+    let addr = xtra::spawn_tokio(Reader, Mailbox::unbounded());
+
+    let aborted = addr.send(SpawnReader(held.clone())).await.unwrap();
+    let running = addr.send(SpawnReader(held.clone())).await.unwrap();
+    aborted.abort();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert!(aborted.is_finished());
+    assert!(!running.is_finished());
+    assert_eq!(Arc::strong_count(&held), 2);
+
+    addr.send(StopSelf).await.unwrap();
+    addr.join().await;
+    tokio::time::sleep(Duration::from_millis(10)).await;
+
+    assert!(running.is_finished());
+    assert_eq!(Arc::strong_count(&held), 1);
+}
+
+#[tokio::test]
+async fn spawned_tasks_without_spawner_run_on_shared_executor_until_actor_stops() {
+    let held = Arc::new(());
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Reader));
+
+    let running = addr.send(SpawnReader(held.clone())).await.unwrap();
+    addr.send(StopSelf).await.unwrap();
+    addr.join().await;
+
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while Arc::strong_count(&held) > 1 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("the task to be aborted on the shared executor");
+
+    assert!(running.is_finished());
+}
+
+/// Report whether the reply is wanted once the handler starts, then wait until it is not.
+struct AwaitCancellation {
+    started: tokio::sync::oneshot::Sender<bool>,
+    cancelled: tokio::sync::oneshot::Sender<()>,
+}
+
+impl Handler<AwaitCancellation> for Reader {
+    type Return = ();
+
+    async fn handle(&mut self, message: AwaitCancellation, ctx: &mut Context<Self>) {
+        let _ = message.started.send(ctx.reply_wanted());
+        ctx.reply_cancelled().await;
+        let _ = message.cancelled.send(());
+    }
+}
+
+#[tokio::test]
+async fn handlers_observe_whether_reply_is_wanted() {
+    let addr = xtra::spawn_tokio(Reader, Mailbox::unbounded());
+
+    // Nobody can wait for the reply of a message which is sent and forgotten.
+    let (started, wanted) = tokio::sync::oneshot::channel();
+    let (cancelled, cancellation) = tokio::sync::oneshot::channel();
+    addr.send_and_forget(AwaitCancellation { started, cancelled })
+        .await
+        .unwrap();
+
+    assert!(!wanted.await.unwrap());
+    cancellation.await.unwrap();
+
+    // The reply is wanted until the sender drops the future which waits for it.
+    let (started, wanted) = tokio::sync::oneshot::channel();
+    let (cancelled, mut cancellation) = tokio::sync::oneshot::channel();
+    let reply = tokio::spawn(addr.send(AwaitCancellation { started, cancelled }));
+
+    assert!(wanted.await.unwrap());
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(cancellation.try_recv().is_err());
+
+    reply.abort();
+    tokio::time::timeout(Duration::from_secs(1), cancellation)
+        .await
+        .expect("handler to observe the cancellation")
+        .unwrap();
+}
+
+#[tokio::test]
+async fn exec_runs_closures_against_actor_state() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Accumulator(0)));
+
+    addr.send(Inc).await.unwrap();
+    addr.exec(|acc, _| acc.0 += 10).await.unwrap();
+    let doubled = addr
+        .exec_async(|acc, _| {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                acc.0 *= 2;
+                acc.0
+            })
+        })
+        .await;
+
+    assert_eq!(doubled, Ok(22));
+    assert_eq!(addr.exec(|acc, _| acc.0).await, Ok(22));
+
+    addr.exec(|_, ctx| ctx.stop_self()).await.unwrap();
+    addr.join().await;
+
+    assert!(addr.exec(|acc, _| acc.0).await.is_err());
+}
+
+#[tokio::test]
+async fn messages_sent_during_async_init_are_handled_after_it() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run_with_init(mailbox, || async {
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        Accumulator(10)
+    }));
+
+    for _ in 0..3 {
+        addr.send_and_forget(Inc).await.unwrap();
+    }
+
+    assert_eq!(addr.send(Report).await.unwrap().0, 13);
+}
+
+/// Block the thread of the actor for the given duration, replying with the name of the thread.
+struct BlockFor(Duration);
+
+impl Handler<BlockFor> for Accumulator {
+    type Return = Option<String>;
+
+    async fn handle(
+        &mut self,
+        BlockFor(duration): BlockFor,
+        _ctx: &mut Context<Self>,
+    ) -> Self::Return {
+        std::thread::sleep(duration);
+        self.0 += 1;
+
+        std::thread::current().name().map(ToOwned::to_owned)
+    }
+}
+
+#[tokio::test]
+async fn actors_spawned_on_a_thread_may_block() {
+    let (addr, thread) = xtra::spawn_thread(Accumulator(0), Mailbox::unbounded());
+
+    let blocking = addr.send(BlockFor(Duration::from_millis(100)));
+    tokio::pin!(blocking);
+
+    // The runtime of the sender keeps running while the handler blocks.
+    tokio::select! {
+        _ = &mut blocking => panic!("handler to block for longer than the sleep"),
+        _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+    }
+
+    let thread_name = blocking.await.unwrap().expect("thread to be named");
+    assert!(thread_name.starts_with("xtra::basic::Accumulator#"));
+
+    addr.send(Inc).await.unwrap();
+    drop(addr);
+
+    assert_eq!(thread.join().unwrap(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn actors_spawned_on_a_tokio_thread_are_isolated_from_the_callers_runtime() {
+    let (sleeper, sleeper_thread) =
+        xtra::spawn_tokio_thread(LongRunningHandler, Mailbox::unbounded());
+    let (addr, thread) = xtra::spawn_tokio_thread(Accumulator(0), Mailbox::unbounded());
+
+    // The handler sleeps on the runtime of the actor's thread.
+    sleeper.send(Duration::from_millis(1)).await.unwrap();
+
+    let mut join_set = JoinSet::new();
+    for _ in 0..10 {
+        let addr = addr.clone();
+        join_set.spawn(async move { addr.send(Inc).await.unwrap() });
+    }
+    while let Some(sent) = join_set.join_next().await {
+        sent.unwrap();
+    }
+
+    let thread_name = addr.send(BlockFor(Duration::ZERO)).await.unwrap();
+    assert!(thread_name
+        .unwrap()
+        .starts_with("xtra::basic::Accumulator#"));
+
+    drop((addr, sleeper));
+    assert_eq!(thread.join().unwrap(), 11);
+    sleeper_thread.join().unwrap();
+}
+
+#[derive(xtra::Actor)]
+struct Cascade(Arc<std::sync::atomic::AtomicUsize>);
+
+/// Count down by notifying the actor itself until zero is reached.
+struct Countdown(usize);
+
+impl Handler<Countdown> for Cascade {
+    type Return = ();
+
+    async fn handle(&mut self, Countdown(n): Countdown, ctx: &mut Context<Self>) {
+        tokio::task::yield_now().await;
+        self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+
+        if n > 0 {
+            ctx.notify(Countdown(n - 1));
+        }
+    }
+}
+
+impl Handler<StopSelf> for Cascade {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+#[tokio::test]
+async fn wait_until_idle_waits_for_self_notifications() {
+    let handled = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let addr = xtra::spawn_tokio(Cascade(handled.clone()), Mailbox::unbounded());
+
+    assert_eq!(addr.wait_until_idle().await, Ok(()));
+
+    addr.send_and_forget(Countdown(10)).await.unwrap();
+    addr.wait_until_idle().await.unwrap();
+
+    assert_eq!(handled.load(std::sync::atomic::Ordering::SeqCst), 11);
+    assert!(addr.is_empty());
+
+    addr.send(StopSelf).await.unwrap();
+    addr.join().await;
+
+    assert!(addr.wait_until_idle().await.is_err());
+}
+
+#[derive(xtra::Actor, Default)]
+struct Vault {
+    deposits: Vec<u32>,
+}
+
+/// Deposit an amount, which is rejected unless it is even.
+struct Deposit(u32);
+
+struct Deposits;
+
+impl Handler<Deposit> for Vault {
+    type Return = Result<usize, u32>;
+
+    fn validate(&self, Deposit(n): &Deposit) -> Result<(), Self::Return> {
+        if n % 2 == 1 {
+            return Err(Err(*n));
+        }
+
+        Ok(())
+    }
+
+    async fn handle(&mut self, Deposit(n): Deposit, _: &mut Context<Self>) -> Self::Return {
+        self.deposits.push(n);
+        Ok(self.deposits.len())
+    }
+}
+
+impl Handler<Deposits> for Vault {
+    type Return = Vec<u32>;
+
+    async fn handle(&mut self, _: Deposits, _: &mut Context<Self>) -> Vec<u32> {
+        self.deposits.clone()
+    }
+}
+
+#[tokio::test]
+async fn rejected_messages_return_without_being_handled() {
+    let addr = xtra::spawn_tokio(Vault::default(), Mailbox::unbounded());
+
+    let results = futures_util::future::join_all((1..=4).map(|n| addr.send(Deposit(n)))).await;
+
+    assert_eq!(results, vec![Ok(Err(1)), Ok(Ok(1)), Ok(Err(3)), Ok(Ok(2))]);
+    assert_eq!(addr.send(Deposits).await, Ok(vec![2, 4]));
+}
+
+/// Records the order in which messages of several types are handled, by sender and sequence number.
+#[derive(xtra::Actor, Default)]
+struct Sequencer(Vec<(usize, u32)>);
+
+struct Left(usize, u32);
+
+struct Right(usize, u32);
+
+struct HandledOrder;
+
+impl Handler<Left> for Sequencer {
+    type Return = ();
+
+    async fn handle(&mut self, Left(sender, n): Left, _: &mut Context<Self>) {
+        self.0.push((sender, n));
+    }
+}
+
+impl Handler<Right> for Sequencer {
+    type Return = ();
+
+    async fn handle(&mut self, Right(sender, n): Right, _: &mut Context<Self>) {
+        self.0.push((sender, n));
+    }
+}
+
+impl Handler<HandledOrder> for Sequencer {
+    type Return = Vec<(usize, u32)>;
+
+    async fn handle(&mut self, _: HandledOrder, _: &mut Context<Self>) -> Self::Return {
+        self.0.clone()
+    }
+}
+
+/// Send the numbers up to `count` from the given sender, alternating between the flavours of
+/// sending and the types of message.
+async fn send_in_sequence(addr: Address<Sequencer>, sender: usize, count: u32) {
+    let channel = MessageChannel::<Left, ()>::new(addr.clone());
+
+    for n in 0..count {
+        match n % 5 {
+            0 => addr.send(Left(sender, n)).await.unwrap(),
+            1 => addr.send_and_forget(Right(sender, n)).await.unwrap(),
+            2 => drop(addr.send(Left(sender, n)).detach().await.unwrap()),
+            3 => channel
+                .send(Left(sender, n))
+                .detach()
+                .await
+                .map(drop)
+                .unwrap(),
+            _ => addr.send_and_forget(Left(sender, n)).await.unwrap(),
+        }
+    }
+}
+
+#[tokio::test]
+async fn messages_from_one_sender_are_handled_in_order_regardless_of_flavour() {
+    let addr = xtra::spawn_tokio(Sequencer::default(), Mailbox::bounded(4));
+
+    send_in_sequence(addr.clone(), 0, 1000).await;
+
+    let expected = (0..1000).map(|n| (0, n)).collect::<Vec<_>>();
+    assert_eq!(addr.send(HandledOrder).await.unwrap(), expected);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_senders_each_keep_their_order() {
+    let addr = xtra::spawn_tokio(Sequencer::default(), Mailbox::bounded(4));
+
+    let mut senders = JoinSet::new();
+    for sender in 0..4 {
+        senders.spawn(send_in_sequence(addr.clone(), sender, 500));
+    }
+    while let Some(sent) = senders.join_next().await {
+        sent.unwrap();
+    }
+
+    let handled = addr.send(HandledOrder).await.unwrap();
+    assert_eq!(handled.len(), 2000);
+
+    for sender in 0..4 {
+        let sequence = handled
+            .iter()
+            .filter(|(from, _)| *from == sender)
+            .map(|(_, n)| *n)
+            .collect::<Vec<_>>();
+
+        assert_eq!(sequence, (0..500).collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn rate_limited_address_waits_for_its_quota() {
+    let runtime = DeterministicRuntime::new(0);
+    let addr = runtime.spawn_actor(Vault::default(), Mailbox::unbounded());
+    let quota = xtra::rate_limit::Quota::with_period(Duration::from_secs(1)).allow_burst(2);
+    let limited = addr.clone().rate_limited(quota);
+    let shared = limited.clone();
+
+    assert_eq!(limited.available(), 2);
+    runtime
+        .block_on(limited.try_send(Deposit(2)).unwrap())
+        .unwrap()
+        .unwrap();
+    runtime.block_on(shared.send(Deposit(2))).unwrap().unwrap();
+    assert_eq!(limited.available(), 0);
+
+    let Err(rate_limited) = limited.try_send(Deposit(2)) else {
+        panic!("quota to be used up");
+    };
+    assert_eq!(rate_limited.retry_after(), Duration::from_secs(1));
+
+    let mut waiting = Box::pin(limited.send(Deposit(2)));
+    assert!((&mut waiting).now_or_never().is_none());
+    runtime.advance(Duration::from_millis(500));
+    assert!((&mut waiting).now_or_never().is_none());
+    runtime.advance(Duration::from_millis(500));
+    runtime.block_on(waiting).unwrap().unwrap();
+    assert_eq!(shared.available(), 0);
+
+    // Other addresses to the actor are not limited.
+    runtime.block_on(addr.send(Deposit(2))).unwrap().unwrap();
+    assert_eq!(runtime.block_on(addr.send(Deposits)).unwrap().len(), 4);
+
+    runtime.advance(Duration::from_secs(5));
+    assert_eq!(limited.available(), 2);
+}
+
+#[tokio::test]
+async fn rate_limited_address_without_timer_waits_on_shared_timer_thread() {
+    let (addr, mailbox) = Mailbox::unbounded();
+    tokio::spawn(xtra::run(mailbox, Vault::default()));
+    let quota = xtra::rate_limit::Quota::with_period(Duration::from_millis(10));
+    let limited = addr.clone().rate_limited(quota);
+
+    for _ in 0..3 {
+        limited.send(Deposit(2)).await.unwrap().unwrap();
+    }
+
+    assert_eq!(addr.send(Deposits).await.unwrap().len(), 3);
+}
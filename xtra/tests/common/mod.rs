@@ -0,0 +1,95 @@
+//! Scenarios which exercise the integration of xtra with an async runtime.
+//!
+//! Every runtime feature runs the same scenarios to ensure behavioural parity between them.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::future::{self, BoxFuture};
+use smol_timeout::TimeoutExt;
+use xtra::prelude::*;
+use xtra::runtime::Timer;
+
+/// A function spawning an actor onto a runtime, such as [`xtra::spawn_tokio`].
+pub type Spawn = fn(Lifecycle, (Address<Lifecycle>, Mailbox<Lifecycle>)) -> Address<Lifecycle>;
+
+#[derive(Default)]
+pub struct Lifecycle {
+    events: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl Actor for Lifecycle {
+    type Stop = ();
+
+    async fn started(&mut self, _: &Mailbox<Self>) -> Result<(), Self::Stop> {
+        self.events.lock().unwrap().push("started");
+        Ok(())
+    }
+
+    async fn stopped(self) {
+        self.events.lock().unwrap().push("stopped");
+    }
+}
+
+impl Handler<Duration> for Lifecycle {
+    type Return = ();
+
+    async fn handle(&mut self, duration: Duration, ctx: &mut Context<Self>) {
+        let timer = ctx
+            .mailbox()
+            .timer()
+            .expect("spawn function to configure a timer");
+        timer.sleep(duration).await;
+        self.events.lock().unwrap().push("handled");
+    }
+}
+
+struct StopSelf;
+
+impl Handler<StopSelf> for Lifecycle {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+pub async fn full_lifecycle(spawn: Spawn) {
+    let actor = Lifecycle::default();
+    let events = actor.events.clone();
+
+    let addr = spawn(actor, Mailbox::bounded(1));
+
+    let sends = (0..4).map(|_| addr.send(Duration::from_millis(10)));
+    for result in future::join_all(sends).await {
+        result.unwrap();
+    }
+
+    addr.send(StopSelf).await.unwrap();
+    addr.join().await;
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        ["started", "handled", "handled", "handled", "handled", "stopped"]
+    );
+}
+
+pub async fn spawn_does_not_override_configured_timer(spawn: Spawn) {
+    #[derive(Clone)]
+    struct NoDelay;
+
+    impl Timer for NoDelay {
+        fn sleep(&self, _: Duration) -> BoxFuture<'static, ()> {
+            Box::pin(future::ready(()))
+        }
+    }
+
+    let (addr, mailbox) = Mailbox::unbounded();
+    let addr = spawn(Lifecycle::default(), (addr, mailbox.with_timer(NoDelay)));
+
+    addr.send(Duration::from_secs(3600))
+        .timeout(Duration::from_secs(1))
+        .await
+        .expect("configured timer to be used")
+        .unwrap();
+}
@@ -24,12 +24,14 @@
 //! SOFTWARE.
 
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 use std::{fmt, io};
 
 use tracing::{Dispatch, Instrument};
 use tracing_subscriber::fmt::MakeWriter;
 use tracing_subscriber::FmtSubscriber;
 use xtra::prelude::*;
+use xtra::test::DeterministicRuntime;
 
 #[tokio::test]
 async fn assert_send_is_child_of_span() {
@@ -37,6 +39,7 @@ async fn assert_send_is_child_of_span() {
     let _g = tracing::dispatcher::set_default(&subscriber);
 
     let addr = xtra::spawn_tokio(Tracer, Mailbox::unbounded());
+    let id = addr.id().as_u64();
     let _ = addr
         .send(Hello("world"))
         .instrument(tracing::info_span!("user_span"))
@@ -44,9 +47,16 @@ async fn assert_send_is_child_of_span() {
 
     assert_eq!(
         buf,
-        [" INFO user_span:xtra_actor_request\
-                {actor_type=instrumentation::Tracer message_type=instrumentation::Hello}:\
-                xtra_message_handler: instrumentation: Hello world"]
+        [
+            format!(" INFO xtra_actor{{actor_type=instrumentation::Tracer actor_name=instrumentation::Tracer actor_id={id}}}: \
+                xtra::instrumentation::tracing: Actor started"),
+            format!("DEBUG user_span:xtra_actor_request\
+                {{actor_type=instrumentation::Tracer actor_name=instrumentation::Tracer actor_id={id} message_type=instrumentation::Hello}}:\
+                xtra_message_handler: xtra::instrumentation::tracing: Handling message"),
+            format!(" INFO user_span:xtra_actor_request\
+                {{actor_type=instrumentation::Tracer actor_name=instrumentation::Tracer actor_id={id} message_type=instrumentation::Hello}}:\
+                xtra_message_handler: instrumentation: Hello world")
+        ]
     );
 }
 
@@ -56,17 +66,148 @@ async fn assert_handler_span_is_child_of_caller_span_with_min_level_info() {
     let _g = tracing::dispatcher::set_default(&subscriber);
 
     let addr = xtra::spawn_tokio(Tracer, Mailbox::unbounded());
+    let id = addr.id().as_u64();
     let _ = addr
         .send(CreateInfoSpan)
         .instrument(tracing::info_span!("sender_span"))
         .await;
 
-    assert_eq!(buf, [" INFO sender_span:info_span: instrumentation: Test!"]);
+    assert_eq!(
+        buf,
+        [
+            format!(" INFO xtra_actor{{actor_type=instrumentation::Tracer actor_name=instrumentation::Tracer actor_id={id}}}: \
+                xtra::instrumentation::tracing: Actor started"),
+            " INFO sender_span:info_span: instrumentation: Test!".to_owned()
+        ]
+    );
+}
+
+#[tokio::test]
+async fn actor_lifecycle_is_traced_with_stop_reason() {
+    let (subscriber, buf) = get_subscriber("xtra=info");
+    let _g = tracing::dispatcher::set_default(&subscriber);
+
+    let addr = xtra::spawn_tokio(Tracer, Mailbox::unbounded());
+    let first = addr.id().as_u64();
+    addr.send(StopSelf).await.unwrap();
+    addr.join().await;
+
+    let (addr, mailbox) = Mailbox::unbounded();
+    let second = addr.id().as_u64();
+    let actor = tokio::spawn(xtra::run(mailbox, Tracer));
+    drop(addr);
+    actor.await.unwrap();
+
+    assert_eq!(
+        buf,
+        [
+            format!(" INFO xtra_actor{{actor_type=instrumentation::Tracer actor_name=instrumentation::Tracer actor_id={first}}}: \
+                xtra::instrumentation::tracing: Actor started"),
+            format!(" INFO xtra_actor{{actor_type=instrumentation::Tracer actor_name=instrumentation::Tracer actor_id={first}}}: \
+                xtra::instrumentation::tracing: Actor stopped reason=\"stop_self\""),
+            format!(" INFO xtra_actor{{actor_type=instrumentation::Tracer actor_name=instrumentation::Tracer actor_id={second}}}: \
+                xtra::instrumentation::tracing: Actor started"),
+            format!(" INFO xtra_actor{{actor_type=instrumentation::Tracer actor_name=instrumentation::Tracer actor_id={second}}}: \
+                xtra::instrumentation::tracing: Actor stopped reason=\"disconnected\""),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn actor_panic_is_traced() {
+    let (subscriber, buf) = get_subscriber("xtra=info");
+    let _g = tracing::dispatcher::set_default(&subscriber);
+
+    let addr = xtra::spawn_tokio(Tracer, Mailbox::unbounded());
+    let id = addr.id().as_u64();
+    let _ = addr.send(Panic).await;
+
+    assert_eq!(
+        buf,
+        [
+            format!(" INFO xtra_actor{{actor_type=instrumentation::Tracer actor_name=instrumentation::Tracer actor_id={id}}}: \
+                xtra::instrumentation::tracing: Actor started"),
+            format!("ERROR xtra_actor{{actor_type=instrumentation::Tracer actor_name=instrumentation::Tracer actor_id={id}}}: \
+                xtra::instrumentation::tracing: Actor panicked")
+        ]
+    );
+}
+
+#[test]
+fn slow_handler_is_traced_until_it_returns() {
+    let (subscriber, buf) = get_subscriber("xtra=warn");
+    let _g = tracing::dispatcher::set_default(&subscriber);
+
+    let runtime = DeterministicRuntime::new(0);
+    let (addr, mailbox) = Mailbox::unbounded();
+    let mailbox = mailbox.warn_if_handler_exceeds(Duration::from_secs(10));
+    let addr = runtime.spawn_actor(Tracer, (addr, mailbox));
+
+    let reply = runtime
+        .block_on(addr.send(Sleep(Duration::from_secs(25))).detach())
+        .unwrap();
+    runtime.run_until_idle();
+
+    for _ in 0..2 {
+        runtime.advance(Duration::from_secs(10));
+        runtime.run_until_idle();
+    }
+    runtime.advance(Duration::from_secs(5));
+    runtime.block_on(reply).unwrap();
+
+    assert_eq!(
+        buf,
+        [
+            " WARN xtra::instrumentation::tracing: Handler is still running \
+                actor_name=instrumentation::Tracer message_type=instrumentation::Sleep elapsed=10s",
+            " WARN xtra::instrumentation::tracing: Handler is still running \
+                actor_name=instrumentation::Tracer message_type=instrumentation::Sleep elapsed=20s",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn handler_blocking_the_thread_is_traced() {
+    let (subscriber, buf) = get_subscriber("xtra=warn");
+    let _g = tracing::dispatcher::set_default(&subscriber);
+
+    let (addr, mailbox) = Mailbox::unbounded();
+    let mailbox = mailbox.warn_if_handler_exceeds(Duration::from_millis(10));
+    let addr = xtra::spawn_tokio(Tracer, (addr, mailbox));
+
+    addr.send(Block(Duration::from_millis(20))).await.unwrap();
+
+    let logs = buf.as_str();
+    assert_eq!(logs.lines().count(), 1);
+    assert!(logs.starts_with(
+        " WARN xtra::instrumentation::tracing: Handler took longer than the threshold \
+            actor_name=instrumentation::Tracer message_type=instrumentation::Block elapsed="
+    ));
 }
 
 #[derive(xtra::Actor)]
 struct Tracer;
 
+struct StopSelf;
+
+impl Handler<StopSelf> for Tracer {
+    type Return = ();
+
+    async fn handle(&mut self, _: StopSelf, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+struct Panic;
+
+impl Handler<Panic> for Tracer {
+    type Return = ();
+
+    async fn handle(&mut self, _: Panic, _: &mut Context<Self>) {
+        panic!("handler panicked");
+    }
+}
+
 struct Hello(&'static str);
 
 impl Handler<Hello> for Tracer {
@@ -77,6 +218,26 @@ impl Handler<Hello> for Tracer {
     }
 }
 
+struct Sleep(Duration);
+
+impl Handler<Sleep> for Tracer {
+    type Return = ();
+
+    async fn handle(&mut self, Sleep(duration): Sleep, ctx: &mut Context<Self>) {
+        ctx.mailbox().timer().unwrap().sleep(duration).await;
+    }
+}
+
+struct Block(Duration);
+
+impl Handler<Block> for Tracer {
+    type Return = ();
+
+    async fn handle(&mut self, Block(duration): Block, _: &mut Context<Self>) {
+        std::thread::sleep(duration);
+    }
+}
+
 struct CreateInfoSpan;
 
 impl Handler<CreateInfoSpan> for Tracer {
@@ -109,6 +270,12 @@ impl<const N: usize> PartialEq<[&str; N]> for Buffer {
     }
 }
 
+impl<const N: usize> PartialEq<[String; N]> for Buffer {
+    fn eq(&self, other: &[String; N]) -> bool {
+        self.as_str().lines().collect::<Vec<_>>().eq(other)
+    }
+}
+
 impl fmt::Debug for Buffer {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         self.as_str().lines().collect::<Vec<_>>().fmt(f)
@@ -0,0 +1,112 @@
+use futures_util::future::join;
+use metrics::{SharedString, Unit};
+use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+use metrics_util::{CompositeKey, MetricKind};
+use xtra::prelude::*;
+
+#[derive(xtra::Actor)]
+struct Greeter;
+
+struct Hello;
+
+impl Handler<Hello> for Greeter {
+    type Return = ();
+
+    async fn handle(&mut self, _: Hello, _: &mut Context<Self>) {}
+}
+
+struct Stop;
+
+impl Handler<Stop> for Greeter {
+    type Return = ();
+
+    async fn handle(&mut self, _: Stop, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+/// Queue two `Hello`s for a [`Greeter`] and run it until it is stopped, then send it a message.
+fn run_greeter((address, mailbox): (Address<Greeter>, Mailbox<Greeter>)) -> Metrics {
+    let recorder = DebuggingRecorder::new();
+    let snapshotter = recorder.snapshotter();
+
+    metrics::with_local_recorder(&recorder, || {
+        smol::block_on(async {
+            let first = address.send(Hello).detach().await.unwrap();
+            let second = address.send(Hello).detach().await.unwrap();
+            let requests = async {
+                first.await.unwrap();
+                second.await.unwrap();
+                address.send(Stop).await.unwrap();
+            };
+            join(xtra::run(mailbox, Greeter), requests).await;
+
+            assert!(address.send(Hello).detach().await.is_err());
+        })
+    });
+
+    Metrics(snapshotter.snapshot().into_vec())
+}
+
+/// A snapshot of all metrics recorded while running the [`Greeter`].
+#[allow(clippy::type_complexity)]
+struct Metrics(Vec<(CompositeKey, Option<Unit>, Option<SharedString>, DebugValue)>);
+
+impl Metrics {
+    /// Find the metrics with the given kind and name, returning their labels and values.
+    fn find(&self, kind: MetricKind, name: &str) -> Vec<(Vec<(String, String)>, &DebugValue)> {
+        self.0
+            .iter()
+            .filter(|(key, ..)| key.kind() == kind && key.key().name() == name)
+            .map(|(key, _, _, value)| {
+                let labels = key
+                    .key()
+                    .labels()
+                    .map(|l| (l.key().to_owned(), l.value().to_owned()))
+                    .collect();
+                (labels, value)
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn metrics_are_labelled_by_actor_name() {
+    let metrics = run_greeter(Mailbox::unbounded());
+    let actor = vec![("actor_name".to_owned(), "metrics::Greeter".to_owned())];
+
+    assert_eq!(
+        metrics.find(MetricKind::Counter, "xtra_messages_handled_total"),
+        [(actor.clone(), &DebugValue::Counter(3))]
+    );
+    assert_eq!(
+        metrics.find(MetricKind::Counter, "xtra_messages_dead_lettered_total"),
+        [(actor.clone(), &DebugValue::Counter(1))]
+    );
+
+    let durations = metrics.find(MetricKind::Histogram, "xtra_handler_duration_seconds");
+    assert_eq!(durations.len(), 1);
+    assert_eq!(durations[0].0, actor);
+    assert!(matches!(durations[0].1, DebugValue::Histogram(h) if h.len() == 3));
+
+    let depth = metrics.find(MetricKind::Gauge, "xtra_mailbox_depth");
+    assert_eq!(depth, [(actor, &DebugValue::Gauge(0.0.into()))]);
+}
+
+#[test]
+fn message_type_labels_are_opt_in() {
+    let (address, mailbox) = Mailbox::unbounded();
+    let metrics = run_greeter((address, mailbox.with_message_type_labels()));
+
+    let handled = metrics.find(MetricKind::Counter, "xtra_messages_handled_total");
+    let label = |message: &str| {
+        vec![
+            ("actor_name".to_owned(), "metrics::Greeter".to_owned()),
+            ("message_type".to_owned(), message.to_owned()),
+        ]
+    };
+
+    assert_eq!(handled.len(), 2);
+    assert!(handled.contains(&(label("metrics::Hello"), &DebugValue::Counter(2))));
+    assert!(handled.contains(&(label("metrics::Stop"), &DebugValue::Counter(1))));
+}
@@ -24,6 +24,7 @@ fn functions_on_address_with_generic_rc_counter<A, Rc, Rc2>(
     address1.capacity();
     let _ = address1.join();
     let _ = address1.send(());
+    let _ = address1.send_and_forget(());
     let _ = address1.broadcast(());
     address1.is_connected();
     address1.is_empty();
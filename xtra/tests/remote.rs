@@ -0,0 +1,159 @@
+use futures_util::{Sink, Stream};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use xtra::prelude::*;
+use xtra::remote::{RemoteReceiver, RemoteSender};
+use xtra::Error;
+
+#[derive(xtra::Actor)]
+struct Doubler;
+
+#[derive(Serialize, Deserialize)]
+struct Double(u32);
+
+#[derive(Serialize, Deserialize)]
+struct Stop;
+
+impl Handler<Double> for Doubler {
+    type Return = u32;
+
+    async fn handle(&mut self, Double(n): Double, _: &mut Context<Self>) -> u32 {
+        n * 2
+    }
+}
+
+/// Fails to serialize if the number is odd.
+#[derive(Debug, PartialEq)]
+struct Even(u32);
+
+impl Serialize for Even {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.0 % 2 == 1 {
+            return Err(serde::ser::Error::custom("odd number"));
+        }
+
+        serializer.serialize_u32(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Even {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u32::deserialize(deserializer).map(Even)
+    }
+}
+
+impl Handler<Even> for Doubler {
+    type Return = Even;
+
+    async fn handle(&mut self, Even(n): Even, _: &mut Context<Self>) -> Even {
+        Even(n / 2)
+    }
+}
+
+impl Handler<Stop> for Doubler {
+    type Return = ();
+
+    async fn handle(&mut self, _: Stop, ctx: &mut Context<Self>) {
+        ctx.stop_self();
+    }
+}
+
+/// An in-memory transport, standing in for a connection between two processes.
+fn pipe() -> (
+    impl Sink<Vec<u8>, Error = ()> + Send + 'static,
+    impl Stream<Item = Vec<u8>> + Send + 'static,
+) {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Vec<u8>>();
+
+    let sink = futures_util::sink::unfold(tx, |tx, frame| async move {
+        tx.send(frame).map_err(|_| ())?;
+        Ok(tx)
+    });
+    let stream = futures_util::stream::unfold(rx, |mut rx| async move {
+        let frame = rx.recv().await?;
+        Some((frame, rx))
+    });
+
+    (sink, stream)
+}
+
+/// Connect to `address` over an in-memory transport, returning the sender and the task serving
+/// the connection on the receiving end.
+fn connect<M, R>(address: Address<Doubler>) -> (RemoteSender<M, R>, tokio::task::JoinHandle<()>)
+where
+    Doubler: Handler<M, Return = R>,
+    M: Serialize + for<'de> Deserialize<'de> + Send + 'static,
+    R: Serialize + for<'de> Deserialize<'de> + Send + 'static,
+{
+    let (requests_tx, requests_rx) = pipe();
+    let (replies_tx, replies_rx) = pipe();
+
+    let receiver = RemoteReceiver::new(address);
+    let serving =
+        tokio::spawn(async move { receiver.serve::<M, _, _>(requests_rx, replies_tx).await });
+
+    let (sender, connection) = RemoteSender::connect(requests_tx, replies_rx);
+    tokio::spawn(connection);
+
+    (sender, serving)
+}
+
+#[tokio::test]
+async fn replies_round_trip_over_transport() {
+    let (sender, _) = connect::<Double, u32>(xtra::spawn_tokio(Doubler, Mailbox::unbounded()));
+    let channel = sender.channel();
+
+    let replies = futures_util::future::join_all((0..10).map(|n| channel.send(Double(n)))).await;
+
+    assert_eq!(replies, (0..10).map(|n| Ok(n * 2)).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn closing_transport_disconnects_channel() {
+    let (sender, serving) =
+        connect::<Double, u32>(xtra::spawn_tokio(Doubler, Mailbox::unbounded()));
+    let channel = sender.channel();
+    assert_eq!(channel.send(Double(1)).await, Ok(2));
+
+    serving.abort();
+    channel.join().await;
+
+    assert!(!channel.is_connected());
+    assert_eq!(channel.send(Double(1)).await, Err(Error::Disconnected));
+}
+
+#[tokio::test]
+async fn stopped_remote_actor_surfaces_as_disconnected() {
+    let address = xtra::spawn_tokio(Doubler, Mailbox::unbounded());
+    let (stop, _) = connect::<Stop, ()>(address.clone());
+    let (sender, _) = connect::<Double, u32>(address.clone());
+
+    stop.channel().send(Stop).await.unwrap();
+    address.join().await;
+
+    assert_eq!(
+        sender.channel().send(Double(1)).await,
+        Err(Error::Disconnected)
+    );
+}
+
+#[tokio::test]
+async fn connection_ends_once_senders_are_dropped() {
+    let (sender, serving) =
+        connect::<Double, u32>(xtra::spawn_tokio(Doubler, Mailbox::unbounded()));
+    let reply = sender.channel().send(Double(2)).detach().await.unwrap();
+    drop(sender);
+
+    assert_eq!(reply.await, Ok(4));
+    serving.await.unwrap();
+}
+
+#[tokio::test]
+async fn serialization_failure_resolves_to_error() {
+    let (sender, _) = connect::<Even, Even>(xtra::spawn_tokio(Doubler, Mailbox::unbounded()));
+    let channel = sender.channel();
+    let odd = Err(Error::Serialization("odd number".to_owned()));
+
+    assert_eq!(channel.send(Even(1)).await, odd);
+    assert_eq!(channel.send(Even(2)).await, odd);
+    assert_eq!(channel.send(Even(4)).await, Ok(Even(2)));
+}
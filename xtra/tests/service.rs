@@ -0,0 +1,104 @@
+use std::future::poll_fn;
+use std::task::Poll;
+
+use futures_util::task::noop_waker_ref;
+use futures_util::FutureExt;
+use tower_service::Service;
+use xtra::prelude::*;
+use xtra::service::{ActorService, ChannelService};
+use xtra::Error;
+
+#[derive(xtra::Actor)]
+struct Echo;
+
+impl Handler<u32> for Echo {
+    type Return = u32;
+
+    async fn handle(&mut self, message: u32, _: &mut Context<Self>) -> u32 {
+        message
+    }
+}
+
+#[tokio::test]
+async fn call_resolves_to_handler_return() {
+    let mut service = ActorService::new(xtra::spawn_tokio(Echo, Mailbox::unbounded()));
+
+    poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+    assert_eq!(service.call(42).await, Ok(42));
+}
+
+#[tokio::test]
+async fn poll_ready_waits_for_mailbox_capacity() {
+    let (addr, mailbox) = Mailbox::bounded(1);
+    let mut service = ActorService::<Echo, u32>::new(addr);
+    let mut cx = std::task::Context::from_waker(noop_waker_ref());
+
+    let response = service.call(1);
+    assert!(matches!(service.poll_ready(&mut cx), Poll::Ready(Ok(()))));
+    let mut response = response.boxed();
+    assert!(response.poll_unpin(&mut cx).is_pending());
+    assert!(service.poll_ready(&mut cx).is_pending());
+
+    tokio::spawn(xtra::run(mailbox, Echo));
+
+    poll_fn(|cx| service.poll_ready(cx)).await.unwrap();
+    assert_eq!(response.await, Ok(1));
+}
+
+#[tokio::test]
+async fn poll_ready_fails_once_actor_is_stopped() {
+    let (addr, mailbox) = Mailbox::bounded(1);
+    let mut service = ActorService::<Echo, u32>::new(addr);
+
+    let mut response = service.call(1).boxed();
+    let mut cx = std::task::Context::from_waker(noop_waker_ref());
+    assert!(response.poll_unpin(&mut cx).is_pending());
+    assert!(service.poll_ready(&mut cx).is_pending());
+
+    drop(mailbox);
+
+    assert_eq!(
+        poll_fn(|cx| service.poll_ready(cx)).await,
+        Err(Error::Disconnected)
+    );
+}
+
+/// Wait for the service to be ready and call it once, like `tower::ServiceExt::oneshot`.
+async fn oneshot<S: Service<u32>>(mut service: S, request: u32) -> Result<S::Response, S::Error> {
+    poll_fn(|cx| service.poll_ready(cx)).await?;
+    service.call(request).await
+}
+
+#[tokio::test]
+async fn channel_service_resolves_to_handler_return() {
+    let addr = xtra::spawn_tokio(Echo, Mailbox::unbounded());
+    let channel = MessageChannel::new(addr).contramap(|n: u32| n * 2);
+
+    assert_eq!(oneshot(ChannelService::new(channel), 21).await, Ok(42));
+}
+
+#[tokio::test]
+async fn channel_service_waits_for_mailbox_capacity() {
+    let (addr, mailbox) = Mailbox::bounded(1);
+    let mut service = ChannelService::<u32, u32>::new(MessageChannel::new(addr));
+    let mut cx = std::task::Context::from_waker(noop_waker_ref());
+
+    let mut response = service.call(1).boxed();
+    assert!(response.poll_unpin(&mut cx).is_pending());
+    assert!(service.poll_ready(&mut cx).is_pending());
+
+    tokio::spawn(xtra::run(mailbox, Echo));
+
+    assert_eq!(oneshot(service, 2).await, Ok(2));
+    assert_eq!(response.await, Ok(1));
+}
+
+#[tokio::test]
+async fn channel_service_fails_once_actor_is_stopped() {
+    let (addr, mailbox) = Mailbox::<Echo>::bounded(1);
+    let service = ChannelService::<u32, u32>::new(MessageChannel::new(addr));
+
+    drop(mailbox);
+
+    assert_eq!(oneshot(service, 1).await, Err(Error::Disconnected));
+}
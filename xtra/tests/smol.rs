@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use xtra::prelude::*;
+
+mod common;
+
+#[test]
+fn full_lifecycle_on_smol_runtime() {
+    smol::block_on(common::full_lifecycle(xtra::spawn_smol));
+}
+
+#[test]
+fn spawn_smol_does_not_override_configured_timer() {
+    smol::block_on(common::spawn_does_not_override_configured_timer(
+        xtra::spawn_smol,
+    ));
+}
+
+#[test]
+fn sleep_factory_can_be_used_as_timer() {
+    smol::block_on(async {
+        let (addr, mailbox) = Mailbox::unbounded();
+        let mailbox = mailbox.with_timer(|duration: Duration| async move {
+            smol::Timer::after(duration).await;
+        });
+        let addr = xtra::spawn_smol(common::Lifecycle::default(), (addr, mailbox));
+
+        addr.send(Duration::from_millis(1)).await.unwrap();
+    })
+}